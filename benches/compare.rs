@@ -0,0 +1,217 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs the same echo scenarios on loopback against fastwebsockets and tokio-tungstenite, and prints a comparison.
+//!
+//! ```text
+//! cargo bench --bench compare --features bench
+//! ```
+//!
+//! Each library serves and drives its own connection. The handshake is skipped on both sides so that only frame
+//! handling is measured. Set `BENCH_MESSAGES` to change the number of messages sent per scenario.
+
+use std::future::Future;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use futures_util::SinkExt;
+use futures_util::StreamExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const SIZES: &[usize] = &[20, 1024, 16 * 1024];
+/// Upper bound on the bytes in flight during the throughput scenario, so that neither side blocks on a full
+/// socket buffer while the other is still writing.
+const WINDOW: usize = 64 * 1024;
+
+#[derive(Clone, Copy)]
+enum Scenario {
+  /// Sends a message and waits for its echo before sending the next one.
+  Echo,
+  /// Sends messages in batches and then reads their echoes.
+  Throughput,
+}
+
+impl Scenario {
+  fn name(self) -> &'static str {
+    match self {
+      Scenario::Echo => "echo",
+      Scenario::Throughput => "throughput",
+    }
+  }
+
+  fn batch(self, size: usize) -> usize {
+    match self {
+      Scenario::Echo => 1,
+      Scenario::Throughput => (WINDOW / size).max(1),
+    }
+  }
+}
+
+async fn pair() -> Result<(TcpStream, TcpStream)> {
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let client = TcpStream::connect(listener.local_addr()?);
+  let (client, (server, _)) = tokio::try_join!(client, listener.accept())?;
+  client.set_nodelay(true)?;
+  server.set_nodelay(true)?;
+  Ok((client, server))
+}
+
+async fn fastwebsockets(
+  scenario: Scenario,
+  size: usize,
+  messages: usize,
+) -> Result<Duration> {
+  let (client, server) = pair().await?;
+  let server = tokio::spawn(async move {
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    loop {
+      let frame = ws.read_frame().await?;
+      match frame.opcode {
+        OpCode::Close => return anyhow::Ok(()),
+        OpCode::Binary => ws.write_frame(frame).await?,
+        _ => {}
+      }
+    }
+  });
+
+  let mut ws = WebSocket::after_handshake(client, Role::Client);
+  let payload = vec![0xfe; size];
+  let batch = scenario.batch(size);
+  let start = Instant::now();
+  let mut sent = 0;
+  while sent < messages {
+    let n = batch.min(messages - sent);
+    for _ in 0..n {
+      ws.write_frame(Frame::binary(payload.as_slice().into()))
+        .await?;
+    }
+    for _ in 0..n {
+      let frame = ws.read_frame().await?;
+      assert_eq!(frame.payload.len(), size);
+    }
+    sent += n;
+  }
+  let elapsed = start.elapsed();
+
+  ws.write_frame(Frame::close(1000, &[])).await?;
+  server.await??;
+  Ok(elapsed)
+}
+
+async fn tungstenite(
+  scenario: Scenario,
+  size: usize,
+  messages: usize,
+) -> Result<Duration> {
+  let (client, server) = pair().await?;
+  let server = tokio::spawn(async move {
+    let mut ws =
+      WebSocketStream::from_raw_socket(server, protocol::Role::Server, None)
+        .await;
+    while let Some(message) = ws.next().await {
+      match message? {
+        Message::Close(_) => break,
+        message @ Message::Binary(_) => ws.send(message).await?,
+        _ => {}
+      }
+    }
+    anyhow::Ok(())
+  });
+
+  let mut ws =
+    WebSocketStream::from_raw_socket(client, protocol::Role::Client, None)
+      .await;
+  let payload = vec![0xfe; size];
+  let batch = scenario.batch(size);
+  let start = Instant::now();
+  let mut sent = 0;
+  while sent < messages {
+    let n = batch.min(messages - sent);
+    for _ in 0..n {
+      ws.feed(Message::binary(payload.clone())).await?;
+    }
+    ws.flush().await?;
+    for _ in 0..n {
+      let message = ws.next().await.expect("connection closed")?;
+      assert_eq!(message.len(), size);
+    }
+    sent += n;
+  }
+  let elapsed = start.elapsed();
+
+  ws.close(None).await?;
+  server.await??;
+  Ok(elapsed)
+}
+
+fn rate(messages: usize, elapsed: Duration) -> f64 {
+  messages as f64 / elapsed.as_secs_f64()
+}
+
+async fn run<F, Fut>(
+  f: F,
+  scenario: Scenario,
+  size: usize,
+  messages: usize,
+) -> Result<f64>
+where
+  F: Fn(Scenario, usize, usize) -> Fut,
+  Fut: Future<Output = Result<Duration>>,
+{
+  // Warm up the allocator and the connection setup path.
+  f(scenario, size, messages / 10 + 1).await?;
+  Ok(rate(messages, f(scenario, size, messages).await?))
+}
+
+fn main() -> Result<()> {
+  let messages = match std::env::var("BENCH_MESSAGES") {
+    Ok(messages) => messages.parse()?,
+    Err(_) => 100_000,
+  };
+  let rt = tokio::runtime::Builder::new_current_thread()
+    .enable_io()
+    .build()?;
+
+  println!(
+    "{:<12} {:>8} {:>18} {:>18} {:>7}",
+    "scenario", "size", "fastwebsockets", "tokio-tungstenite", "ratio"
+  );
+  for scenario in [Scenario::Echo, Scenario::Throughput] {
+    for &size in SIZES {
+      let (fast, tung) = rt.block_on(async {
+        let fast = run(fastwebsockets, scenario, size, messages).await?;
+        let tung = run(tungstenite, scenario, size, messages).await?;
+        anyhow::Ok((fast, tung))
+      })?;
+      println!(
+        "{:<12} {:>8} {:>12.0} msg/s {:>12.0} msg/s {:>6.2}x",
+        scenario.name(),
+        size,
+        fast,
+        tung,
+        fast / tung,
+      );
+    }
+  }
+  Ok(())
+}
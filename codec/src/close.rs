@@ -0,0 +1,263 @@
+// Mostly copied from https://github.com/snapview/tungstenite-rs/blob/42b8797e8b7f39efb7d9322dc8af3e9089db4f7d/src/protocol/frame/coding.rs#L117
+//
+// Copyright (c) 2017 Alexey Galakhov
+// Copyright (c) 2016 Jason Housley
+// Dual licensed under MIT and Apache 2.0
+// ---
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::CloseCode::*;
+
+/// Status code used to indicate why an endpoint is closing the WebSocket connection.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum CloseCode {
+  /// Indicates a normal closure, meaning that the purpose for
+  /// which the connection was established has been fulfilled.
+  Normal,
+  /// Indicates that an endpoint is "going away", such as a server
+  /// going down or a browser having navigated away from a page.
+  Away,
+  /// Indicates that an endpoint is terminating the connection due
+  /// to a protocol error.
+  Protocol,
+  /// Indicates that an endpoint is terminating the connection
+  /// because it has received a type of data it cannot accept (e.g., an
+  /// endpoint that understands only text data MAY send this if it
+  /// receives a binary message).
+  Unsupported,
+  /// Indicates that no status code was included in a closing frame. This
+  /// close code makes it possible to use a single method, `on_close` to
+  /// handle even cases where no close code was provided.
+  Status,
+  /// Indicates an abnormal closure. If the abnormal closure was due to an
+  /// error, this close code will not be used. Instead, the `on_error` method
+  /// of the handler will be called with the error. However, if the connection
+  /// is simply dropped, without an error, this close code will be sent to the
+  /// handler.
+  Abnormal,
+  /// Indicates that an endpoint is terminating the connection
+  /// because it has received data within a message that was not
+  /// consistent with the type of the message (e.g., non-UTF-8 \[RFC3629\]
+  /// data within a text message).
+  Invalid,
+  /// Indicates that an endpoint is terminating the connection
+  /// because it has received a message that violates its policy.  This
+  /// is a generic status code that can be returned when there is no
+  /// other more suitable status code (e.g., Unsupported or Size) or if there
+  /// is a need to hide specific details about the policy.
+  Policy,
+  /// Indicates that an endpoint is terminating the connection
+  /// because it has received a message that is too big for it to
+  /// process.
+  Size,
+  /// Indicates that an endpoint (client) is terminating the
+  /// connection because it has expected the server to negotiate one or
+  /// more extension, but the server didn't return them in the response
+  /// message of the WebSocket handshake.  The list of extensions that
+  /// are needed should be given as the reason for closing.
+  /// Note that this status code is not used by the server, because it
+  /// can fail the WebSocket handshake instead.
+  Extension,
+  /// Indicates that a server is terminating the connection because
+  /// it encountered an unexpected condition that prevented it from
+  /// fulfilling the request.
+  Error,
+  /// Indicates that the server is restarting. A client may choose to reconnect,
+  /// and if it does, it should use a randomized delay of 5-30 seconds between attempts.
+  Restart,
+  /// Indicates that the server is overloaded and the client should either connect
+  /// to a different IP (when multiple targets exist), or reconnect to the same IP
+  /// when a user has performed an action.
+  Again,
+  /// Indicates that the server was acting as a gateway or proxy and received an
+  /// invalid response from the upstream server.
+  BadGateway,
+  /// Indicates that the connection was closed due to a failure to perform a TLS
+  /// handshake. Like `Status` and `Abnormal`, it must not be sent in a close frame.
+  Tls,
+  #[doc(hidden)]
+  Reserved(u16),
+  #[doc(hidden)]
+  Iana(u16),
+  #[doc(hidden)]
+  Library(u16),
+  #[doc(hidden)]
+  Bad(u16),
+}
+
+impl CloseCode {
+  /// 1000, see `CloseCode::Normal`.
+  pub const NORMAL: u16 = 1000;
+  /// 1001, see `CloseCode::Away`.
+  pub const GOING_AWAY: u16 = 1001;
+  /// 1002, see `CloseCode::Protocol`.
+  pub const PROTOCOL_ERROR: u16 = 1002;
+  /// 1003, see `CloseCode::Unsupported`.
+  pub const UNSUPPORTED_DATA: u16 = 1003;
+  /// 1005, see `CloseCode::Status`.
+  pub const NO_STATUS_RECEIVED: u16 = 1005;
+  /// 1006, see `CloseCode::Abnormal`.
+  pub const ABNORMAL_CLOSURE: u16 = 1006;
+  /// 1007, see `CloseCode::Invalid`.
+  pub const INVALID_PAYLOAD: u16 = 1007;
+  /// 1008, see `CloseCode::Policy`.
+  pub const POLICY_VIOLATION: u16 = 1008;
+  /// 1009, see `CloseCode::Size`.
+  pub const MESSAGE_TOO_BIG: u16 = 1009;
+  /// 1010, see `CloseCode::Extension`.
+  pub const MANDATORY_EXTENSION: u16 = 1010;
+  /// 1011, see `CloseCode::Error`.
+  pub const INTERNAL_ERROR: u16 = 1011;
+  /// 1012, see `CloseCode::Restart`.
+  pub const SERVICE_RESTART: u16 = 1012;
+  /// 1013, see `CloseCode::Again`.
+  pub const TRY_AGAIN_LATER: u16 = 1013;
+  /// 1014, see `CloseCode::BadGateway`.
+  pub const BAD_GATEWAY: u16 = 1014;
+  /// 1015, see `CloseCode::Tls`.
+  pub const TLS_HANDSHAKE: u16 = 1015;
+
+  /// Check if this CloseCode is allowed.
+  pub fn is_allowed(self) -> bool {
+    !matches!(self, Bad(_) | Reserved(_) | Status | Abnormal | Tls)
+  }
+
+  /// Check if this CloseCode is reserved by RFC 6455 and must not be sent in a close frame: 1004, 1005, 1006, 1015
+  /// and the unassigned codes up to 2999.
+  pub fn is_reserved(self) -> bool {
+    matches!(self, Reserved(_) | Status | Abnormal | Tls)
+  }
+
+  /// Check if this CloseCode is in the 4000-4999 range for private use by applications.
+  pub fn is_application(self) -> bool {
+    matches!(self, Library(_))
+  }
+
+  /// A short human-readable description, using the names from the IANA registry.
+  pub fn description(self) -> &'static str {
+    match self {
+      Normal => "Normal Closure",
+      Away => "Going Away",
+      Protocol => "Protocol Error",
+      Unsupported => "Unsupported Data",
+      Status => "No Status Received",
+      Abnormal => "Abnormal Closure",
+      Invalid => "Invalid Frame Payload Data",
+      Policy => "Policy Violation",
+      Size => "Message Too Big",
+      Extension => "Mandatory Extension",
+      Error => "Internal Error",
+      Restart => "Service Restart",
+      Again => "Try Again Later",
+      BadGateway => "Bad Gateway",
+      Tls => "TLS Handshake",
+      Reserved(_) => "Reserved",
+      Iana(_) => "Registered",
+      Library(_) => "Private Use",
+      Bad(_) => "Invalid Status Code",
+    }
+  }
+}
+
+impl core::fmt::Display for CloseCode {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{} {}", u16::from(*self), self.description())
+  }
+}
+
+impl From<u16> for CloseCode {
+  fn from(code: u16) -> CloseCode {
+    match code {
+      1000 => Normal,
+      1001 => Away,
+      1002 => Protocol,
+      1003 => Unsupported,
+      1005 => Status,
+      1006 => Abnormal,
+      1007 => Invalid,
+      1008 => Policy,
+      1009 => Size,
+      1010 => Extension,
+      1011 => Error,
+      1012 => Restart,
+      1013 => Again,
+      1014 => BadGateway,
+      1015 => Tls,
+      1..=999 => Bad(code),
+      1004 => Reserved(code),
+      1016..=2999 => Reserved(code),
+      3000..=3999 => Iana(code),
+      4000..=4999 => Library(code),
+      _ => Bad(code),
+    }
+  }
+}
+
+impl From<CloseCode> for u16 {
+  fn from(code: CloseCode) -> u16 {
+    match code {
+      Normal => 1000,
+      Away => 1001,
+      Protocol => 1002,
+      Unsupported => 1003,
+      Status => 1005,
+      Abnormal => 1006,
+      Invalid => 1007,
+      Policy => 1008,
+      Size => 1009,
+      Extension => 1010,
+      Error => 1011,
+      Restart => 1012,
+      Again => 1013,
+      BadGateway => 1014,
+      Tls => 1015,
+      Reserved(code) => code,
+      Iana(code) => code,
+      Library(code) => code,
+      Bad(code) => code,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    for code in 0..=u16::MAX {
+      assert_eq!(u16::from(CloseCode::from(code)), code);
+    }
+  }
+
+  #[test]
+  fn classification() {
+    assert_eq!(CloseCode::from(CloseCode::BAD_GATEWAY), BadGateway);
+    assert!(BadGateway.is_allowed());
+    for code in [1004, 1005, 1006, 1015, 1016, 2999] {
+      assert!(CloseCode::from(code).is_reserved(), "{code}");
+      assert!(!CloseCode::from(code).is_allowed(), "{code}");
+    }
+    assert!(!Normal.is_reserved());
+    assert!(CloseCode::from(4000).is_application());
+    assert!(!CloseCode::from(3000).is_application());
+  }
+
+  #[test]
+  fn display() {
+    assert_eq!(Size.to_string(), "1009 Message Too Big");
+    assert_eq!(CloseCode::from(4321).to_string(), "4321 Private Use");
+  }
+}
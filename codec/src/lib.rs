@@ -0,0 +1,323 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frame header parsing and encoding, masking and close frame validation on byte slices, for transports with their
+//! own IO such as embedded TCP stacks.
+//!
+//! This crate is `no_std` and only uses `core`: it allocates nothing and has no IO. It is the codec of the
+//! `fastwebsockets` crate, which re-exports it as `fastwebsockets::codec` and encodes its frame headers with
+//! [`encode_head`].
+//!
+//! # Example
+//!
+//! ```
+//! use fastwebsockets_codec::{self as codec, Head, OpCode, MAX_HEAD_SIZE};
+//!
+//! let mut buf = [0; MAX_HEAD_SIZE + 5];
+//! let head = Head {
+//!   fin: true,
+//!   rsv: 0,
+//!   opcode: OpCode::Text,
+//!   mask: Some([1, 2, 3, 4]),
+//!   payload_len: 5,
+//! };
+//! let n = codec::encode_head(&head, &mut buf);
+//! buf[n..n + 5].copy_from_slice(b"hello");
+//! codec::unmask(&mut buf[n..n + 5], [1, 2, 3, 4]);
+//!
+//! let (parsed, size) = codec::decode_head(&buf).unwrap().unwrap();
+//! assert_eq!((parsed, size), (head, n));
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod close;
+mod mask;
+
+use core::fmt;
+
+pub use crate::close::CloseCode;
+pub use crate::mask::unmask;
+
+/// The largest size of a frame header: 2 bytes, an 8 byte extended payload length and a 4 byte masking key.
+pub const MAX_HEAD_SIZE: usize = 14;
+
+macro_rules! repr_u8 {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident {
+      $($(#[$vmeta:meta])* $vname:ident $(= $val:expr)?,)*
+    }) => {
+      $(#[$meta])*
+      $vis enum $name {
+        $($(#[$vmeta])* $vname $(= $val)?,)*
+      }
+
+      impl core::convert::TryFrom<u8> for $name {
+        type Error = CodecError;
+
+        fn try_from(v: u8) -> Result<Self, Self::Error> {
+          match v {
+            $(x if x == $name::$vname as u8 => Ok($name::$vname),)*
+            _ => Err(CodecError::ReservedOpcode(v)),
+          }
+        }
+      }
+
+      impl From<$name> for u8 {
+        fn from(v: $name) -> u8 {
+          v as u8
+        }
+      }
+    }
+}
+
+repr_u8! {
+    /// The opcode of a WebSocket frame (RFC 6455, Section 5.2).
+    ///
+    /// The discriminants are the on-the-wire values. Use `OpCode::try_from(u8)` and `u8::from(OpCode)` to convert;
+    /// reserved opcodes fail to convert with `CodecError::ReservedOpcode`.
+    #[repr(u8)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum OpCode {
+        /// `0x0`: continues a fragmented message.
+        Continuation = 0x0,
+        /// `0x1`: UTF-8 text data.
+        Text = 0x1,
+        /// `0x2`: binary data.
+        Binary = 0x2,
+        /// `0x8`: connection close.
+        Close = 0x8,
+        /// `0x9`: ping.
+        Ping = 0x9,
+        /// `0xA`: pong.
+        Pong = 0xA,
+    }
+}
+
+impl OpCode {
+  /// Returns `true` for `Close`, `Ping` and `Pong`.
+  #[inline]
+  pub fn is_control(self) -> bool {
+    is_control(self)
+  }
+
+  /// Returns `true` for `Continuation`, `Text` and `Binary`.
+  #[inline]
+  pub fn is_data(self) -> bool {
+    !is_control(self)
+  }
+}
+
+#[inline]
+fn is_control(opcode: OpCode) -> bool {
+  matches!(opcode, OpCode::Close | OpCode::Ping | OpCode::Pong)
+}
+
+/// A decoded frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Head {
+  pub fin: bool,
+  /// The RSV bits in their positions in the first byte: `0x40` for RSV1, `0x20` for RSV2 and `0x10` for RSV3.
+  pub rsv: u8,
+  pub opcode: OpCode,
+  pub mask: Option<[u8; 4]>,
+  pub payload_len: u64,
+}
+
+/// A protocol violation found by the codec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+  /// The frame uses a reserved opcode.
+  ReservedOpcode(u8),
+  ControlFrameFragmented,
+  /// A control frame has a payload longer than 125 bytes.
+  ControlFrameTooLarge,
+  /// A close frame payload is a single byte.
+  InvalidCloseFrame,
+  InvalidCloseCode,
+  InvalidUtf8,
+}
+
+impl fmt::Display for CodecError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CodecError::ReservedOpcode(opcode) => {
+        write!(f, "reserved opcode {opcode:#x}")
+      }
+      CodecError::ControlFrameFragmented => {
+        f.write_str("control frame is fragmented")
+      }
+      CodecError::ControlFrameTooLarge => {
+        f.write_str("control frame payload is longer than 125 bytes")
+      }
+      CodecError::InvalidCloseFrame => f.write_str("invalid close frame"),
+      CodecError::InvalidCloseCode => f.write_str("invalid close code"),
+      CodecError::InvalidUtf8 => f.write_str("invalid UTF-8"),
+    }
+  }
+}
+
+/// Decodes the frame header at the start of `buf`. Returns the header and its size, or `None` if `buf` does not
+/// hold the whole header yet.
+///
+/// The RSV bits are returned as they are, for the caller to check against the negotiated extensions.
+pub fn decode_head(buf: &[u8]) -> Result<Option<(Head, usize)>, CodecError> {
+  if buf.len() < 2 {
+    return Ok(None);
+  }
+  let fin = buf[0] & 0x80 != 0;
+  let rsv = buf[0] & 0x70;
+  let opcode = match buf[0] & 0x0F {
+    0x0 => OpCode::Continuation,
+    0x1 => OpCode::Text,
+    0x2 => OpCode::Binary,
+    0x8 => OpCode::Close,
+    0x9 => OpCode::Ping,
+    0xA => OpCode::Pong,
+    opcode => return Err(CodecError::ReservedOpcode(opcode)),
+  };
+  let masked = buf[1] & 0x80 != 0;
+  let length_code = buf[1] & 0x7F;
+  let extra = match length_code {
+    126 => 2,
+    127 => 8,
+    _ => 0,
+  };
+
+  if opcode.is_control() && !fin {
+    return Err(CodecError::ControlFrameFragmented);
+  }
+  if opcode.is_control() && length_code > 125 {
+    return Err(CodecError::ControlFrameTooLarge);
+  }
+
+  let size = 2 + extra + masked as usize * 4;
+  if buf.len() < size {
+    return Ok(None);
+  }
+  let payload_len = match extra {
+    0 => u64::from(length_code),
+    2 => u64::from(u16::from_be_bytes([buf[2], buf[3]])),
+    _ => u64::from_be_bytes(buf[2..10].try_into().unwrap()),
+  };
+  let mask = masked.then(|| buf[size - 4..size].try_into().unwrap());
+
+  Ok(Some((
+    Head {
+      fin,
+      rsv,
+      opcode,
+      mask,
+      payload_len,
+    },
+    size,
+  )))
+}
+
+/// Encodes a frame header into `buf` and returns its size, which is at most [`MAX_HEAD_SIZE`].
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than the header.
+pub fn encode_head(head: &Head, buf: &mut [u8]) -> usize {
+  buf[0] = (head.fin as u8) << 7 | (head.rsv & 0x70) | (head.opcode as u8);
+
+  let len = head.payload_len;
+  let size = if len < 126 {
+    buf[1] = len as u8;
+    2
+  } else if len < 65536 {
+    buf[1] = 126;
+    buf[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+    4
+  } else {
+    buf[1] = 127;
+    buf[2..10].copy_from_slice(&len.to_be_bytes());
+    10
+  };
+
+  if let Some(mask) = head.mask {
+    buf[1] |= 0x80;
+    buf[size..size + 4].copy_from_slice(&mask);
+    size + 4
+  } else {
+    size
+  }
+}
+
+/// Checks that a close frame payload is empty or an allowed status code followed by a UTF-8 reason.
+pub fn validate_close(payload: &[u8]) -> Result<(), CodecError> {
+  match payload.len() {
+    0 => Ok(()),
+    1 => Err(CodecError::InvalidCloseFrame),
+    _ => {
+      let code = CloseCode::from(u16::from_be_bytes([payload[0], payload[1]]));
+      if core::str::from_utf8(&payload[2..]).is_err() {
+        return Err(CodecError::InvalidUtf8);
+      }
+      if !code.is_allowed() {
+        return Err(CodecError::InvalidCloseCode);
+      }
+      Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    for len in [0, 125, 126, 65535, 65536] {
+      let head = Head {
+        fin: true,
+        rsv: 0x40,
+        opcode: OpCode::Binary,
+        mask: Some([9; 4]),
+        payload_len: len,
+      };
+      let mut buf = [0; MAX_HEAD_SIZE];
+      let size = encode_head(&head, &mut buf);
+      assert_eq!(decode_head(&buf[..size]), Ok(Some((head, size))));
+      assert_eq!(decode_head(&buf[..size - 1]), Ok(None));
+    }
+  }
+
+  #[test]
+  fn opcodes() {
+    assert_eq!(OpCode::try_from(0xA), Ok(OpCode::Pong));
+    assert_eq!(OpCode::try_from(3), Err(CodecError::ReservedOpcode(3)));
+    assert_eq!(u8::from(OpCode::Close), 0x8);
+  }
+
+  #[test]
+  fn violations() {
+    assert_eq!(decode_head(&[0x83, 0]), Err(CodecError::ReservedOpcode(3)));
+    assert_eq!(
+      decode_head(&[0x09, 0]),
+      Err(CodecError::ControlFrameFragmented)
+    );
+    assert_eq!(
+      decode_head(&[0x89, 126]),
+      Err(CodecError::ControlFrameTooLarge)
+    );
+    assert_eq!(validate_close(&[3]), Err(CodecError::InvalidCloseFrame));
+    assert_eq!(validate_close(&[3, 237]), Err(CodecError::InvalidCloseCode));
+    assert_eq!(
+      validate_close(&[3, 232, 0xff]),
+      Err(CodecError::InvalidUtf8)
+    );
+    assert_eq!(validate_close(&[3, 232, b'o', b'k']), Ok(()));
+  }
+}
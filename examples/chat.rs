@@ -0,0 +1,173 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Multi-room chat server. Connect to `ws://127.0.0.1:8080/<room>` and every text message is relayed to the
+//! clients in the same room.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use fastwebsockets::upgrade;
+use fastwebsockets::FragmentCollectorRead;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketError;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper::Response;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+
+type Room = broadcast::Sender<Arc<str>>;
+
+#[derive(Clone, Default)]
+struct Rooms(Arc<Mutex<HashMap<String, Room>>>);
+
+impl Rooms {
+  fn join(&self, name: &str) -> Room {
+    self
+      .0
+      .lock()
+      .unwrap()
+      .entry(name.to_owned())
+      .or_insert_with(|| broadcast::channel(256).0)
+      .clone()
+  }
+
+  /// Drops the room once its last client has left.
+  fn leave(&self, name: &str) {
+    let mut rooms = self.0.lock().unwrap();
+    if rooms
+      .get(name)
+      .is_some_and(|room| room.receiver_count() == 0)
+    {
+      rooms.remove(name);
+    }
+  }
+}
+
+async fn handle_client(
+  fut: upgrade::UpgradeFut,
+  room: Room,
+  name: SocketAddr,
+) -> Result<()> {
+  let ws = fut.await?;
+  let (rx, mut tx) = ws.split(tokio::io::split);
+  let mut rx = FragmentCollectorRead::new(rx);
+
+  // Pongs and close replies from the reader, and messages from the room, all go through the writer task.
+  let (control_tx, mut control) = mpsc::channel::<Frame<'static>>(16);
+  let mut messages = room.subscribe();
+  let writer = tokio::spawn(async move {
+    loop {
+      let frame = tokio::select! {
+        frame = control.recv() => match frame {
+          Some(frame) => frame,
+          None => break,
+        },
+        message = messages.recv() => match message {
+          Ok(message) => Frame::text(message.as_bytes().to_vec().into()),
+          Err(broadcast::error::RecvError::Lagged(_)) => continue,
+          Err(broadcast::error::RecvError::Closed) => break,
+        },
+      };
+      let close = frame.opcode == OpCode::Close;
+      tx.write_frame(frame).await?;
+      if close {
+        break;
+      }
+    }
+    Ok::<_, WebSocketError>(())
+  });
+
+  let _ = room.send(format!("{} joined", name).into());
+  let result = async {
+    loop {
+      let frame = rx
+        .read_frame(&mut |frame| {
+          let control_tx = control_tx.clone();
+          async move { control_tx.send(frame).await }
+        })
+        .await?;
+      match frame.opcode {
+        OpCode::Close => break,
+        OpCode::Text => {
+          let text = String::from_utf8_lossy(&frame.payload);
+          let _ = room.send(format!("{}: {}", name, text).into());
+        }
+        _ => {}
+      }
+    }
+    anyhow::Ok(())
+  }
+  .await;
+  let _ = room.send(format!("{} left", name).into());
+
+  drop(control_tx);
+  writer.await??;
+  result
+}
+
+async fn server_upgrade(
+  mut req: Request<Incoming>,
+  rooms: Rooms,
+  addr: SocketAddr,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let (response, fut) = upgrade::upgrade(&mut req)?;
+
+  let name = match req.uri().path().trim_matches('/') {
+    "" => "lobby".to_owned(),
+    name => name.to_owned(),
+  };
+  tokio::task::spawn(async move {
+    let room = rooms.join(&name);
+    if let Err(e) = handle_client(fut, room, addr).await {
+      eprintln!("Error in websocket connection: {}", e);
+    }
+    rooms.leave(&name);
+  });
+
+  Ok(response)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+  let listener = TcpListener::bind("127.0.0.1:8080").await?;
+  println!("Server started, listening on 127.0.0.1:8080");
+  let rooms = Rooms::default();
+  loop {
+    let (stream, addr) = listener.accept().await?;
+    let rooms = rooms.clone();
+    tokio::spawn(async move {
+      let io = hyper_util::rt::TokioIo::new(stream);
+      let conn_fut = http1::Builder::new()
+        .serve_connection(
+          io,
+          service_fn(move |req| server_upgrade(req, rooms.clone(), addr)),
+        )
+        .with_upgrades();
+      if let Err(e) = conn_fut.await {
+        println!("An error occurred: {:?}", e);
+      }
+    });
+  }
+}
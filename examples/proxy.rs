@@ -0,0 +1,121 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WebSocket reverse proxy. Listens on `127.0.0.1:8081` and relays every connection, frame by frame, to the same
+//! path on the upstream server given as the first argument (default: `127.0.0.1:8080`).
+
+use std::future::Future;
+
+use anyhow::Result;
+use fastwebsockets::forward_bidirectional;
+use fastwebsockets::handshake;
+use fastwebsockets::upgrade;
+use fastwebsockets::WebSocketError;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::header::CONNECTION;
+use hyper::header::UPGRADE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper::Response;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::task::spawn(fut);
+  }
+}
+
+async fn handle_client(
+  fut: upgrade::UpgradeFut,
+  upstream: String,
+  path: String,
+) -> Result<()> {
+  let client = fut.await?;
+
+  let stream = TcpStream::connect(&upstream).await?;
+  let req = Request::builder()
+    .method("GET")
+    .uri(format!("ws://{}{}", upstream, path))
+    .header("Host", &upstream)
+    .header(UPGRADE, "websocket")
+    .header(CONNECTION, "upgrade")
+    .header("Sec-WebSocket-Key", handshake::generate_key())
+    .header("Sec-WebSocket-Version", "13")
+    .body(Empty::<Bytes>::new())?;
+  let (server, _) = handshake::client(&SpawnExecutor, req, stream).await?;
+
+  let (mut client_rx, mut client_tx) = client.split(tokio::io::split);
+  let (mut server_rx, mut server_tx) = server.split(tokio::io::split);
+  forward_bidirectional(
+    (&mut client_rx, &mut client_tx),
+    (&mut server_rx, &mut server_tx),
+  )
+  .await?;
+  Ok(())
+}
+
+async fn server_upgrade(
+  mut req: Request<Incoming>,
+  upstream: String,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let (response, fut) = upgrade::upgrade(&mut req)?;
+
+  let path = req
+    .uri()
+    .path_and_query()
+    .map_or("/", |path| path.as_str())
+    .to_owned();
+  tokio::task::spawn(async move {
+    if let Err(e) = handle_client(fut, upstream, path).await {
+      eprintln!("Error in websocket connection: {}", e);
+    }
+  });
+
+  Ok(response)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+  let upstream = std::env::args()
+    .nth(1)
+    .unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+  let listener = TcpListener::bind("127.0.0.1:8081").await?;
+  println!("Proxy started, forwarding 127.0.0.1:8081 to {}", upstream);
+  loop {
+    let (stream, _) = listener.accept().await?;
+    let upstream = upstream.clone();
+    tokio::spawn(async move {
+      let io = hyper_util::rt::TokioIo::new(stream);
+      let conn_fut = http1::Builder::new()
+        .serve_connection(
+          io,
+          service_fn(move |req| server_upgrade(req, upstream.clone())),
+        )
+        .with_upgrades();
+      if let Err(e) = conn_fut.await {
+        println!("An error occurred: {:?}", e);
+      }
+    });
+  }
+}
@@ -0,0 +1,53 @@
+#![no_main]
+
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use tokio::io::AsyncWriteExt;
+
+use fastwebsockets::DeflateConfig;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+
+// Wraps the input in compressed server frames so that the fuzzer spends its time on the
+// deflate stream rather than on finding valid frame headers.
+//
+// data[0]: bit 0 enables server_no_context_takeover, bit 1 sets a preset dictionary
+// data[1]: payload bytes per frame, up to 125; a message ends at every frame shorter than that
+fuzz_target!(|data: &[u8]| {
+  if data.len() < 2 {
+    return;
+  }
+  let config = DeflateConfig {
+    server_no_context_takeover: data[0] & 1 != 0,
+    ..Default::default()
+  };
+  let chunk = (data[1] % 125) as usize + 1;
+
+  let mut bytes = Vec::new();
+  let mut first = true;
+  for payload in data[2..].chunks(chunk) {
+    let fin = payload.len() < chunk;
+    let opcode = if first { 0x2 } else { 0x0 };
+    let rsv1 = if first { 0x40 } else { 0 };
+    bytes.push(((fin as u8) << 7) | rsv1 | opcode);
+    bytes.push(payload.len() as u8);
+    bytes.extend_from_slice(payload);
+    first = fin;
+  }
+
+  let (mut server, client) = tokio::io::duplex(bytes.len().max(1));
+  let mut ws = WebSocket::after_handshake(client, Role::Client);
+  ws.set_deflate(Some(config));
+  if data[0] & 2 != 0 {
+    ws.set_deflate_dictionary(Some(Arc::from(&b"fastwebsockets"[..])));
+  }
+  ws.set_max_message_size(u16::MAX as usize);
+
+  futures::executor::block_on(async move {
+    server.write_all(&bytes).await.unwrap();
+    drop(server);
+    // Reads until the input is exhausted or rejected.
+    while ws.read_frame().await.is_ok() {}
+  });
+});
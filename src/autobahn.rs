@@ -0,0 +1,202 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::header::CONNECTION;
+use hyper::header::UPGRADE;
+use hyper::upgrade::Upgraded;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpStream;
+
+use crate::handshake;
+use crate::FragmentCollector;
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Runs the [Autobahn|Testsuite](https://github.com/crossbario/autobahn-testsuite) against the client role.
+///
+/// Start the suite with `wstest -m fuzzingserver`, then let the runner fetch the number of cases, connect once per
+/// case and ask the server to write its reports. Each case connection is handed to a handler, which is where an
+/// application plugs in its own wrapper around `WebSocket`; [`echo`] is the behaviour the suite expects.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::autobahn;
+/// use fastwebsockets::autobahn::AutobahnClient;
+///
+/// # async fn run() -> Result<(), fastwebsockets::WebSocketError> {
+/// let mut client = AutobahnClient::new("localhost:9001", "fastwebsockets");
+/// client.set_extensions(Some("permessage-deflate".to_owned()));
+/// client.run(autobahn::echo).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AutobahnClient {
+  addr: String,
+  agent: String,
+  extensions: Option<String>,
+}
+
+impl AutobahnClient {
+  /// Creates a runner for the fuzzing server at `addr`, such as `localhost:9001`. Results are reported under the
+  /// name `agent`, which must be safe to use in a URL query.
+  pub fn new(addr: impl Into<String>, agent: impl Into<String>) -> Self {
+    Self {
+      addr: addr.into(),
+      agent: agent.into(),
+      extensions: None,
+    }
+  }
+
+  /// Sets the `Sec-WebSocket-Extensions` header offered when connecting to a case, to test compression. The
+  /// extensions the server accepts are enabled on the connection passed to the handler.
+  ///
+  /// Default: `None`
+  pub fn set_extensions(&mut self, extensions: Option<String>) {
+    self.extensions = extensions;
+  }
+
+  /// Connects to `path` on the fuzzing server, for example `runCase?case=1&agent=fastwebsockets`.
+  pub async fn connect(
+    &self,
+    path: &str,
+  ) -> Result<WebSocket<TokioIo<Upgraded>>, WebSocketError> {
+    let stream = TcpStream::connect(&self.addr).await?;
+
+    let mut request = Request::builder()
+      .method("GET")
+      .uri(format!("http://{}/{}", self.addr, path))
+      .header("Host", &self.addr)
+      .header(UPGRADE, "websocket")
+      .header(CONNECTION, "upgrade")
+      .header("Sec-WebSocket-Key", handshake::generate_key())
+      .header("Sec-WebSocket-Version", "13");
+    if let Some(extensions) = &self.extensions {
+      request = request.header("Sec-WebSocket-Extensions", extensions);
+    }
+    let request = request
+      .body(Empty::<Bytes>::new())
+      .map_err(|_| WebSocketError::InvalidValue)?;
+
+    let (ws, _) = handshake::client(&SpawnExecutor, request, stream).await?;
+    Ok(ws)
+  }
+
+  /// Asks the fuzzing server for the number of test cases.
+  pub async fn case_count(&self) -> Result<u32, WebSocketError> {
+    let mut ws = FragmentCollector::new(self.connect("getCaseCount").await?);
+    let frame = ws.read_frame().await?;
+    let count = std::str::from_utf8(&frame.payload)
+      .ok()
+      .and_then(|count| count.parse().ok())
+      .ok_or(WebSocketError::InvalidValue)?;
+    ws.write_frame(Frame::close(1000, &[])).await?;
+    Ok(count)
+  }
+
+  /// Runs the test case numbered `case`, starting at 1, and returns the result of `handler`.
+  pub async fn run_case<F, Fut>(
+    &self,
+    case: u32,
+    handler: F,
+  ) -> Result<(), WebSocketError>
+  where
+    F: FnOnce(WebSocket<TokioIo<Upgraded>>) -> Fut,
+    Fut: Future<Output = Result<(), WebSocketError>>,
+  {
+    handler(self.connect(&self.case_path(case)).await?).await
+  }
+
+  /// Asks the fuzzing server to write the reports of the agent, and waits until it has.
+  pub async fn update_reports(&self) -> Result<(), WebSocketError> {
+    let path = format!("updateReports?agent={}", self.agent);
+    let mut ws = self.connect(&path).await?;
+    // The server closes the connection once the reports are written.
+    loop {
+      match ws.read_frame().await {
+        Ok(frame) if frame.opcode != OpCode::Close => {}
+        _ => return Ok(()),
+      }
+    }
+  }
+
+  /// Runs every test case with `handler` and updates the reports.
+  ///
+  /// Many cases break the protocol on purpose and the suite grades how the connection ended, so errors returned by
+  /// `handler` are part of the outcome and do not stop the run. Failing to connect does.
+  pub async fn run<F, Fut>(&self, mut handler: F) -> Result<(), WebSocketError>
+  where
+    F: FnMut(WebSocket<TokioIo<Upgraded>>) -> Fut,
+    Fut: Future<Output = Result<(), WebSocketError>>,
+  {
+    let count = self.case_count().await?;
+    for case in 1..=count {
+      let ws = self.connect(&self.case_path(case)).await?;
+      let _ = handler(ws).await;
+    }
+    self.update_reports().await
+  }
+
+  fn case_path(&self, case: u32) -> String {
+    format!("runCase?case={}&agent={}", case, self.agent)
+  }
+}
+
+/// Echoes every message back until the connection is closed, which is what the test cases expect of a client.
+///
+/// A frame that fails to read is answered with a close frame before the error is returned.
+pub async fn echo<S>(ws: WebSocket<S>) -> Result<(), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let mut ws = FragmentCollector::new(ws);
+  loop {
+    let frame = match ws.read_frame().await {
+      Ok(frame) => frame,
+      Err(e) => {
+        let _ = ws.write_frame(Frame::close_raw(vec![].into())).await;
+        return Err(e);
+      }
+    };
+    match frame.opcode {
+      OpCode::Text | OpCode::Binary => {
+        ws.write_frame(Frame::new(true, frame.opcode, None, frame.payload))
+          .await?;
+      }
+      OpCode::Close => return Ok(()),
+      _ => {}
+    }
+  }
+}
+
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::task::spawn(fut);
+  }
+}
@@ -0,0 +1,226 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Write;
+
+use brotli::CompressorWriter;
+use brotli::DecompressorWriter;
+
+use crate::WebSocketError;
+
+const EXTENSION_NAME: &str = "permessage-brotli";
+const BUFFER_SIZE: usize = 4096;
+
+/// Settings of the non-standard `permessage-brotli` extension.
+///
+/// The extension works like permessage-deflate, but compresses every message as a separate Brotli stream. It is not
+/// registered with IANA, so only enable it when both endpoints use this crate. It has no parameters: a client offers
+/// it with [`BrotliConfig::offer`] and a server accepts it with `upgrade::upgrade_with_brotli`. These settings only
+/// affect the messages written by the local end.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::BrotliConfig;
+///
+/// let config = BrotliConfig {
+///   quality: 9,
+///   ..Default::default()
+/// };
+/// assert_eq!(config.offer(), "permessage-brotli");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrotliConfig {
+  /// Compression quality, from 0 (fastest) to 11 (smallest output).
+  pub quality: u32,
+  /// Base-2 logarithm of the window the compressor uses, between 10 and 24.
+  pub window_bits: u32,
+}
+
+impl Default for BrotliConfig {
+  fn default() -> Self {
+    Self {
+      quality: 5,
+      window_bits: 22,
+    }
+  }
+}
+
+impl BrotliConfig {
+  /// The value of the `Sec-WebSocket-Extensions` header a client sends to offer the extension.
+  pub fn offer(&self) -> String {
+    EXTENSION_NAME.to_owned()
+  }
+
+  /// The value of the `Sec-WebSocket-Extensions` header a server sends to accept the extension.
+  pub fn response(&self) -> String {
+    EXTENSION_NAME.to_owned()
+  }
+}
+
+/// Returns whether a `Sec-WebSocket-Extensions` header lists the `permessage-brotli` extension. Elements with
+/// parameters are ignored, since the extension has none.
+pub(crate) fn is_listed(header: &str) -> bool {
+  header
+    .split(',')
+    .any(|element| element.trim().eq_ignore_ascii_case(EXTENSION_NAME))
+}
+
+/// Compresses outgoing messages.
+pub(crate) struct BrotliEncoder {
+  config: BrotliConfig,
+  /// The stream of the message being written.
+  writer: Option<CompressorWriter<Vec<u8>>>,
+}
+
+impl BrotliEncoder {
+  pub fn new(config: &BrotliConfig) -> Self {
+    Self {
+      config: *config,
+      writer: None,
+    }
+  }
+
+  /// Compresses the payload of one frame. `fin` marks the last frame of the message, which ends the stream.
+  pub fn compress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let config = self.config;
+    let writer = self.writer.get_or_insert_with(|| {
+      CompressorWriter::new(
+        Vec::new(),
+        BUFFER_SIZE,
+        config.quality.min(11),
+        config.window_bits.clamp(10, 24),
+      )
+    });
+    writer.write_all(input)?;
+    if fin {
+      return Ok(self.writer.take().unwrap().into_inner());
+    }
+    writer.flush()?;
+    Ok(std::mem::take(writer.get_mut()))
+  }
+}
+
+/// Decompresses incoming messages.
+pub(crate) struct BrotliDecoder {
+  /// The stream of the message being read.
+  writer: Option<DecompressorWriter<Bounded>>,
+}
+
+impl BrotliDecoder {
+  pub fn new() -> Self {
+    Self { writer: None }
+  }
+
+  /// Decompresses the payload of one frame. `fin` marks the last frame of the message, which must end the stream.
+  /// Fails with `WebSocketError::FrameTooLarge` if the output would grow beyond `max_size` bytes.
+  pub fn decompress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+    max_size: usize,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let writer = self.writer.get_or_insert_with(|| {
+      DecompressorWriter::new(Bounded::default(), BUFFER_SIZE)
+    });
+    writer.get_mut().max_size = max_size;
+
+    let result = writer.write_all(input).and_then(|_| match fin {
+      true => writer.close(),
+      false => writer.flush(),
+    });
+    if result.is_err() {
+      let exceeded = writer.get_ref().exceeded;
+      self.writer = None;
+      return Err(match exceeded {
+        true => WebSocketError::FrameTooLarge,
+        false => WebSocketError::InvalidCompressedData,
+      });
+    }
+
+    let output = std::mem::take(&mut writer.get_mut().output);
+    if fin {
+      self.writer = None;
+    }
+    Ok(output)
+  }
+}
+
+/// Collects decompressed output up to a limit.
+#[derive(Default)]
+struct Bounded {
+  output: Vec<u8>,
+  max_size: usize,
+  exceeded: bool,
+}
+
+impl Write for Bounded {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    if self.output.len() + buf.len() > self.max_size {
+      self.exceeded = true;
+      return Err(std::io::ErrorKind::OutOfMemory.into());
+    }
+    self.output.extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiation() {
+    assert!(is_listed("permessage-deflate, permessage-brotli"));
+    assert!(!is_listed("permessage-deflate"));
+    assert!(!is_listed("permessage-brotli; quality=5"));
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut encoder = BrotliEncoder::new(&BrotliConfig::default());
+    let mut decoder = BrotliDecoder::new();
+    let text = r#"{"type":"telemetry","value":42}"#.repeat(20);
+
+    // A message split across frames is a single stream.
+    let first = encoder.compress(&text.as_bytes()[..100], false).unwrap();
+    let last = encoder.compress(&text.as_bytes()[100..], true).unwrap();
+    assert!(first.len() + last.len() < text.len() / 4);
+    let mut output = decoder.decompress(&first, false, 1024).unwrap();
+    output.extend(decoder.decompress(&last, true, 1024).unwrap());
+    assert_eq!(output, text.as_bytes());
+
+    // Every message starts a new stream.
+    let message = encoder.compress(text.as_bytes(), true).unwrap();
+    let output = decoder.decompress(&message, true, 1024).unwrap();
+    assert_eq!(output, text.as_bytes());
+
+    assert!(matches!(
+      decoder.decompress(&message, true, 100),
+      Err(WebSocketError::FrameTooLarge)
+    ));
+    assert!(matches!(
+      decoder.decompress(&message[..message.len() / 2], true, 1024),
+      Err(WebSocketError::InvalidCompressedData)
+    ));
+  }
+}
@@ -0,0 +1,262 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use js_sys::ArrayBuffer;
+use js_sys::Uint8Array;
+use tokio::sync::mpsc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::BinaryType;
+use web_sys::CloseEvent;
+use web_sys::MessageEvent;
+
+use crate::CloseCode;
+use crate::CloseFrame;
+use crate::Message;
+use crate::WebSocketError;
+
+/// What the event handlers of the browser's WebSocket pass on to `BrowserWebSocket`.
+enum Event {
+  Open,
+  Message(Message),
+  Error,
+  Close(CloseEvent),
+}
+
+/// A websocket client that sends and receives messages through the browser's `WebSocket`, for code compiled to
+/// `wasm32-unknown-unknown`.
+///
+/// It has the message methods of `WebSocket` and `FragmentCollector`, so code written against `read_message`,
+/// `write_message` and `close` runs in the browser as well. The browser does the handshake, fragmentation, masking
+/// and compression, and answers pings itself, so there are no frames: ping and pong messages can be neither read nor
+/// sent.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::{BrowserWebSocket, CloseCode, Message, WebSocketError};
+///
+/// async fn echo_once() -> Result<(), WebSocketError> {
+///   let mut ws = BrowserWebSocket::connect("wss://echo.example.com", &[]).await?;
+///   ws.write_message(Message::text("hello")).await?;
+///   let reply = ws.read_message().await?;
+///   assert_eq!(reply.as_text(), Some("hello"));
+///   ws.close(CloseCode::Normal, "").await
+/// }
+/// ```
+pub struct BrowserWebSocket {
+  socket: web_sys::WebSocket,
+  events: mpsc::UnboundedReceiver<Event>,
+  _on_open: Closure<dyn FnMut()>,
+  _on_message: Closure<dyn FnMut(MessageEvent)>,
+  _on_error: Closure<dyn FnMut()>,
+  _on_close: Closure<dyn FnMut(CloseEvent)>,
+  closed: bool,
+}
+
+impl BrowserWebSocket {
+  /// Opens a connection to `url`, offering the subprotocols in `protocols`, and waits until it is open.
+  pub async fn connect(
+    url: &str,
+    protocols: &[&str],
+  ) -> Result<Self, WebSocketError> {
+    let socket = match protocols {
+      [] => web_sys::WebSocket::new(url),
+      protocols => {
+        let protocols: js_sys::Array =
+          protocols.iter().copied().map(JsValue::from_str).collect();
+        web_sys::WebSocket::new_with_str_sequence(url, &protocols)
+      }
+    }
+    .map_err(browser_error)?;
+    socket.set_binary_type(BinaryType::Arraybuffer);
+
+    let (sender, events) = mpsc::unbounded_channel();
+    // The handlers only hold a sender, so dropping `BrowserWebSocket` frees them and the socket.
+    let on_open = Closure::<dyn FnMut()>::new({
+      let sender = sender.clone();
+      move || {
+        let _ = sender.send(Event::Open);
+      }
+    });
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new({
+      let sender = sender.clone();
+      move |event: MessageEvent| {
+        let data = event.data();
+        let message = match data.as_string() {
+          Some(text) => Message::Text(text),
+          None => match data.dyn_into::<ArrayBuffer>() {
+            Ok(buffer) => {
+              Message::Binary(Bytes::from(Uint8Array::new(&buffer).to_vec()))
+            }
+            Err(_) => return,
+          },
+        };
+        let _ = sender.send(Event::Message(message));
+      }
+    });
+    let on_error = Closure::<dyn FnMut()>::new({
+      let sender = sender.clone();
+      move || {
+        let _ = sender.send(Event::Error);
+      }
+    });
+    let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event| {
+      let _ = sender.send(Event::Close(event));
+    });
+    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+    let mut ws = Self {
+      socket,
+      events,
+      _on_open: on_open,
+      _on_message: on_message,
+      _on_error: on_error,
+      _on_close: on_close,
+      closed: false,
+    };
+    match ws.events.recv().await {
+      Some(Event::Open) => Ok(ws),
+      // Browsers do not tell why a connection failed, only that it did.
+      _ => {
+        ws.closed = true;
+        Err(WebSocketError::BrowserError(format!(
+          "failed to connect to {}",
+          url
+        )))
+      }
+    }
+  }
+
+  /// Returns the subprotocol selected by the server, or an empty string.
+  pub fn protocol(&self) -> String {
+    self.socket.protocol()
+  }
+
+  /// Returns the extensions selected by the server, or an empty string.
+  pub fn extensions(&self) -> String {
+    self.socket.extensions()
+  }
+
+  /// Returns the number of bytes of written messages that the browser has not sent yet. Writes never wait, so check
+  /// this to avoid buffering without bound.
+  pub fn buffered_amount(&self) -> u32 {
+    self.socket.buffered_amount()
+  }
+
+  /// Reads a message. A close message is returned once, when the connection has closed; reads after it fail with
+  /// `WebSocketError::ConnectionClosed`.
+  ///
+  /// Unlike `WebSocket::read_message`, this never returns ping or pong messages.
+  pub async fn read_message(&mut self) -> Result<Message, WebSocketError> {
+    if self.closed {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+    loop {
+      match self.events.recv().await {
+        Some(Event::Message(message)) => return Ok(message),
+        Some(Event::Open) => {}
+        // An error event is always followed by a close event, which tells more.
+        Some(Event::Error) => {}
+        Some(Event::Close(event)) => {
+          self.closed = true;
+          return match event.code() {
+            CloseCode::NO_STATUS_RECEIVED => Ok(Message::Close(None)),
+            CloseCode::ABNORMAL_CLOSURE => Err(WebSocketError::UnexpectedEOF),
+            code => Ok(Message::Close(Some(CloseFrame {
+              code: code.into(),
+              reason: event.reason(),
+            }))),
+          };
+        }
+        None => {
+          self.closed = true;
+          return Err(WebSocketError::ConnectionClosed);
+        }
+      }
+    }
+  }
+
+  /// Writes a message. The browser buffers it and returns right away; see `buffered_amount`.
+  ///
+  /// A close message starts the closing handshake like `close`. Ping and pong messages fail with
+  /// `WebSocketError::BrowserControlMessage`, since the browser does not let applications send them.
+  pub async fn write_message(
+    &mut self,
+    message: Message,
+  ) -> Result<(), WebSocketError> {
+    match message {
+      Message::Text(text) => self.socket.send_with_str(&text),
+      Message::Binary(data) => self.socket.send_with_u8_array(&data),
+      Message::Close(Some(close)) => {
+        return self.close(close.code, &close.reason).await
+      }
+      Message::Close(None) => self.socket.close(),
+      Message::Ping(_) | Message::Pong(_) => {
+        return Err(WebSocketError::BrowserControlMessage)
+      }
+    }
+    .map_err(browser_error)
+  }
+
+  /// Starts the closing handshake with `code` and `reason`. The close message of the server is returned by
+  /// `read_message`.
+  ///
+  /// Browsers only let applications close with `CloseCode::Normal` or a code between 3000 and 4999.
+  pub async fn close(
+    &mut self,
+    code: CloseCode,
+    reason: &str,
+  ) -> Result<(), WebSocketError> {
+    self
+      .socket
+      .close_with_code_and_reason(code.into(), reason)
+      .map_err(browser_error)
+  }
+}
+
+impl Drop for BrowserWebSocket {
+  fn drop(&mut self) {
+    self.socket.set_onopen(None);
+    self.socket.set_onmessage(None);
+    self.socket.set_onerror(None);
+    self.socket.set_onclose(None);
+    if !self.closed {
+      let _ = self.socket.close();
+    }
+  }
+}
+
+impl std::fmt::Debug for BrowserWebSocket {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("BrowserWebSocket")
+      .field("url", &self.socket.url())
+      .field("closed", &self.closed)
+      .finish()
+  }
+}
+
+/// Converts an exception thrown by the browser.
+fn browser_error(error: JsValue) -> WebSocketError {
+  let message = match error.dyn_ref::<js_sys::Error>() {
+    Some(error) => error.message().into(),
+    None => format!("{:?}", error),
+  };
+  WebSocketError::BrowserError(message)
+}
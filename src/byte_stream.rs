@@ -0,0 +1,269 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+use crate::Frame;
+use crate::OpCode;
+use crate::Payload;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// A byte stream over the binary messages of a websocket, for tunneling another protocol such as SSH or yamux.
+///
+/// Each write is sent as one binary frame, and reads return the payloads of the binary frames received, in order
+/// and regardless of message boundaries. Pings are answered as usual. A close frame from the peer ends the stream,
+/// and shutting it down sends a close frame with status code 1000 without closing the underlying stream.
+///
+/// Text frames cannot be represented and fail the read with `io::ErrorKind::InvalidData`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{WebSocket, WsByteStream};
+/// use tokio::net::TcpStream;
+///
+/// async fn tunnel(
+///   ws: WebSocket<TcpStream>,
+///   mut local: TcpStream,
+/// ) -> std::io::Result<()> {
+///   let mut ws = WsByteStream::new(ws);
+///   tokio::io::copy_bidirectional(&mut ws, &mut local).await?;
+///   Ok(())
+/// }
+/// ```
+pub struct WsByteStream<S> {
+  ws: WebSocket<S>,
+  payload: Payload<'static>,
+  read: usize,
+  eof: bool,
+}
+
+impl<S> WsByteStream<S> {
+  /// Creates a byte stream over `ws`.
+  pub fn new(ws: WebSocket<S>) -> Self {
+    Self {
+      ws,
+      payload: Payload::Owned(Vec::new()),
+      read: 0,
+      eof: false,
+    }
+  }
+
+  /// Returns a reference to the websocket.
+  pub fn get_ref(&self) -> &WebSocket<S> {
+    &self.ws
+  }
+
+  /// Returns a mutable reference to the websocket. Reading or writing frames directly interleaves them with the
+  /// byte stream.
+  pub fn get_mut(&mut self) -> &mut WebSocket<S> {
+    &mut self.ws
+  }
+
+  /// Consumes the `WsByteStream` and returns the websocket. Received bytes that have not been read yet are lost.
+  pub fn into_inner(self) -> WebSocket<S> {
+    self.ws
+  }
+}
+
+impl<S> AsyncRead for WsByteStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    loop {
+      let remaining = &this.payload[this.read..];
+      if !remaining.is_empty() {
+        let n = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..n]);
+        this.read += n;
+        return Poll::Ready(Ok(()));
+      }
+      if this.eof {
+        // Send the close frame echoed for the peer before reporting the end of the stream.
+        let _ = ready!(this.ws.poll_flush(cx));
+        return Poll::Ready(Ok(()));
+      }
+
+      let frame = ready!(this.ws.poll_read_frame(cx)).map_err(io_error)?;
+      match frame.opcode {
+        OpCode::Binary | OpCode::Continuation => {
+          this.payload = frame.payload;
+          this.read = 0;
+        }
+        OpCode::Close => this.eof = true,
+        OpCode::Text => {
+          return Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "text frame in a websocket byte stream",
+          )))
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+impl<S> AsyncWrite for WsByteStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let this = self.get_mut();
+    if buf.is_empty() {
+      return Poll::Ready(Ok(0));
+    }
+    // Finish the previous frame first. The frame for `buf` has been accepted once it is encoded, even if it is
+    // still being written.
+    ready!(this.ws.write_half.poll_pending(cx, &mut this.ws.stream))
+      .map_err(io_error)?;
+    let frame = Frame::binary(Payload::Borrowed(buf));
+    match this.ws.poll_write_frame(cx, &frame) {
+      Poll::Ready(Err(e)) => Poll::Ready(Err(io_error(e))),
+      _ => Poll::Ready(Ok(buf.len())),
+    }
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    self.get_mut().ws.poll_flush(cx).map_err(io_error)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    self.get_mut().ws.poll_close(cx).map_err(io_error)
+  }
+}
+
+pub(crate) fn io_error(e: WebSocketError) -> io::Error {
+  match e {
+    WebSocketError::IoError(e) => e,
+    WebSocketError::ConnectionClosed => io::ErrorKind::BrokenPipe.into(),
+    WebSocketError::UnexpectedEOF => io::ErrorKind::UnexpectedEof.into(),
+    e => io::Error::new(io::ErrorKind::InvalidData, e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Role;
+  use tokio::io::AsyncReadExt;
+  use tokio::io::AsyncWriteExt;
+
+  #[tokio::test]
+  async fn tunnels_bytes() {
+    let (client, server) = tokio::io::duplex(64);
+    let mut client =
+      WsByteStream::new(WebSocket::after_handshake(client, Role::Client));
+    let mut server =
+      WsByteStream::new(WebSocket::after_handshake(server, Role::Server));
+
+    let data: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+      for chunk in data.chunks(1000) {
+        client.write_all(chunk).await.unwrap();
+      }
+      client.shutdown().await.unwrap();
+      client
+    });
+
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received, expected);
+
+    // The server echoed the close frame, which ends the client's stream too.
+    let mut client = writer.await.unwrap();
+    assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+    assert!(client.get_ref().is_closed());
+  }
+
+  #[tokio::test]
+  async fn pings_are_answered() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server =
+      WsByteStream::new(WebSocket::after_handshake(server, Role::Server));
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        false,
+        OpCode::Binary,
+        None,
+        b"ab".to_vec().into(),
+      ))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        b"c".to_vec().into(),
+      ))
+      .await
+      .unwrap();
+
+    let mut buf = [0; 3];
+    server.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"abc");
+    server.flush().await.unwrap();
+
+    let pong = client.read_frame().await.unwrap();
+    assert_eq!(pong.opcode, OpCode::Pong);
+    assert_eq!(&*pong.payload, b"hi");
+  }
+
+  #[tokio::test]
+  async fn text_frames_fail_the_read() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server =
+      WsByteStream::new(WebSocket::after_handshake(server, Role::Server));
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let err = server.read(&mut [0; 16]).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+  }
+}
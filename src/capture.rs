@@ -0,0 +1,471 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+use crate::Frame;
+use crate::OpCode;
+
+const MAGIC: &[u8; 8] = b"FWSCAP\0\x01";
+const RECORD_HEADER_SIZE: usize = 27;
+
+/// Whether a captured frame was received or sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+  Inbound,
+  Outbound,
+}
+
+/// A frame read from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+  /// When the frame was read or written.
+  pub timestamp: SystemTime,
+  /// Whether the frame was read or written.
+  pub direction: FrameDirection,
+  /// The first byte of the frame header, holding FIN, the RSV bits and the opcode.
+  pub head: u8,
+  /// The masking key of the frame, if it was masked.
+  pub mask: Option<[u8; 4]>,
+  /// The length of the payload on the wire, which is larger than `payload` if the recorder cut it short.
+  pub payload_len: u64,
+  /// The unmasked payload, up to the limit of the recorder.
+  pub payload: Vec<u8>,
+}
+
+impl CapturedFrame {
+  /// Indicates if this is the final frame in a message.
+  pub fn fin(&self) -> bool {
+    self.head & 0x80 != 0
+  }
+
+  /// The opcode of the frame, if it is a valid one.
+  pub fn opcode(&self) -> Option<OpCode> {
+    OpCode::try_from(self.head & 0x0F).ok()
+  }
+
+  /// Whether the whole payload was captured.
+  pub fn is_complete(&self) -> bool {
+    self.payload.len() as u64 == self.payload_len
+  }
+
+  /// Encodes the frame the way it was sent, masked with the captured key. The header gives the length of the
+  /// captured payload, so a frame that was cut short stays readable but carries only what was captured.
+  pub fn encode(&self) -> Vec<u8> {
+    let len = self.payload.len();
+    let mut bytes = Vec::with_capacity(len + 14);
+    bytes.push(self.head);
+    let mask_bit = if self.mask.is_some() { 0x80 } else { 0 };
+    if len < 126 {
+      bytes.push(mask_bit | len as u8);
+    } else if len < 65536 {
+      bytes.push(mask_bit | 126);
+      bytes.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+      bytes.push(mask_bit | 127);
+      bytes.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    if let Some(mask) = self.mask {
+      bytes.extend_from_slice(&mask);
+    }
+    let start = bytes.len();
+    bytes.extend_from_slice(&self.payload);
+    if let Some(mask) = self.mask {
+      crate::unmask(&mut bytes[start..], mask);
+    }
+    bytes
+  }
+}
+
+/// Records the frames of a connection to a capture file, for reproducing problems outside of production.
+///
+/// The recorder is cheap to clone and all clones write to the same file, so it can be shared by the halves of a split
+/// connection. Records are written synchronously through a buffer, which is flushed when the last clone is dropped.
+/// A write error stops the recording but does not affect the connection.
+///
+/// # Format
+///
+/// Capture files start with the 8 byte magic `FWSCAP\0\x01`, followed by one record per frame:
+///
+/// | Size | Field                                                          |
+/// |------|----------------------------------------------------------------|
+/// | 8    | Timestamp in microseconds since the Unix epoch, big endian     |
+/// | 1    | FrameDirection, 0 for inbound and 1 for outbound                    |
+/// | 1    | First header byte: FIN, RSV1 to RSV3 and the opcode            |
+/// | 1    | 1 if the frame was masked, 0 otherwise                         |
+/// | 4    | Masking key, zero if the frame was not masked                  |
+/// | 8    | Payload length on the wire, big endian                         |
+/// | 4    | Number of payload bytes captured, big endian                   |
+/// | n    | The captured payload bytes, unmasked                           |
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::FrameRecorder;
+/// use fastwebsockets::Role;
+/// use fastwebsockets::WebSocket;
+/// use tokio::net::TcpStream;
+///
+/// fn record(socket: TcpStream) -> std::io::Result<WebSocket<TcpStream>> {
+///   let mut ws = WebSocket::after_handshake(socket, Role::Server);
+///   ws.set_recorder(Some(FrameRecorder::create("connection.fwscap", 4096)?));
+///   Ok(ws)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FrameRecorder {
+  writer: Arc<Mutex<Option<Box<dyn Write + Send>>>>,
+  max_payload: usize,
+}
+
+impl FrameRecorder {
+  /// Starts a capture in `writer`, keeping at most `max_payload` bytes of every payload.
+  pub fn new<W>(writer: W, max_payload: usize) -> std::io::Result<Self>
+  where
+    W: Write + Send + 'static,
+  {
+    let mut writer: Box<dyn Write + Send> = Box::new(writer);
+    writer.write_all(MAGIC)?;
+    Ok(Self {
+      writer: Arc::new(Mutex::new(Some(writer))),
+      max_payload,
+    })
+  }
+
+  /// Starts a capture in a new file at `path`, keeping at most `max_payload` bytes of every payload.
+  pub fn create<P: AsRef<Path>>(
+    path: P,
+    max_payload: usize,
+  ) -> std::io::Result<Self> {
+    Self::new(BufWriter::new(File::create(path)?), max_payload)
+  }
+
+  /// Writes any buffered records.
+  pub fn flush(&self) -> std::io::Result<()> {
+    match &mut *self.writer.lock().unwrap() {
+      Some(writer) => writer.flush(),
+      None => Ok(()),
+    }
+  }
+
+  /// Records a frame whose payload is as it is on the wire, masked if the frame has a masking key.
+  pub(crate) fn record_frame(&self, direction: FrameDirection, frame: &Frame) {
+    let head = head_byte(frame);
+    self.record(direction, head, frame.mask_key(), &frame.payload, true);
+  }
+
+  /// Records a frame. `payload` is masked with `mask` if `masked` is set, as it is on the wire.
+  pub(crate) fn record(
+    &self,
+    direction: FrameDirection,
+    head: u8,
+    mask: Option<[u8; 4]>,
+    payload: &[u8],
+    masked: bool,
+  ) {
+    let mut guard = self.writer.lock().unwrap();
+    let Some(writer) = &mut *guard else {
+      return;
+    };
+
+    let limit = self.max_payload.min(u32::MAX as usize);
+    let captured = &payload[..payload.len().min(limit)];
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + captured.len());
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_micros() as u64;
+    record.extend_from_slice(&timestamp.to_be_bytes());
+    record.push(direction as u8);
+    record.push(head);
+    record.push(mask.is_some() as u8);
+    record.extend_from_slice(&mask.unwrap_or_default());
+    record.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    record.extend_from_slice(&(captured.len() as u32).to_be_bytes());
+    let start = record.len();
+    record.extend_from_slice(captured);
+    if let (Some(mask), true) = (mask, masked) {
+      crate::unmask(&mut record[start..], mask);
+    }
+
+    if writer.write_all(&record).is_err() {
+      *guard = None;
+    }
+  }
+}
+
+/// The first header byte of `frame`.
+pub(crate) fn head_byte(frame: &Frame) -> u8 {
+  (frame.fin as u8) << 7 | frame.rsv_bits() | frame.opcode as u8
+}
+
+/// Reads the frames of a capture file written by [`FrameRecorder`].
+pub struct CaptureReader<R> {
+  reader: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+  /// Opens the capture file at `path`.
+  pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+    Self::new(BufReader::new(File::open(path)?))
+  }
+}
+
+impl<R: Read> CaptureReader<R> {
+  /// Reads a capture from `reader`. Fails with `InvalidData` if it does not start like a capture file.
+  pub fn new(mut reader: R) -> std::io::Result<Self> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "not a frame capture",
+      ));
+    }
+    Ok(Self { reader })
+  }
+
+  /// Reads the next frame, or returns `None` at the end of the capture.
+  pub fn read_frame(&mut self) -> std::io::Result<Option<CapturedFrame>> {
+    let mut header = [0; RECORD_HEADER_SIZE];
+    // A capture cut off in the middle of a record, for example by a crash, ends at the last complete one.
+    let mut read = 0;
+    while read < header.len() {
+      match self.reader.read(&mut header[read..])? {
+        0 => return Ok(None),
+        n => read += n,
+      }
+    }
+
+    let timestamp = u64::from_be_bytes(header[0..8].try_into().unwrap());
+    let direction = match header[8] {
+      0 => FrameDirection::Inbound,
+      _ => FrameDirection::Outbound,
+    };
+    let mask = match header[10] {
+      0 => None,
+      _ => Some(header[11..15].try_into().unwrap()),
+    };
+    let payload_len = u64::from_be_bytes(header[15..23].try_into().unwrap());
+    let captured = u32::from_be_bytes(header[23..27].try_into().unwrap());
+
+    let mut payload = Vec::new();
+    (&mut self.reader)
+      .take(captured as u64)
+      .read_to_end(&mut payload)?;
+    if payload.len() != captured as usize {
+      return Ok(None);
+    }
+
+    Ok(Some(CapturedFrame {
+      timestamp: UNIX_EPOCH + Duration::from_micros(timestamp),
+      direction,
+      head: header[9],
+      mask,
+      payload_len,
+      payload,
+    }))
+  }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+  type Item = std::io::Result<CapturedFrame>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.read_frame().transpose()
+  }
+}
+
+/// A stream that plays captured frames back to a `WebSocket`, so that they go through the same parser, limits and
+/// extensions as they did in production. Writes, such as automatic pongs, are discarded.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::CaptureReader;
+/// use fastwebsockets::FrameDirection;
+/// use fastwebsockets::ReplayStream;
+/// use fastwebsockets::Role;
+/// use fastwebsockets::WebSocket;
+///
+/// # async fn replay() -> anyhow::Result<()> {
+/// let capture = CaptureReader::open("connection.fwscap")?;
+/// let stream = ReplayStream::new(capture, FrameDirection::Inbound)?;
+/// let mut ws = WebSocket::after_handshake(stream, Role::Server);
+/// loop {
+///   let frame = ws.read_frame().await?;
+///   println!("{}", frame);
+/// }
+/// # }
+/// ```
+pub struct ReplayStream {
+  data: Vec<u8>,
+  position: usize,
+}
+
+impl ReplayStream {
+  /// Collects the frames of `capture` that went in `direction`. Reading the stream ends with an EOF after the last
+  /// one.
+  pub fn new<I>(capture: I, direction: FrameDirection) -> std::io::Result<Self>
+  where
+    I: IntoIterator<Item = std::io::Result<CapturedFrame>>,
+  {
+    let mut data = Vec::new();
+    for frame in capture {
+      let frame = frame?;
+      if frame.direction == direction {
+        data.extend(frame.encode());
+      }
+    }
+    Ok(Self { data, position: 0 })
+  }
+}
+
+impl AsyncRead for ReplayStream {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    let this = self.get_mut();
+    let remaining = &this.data[this.position..];
+    let len = remaining.len().min(buf.remaining());
+    buf.put_slice(&remaining[..len]);
+    this.position += len;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for ReplayStream {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<std::io::Result<usize>> {
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Role;
+  use crate::WebSocket;
+
+  #[derive(Clone, Default)]
+  struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn record_and_replay() {
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    let buffer = SharedBuffer::default();
+    server.set_recorder(Some(FrameRecorder::new(buffer.clone(), 8).unwrap()));
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::binary(b"a longer payload"[..].into()))
+      .await
+      .unwrap();
+    server.read_frame().await.unwrap();
+    server.read_frame().await.unwrap();
+    server
+      .write_frame(Frame::text(b"world".to_vec().into()))
+      .await
+      .unwrap();
+
+    let capture = buffer.0.lock().unwrap().clone();
+    let frames = CaptureReader::new(capture.as_slice())
+      .unwrap()
+      .collect::<std::io::Result<Vec<_>>>()
+      .unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].direction, FrameDirection::Inbound);
+    assert_eq!(frames[0].opcode(), Some(OpCode::Text));
+    assert!(frames[0].fin() && frames[0].mask.is_some());
+    assert_eq!(frames[0].payload, b"hello");
+    assert!(!frames[1].is_complete());
+    assert_eq!(frames[1].payload_len, 16);
+    assert_eq!(frames[1].payload, b"a longer");
+    assert_eq!(frames[2].direction, FrameDirection::Outbound);
+    assert_eq!(frames[2].mask, None);
+    assert_eq!(frames[2].payload, b"world");
+
+    // Frames cut short are replayed with the part that was captured.
+    let stream = ReplayStream::new(
+      CaptureReader::new(capture.as_slice()).unwrap(),
+      FrameDirection::Inbound,
+    )
+    .unwrap();
+    let mut replay = WebSocket::after_handshake(stream, Role::Server);
+    let frame = replay.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    let frame = replay.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, b"a longer");
+    assert!(matches!(
+      replay.read_frame().await,
+      Err(crate::WebSocketError::UnexpectedEOF)
+    ));
+
+    // A capture cut off in the middle of a record ends at the last complete one.
+    let truncated = &capture[..capture.len() - 2];
+    assert_eq!(CaptureReader::new(truncated).unwrap().count(), 2);
+    assert!(CaptureReader::new(&b"not a capture"[..]).is_err());
+  }
+}
@@ -0,0 +1,109 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frame header parsing and encoding, masking and close frame validation on byte slices, for transports with their
+//! own IO such as embedded TCP stacks.
+//!
+//! This module re-exports the `fastwebsockets-codec` crate, which only uses `core`: it allocates nothing and has no
+//! IO. `WebSocket` encodes its frame headers with [`encode_head`]. Depend on `fastwebsockets-codec` directly to use
+//! the same codec on `no_std` targets.
+//!
+//! The codec has its own [`OpCode`], which converts to and from `fastwebsockets::OpCode` with `From`.
+//!
+//! # Example
+//!
+//! ```
+//! use fastwebsockets::codec::{self, Head, OpCode, MAX_HEAD_SIZE};
+//!
+//! let mut buf = [0; MAX_HEAD_SIZE + 5];
+//! let head = Head {
+//!   fin: true,
+//!   rsv: 0,
+//!   opcode: OpCode::Text,
+//!   mask: Some([1, 2, 3, 4]),
+//!   payload_len: 5,
+//! };
+//! let n = codec::encode_head(&head, &mut buf);
+//! buf[n..n + 5].copy_from_slice(b"hello");
+//! codec::unmask(&mut buf[n..n + 5], [1, 2, 3, 4]);
+//!
+//! let (parsed, size) = codec::decode_head(&buf).unwrap().unwrap();
+//! assert_eq!((parsed, size), (head, n));
+//! assert_eq!(fastwebsockets::OpCode::from(parsed.opcode), fastwebsockets::OpCode::Text);
+//! ```
+
+pub use fastwebsockets_codec::decode_head;
+pub use fastwebsockets_codec::encode_head;
+pub use fastwebsockets_codec::unmask;
+pub use fastwebsockets_codec::validate_close;
+pub use fastwebsockets_codec::CodecError;
+pub use fastwebsockets_codec::Head;
+pub use fastwebsockets_codec::OpCode;
+pub use fastwebsockets_codec::MAX_HEAD_SIZE;
+
+impl From<crate::OpCode> for OpCode {
+  fn from(opcode: crate::OpCode) -> Self {
+    match opcode {
+      crate::OpCode::Continuation => OpCode::Continuation,
+      crate::OpCode::Text => OpCode::Text,
+      crate::OpCode::Binary => OpCode::Binary,
+      crate::OpCode::Close => OpCode::Close,
+      crate::OpCode::Ping => OpCode::Ping,
+      crate::OpCode::Pong => OpCode::Pong,
+    }
+  }
+}
+
+impl From<OpCode> for crate::OpCode {
+  fn from(opcode: OpCode) -> Self {
+    match opcode {
+      OpCode::Continuation => crate::OpCode::Continuation,
+      OpCode::Text => crate::OpCode::Text,
+      OpCode::Binary => crate::OpCode::Binary,
+      OpCode::Close => crate::OpCode::Close,
+      OpCode::Ping => crate::OpCode::Ping,
+      OpCode::Pong => crate::OpCode::Pong,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Frame;
+
+  #[test]
+  fn heads_match_frames() {
+    for len in [0, 125, 126, 65535, 65536] {
+      let frame = Frame::new(
+        true,
+        crate::OpCode::Binary,
+        Some([9; 4]),
+        vec![0; len].into(),
+      );
+      let mut expected = [0; MAX_HEAD_SIZE];
+      let size = frame.fmt_head(&mut expected);
+
+      let (head, n) = decode_head(&expected[..size]).unwrap().unwrap();
+      assert_eq!(n, size);
+      assert_eq!(head.payload_len, len as u64);
+      assert_eq!(head.mask, Some([9; 4]));
+      assert!(decode_head(&expected[..size - 1]).unwrap().is_none());
+
+      let mut buf = [0; MAX_HEAD_SIZE];
+      assert_eq!(encode_head(&head, &mut buf), size);
+      assert_eq!(buf, expected);
+    }
+  }
+}
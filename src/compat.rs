@@ -0,0 +1,73 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs a `WebSocket` on async-std, smol and other runtimes whose streams implement the `futures-io` traits.
+//!
+//! Wrap the stream in [`FuturesIo`] before the handshake. With async-std:
+//!
+//! ```ignore
+//! use async_std::net::TcpStream;
+//! use fastwebsockets::compat::FuturesIo;
+//! use fastwebsockets::{Role, WebSocket};
+//!
+//! let stream = TcpStream::connect("127.0.0.1:9001").await?;
+//! let mut ws = WebSocket::after_handshake(FuturesIo::new(stream), Role::Client);
+//! ```
+//!
+//! With smol:
+//!
+//! ```ignore
+//! use fastwebsockets::compat::FuturesIo;
+//! use fastwebsockets::{Role, WebSocket};
+//! use smol::Async;
+//! use std::net::TcpListener;
+//!
+//! let listener = Async::<TcpListener>::bind(([127, 0, 0, 1], 9001))?;
+//! let (stream, _) = listener.accept().await?;
+//! let mut ws = WebSocket::after_handshake(FuturesIo::new(stream), Role::Server);
+//! ```
+//!
+//! The `upgrade` and `handshake` modules run on hyper with tokio's IO traits, so a `FuturesIo` stream can be passed
+//! to `handshake::client` as well.
+//!
+//! Streams implementing the `embedded-io-async` traits, as the sockets of embedded network stacks do, run on
+//! [`EmbeddedWebSocket`] instead, since those traits cannot be adapted to tokio's poll-based traits.
+//!
+//! On Linux, [`UringWebSocket`] runs on the `tokio-uring` runtime, whose `TcpStream` takes owned buffers for its
+//! io_uring operations instead of implementing tokio's IO traits.
+//!
+//! The `tungstenite` module helps moving from tokio-tungstenite, with a `WebSocketStream` that has the same `read`,
+//! `send` and `next` methods.
+
+#[cfg(feature = "embedded-io")]
+mod embedded_io;
+#[cfg(feature = "futures-io")]
+mod futures_io;
+#[cfg(feature = "tungstenite-compat")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tungstenite-compat")))]
+pub mod tungstenite;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+#[cfg(feature = "embedded-io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "embedded-io")))]
+pub use embedded_io::EmbeddedWebSocket;
+#[cfg(feature = "futures-io")]
+pub use futures_io::FuturesIo;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[cfg_attr(
+  docsrs,
+  doc(cfg(all(feature = "io-uring", target_os = "linux")))
+)]
+pub use uring::UringWebSocket;
@@ -0,0 +1,242 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::io::IoSlice;
+
+use bytes::BytesMut;
+use embedded_io_async::Read;
+use embedded_io_async::Write;
+
+use crate::io::WsRead;
+use crate::io::WsWrite;
+use crate::Frame;
+use crate::ReadHalf;
+use crate::Role;
+use crate::WebSocketError;
+use crate::WriteHalf;
+
+/// Implements the transport traits of the protocol core for a stream implementing the `embedded-io-async` traits.
+struct EmbeddedIo<S>(S);
+
+fn io_error<E: embedded_io_async::Error>(error: E) -> io::Error {
+  io::Error::new(error.kind().into(), format!("{error:?}"))
+}
+
+impl<S: Read> WsRead for EmbeddedIo<S> {
+  async fn read_into(
+    &mut self,
+    buf: &mut BytesMut,
+    limit: usize,
+  ) -> io::Result<usize> {
+    // `embedded-io` reads into initialized memory, so the spare capacity is zeroed first.
+    let len = buf.len();
+    if buf.capacity() == len {
+      buf.reserve(1024);
+    }
+    buf.resize(len + limit.min(buf.capacity() - len), 0);
+    let read = self.0.read(&mut buf[len..]).await;
+    buf.truncate(len + read.as_ref().map_or(0, |n| *n));
+    read.map_err(io_error)
+  }
+}
+
+impl<S: Write> WsWrite for EmbeddedIo<S> {
+  async fn send_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+    // `embedded_io_async::Write::write_all` panics when nothing is written.
+    while !buf.is_empty() {
+      match self.0.write(buf).await.map_err(io_error)? {
+        0 => return Err(io::ErrorKind::WriteZero.into()),
+        n => buf = &buf[n..],
+      }
+    }
+    Ok(())
+  }
+
+  async fn send_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    match bufs.iter().find(|buf| !buf.is_empty()) {
+      Some(buf) => self.0.write(buf).await.map_err(io_error),
+      None => Ok(0),
+    }
+  }
+}
+
+/// A websocket over a stream implementing `embedded_io_async::Read` and `embedded_io_async::Write`, such as the
+/// sockets of embedded network stacks. It runs the same protocol core as `WebSocket` without a tokio runtime.
+///
+/// Keepalive, timeouts, compression and the other `WebSocket` options are not available.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::compat::EmbeddedWebSocket;
+/// use fastwebsockets::{OpCode, Role, WebSocketError};
+///
+/// async fn echo<S>(socket: S) -> Result<(), WebSocketError>
+/// where
+///   S: embedded_io_async::Read + embedded_io_async::Write,
+/// {
+///   let mut ws = EmbeddedWebSocket::after_handshake(socket, Role::Server);
+///   loop {
+///     let frame = ws.read_frame().await?;
+///     match frame.opcode {
+///       OpCode::Close => return Ok(()),
+///       OpCode::Text | OpCode::Binary => ws.write_frame(frame).await?,
+///       _ => {}
+///     }
+///   }
+/// }
+/// ```
+pub struct EmbeddedWebSocket<S> {
+  stream: EmbeddedIo<S>,
+  read_half: ReadHalf,
+  write_half: WriteHalf,
+}
+
+impl<S> EmbeddedWebSocket<S> {
+  /// Creates a websocket from a stream that has already completed the handshake.
+  pub fn after_handshake(stream: S, role: Role) -> Self {
+    Self {
+      stream: EmbeddedIo(stream),
+      read_half: ReadHalf::after_handshake(role),
+      write_half: WriteHalf::after_handshake(role),
+    }
+  }
+
+  /// Consumes the websocket and returns the underlying stream. Received bytes that have not been read as frames are
+  /// lost.
+  pub fn into_inner(self) -> S {
+    self.stream.0
+  }
+
+  /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the
+  /// application will have to manually send close frames.
+  ///
+  /// Default: `true`
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.read_half.auto_close = auto_close;
+  }
+
+  /// Sets whether to automatically send a pong frame when a ping frame is received.
+  ///
+  /// Default: `true`
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.read_half.auto_pong = auto_pong;
+  }
+
+  /// Sets the maximum message size in bytes. If a message is received that is larger than this, the connection will
+  /// be closed.
+  ///
+  /// Default: 64 MiB
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.read_half.max_message_size = max_message_size;
+  }
+}
+
+impl<'f, S: Read + Write> EmbeddedWebSocket<S> {
+  /// Reads a frame. Like `WebSocket::read_frame`, pings are answered and close frames echoed unless `auto_pong` and
+  /// `auto_close` are disabled.
+  pub async fn read_frame(&mut self) -> Result<Frame<'f>, WebSocketError> {
+    loop {
+      let (res, obligated_send) =
+        self.read_half.read_frame_inner(&mut self.stream).await;
+      let is_closed = self.write_half.closed;
+      if let Some(frame) = obligated_send {
+        if !is_closed {
+          self.write_half.write_frame(&mut self.stream, frame).await?;
+        }
+      }
+      if let Some(frame) = res? {
+        if is_closed && frame.opcode != crate::OpCode::Close {
+          return Err(WebSocketError::ConnectionClosed);
+        }
+        break Ok(frame);
+      }
+    }
+  }
+
+  /// Writes a frame. Clients mask it with a fresh key.
+  pub async fn write_frame(
+    &mut self,
+    frame: Frame<'f>,
+  ) -> Result<(), WebSocketError> {
+    self.write_half.write_frame(&mut self.stream, frame).await
+  }
+
+  /// Flushes the underlying stream.
+  pub async fn flush(&mut self) -> Result<(), WebSocketError> {
+    self.stream.0.flush().await.map_err(io_error)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use crate::WebSocket;
+  use tokio::io::AsyncReadExt;
+  use tokio::io::AsyncWriteExt;
+
+  /// A stream that only implements the `embedded-io-async` traits.
+  struct EmbeddedStream(tokio::io::DuplexStream);
+
+  impl embedded_io_async::ErrorType for EmbeddedStream {
+    type Error = io::Error;
+  }
+
+  impl Read for EmbeddedStream {
+    async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      self.0.read(buf).await
+    }
+  }
+
+  impl Write for EmbeddedStream {
+    async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.write(buf).await
+    }
+  }
+
+  #[tokio::test]
+  async fn websocket_over_embedded_io() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server =
+      EmbeddedWebSocket::after_handshake(EmbeddedStream(server), Role::Server);
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"p".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    // The ping is answered before the text frame is returned.
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    server.write_frame(frame).await.unwrap();
+
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Pong);
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+  }
+}
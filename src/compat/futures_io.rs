@@ -0,0 +1,192 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+/// Adapts a stream implementing `futures_io::AsyncRead` and `futures_io::AsyncWrite`, so that a `WebSocket` can run
+/// on it outside of tokio.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::compat::FuturesIo;
+/// use fastwebsockets::{Role, WebSocket, WebSocketError};
+///
+/// async fn handle<S>(socket: S) -> Result<(), WebSocketError>
+/// where
+///   S: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin,
+/// {
+///   let mut ws = WebSocket::after_handshake(FuturesIo::new(socket), Role::Server);
+///   let frame = ws.read_frame().await?;
+///   ws.write_frame(frame).await
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FuturesIo<S> {
+  inner: S,
+}
+
+impl<S> FuturesIo<S> {
+  /// Wraps `inner`.
+  pub fn new(inner: S) -> Self {
+    Self { inner }
+  }
+
+  /// Returns a reference to the wrapped stream.
+  pub fn get_ref(&self) -> &S {
+    &self.inner
+  }
+
+  /// Returns a mutable reference to the wrapped stream.
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.inner
+  }
+
+  /// Consumes the `FuturesIo` and returns the wrapped stream.
+  pub fn into_inner(self) -> S {
+    self.inner
+  }
+}
+
+impl<S: futures_io::AsyncRead + Unpin> AsyncRead for FuturesIo<S> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let n = ready!(Pin::new(&mut self.get_mut().inner)
+      .poll_read(cx, buf.initialize_unfilled()))?;
+    buf.advance(n);
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl<S: futures_io::AsyncWrite + Unpin> AsyncWrite for FuturesIo<S> {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+  }
+
+  fn poll_write_vectored(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    bufs: &[IoSlice<'_>],
+  ) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+  }
+
+  fn is_write_vectored(&self) -> bool {
+    true
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_close(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Frame;
+  use crate::OpCode;
+  use crate::Role;
+  use crate::WebSocket;
+
+  /// A stream that only implements the `futures-io` traits.
+  struct FuturesStream(tokio::io::DuplexStream);
+
+  impl futures_io::AsyncRead for FuturesStream {
+    fn poll_read(
+      mut self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+      buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+      let mut buf = ReadBuf::new(buf);
+      ready!(Pin::new(&mut self.0).poll_read(cx, &mut buf))?;
+      Poll::Ready(Ok(buf.filled().len()))
+    }
+  }
+
+  impl futures_io::AsyncWrite for FuturesStream {
+    fn poll_write(
+      mut self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+      buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+      Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+      mut self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+      Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(
+      mut self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+      Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+  }
+
+  #[tokio::test]
+  async fn websocket_over_futures_io() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(
+      FuturesIo::new(FuturesStream(client)),
+      Role::Client,
+    );
+    let mut server = WebSocket::after_handshake(
+      FuturesIo::new(FuturesStream(server)),
+      Role::Server,
+    );
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    server.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+  }
+}
@@ -0,0 +1,185 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A `WebSocketStream` with the surface of tokio-tungstenite, for moving existing code over without rewriting every
+//! call site at once.
+//!
+//! Replace the imports and the construction of the stream:
+//!
+//! ```ignore
+//! // use tokio_tungstenite::tungstenite::{Error, Message};
+//! use fastwebsockets::compat::tungstenite::{Error, Message, WebSocketStream};
+//!
+//! // With the handshake done by `fastwebsockets::upgrade` or `fastwebsockets::handshake`:
+//! let mut ws = WebSocketStream::new(ws);
+//! ```
+//!
+//! Loops over `ws.next()` and calls to `ws.send(message)` then keep working, as do `read`, `close` and matches on
+//! `Message`. The differences are:
+//!
+//! - `next`, `send`, `flush` and `close` are inherent methods, so `StreamExt` and `SinkExt` are not needed.
+//! - Pings are answered automatically and not returned, unless `set_auto_pong(false)` was called on the
+//!   `WebSocket`.
+//! - `Message::Text` holds a `String` and there is no `Message::Frame`.
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::FragmentCollector;
+use crate::WebSocket;
+
+pub use crate::CloseCode;
+pub use crate::CloseFrame;
+pub use crate::Message;
+pub use crate::WebSocketError as Error;
+
+/// A result with the error type of this module.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A websocket that reads and writes complete messages, like tokio-tungstenite's `WebSocketStream`.
+///
+/// Fragmented messages are collected with a `FragmentCollector`. Once a close message has been read, and the close
+/// frame answered, reads fail with `Error::ConnectionClosed` and `next` returns `None`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::compat::tungstenite::{Message, Result, WebSocketStream};
+/// use tokio::net::TcpStream;
+///
+/// async fn echo(mut ws: WebSocketStream<TcpStream>) -> Result<()> {
+///   while let Some(message) = ws.next().await {
+///     let message = message?;
+///     if message.is_text() || message.is_binary() {
+///       ws.send(message).await?;
+///     }
+///   }
+///   Ok(())
+/// }
+/// ```
+pub struct WebSocketStream<S> {
+  ws: FragmentCollector<S>,
+  /// Whether a close message has been read.
+  terminated: bool,
+}
+
+impl<S> WebSocketStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Wraps a `WebSocket` after the handshake.
+  pub fn new(ws: WebSocket<S>) -> Self {
+    Self {
+      ws: FragmentCollector::new(ws),
+      terminated: false,
+    }
+  }
+
+  /// Reads the next message.
+  ///
+  /// A close frame from the peer is answered and returned as `Message::Close`. Reads after that fail with
+  /// `Error::ConnectionClosed`.
+  pub async fn read(&mut self) -> Result<Message> {
+    if self.terminated {
+      return Err(Error::ConnectionClosed);
+    }
+    let message = self.ws.read_message().await?;
+    if message.is_close() {
+      self.terminated = true;
+      // The answer to the close frame has been written, but not flushed.
+      self.ws.flush().await?;
+    }
+    Ok(message)
+  }
+
+  /// Reads the next message, or returns `None` once the connection has been closed, like `StreamExt::next`.
+  pub async fn next(&mut self) -> Option<Result<Message>> {
+    if self.terminated {
+      return None;
+    }
+    Some(self.read().await)
+  }
+
+  /// Writes a message and flushes the stream.
+  pub async fn send(&mut self, message: Message) -> Result<()> {
+    self.write(message).await?;
+    self.flush().await
+  }
+
+  /// Writes a message without flushing the stream, like `SinkExt::feed`.
+  pub async fn write(&mut self, message: Message) -> Result<()> {
+    self.ws.write_message(message).await
+  }
+
+  /// Flushes the stream.
+  pub async fn flush(&mut self) -> Result<()> {
+    self.ws.flush().await
+  }
+
+  /// Sends a close frame. Keep reading until the peer's close message arrives, or `next` returns `None`, to
+  /// complete the close handshake.
+  pub async fn close(&mut self, frame: Option<CloseFrame>) -> Result<()> {
+    self.send(Message::Close(frame)).await
+  }
+
+  /// Returns the `FragmentCollector`, for the methods of fastwebsockets.
+  pub fn into_inner(self) -> FragmentCollector<S> {
+    self.ws
+  }
+}
+
+impl<S> From<WebSocket<S>> for WebSocketStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn from(ws: WebSocket<S>) -> Self {
+    Self::new(ws)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Role;
+
+  #[tokio::test]
+  async fn echo_and_close() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client =
+      WebSocketStream::new(WebSocket::after_handshake(client, Role::Client));
+    let mut server =
+      WebSocketStream::new(WebSocket::after_handshake(server, Role::Server));
+
+    client.send(Message::text("hello")).await.unwrap();
+    let message = server.next().await.unwrap().unwrap();
+    assert_eq!(message.as_text(), Some("hello"));
+    server.send(message).await.unwrap();
+    assert_eq!(client.read().await.unwrap(), Message::text("hello"));
+
+    let close = CloseFrame {
+      code: CloseCode::Normal,
+      reason: "bye".into(),
+    };
+    client.close(Some(close.clone())).await.unwrap();
+    assert_eq!(
+      server.read().await.unwrap(),
+      Message::Close(Some(close.clone()))
+    );
+    assert!(server.next().await.is_none());
+
+    // The server answered with the same close frame.
+    assert_eq!(client.read().await.unwrap(), Message::Close(Some(close)));
+    assert!(matches!(client.read().await, Err(Error::ConnectionClosed)));
+  }
+}
@@ -0,0 +1,121 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+
+use bytes::BytesMut;
+use tokio_uring::buf::IoBuf;
+use tokio_uring::net::TcpStream;
+
+use crate::OwnedRead;
+use crate::OwnedWebSocket;
+use crate::OwnedWrite;
+
+impl OwnedRead for TcpStream {
+  async fn read_owned(
+    &mut self,
+    buf: BytesMut,
+    limit: usize,
+  ) -> (io::Result<usize>, BytesMut) {
+    // The kernel owns the buffer until the read completes, so the read goes into a slice of its spare capacity.
+    let len = buf.len();
+    let (read, slice) = self.read(buf.slice(len..len + limit)).await;
+    (read, slice.into_inner())
+  }
+}
+
+impl OwnedWrite for TcpStream {
+  async fn write_all_owned(
+    &mut self,
+    buf: Vec<u8>,
+  ) -> (io::Result<()>, Vec<u8>) {
+    self.write_all(buf).await
+  }
+}
+
+/// A websocket over a `tokio_uring::net::TcpStream`, whose reads and writes are io_uring operations on owned buffers.
+/// It is an [`OwnedWebSocket`](crate::OwnedWebSocket) on the `tokio-uring` runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::compat::UringWebSocket;
+/// use fastwebsockets::{OpCode, Role};
+/// use tokio_uring::net::TcpListener;
+///
+/// tokio_uring::start(async {
+///   let listener = TcpListener::bind("127.0.0.1:9001".parse().unwrap())?;
+///   let (stream, _) = listener.accept().await?;
+///   // The HTTP upgrade is done elsewhere, for example by a proxy in front.
+///   let mut ws = UringWebSocket::after_handshake(stream, Role::Server);
+///   loop {
+///     let frame = ws.read_frame().await?;
+///     match frame.opcode {
+///       OpCode::Close => break,
+///       OpCode::Text | OpCode::Binary => ws.write_frame(frame).await?,
+///       _ => {}
+///     }
+///   }
+///   Ok::<_, Box<dyn std::error::Error>>(())
+/// })
+/// .unwrap();
+/// ```
+pub type UringWebSocket = OwnedWebSocket<TcpStream>;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Frame;
+  use crate::OpCode;
+  use crate::Role;
+  use tokio_uring::net::TcpListener;
+
+  #[test]
+  fn websocket_over_io_uring() {
+    tokio_uring::start(async {
+      let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+      let addr = listener.local_addr().unwrap();
+      let client = TcpStream::connect(addr).await.unwrap();
+      let (server, _) = listener.accept().await.unwrap();
+      let mut client = UringWebSocket::after_handshake(client, Role::Client);
+      let mut server = UringWebSocket::after_handshake(server, Role::Server);
+
+      // A payload larger than the initial read buffer takes several reads.
+      let payload = vec![b'a'; 64 * 1024];
+      client
+        .write_frame(Frame::new(true, OpCode::Ping, None, b"p".to_vec().into()))
+        .await
+        .unwrap();
+      client
+        .write_frame(Frame::binary(payload.clone().into()))
+        .await
+        .unwrap();
+      // The ping is answered before the binary frame is returned.
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(&*frame.payload, &payload[..]);
+      server.write_frame(frame).await.unwrap();
+
+      let frame = client.read_frame().await.unwrap();
+      assert_eq!(frame.opcode, OpCode::Pong);
+      let frame = client.read_frame().await.unwrap();
+      assert_eq!(&*frame.payload, &payload[..]);
+
+      client.write_frame(Frame::close(1000, b"")).await.unwrap();
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(frame.opcode, OpCode::Close);
+      let frame = client.read_frame().await.unwrap();
+      assert_eq!(frame.opcode, OpCode::Close);
+    });
+  }
+}
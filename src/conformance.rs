@@ -0,0 +1,289 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protocol test vectors for checking that a custom transport drives the frame parser correctly.
+//!
+//! Every vector is a byte sequence as it arrives on the wire and the outcome of the first `read_frame` on a
+//! `WebSocket` created with `after_handshake` and default options. A transport passes when it delivers `bytes`
+//! and then reports EOF, accepts anything written to it (pings and close frames are answered automatically), and
+//! every outcome matches.
+//!
+//! # Example
+//!
+//! ```
+//! use fastwebsockets::conformance;
+//! use fastwebsockets::WebSocket;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! for vector in conformance::vectors() {
+//!   // Replace with the custom transport, fed with `vector.bytes`.
+//!   let transport = tokio::io::join(vector.bytes, tokio::io::sink());
+//!   let mut ws = WebSocket::after_handshake(transport, vector.role);
+//!   let result = ws.read_frame().await;
+//!   assert!(vector.matches(&result), "{}: {:?}", vector.name, result);
+//! }
+//! # }
+//! ```
+
+use crate::Frame;
+use crate::OpCode;
+use crate::Role;
+use crate::WebSocketError;
+
+/// A byte sequence and the outcome of parsing it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TestVector {
+  /// A short description of what the vector checks.
+  pub name: &'static str,
+  /// The role of the endpoint that reads `bytes`.
+  pub role: Role,
+  /// The bytes received from the peer.
+  pub bytes: &'static [u8],
+  /// The expected result of the first `read_frame`.
+  pub expected: Outcome,
+}
+
+/// The expected result of reading a test vector.
+#[derive(Debug)]
+pub enum Outcome {
+  /// A frame with these fields, with the payload unmasked.
+  Frame {
+    fin: bool,
+    opcode: OpCode,
+    payload: &'static [u8],
+  },
+  /// An error of this variant. Fields of the variant are not compared.
+  Error(WebSocketError),
+}
+
+impl TestVector {
+  /// Returns true if `result` is the expected outcome of reading the vector.
+  pub fn matches(&self, result: &Result<Frame<'_>, WebSocketError>) -> bool {
+    match (&self.expected, result) {
+      (
+        Outcome::Frame {
+          fin,
+          opcode,
+          payload,
+        },
+        Ok(frame),
+      ) => {
+        frame.fin == *fin
+          && frame.opcode == *opcode
+          && &*frame.payload == *payload
+      }
+      (Outcome::Error(expected), Err(e)) => {
+        std::mem::discriminant(expected) == std::mem::discriminant(e)
+      }
+      _ => false,
+    }
+  }
+}
+
+/// Returns every test vector.
+pub fn vectors() -> &'static [TestVector] {
+  VECTORS
+}
+
+const fn frame(fin: bool, opcode: OpCode, payload: &'static [u8]) -> Outcome {
+  Outcome::Frame {
+    fin,
+    opcode,
+    payload,
+  }
+}
+
+/// A binary frame with a 16-bit payload length and 126 zero bytes of payload.
+const EXTENDED_LENGTH: [u8; 130] = {
+  let mut bytes = [0; 130];
+  bytes[0] = 0x82;
+  bytes[1] = 0x7e;
+  bytes[3] = 126;
+  bytes
+};
+
+static VECTORS: &[TestVector] = &[
+  TestVector {
+    name: "unmasked text frame",
+    role: Role::Client,
+    bytes: b"\x81\x05hello",
+    expected: frame(true, OpCode::Text, b"hello"),
+  },
+  TestVector {
+    // RFC 6455, section 5.7.
+    name: "masked text frame",
+    role: Role::Server,
+    bytes: b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58",
+    expected: frame(true, OpCode::Text, b"Hello"),
+  },
+  TestVector {
+    name: "empty binary frame",
+    role: Role::Client,
+    bytes: b"\x82\x00",
+    expected: frame(true, OpCode::Binary, b""),
+  },
+  TestVector {
+    name: "16-bit payload length",
+    role: Role::Client,
+    bytes: &EXTENDED_LENGTH,
+    expected: frame(true, OpCode::Binary, &[0; 126]),
+  },
+  TestVector {
+    name: "first fragment of a text message",
+    role: Role::Client,
+    bytes: b"\x01\x03Hel",
+    expected: frame(false, OpCode::Text, b"Hel"),
+  },
+  TestVector {
+    name: "fragment splitting a code point",
+    role: Role::Client,
+    bytes: b"\x01\x01\xc3",
+    expected: frame(false, OpCode::Text, b"\xc3"),
+  },
+  TestVector {
+    name: "ping is answered and not returned",
+    role: Role::Client,
+    bytes: b"\x89\x02hi\x81\x02ok",
+    expected: frame(true, OpCode::Text, b"ok"),
+  },
+  TestVector {
+    name: "pong frame",
+    role: Role::Client,
+    bytes: b"\x8a\x00",
+    expected: frame(true, OpCode::Pong, b""),
+  },
+  TestVector {
+    name: "close frame with a code",
+    role: Role::Client,
+    bytes: b"\x88\x02\x03\xe8",
+    expected: frame(true, OpCode::Close, b"\x03\xe8"),
+  },
+  TestVector {
+    name: "reserved bit 1 without an extension",
+    role: Role::Client,
+    bytes: b"\xc1\x00",
+    expected: Outcome::Error(WebSocketError::ReservedBitsNotZero),
+  },
+  TestVector {
+    name: "reserved bit 2",
+    role: Role::Client,
+    bytes: b"\xa2\x00",
+    expected: Outcome::Error(WebSocketError::ReservedBitsNotZero),
+  },
+  TestVector {
+    name: "reserved opcode",
+    role: Role::Client,
+    bytes: b"\x83\x00",
+    expected: Outcome::Error(WebSocketError::InvalidValue),
+  },
+  TestVector {
+    name: "unmasked frame from a client",
+    role: Role::Server,
+    bytes: b"\x81\x02hi",
+    expected: Outcome::Error(WebSocketError::UnmaskedFrame),
+  },
+  TestVector {
+    name: "fragmented control frame",
+    role: Role::Client,
+    bytes: b"\x09\x00",
+    expected: Outcome::Error(WebSocketError::ControlFrameFragmented),
+  },
+  TestVector {
+    name: "ping longer than 125 bytes",
+    role: Role::Client,
+    bytes: b"\x89\x7e\x00\x7e",
+    expected: Outcome::Error(WebSocketError::PingFrameTooLarge),
+  },
+  TestVector {
+    name: "pong longer than 125 bytes",
+    role: Role::Client,
+    bytes: b"\x8a\x7e\x00\x7e",
+    expected: Outcome::Error(WebSocketError::ControlFrameTooLarge),
+  },
+  TestVector {
+    name: "payload longer than the maximum message size",
+    role: Role::Client,
+    bytes: b"\x82\x7f\x7f\xff\xff\xff\xff\xff\xff\xff",
+    expected: Outcome::Error(WebSocketError::FrameTooLarge),
+  },
+  TestVector {
+    name: "invalid UTF-8 in a text frame",
+    role: Role::Client,
+    bytes: b"\x81\x02\xff\xfe",
+    expected: Outcome::Error(WebSocketError::InvalidUTF8),
+  },
+  TestVector {
+    name: "close frame with a one byte payload",
+    role: Role::Client,
+    bytes: b"\x88\x01\x03",
+    expected: Outcome::Error(WebSocketError::InvalidCloseFrame),
+  },
+  TestVector {
+    name: "close frame with a reserved code",
+    role: Role::Client,
+    bytes: b"\x88\x02\x03\xec",
+    expected: Outcome::Error(WebSocketError::InvalidCloseCode),
+  },
+  TestVector {
+    name: "EOF inside the header",
+    role: Role::Client,
+    bytes: b"\x81",
+    expected: Outcome::Error(WebSocketError::UnexpectedEOF),
+  },
+  TestVector {
+    name: "EOF inside the payload",
+    role: Role::Client,
+    bytes: b"\x81\x05he",
+    expected: Outcome::Error(WebSocketError::UnexpectedEOF),
+  },
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::WebSocket;
+
+  #[tokio::test]
+  async fn vectors_pass() {
+    for vector in vectors() {
+      let transport = tokio::io::join(vector.bytes, tokio::io::sink());
+      let mut ws = WebSocket::after_handshake(transport, vector.role);
+      let result = ws.read_frame().await;
+      assert!(vector.matches(&result), "{}: {:?}", vector.name, result);
+    }
+  }
+
+  #[tokio::test]
+  async fn vectors_pass_byte_by_byte() {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    for vector in vectors() {
+      let (client, server) = tokio::io::duplex(1);
+      let (mut reader, mut writer) = tokio::io::split(client);
+      let bytes = vector.bytes;
+      tokio::spawn(async move {
+        let _ = writer.write_all(bytes).await;
+        let _ = writer.shutdown().await;
+      });
+      // Drain whatever the reader answers with.
+      tokio::spawn(async move { reader.read_to_end(&mut vec![]).await });
+      let mut ws = WebSocket::after_handshake(server, vector.role);
+      let result = ws.read_frame().await;
+      assert!(vector.matches(&result), "{}: {:?}", vector.name, result);
+    }
+  }
+}
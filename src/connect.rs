@@ -0,0 +1,426 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+
+use hyper::body::Incoming;
+use hyper::header::AUTHORIZATION;
+use hyper::header::COOKIE;
+use hyper::header::LOCATION;
+use hyper::header::PROXY_AUTHORIZATION;
+use hyper::upgrade::Upgraded;
+use hyper::HeaderMap;
+use hyper::Response;
+use hyper::Uri;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+
+use crate::handshake;
+use crate::handshake::ClientHandshake;
+use crate::handshake::ClientOptions;
+use crate::handshake::ClientRequestBuilder;
+use crate::socks;
+use crate::socks::Socks5Proxy;
+#[cfg(feature = "tls-native")]
+use crate::tls::NativeTlsConnector;
+#[cfg(feature = "tls-rustls")]
+use crate::tls::RustlsConnector;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Options for [`connect`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+  /// Extra headers for the handshake request, such as `Authorization` or `Origin`.
+  pub headers: HeaderMap,
+  /// Subprotocols to offer, in order of preference.
+  pub protocols: Vec<String>,
+  /// A SOCKS5 proxy to connect through. The proxy resolves the host of the URL.
+  pub socks5_proxy: Option<Socks5Proxy>,
+  /// The rustls connector for `wss` URLs.
+  ///
+  /// Default: `RustlsConnector::default()`
+  #[cfg(feature = "tls-rustls")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "tls-rustls")))]
+  pub tls: Option<RustlsConnector>,
+  /// The native-tls connector for `wss` URLs, used instead of `tls` when set.
+  ///
+  /// Default: `NativeTlsConnector::new()` if the `tls-rustls` feature is disabled, unset otherwise
+  #[cfg(feature = "tls-native")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "tls-native")))]
+  pub native_tls: Option<NativeTlsConnector>,
+  /// The number of redirects to follow when the server responds with status 301, 302, 307 or 308 and a
+  /// `Location` header. When it is 0, a redirect fails with `WebSocketError::InvalidStatusCode`, and when more
+  /// are needed, with `WebSocketError::TooManyRedirects`.
+  ///
+  /// Default: `0`
+  pub max_redirects: usize,
+  /// Options for validating the handshake response.
+  pub handshake: ClientOptions,
+  /// Whether to follow redirects to another scheme, host or port. They fail with
+  /// `WebSocketError::CrossOriginRedirect` otherwise. The `Authorization`, `Cookie` and `Proxy-Authorization`
+  /// headers are not sent to another origin.
+  ///
+  /// Default: `false`
+  pub cross_origin_redirects: bool,
+}
+
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::spawn(fut);
+  }
+}
+
+/// Connects to a websocket server at a `ws://` or `wss://` URL: resolves the host, opens a TCP connection, directly
+/// or through a SOCKS5 proxy, sets up TLS for `wss` and performs the handshake with `handshake::client`.
+///
+/// URLs that are not `ws://` or `wss://` fail with `WebSocketError::InvalidUrl`, and so do `wss://` URLs unless the
+/// `tls-rustls` or `tls-native` feature is enabled. Must be called within a tokio runtime, which runs the HTTP
+/// connection.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::{connect, ConnectOptions, FragmentCollector, Frame, WebSocketError};
+///
+/// async fn subscribe() -> Result<(), WebSocketError> {
+///   let (ws, _response) =
+///     connect("ws://localhost:9001/feed", ConnectOptions::default()).await?;
+///   let mut ws = FragmentCollector::new(ws);
+///   ws.write_frame(Frame::text(b"subscribe"[..].into())).await?;
+///   let message = ws.read_frame().await?;
+///   Ok(())
+/// }
+/// ```
+pub async fn connect(
+  url: &str,
+  options: ConnectOptions,
+) -> Result<(WebSocket<TokioIo<Upgraded>>, Response<Incoming>), WebSocketError>
+{
+  let mut uri: Uri = url.parse().map_err(|_| WebSocketError::InvalidUrl)?;
+  let mut headers = options.headers.clone();
+  let mut redirects = 0;
+  loop {
+    let response = match connect_once(&uri, headers.clone(), &options).await? {
+      ClientHandshake::Upgraded(ws, response) => return Ok((*ws, response)),
+      ClientHandshake::Redirected(response) => response,
+    };
+    if options.max_redirects == 0 {
+      return Err(WebSocketError::InvalidStatusCode(
+        response.status().as_u16(),
+      ));
+    }
+    if redirects == options.max_redirects {
+      return Err(WebSocketError::TooManyRedirects);
+    }
+    let location = redirect_target(&uri, &response)?;
+    if origin(&location)? != origin(&uri)? {
+      if !options.cross_origin_redirects {
+        return Err(WebSocketError::CrossOriginRedirect);
+      }
+      // Credentials meant for the original server are not passed on.
+      for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+        headers.remove(name);
+      }
+    }
+    uri = location;
+    redirects += 1;
+  }
+}
+
+async fn connect_once(
+  uri: &Uri,
+  headers: HeaderMap,
+  options: &ConnectOptions,
+) -> Result<ClientHandshake, WebSocketError> {
+  let (tls, host, port) = origin(uri)?;
+  // IPv6 addresses keep their brackets in the URL but not in the socket address.
+  let host_name = host.trim_start_matches('[').trim_end_matches(']');
+
+  let request = ClientRequestBuilder::new(uri.clone())
+    .headers(headers)
+    .protocols(&options.protocols)
+    .build()?;
+
+  let stream = match &options.socks5_proxy {
+    Some(proxy) => {
+      let mut stream = TcpStream::connect(proxy.addr.as_str()).await?;
+      socks::handshake(
+        &mut stream,
+        host_name,
+        port,
+        proxy.credentials.as_ref(),
+      )
+      .await?;
+      stream
+    }
+    None => TcpStream::connect((host_name, port)).await?,
+  };
+  #[cfg(feature = "tls-native")]
+  if tls && (options.native_tls.is_some() || cfg!(not(feature = "tls-rustls")))
+  {
+    let connector = match &options.native_tls {
+      Some(connector) => connector.clone(),
+      None => NativeTlsConnector::new()?,
+    };
+    let stream = connector.connect(host, stream).await?;
+    return handshake::client_or_redirect(
+      &SpawnExecutor,
+      request,
+      stream,
+      &options.handshake,
+    )
+    .await;
+  }
+  #[cfg(feature = "tls-rustls")]
+  if tls {
+    let connector = options.tls.clone().unwrap_or_default();
+    let stream = connector.connect(host, stream).await?;
+    return handshake::client_or_redirect(
+      &SpawnExecutor,
+      request,
+      stream,
+      &options.handshake,
+    )
+    .await;
+  }
+  handshake::client_or_redirect(
+    &SpawnExecutor,
+    request,
+    stream,
+    &options.handshake,
+  )
+  .await
+}
+
+/// Returns whether the URL uses TLS, its host and its port, failing for schemes other than `ws` and `wss`.
+fn origin(uri: &Uri) -> Result<(bool, &str, u16), WebSocketError> {
+  let tls = match uri.scheme_str() {
+    Some("ws") => false,
+    Some("wss")
+      if cfg!(any(feature = "tls-rustls", feature = "tls-native")) =>
+    {
+      true
+    }
+    _ => return Err(WebSocketError::InvalidUrl),
+  };
+  let host = uri.host().ok_or(WebSocketError::InvalidUrl)?;
+  let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+  Ok((tls, host, port))
+}
+
+/// Resolves the `Location` of a redirect against the URL that was requested. `http` and `https` locations are
+/// mapped to `ws` and `wss`, as servers often redirect with the scheme of the page.
+fn redirect_target(
+  uri: &Uri,
+  response: &Response<Incoming>,
+) -> Result<Uri, WebSocketError> {
+  let location = response
+    .headers()
+    .get(LOCATION)
+    .and_then(|location| location.to_str().ok())
+    .ok_or(WebSocketError::InvalidUrl)?;
+  if location.starts_with('/') && !location.starts_with("//") {
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query =
+      Some(location.parse().map_err(|_| WebSocketError::InvalidUrl)?);
+    return Uri::from_parts(parts).map_err(|_| WebSocketError::InvalidUrl);
+  }
+
+  let mut parts = location
+    .parse::<Uri>()
+    .map_err(|_| WebSocketError::InvalidUrl)?
+    .into_parts();
+  let scheme = match parts.scheme.as_ref().map(|scheme| scheme.as_str()) {
+    Some("ws" | "http") => "ws",
+    Some("wss" | "https") => "wss",
+    _ => return Err(WebSocketError::InvalidUrl),
+  };
+  parts.scheme = Some(scheme.parse().expect("bug: invalid scheme"));
+  if parts.path_and_query.is_none() {
+    parts.path_and_query = Some("/".parse().expect("bug: invalid path"));
+  }
+  Uri::from_parts(parts).map_err(|_| WebSocketError::InvalidUrl)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::upgrade;
+  use crate::Frame;
+  use http_body_util::Empty;
+  use hyper::header::HeaderValue;
+  use hyper::server::conn::http1;
+  use hyper::service::service_fn;
+  use hyper::Request;
+  use tokio::net::TcpListener;
+
+  /// Starts a server that echoes one frame on `/echo?room=1` with the `chat` subprotocol. Returns its port.
+  ///
+  /// `/old` redirects there, `/elsewhere` redirects there on another origin, `localhost` instead of `127.0.0.1`,
+  /// and `/loop` redirects to itself.
+  async fn echo_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+      loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        let service =
+          service_fn(move |mut req: Request<Incoming>| async move {
+            let location = match req.uri().path() {
+              "/old" => "/echo?room=1".to_owned(),
+              "/elsewhere" => format!("http://localhost:{port}/echo?room=1"),
+              "/loop" => "/loop".to_owned(),
+              _ => {
+                assert_eq!(req.uri().path_and_query().unwrap(), "/echo?room=1");
+                assert!(!req.headers().contains_key(AUTHORIZATION));
+                let (response, fut) =
+                  upgrade::upgrade_with_protocol(&mut req, &["chat"])?;
+                tokio::spawn(async move {
+                  let mut ws = fut.await.unwrap();
+                  let frame = ws.read_frame().await.unwrap();
+                  ws.write_frame(frame).await.unwrap();
+                });
+                return Ok::<_, WebSocketError>(response);
+              }
+            };
+            let response = Response::builder()
+              .status(308)
+              .header(LOCATION, location)
+              .body(Empty::new())
+              .unwrap();
+            Ok(response)
+          });
+        tokio::spawn(
+          http1::Builder::new()
+            .serve_connection(TokioIo::new(stream), service)
+            .with_upgrades(),
+        );
+      }
+    });
+    port
+  }
+
+  #[tokio::test]
+  async fn connects_to_ws_url() {
+    let port = echo_server().await;
+    let options = ConnectOptions {
+      protocols: vec!["chat".to_owned()],
+      ..Default::default()
+    };
+    let url = format!("ws://127.0.0.1:{port}/echo?room=1");
+    let (mut ws, response) = connect(&url, options).await.unwrap();
+    assert_eq!(upgrade::selected_protocol(&response), Some("chat"));
+    ws.write_frame(Frame::text(b"hello"[..].into()))
+      .await
+      .unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+  }
+
+  #[tokio::test]
+  async fn connects_through_socks5_proxy() {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    let port = echo_server().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut buf = [0; 3];
+      stream.read_exact(&mut buf).await.unwrap();
+      stream.write_all(&[5, 0]).await.unwrap();
+
+      // The proxy receives the host name, not an address resolved by the client.
+      let mut buf = [0; 16];
+      stream.read_exact(&mut buf).await.unwrap();
+      assert_eq!(&buf[..14], b"\x05\x01\x00\x03\x09localhost");
+      let port = u16::from_be_bytes([buf[14], buf[15]]);
+      let mut server = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+      stream
+        .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+        .await
+        .unwrap();
+      let _ = tokio::io::copy_bidirectional(&mut stream, &mut server).await;
+    });
+
+    let options = ConnectOptions {
+      socks5_proxy: Some(Socks5Proxy::new(proxy_addr.to_string())),
+      protocols: vec!["chat".to_owned()],
+      ..Default::default()
+    };
+    let url = format!("ws://localhost:{port}/echo?room=1");
+    let (mut ws, _) = connect(&url, options).await.unwrap();
+    ws.write_frame(Frame::text(b"hello"[..].into()))
+      .await
+      .unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+  }
+
+  #[tokio::test]
+  async fn follows_redirects() {
+    let port = echo_server().await;
+    let options = |max_redirects, cross_origin_redirects| ConnectOptions {
+      max_redirects,
+      cross_origin_redirects,
+      ..Default::default()
+    };
+
+    let url = format!("ws://127.0.0.1:{port}/old");
+    assert!(matches!(
+      connect(&url, options(0, false)).await,
+      Err(WebSocketError::InvalidStatusCode(308))
+    ));
+    let (mut ws, _) = connect(&url, options(1, false)).await.unwrap();
+    ws.write_frame(Frame::text(b"hello"[..].into()))
+      .await
+      .unwrap();
+    assert_eq!(ws.read_frame().await.unwrap().as_text(), Some("hello"));
+
+    let url = format!("ws://127.0.0.1:{port}/loop");
+    assert!(matches!(
+      connect(&url, options(3, false)).await,
+      Err(WebSocketError::TooManyRedirects)
+    ));
+
+    let url = format!("ws://127.0.0.1:{port}/elsewhere");
+    assert!(matches!(
+      connect(&url, options(1, false)).await,
+      Err(WebSocketError::CrossOriginRedirect)
+    ));
+    let mut options = options(1, true);
+    options
+      .headers
+      .insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+    connect(&url, options).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn rejects_other_schemes() {
+    for url in ["http://localhost/", "localhost:80", "ws:///path"] {
+      assert!(matches!(
+        connect(url, ConnectOptions::default()).await,
+        Err(WebSocketError::InvalidUrl)
+      ));
+    }
+  }
+}
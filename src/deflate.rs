@@ -0,0 +1,1115 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use flate2::Compress;
+use flate2::Compression;
+use flate2::Decompress;
+use flate2::FlushCompress;
+use flate2::FlushDecompress;
+use flate2::Status;
+
+use std::sync::Arc;
+
+#[cfg(feature = "brotli")]
+use crate::brotli::BrotliDecoder;
+#[cfg(feature = "brotli")]
+use crate::brotli::BrotliEncoder;
+#[cfg(feature = "zstd")]
+use crate::zstd::ZstdDecoder;
+#[cfg(feature = "zstd")]
+use crate::zstd::ZstdEncoder;
+use crate::Role;
+use crate::WebSocketError;
+
+const EXTENSION_NAME: &str = "permessage-deflate";
+/// Appended to the payload of the final frame before inflating, and stripped after deflating (RFC 7692 Section 7.2).
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+/// zlib does not support raw deflate streams with a window smaller than 2^9 bytes.
+const MIN_WINDOW_BITS: u8 = 9;
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Parameters of the permessage-deflate extension (RFC 7692).
+///
+/// On a server this is the policy that client offers are negotiated against with [`DeflateConfig::accept`]. The
+/// negotiated parameters are then passed to `WebSocket::set_deflate` on both ends of the connection.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::DeflateConfig;
+///
+/// // Reset the compression context after every message to bound the memory held by idle connections.
+/// let policy = DeflateConfig {
+///   server_no_context_takeover: true,
+///   client_no_context_takeover: true,
+///   ..Default::default()
+/// };
+///
+/// let agreed = policy.accept("permessage-deflate; client_max_window_bits").unwrap();
+/// assert_eq!(
+///   agreed.response(),
+///   "permessage-deflate; server_no_context_takeover; client_no_context_takeover"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateConfig {
+  /// The server resets its compressor after every message.
+  ///
+  /// When negotiating, the server requires this even if the client did not ask for it.
+  pub server_no_context_takeover: bool,
+  /// The client resets its compressor after every message, so the server does not have to keep the inflate window
+  /// of the connection between messages.
+  ///
+  /// When negotiating, the server requires this even if the client did not ask for it.
+  pub client_no_context_takeover: bool,
+  /// Base-2 logarithm of the LZ77 window the server compresses with, between 9 and 15.
+  pub server_max_window_bits: u8,
+  /// Base-2 logarithm of the LZ77 window the client compresses with, between 9 and 15.
+  ///
+  /// When negotiating, offers that do not allow the server to limit it are declined if this is below 15.
+  pub client_max_window_bits: u8,
+  /// Compression level between 0 and 9 this end compresses with. It is not part of the negotiation: a server policy
+  /// passes it on to the agreed parameters, and `None` keeps the level set with `WebSocket::set_compression_level`.
+  pub level: Option<u32>,
+}
+
+impl Default for DeflateConfig {
+  fn default() -> Self {
+    Self {
+      server_no_context_takeover: false,
+      client_no_context_takeover: false,
+      server_max_window_bits: MAX_WINDOW_BITS,
+      client_max_window_bits: MAX_WINDOW_BITS,
+      level: None,
+    }
+  }
+}
+
+/// One `permessage-deflate` offer from the `Sec-WebSocket-Extensions` header of a client request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeflateOffer {
+  server_no_context_takeover: bool,
+  client_no_context_takeover: bool,
+  server_max_window_bits: Option<u8>,
+  /// `Some(None)` when the parameter is present without a value.
+  client_max_window_bits: Option<Option<u8>>,
+}
+
+impl DeflateOffer {
+  /// Whether the client asks the server to reset its compressor after every message.
+  pub fn server_no_context_takeover(&self) -> bool {
+    self.server_no_context_takeover
+  }
+
+  /// Whether the client announces that it resets its compressor after every message.
+  pub fn client_no_context_takeover(&self) -> bool {
+    self.client_no_context_takeover
+  }
+
+  /// The largest window the client allows the server to compress with, if it limits it.
+  pub fn server_max_window_bits(&self) -> Option<u8> {
+    self.server_max_window_bits
+  }
+
+  /// Whether the client allows the server to limit the window the client compresses with.
+  pub fn accepts_client_max_window_bits(&self) -> bool {
+    self.client_max_window_bits.is_some()
+  }
+
+  /// The window the client compresses with, if it announced one.
+  pub fn client_max_window_bits(&self) -> Option<u8> {
+    self.client_max_window_bits.flatten()
+  }
+
+  /// Negotiates this offer against a server policy. See [`DeflateConfig::accept`].
+  pub fn accept(&self, policy: &DeflateConfig) -> Option<DeflateConfig> {
+    let requested = self.server_max_window_bits.unwrap_or(MAX_WINDOW_BITS);
+    if requested < MIN_WINDOW_BITS {
+      return None;
+    }
+    let client_max_window_bits = match self.client_max_window_bits {
+      Some(bits) => policy
+        .client_max_window_bits
+        .min(bits.unwrap_or(MAX_WINDOW_BITS)),
+      None if policy.client_max_window_bits < MAX_WINDOW_BITS => return None,
+      None => MAX_WINDOW_BITS,
+    };
+
+    Some(DeflateConfig {
+      server_no_context_takeover: policy.server_no_context_takeover
+        || self.server_no_context_takeover,
+      client_no_context_takeover: policy.client_no_context_takeover
+        || self.client_no_context_takeover,
+      server_max_window_bits: policy
+        .server_max_window_bits
+        .clamp(MIN_WINDOW_BITS, MAX_WINDOW_BITS)
+        .min(requested),
+      client_max_window_bits,
+      level: policy.level,
+    })
+  }
+
+  /// Whether a server may answer this offer with `config` (RFC 7692 Section 7.1).
+  #[cfg(feature = "upgrade")]
+  pub(crate) fn permits(&self, config: &DeflateConfig) -> bool {
+    let window = MIN_WINDOW_BITS..=MAX_WINDOW_BITS;
+    // The server may only limit the client window if the client allows it.
+    let client_max_window_bits = match self.client_max_window_bits {
+      Some(bits) => bits.unwrap_or(MAX_WINDOW_BITS),
+      None if config.client_max_window_bits < MAX_WINDOW_BITS => return false,
+      None => MAX_WINDOW_BITS,
+    };
+    (config.server_no_context_takeover || !self.server_no_context_takeover)
+      && window.contains(&config.server_max_window_bits)
+      && config.server_max_window_bits
+        <= self.server_max_window_bits.unwrap_or(MAX_WINDOW_BITS)
+      && window.contains(&config.client_max_window_bits)
+      && config.client_max_window_bits <= client_max_window_bits
+  }
+}
+
+/// Parses the `permessage-deflate` offers of a `Sec-WebSocket-Extensions` header, skipping malformed ones.
+pub(crate) fn parse_offers(
+  header: &str,
+) -> impl Iterator<Item = DeflateOffer> + '_ {
+  header
+    .split(',')
+    .filter_map(parse_element)
+    .filter_map(Result::ok)
+}
+
+impl DeflateConfig {
+  /// The value of the `Sec-WebSocket-Extensions` header a client sends to offer these parameters.
+  pub fn offer(&self) -> String {
+    let mut offer = String::from(EXTENSION_NAME);
+    if self.server_no_context_takeover {
+      offer.push_str("; server_no_context_takeover");
+    }
+    if self.client_no_context_takeover {
+      offer.push_str("; client_no_context_takeover");
+    }
+    if self.server_max_window_bits < MAX_WINDOW_BITS {
+      offer.push_str(&format!(
+        "; server_max_window_bits={}",
+        self.server_max_window_bits
+      ));
+    }
+    // Let the server limit our window.
+    if self.client_max_window_bits < MAX_WINDOW_BITS {
+      offer.push_str(&format!(
+        "; client_max_window_bits={}",
+        self.client_max_window_bits
+      ));
+    } else {
+      offer.push_str("; client_max_window_bits");
+    }
+    offer
+  }
+
+  /// Negotiates the offers in a client's `Sec-WebSocket-Extensions` header against this server policy. Returns the
+  /// agreed parameters of the first acceptable offer, or `None` if compression should not be used.
+  pub fn accept(&self, offers: &str) -> Option<DeflateConfig> {
+    parse_offers(offers).find_map(|offer| offer.accept(self))
+  }
+
+  /// The value of the `Sec-WebSocket-Extensions` header a server sends to accept these parameters.
+  pub fn response(&self) -> String {
+    let mut response = String::from(EXTENSION_NAME);
+    if self.server_no_context_takeover {
+      response.push_str("; server_no_context_takeover");
+    }
+    if self.client_no_context_takeover {
+      response.push_str("; client_no_context_takeover");
+    }
+    if self.server_max_window_bits < MAX_WINDOW_BITS {
+      response.push_str(&format!(
+        "; server_max_window_bits={}",
+        self.server_max_window_bits
+      ));
+    }
+    if self.client_max_window_bits < MAX_WINDOW_BITS {
+      response.push_str(&format!(
+        "; client_max_window_bits={}",
+        self.client_max_window_bits
+      ));
+    }
+    response
+  }
+
+  /// Parses the `Sec-WebSocket-Extensions` header of a server response. Returns `None` if the server did not accept
+  /// compression.
+  pub fn from_response(
+    response: &str,
+  ) -> Result<Option<DeflateConfig>, WebSocketError> {
+    let mut elements = response.split(',').filter_map(parse_element);
+    let Some(params) = elements.next() else {
+      return Ok(None);
+    };
+    let params =
+      params.map_err(|_| WebSocketError::InvalidDeflateParameters)?;
+    if elements.next().is_some() {
+      return Err(WebSocketError::InvalidDeflateParameters);
+    }
+
+    let client_max_window_bits = match params.client_max_window_bits {
+      Some(Some(bits)) => bits,
+      Some(None) => return Err(WebSocketError::InvalidDeflateParameters),
+      None => MAX_WINDOW_BITS,
+    };
+    Ok(Some(DeflateConfig {
+      server_no_context_takeover: params.server_no_context_takeover,
+      client_no_context_takeover: params.client_no_context_takeover,
+      server_max_window_bits: params
+        .server_max_window_bits
+        .unwrap_or(MAX_WINDOW_BITS),
+      client_max_window_bits: client_max_window_bits.max(MIN_WINDOW_BITS),
+      level: None,
+    }))
+  }
+
+  /// Whether the end with `role` resets its compressor after every message, and the window it compresses with.
+  fn compressor(&self, role: Role) -> (bool, u8) {
+    match role {
+      Role::Server => {
+        (self.server_no_context_takeover, self.server_max_window_bits)
+      }
+      Role::Client => {
+        (self.client_no_context_takeover, self.client_max_window_bits)
+      }
+    }
+  }
+}
+
+/// Parses one extension element. Returns `None` for other extensions and `Some(Err(()))` for a malformed
+/// `permessage-deflate` element.
+fn parse_element(element: &str) -> Option<Result<DeflateOffer, ()>> {
+  let mut parts = element.split(';').map(str::trim);
+  if !parts.next()?.eq_ignore_ascii_case(EXTENSION_NAME) {
+    return None;
+  }
+
+  let mut params = DeflateOffer::default();
+  for part in parts {
+    let (name, value) = match part.split_once('=') {
+      Some((name, value)) => {
+        (name.trim(), Some(value.trim().trim_matches('"')))
+      }
+      None => (part, None),
+    };
+    let bits = |value: Option<&str>| -> Result<Option<u8>, ()> {
+      value
+        .map(|value| match value.parse() {
+          Ok(bits @ 8..=15) => Ok(bits),
+          _ => Err(()),
+        })
+        .transpose()
+    };
+    let duplicate = match name {
+      "server_no_context_takeover" if value.is_none() => {
+        std::mem::replace(&mut params.server_no_context_takeover, true)
+      }
+      "client_no_context_takeover" if value.is_none() => {
+        std::mem::replace(&mut params.client_no_context_takeover, true)
+      }
+      "server_max_window_bits" => {
+        let Ok(Some(bits)) = bits(value) else {
+          return Some(Err(()));
+        };
+        params.server_max_window_bits.replace(bits).is_some()
+      }
+      "client_max_window_bits" => {
+        let Ok(bits) = bits(value) else {
+          return Some(Err(()));
+        };
+        params.client_max_window_bits.replace(bits).is_some()
+      }
+      _ => return Some(Err(())),
+    };
+    if duplicate {
+      return Some(Err(()));
+    }
+  }
+  Some(Ok(params))
+}
+
+/// Compresses outgoing messages.
+pub(crate) struct Deflater {
+  compress: Compress,
+  window_bits: u8,
+  no_context_takeover: bool,
+  dictionary: Option<Arc<[u8]>>,
+}
+
+impl Deflater {
+  pub fn new(
+    config: &DeflateConfig,
+    role: Role,
+    level: u32,
+    dictionary: Option<Arc<[u8]>>,
+  ) -> Self {
+    let (no_context_takeover, window_bits) = config.compressor(role);
+    let window_bits = window_bits.clamp(MIN_WINDOW_BITS, MAX_WINDOW_BITS);
+    let level = config.level.unwrap_or(level).min(9);
+    let mut deflater = Self {
+      compress: Compress::new_with_window_bits(
+        Compression::new(level),
+        false,
+        window_bits,
+      ),
+      window_bits,
+      no_context_takeover,
+      dictionary,
+    };
+    deflater.reset();
+    deflater
+  }
+
+  /// Replaces the preset dictionary and starts a new compression context.
+  pub fn set_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    self.dictionary = dictionary;
+    self.reset();
+  }
+
+  fn reset(&mut self) {
+    self.compress.reset();
+    if let Some(dictionary) = &self.dictionary {
+      // Setting a dictionary on a raw deflate stream right after a reset cannot fail.
+      let _ = self.compress.set_dictionary(dictionary);
+    }
+  }
+
+  /// Changes the compression level, starting with the next frame.
+  pub fn set_level(&mut self, level: u32) {
+    // zlib can only switch between some levels in place. Otherwise start a new compressor, which is valid at any
+    // frame boundary since every frame ends with a flush, but gives up the data compressed so far as dictionary.
+    if self.compress.set_level(Compression::new(level)).is_err() {
+      self.compress = Compress::new_with_window_bits(
+        Compression::new(level),
+        false,
+        self.window_bits,
+      );
+    }
+  }
+
+  /// Compresses the payload of one frame. `fin` marks the last frame of the message.
+  pub fn compress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 64);
+    let start = self.compress.total_in();
+    loop {
+      let consumed = (self.compress.total_in() - start) as usize;
+      self
+        .compress
+        .compress_vec(&input[consumed..], &mut output, FlushCompress::Sync)
+        .map_err(std::io::Error::other)?;
+      // The flush is complete once all input is consumed and there was room left in the output.
+      let consumed = (self.compress.total_in() - start) as usize;
+      if consumed == input.len() && output.len() < output.capacity() {
+        break;
+      }
+      output.reserve(output.capacity().max(64));
+    }
+
+    if fin {
+      if output.ends_with(&TRAILER) {
+        output.truncate(output.len() - TRAILER.len());
+      }
+      if self.no_context_takeover {
+        self.reset();
+      }
+    }
+    Ok(output)
+  }
+}
+
+/// Decompresses incoming messages.
+pub(crate) struct Inflater {
+  decompress: Decompress,
+  no_context_takeover: bool,
+  dictionary: Option<Arc<[u8]>>,
+}
+
+impl Inflater {
+  pub fn new(
+    config: &DeflateConfig,
+    role: Role,
+    dictionary: Option<Arc<[u8]>>,
+  ) -> Self {
+    let peer = match role {
+      Role::Server => Role::Client,
+      Role::Client => Role::Server,
+    };
+    let (no_context_takeover, _) = config.compressor(peer);
+    let mut inflater = Self {
+      // A full window can inflate data compressed with any smaller window. zlib-rs allocates a full window
+      // regardless, so a smaller one would not bound memory either.
+      decompress: Decompress::new(false),
+      no_context_takeover,
+      dictionary,
+    };
+    inflater.reset();
+    inflater
+  }
+
+  /// Replaces the preset dictionary and starts a new decompression context.
+  pub fn set_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    self.dictionary = dictionary;
+    self.reset();
+  }
+
+  fn reset(&mut self) {
+    self.decompress.reset(false);
+    if let Some(dictionary) = &self.dictionary {
+      let _ = self.decompress.set_dictionary(dictionary);
+    }
+  }
+
+  /// Decompresses the payload of one frame. `fin` marks the last frame of the message. Fails with
+  /// `WebSocketError::FrameTooLarge` if the output would grow beyond `max_size` bytes.
+  ///
+  /// The decompression context is reset after an error, so a corrupt message cannot leave state behind for the next.
+  pub fn decompress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+    max_size: usize,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let mut output = Vec::with_capacity((input.len() * 2 + 64).min(max_size));
+    let mut result = self.feed(input, &mut output, max_size);
+    if fin && result.is_ok() {
+      result = self.feed(&TRAILER, &mut output, max_size);
+    }
+    if result.is_err() || (fin && self.no_context_takeover) {
+      self.reset();
+    }
+    result.map(|_| output)
+  }
+
+  fn feed(
+    &mut self,
+    input: &[u8],
+    output: &mut Vec<u8>,
+    max_size: usize,
+  ) -> Result<(), WebSocketError> {
+    let mut consumed = 0;
+    // Room for one byte past the limit tells a message that inflates to exactly `max_size` from a larger one.
+    let room = max_size.saturating_add(1);
+    loop {
+      if output.len() > max_size {
+        return Err(WebSocketError::FrameTooLarge);
+      }
+      if output.len() == output.capacity() {
+        let additional = output.capacity().max(64).min(room - output.len());
+        output.reserve_exact(additional);
+      }
+
+      let total_in = self.decompress.total_in();
+      let produced = output.len();
+      let status = self
+        .decompress
+        .decompress_vec(&input[consumed..], output, FlushDecompress::Sync)
+        .map_err(|_| WebSocketError::InvalidCompressedData)?;
+      // Tracked per call, since a reset zeroes the counter.
+      let read = (self.decompress.total_in() - total_in) as usize;
+      consumed += read;
+
+      if status == Status::StreamEnd {
+        // The peer finished the deflate stream with a final block. Anything after it starts a new stream.
+        self.reset();
+        if consumed == input.len() {
+          break;
+        }
+        continue;
+      }
+      if consumed == input.len() && output.len() < output.capacity() {
+        break;
+      }
+      if status == Status::BufError && output.len() < output.capacity() {
+        return Err(WebSocketError::InvalidCompressedData);
+      }
+      // Guards against spinning on input the decompressor neither consumes nor reports as invalid.
+      if read == 0 && output.len() == produced {
+        return Err(WebSocketError::InvalidCompressedData);
+      }
+    }
+    if output.len() > max_size {
+      return Err(WebSocketError::FrameTooLarge);
+    }
+    Ok(())
+  }
+}
+
+/// Payloads shorter than this are always compressed, since their entropy cannot be estimated reliably.
+const ENTROPY_MIN_SAMPLE: usize = 512;
+const ENTROPY_SAMPLE: usize = 4096;
+/// Already compressed data, such as JPEG images or gzip archives, is close to 8 bits of entropy per byte.
+const INCOMPRESSIBLE_BITS_PER_BYTE: f64 = 7.5;
+
+/// Estimates whether compressing `payload` is not worth it, from the byte entropy of its first few KiB.
+pub(crate) fn looks_incompressible(payload: &[u8]) -> bool {
+  if payload.len() < ENTROPY_MIN_SAMPLE {
+    return false;
+  }
+  let sample = &payload[..payload.len().min(ENTROPY_SAMPLE)];
+  let mut counts = [0u32; 256];
+  for &byte in sample {
+    counts[byte as usize] += 1;
+  }
+  let len = sample.len() as f64;
+  let entropy: f64 = counts
+    .iter()
+    .filter(|&&count| count > 0)
+    .map(|&count| {
+      let p = count as f64 / len;
+      -p * p.log2()
+    })
+    .sum();
+  entropy > INCOMPRESSIBLE_BITS_PER_BYTE
+}
+
+/// Compresses outgoing messages with the extension negotiated for the connection.
+pub(crate) enum Compressor {
+  Deflate(Deflater),
+  #[cfg(feature = "brotli")]
+  Brotli(Box<BrotliEncoder>),
+  #[cfg(feature = "zstd")]
+  Zstd(ZstdEncoder),
+}
+
+impl Compressor {
+  pub fn compress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    match self {
+      Self::Deflate(deflater) => deflater.compress(input, fin),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(encoder) => encoder.compress(input, fin),
+      #[cfg(feature = "zstd")]
+      Self::Zstd(encoder) => encoder.compress(input, fin),
+    }
+  }
+
+  /// Only applies to permessage-deflate.
+  pub fn set_level(&mut self, level: u32) {
+    match self {
+      Self::Deflate(deflater) => deflater.set_level(level),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(_) => {}
+      #[cfg(feature = "zstd")]
+      Self::Zstd(_) => {}
+    }
+  }
+
+  /// Only applies to permessage-deflate.
+  pub fn set_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    match self {
+      Self::Deflate(deflater) => deflater.set_dictionary(dictionary),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(_) => {}
+      #[cfg(feature = "zstd")]
+      Self::Zstd(_) => {}
+    }
+  }
+}
+
+/// Decompresses incoming messages with the extension negotiated for the connection.
+pub(crate) enum Decompressor {
+  Deflate(Inflater),
+  #[cfg(feature = "brotli")]
+  Brotli(Box<BrotliDecoder>),
+  #[cfg(feature = "zstd")]
+  Zstd(ZstdDecoder),
+}
+
+impl Decompressor {
+  pub fn decompress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+    max_size: usize,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    match self {
+      Self::Deflate(inflater) => inflater.decompress(input, fin, max_size),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(decoder) => decoder.decompress(input, fin, max_size),
+      #[cfg(feature = "zstd")]
+      Self::Zstd(decoder) => decoder.decompress(input, fin, max_size),
+    }
+  }
+
+  /// Only applies to permessage-deflate.
+  pub fn set_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    match self {
+      Self::Deflate(inflater) => inflater.set_dictionary(dictionary),
+      #[cfg(feature = "brotli")]
+      Self::Brotli(_) => {}
+      #[cfg(feature = "zstd")]
+      Self::Zstd(_) => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiation() {
+    let policy = DeflateConfig::default();
+    let agreed = policy
+      .accept(
+        "x-webkit-deflate-frame, permessage-deflate; client_max_window_bits",
+      )
+      .unwrap();
+    assert_eq!(agreed, DeflateConfig::default());
+    assert_eq!(agreed.response(), "permessage-deflate");
+
+    // Malformed offers are skipped in favour of the next one.
+    let agreed = policy
+      .accept("permessage-deflate; foo, permessage-deflate; server_max_window_bits=10")
+      .unwrap();
+    assert_eq!(agreed.server_max_window_bits, 10);
+    assert_eq!(
+      agreed.response(),
+      "permessage-deflate; server_max_window_bits=10"
+    );
+    assert!(policy
+      .accept("permessage-deflate; server_max_window_bits=8")
+      .is_none());
+    assert!(policy.accept("permessage-deflate; client_no_context_takeover; client_no_context_takeover").is_none());
+
+    // The server can require no_context_takeover, but can only limit the client window if the client allows it.
+    let policy = DeflateConfig {
+      client_no_context_takeover: true,
+      client_max_window_bits: 12,
+      ..Default::default()
+    };
+    assert!(policy.accept("permessage-deflate").is_none());
+    let agreed = policy
+      .accept("permessage-deflate; client_max_window_bits")
+      .unwrap();
+    assert_eq!(
+      agreed.response(),
+      "permessage-deflate; client_no_context_takeover; client_max_window_bits=12"
+    );
+
+    assert_eq!(
+      DeflateConfig::from_response(&agreed.response()).unwrap(),
+      Some(agreed)
+    );
+    assert_eq!(DeflateConfig::from_response("").unwrap(), None);
+    assert!(DeflateConfig::from_response(
+      "permessage-deflate; client_max_window_bits"
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn context_takeover() {
+    // RFC 7692 Section 7.2.3.2
+    let config = DeflateConfig::default();
+    let mut deflater = Deflater::new(&config, Role::Server, 6, None);
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    let first = deflater.compress(b"Hello", true).unwrap();
+    assert_eq!(first, [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00]);
+    let second = deflater.compress(b"Hello", true).unwrap();
+    assert_eq!(second, [0xf2, 0x00, 0x11, 0x00, 0x00]);
+    assert_eq!(inflater.decompress(&first, true, 1024).unwrap(), b"Hello");
+    assert_eq!(inflater.decompress(&second, true, 1024).unwrap(), b"Hello");
+
+    let config = DeflateConfig {
+      server_no_context_takeover: true,
+      ..Default::default()
+    };
+    let mut deflater = Deflater::new(&config, Role::Server, 6, None);
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    for _ in 0..2 {
+      let message = deflater.compress(b"Hello", true).unwrap();
+      assert_eq!(message, [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00]);
+      assert_eq!(inflater.decompress(&message, true, 1024).unwrap(), b"Hello");
+    }
+  }
+
+  #[test]
+  fn decompression_is_bounded() {
+    let config = DeflateConfig::default();
+    let mut deflater = Deflater::new(&config, Role::Client, 6, None);
+    let mut inflater = Inflater::new(&config, Role::Server, None);
+    let message = deflater.compress(&[0; 4096], true).unwrap();
+    assert!(matches!(
+      inflater.decompress(&message, true, 1024),
+      Err(WebSocketError::FrameTooLarge)
+    ));
+
+    // A message that inflates to exactly the limit is accepted, one byte more is not.
+    let inflate = |len, max_size| {
+      let mut deflater = Deflater::new(&config, Role::Client, 6, None);
+      let mut inflater = Inflater::new(&config, Role::Server, None);
+      let message = deflater.compress(&vec![0; len], true).unwrap();
+      inflater.decompress(&message, true, max_size)
+    };
+    assert_eq!(inflate(1024, 1024).unwrap().len(), 1024);
+    assert!(matches!(
+      inflate(1025, 1024),
+      Err(WebSocketError::FrameTooLarge)
+    ));
+    assert!(inflate(0, 0).unwrap().is_empty());
+  }
+
+  #[test]
+  fn malformed_input() {
+    let config = DeflateConfig::default();
+    let text = "telemetry ".repeat(100);
+
+    // Back-references to history the decompressor has not seen.
+    let mut deflater = Deflater::new(&config, Role::Server, 6, None);
+    deflater.compress(text.as_bytes(), true).unwrap();
+    let second = deflater.compress(text.as_bytes(), true).unwrap();
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    assert!(matches!(
+      inflater.decompress(&second, true, 4096),
+      Err(WebSocketError::InvalidCompressedData)
+    ));
+
+    // Truncated streams cannot be told apart from short messages, but must not panic.
+    let mut deflater = Deflater::new(&config, Role::Server, 6, None);
+    let message = deflater.compress(text.as_bytes(), true).unwrap();
+    for len in 0..message.len() {
+      let mut inflater = Inflater::new(&config, Role::Client, None);
+      let _ = inflater.decompress(&message[..len], true, 4096);
+    }
+
+    // Invalid blocks fail and reset the context, so the next message is read from a clean state.
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    for garbage in [&[0xff; 16][..], &[0x07, 0x00]] {
+      assert!(matches!(
+        inflater.decompress(garbage, true, 4096),
+        Err(WebSocketError::InvalidCompressedData)
+      ));
+      let output = inflater.decompress(&message, true, 4096).unwrap();
+      assert_eq!(output, text.as_bytes());
+    }
+  }
+
+  #[test]
+  fn compression_level() {
+    let config = DeflateConfig::default();
+    let input = "telemetry ".repeat(100);
+    let mut deflater = Deflater::new(&config, Role::Server, 0, None);
+    let stored = deflater.compress(input.as_bytes(), true).unwrap();
+    assert!(stored.len() > input.len());
+    deflater.set_level(9);
+    let compressed = deflater.compress(input.as_bytes(), true).unwrap();
+    assert!(compressed.len() < input.len() / 10);
+
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    for message in [stored, compressed] {
+      let output = inflater.decompress(&message, true, usize::MAX).unwrap();
+      assert_eq!(output, input.as_bytes());
+    }
+
+    // The level of the config takes precedence, and a server policy passes it on to the agreed parameters.
+    let policy = DeflateConfig {
+      level: Some(0),
+      ..Default::default()
+    };
+    let agreed = policy.accept("permessage-deflate").unwrap();
+    assert_eq!(agreed.level, Some(0));
+    let mut deflater = Deflater::new(&agreed, Role::Server, 9, None);
+    let message = deflater.compress(input.as_bytes(), true).unwrap();
+    assert!(message.len() > input.len());
+  }
+
+  #[test]
+  fn preset_dictionary() {
+    let dictionary: Arc<[u8]> =
+      Arc::from(&br#"{"type":"telemetry","device":"","value":}"#[..]);
+    let message = br#"{"type":"telemetry","device":"a1","value":42}"#;
+
+    for no_context_takeover in [false, true] {
+      let config = DeflateConfig {
+        server_no_context_takeover: no_context_takeover,
+        ..Default::default()
+      };
+      let mut plain = Deflater::new(&config, Role::Server, 6, None);
+      let mut deflater =
+        Deflater::new(&config, Role::Server, 6, Some(dictionary.clone()));
+      let mut inflater =
+        Inflater::new(&config, Role::Client, Some(dictionary.clone()));
+
+      let first = deflater.compress(message, true).unwrap();
+      assert!(first.len() < plain.compress(message, true).unwrap().len() / 2);
+      for compressed in [first, deflater.compress(message, true).unwrap()] {
+        let output = inflater.decompress(&compressed, true, 1024).unwrap();
+        assert_eq!(output, message);
+      }
+    }
+
+    // A decompressor without the dictionary cannot resolve references into it.
+    let config = DeflateConfig::default();
+    let mut deflater =
+      Deflater::new(&config, Role::Server, 6, Some(dictionary));
+    let mut inflater = Inflater::new(&config, Role::Client, None);
+    let compressed = deflater.compress(message, true).unwrap();
+    assert!(matches!(
+      inflater.decompress(&compressed, true, 1024),
+      Err(WebSocketError::InvalidCompressedData)
+    ));
+  }
+
+  #[tokio::test]
+  async fn incompressible_payloads() {
+    use crate::Frame;
+    use crate::WebSocket;
+
+    let text = r#"{"type":"telemetry","value":42}"#.repeat(100);
+    assert!(!looks_incompressible(text.as_bytes()));
+    let random: Vec<u8> = (0..4096).map(|_| rand::random()).collect();
+    assert!(looks_incompressible(&random));
+    assert!(!looks_incompressible(&random[..256]));
+
+    // The peer has not enabled compression, so it would reject a frame with RSV1 set.
+    let (client, server) = tokio::io::duplex(8192);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(DeflateConfig::default()));
+    client
+      .write_frame(Frame::binary(random.as_slice().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, random.as_slice());
+  }
+
+  #[tokio::test]
+  async fn compression_disabled() {
+    use crate::Frame;
+    use crate::WebSocket;
+
+    // The peer has not enabled compression, so it would reject a frame with RSV1 set.
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(DeflateConfig::default()));
+    assert!(client.compression_enabled());
+    client.set_compression_enabled(false);
+
+    let text = "hello ".repeat(100);
+    client
+      .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some(text.as_str()));
+  }
+
+  #[tokio::test]
+  async fn compression_by_opcode() {
+    use crate::Frame;
+    use crate::WebSocket;
+
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(DeflateConfig::default()));
+    client.set_compress_binary(false);
+
+    // The peer has not enabled compression, so it would reject a frame with RSV1 set.
+    let data = "hello ".repeat(100);
+    client
+      .write_frame(Frame::binary(data.as_bytes().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, data.as_bytes());
+
+    client
+      .write_frame(Frame::text(data.as_bytes().to_vec().into()))
+      .await
+      .unwrap();
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::ReservedBitsNotZero)
+    ));
+  }
+
+  #[tokio::test]
+  async fn fragmented_message_is_bounded() {
+    use crate::Frame;
+    use crate::OpCode;
+    use crate::WebSocket;
+
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(DeflateConfig::default()));
+    server.set_deflate(Some(DeflateConfig::default()));
+    server.set_max_message_size(1024);
+
+    // Every frame is small, but together they decompress beyond the limit.
+    client
+      .write_frame(Frame::new(false, OpCode::Binary, None, vec![0; 600].into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        vec![0; 600].into(),
+      ))
+      .await
+      .unwrap();
+    server.read_frame().await.unwrap();
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::FrameTooLarge)
+    ));
+  }
+
+  #[tokio::test]
+  async fn compressed_messages() {
+    use crate::Frame;
+    use crate::OpCode;
+    use crate::WebSocket;
+
+    let config = DeflateConfig {
+      client_no_context_takeover: true,
+      ..Default::default()
+    };
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(config));
+    server.set_deflate(Some(config));
+
+    let text = "hello ".repeat(100);
+    for _ in 0..2 {
+      client
+        .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+        .await
+        .unwrap();
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(frame.as_text(), Some(text.as_str()));
+    }
+
+    // A fragmented message is compressed as a whole, only the first frame carries RSV1.
+    server
+      .write_frame(Frame::new(
+        false,
+        OpCode::Text,
+        None,
+        b"abc".to_vec().into(),
+      ))
+      .await
+      .unwrap();
+    server
+      .write_frame(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        b"abc".to_vec().into(),
+      ))
+      .await
+      .unwrap();
+    let first = client.read_frame().await.unwrap();
+    assert_eq!((first.fin, &*first.payload), (false, &b"abc"[..]));
+    let second = client.read_frame().await.unwrap();
+    assert_eq!((second.fin, &*second.payload), (true, &b"abc"[..]));
+  }
+
+  #[tokio::test]
+  async fn passthrough_forwards_compressed_frames() {
+    use crate::Frame;
+    use crate::WebSocket;
+
+    let (a, relay_in) = tokio::io::duplex(4096);
+    let (relay_out, b) = tokio::io::duplex(4096);
+    let mut a = WebSocket::after_handshake(a, Role::Client);
+    let mut relay_in = WebSocket::after_handshake(relay_in, Role::Server);
+    let mut relay_out = WebSocket::after_handshake(relay_out, Role::Client);
+    let mut b = WebSocket::after_handshake(b, Role::Server);
+    for ws in [&mut a, &mut b] {
+      ws.set_deflate(Some(DeflateConfig::default()));
+    }
+    for ws in [&mut relay_in, &mut relay_out] {
+      ws.set_deflate(Some(DeflateConfig::default()));
+      ws.set_compression_passthrough(true);
+    }
+
+    // Twice, as the second message refers back to the first one.
+    let text = "hello ".repeat(100);
+    for _ in 0..2 {
+      a.write_frame(Frame::text(text.as_bytes().to_vec().into()))
+        .await
+        .unwrap();
+      let frame = relay_in.read_frame().await.unwrap();
+      assert!(frame.is_compressed());
+      assert!(frame.payload.len() < text.len());
+      relay_out.write_frame(frame).await.unwrap();
+
+      let frame = b.read_frame().await.unwrap();
+      assert!(!frame.is_compressed());
+      assert_eq!(frame.as_text(), Some(text.as_str()));
+    }
+
+    // Messages written by the relay itself are sent uncompressed.
+    relay_out
+      .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+      .await
+      .unwrap();
+    let frame = b.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some(text.as_str()));
+  }
+
+  #[tokio::test]
+  async fn small_and_opted_out_messages_are_not_compressed() {
+    use crate::Frame;
+    use crate::WebSocket;
+
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_deflate(Some(DeflateConfig::default()));
+    server.set_deflate(Some(DeflateConfig::default()));
+    client.set_min_compress_size(100);
+    // Leaves the payloads as they were sent, so that `is_compressed` shows what the client did.
+    server.set_compression_passthrough(true);
+
+    let text = "hello ".repeat(100);
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    assert!(!server.read_frame().await.unwrap().is_compressed());
+
+    client
+      .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+      .await
+      .unwrap();
+    assert!(server.read_frame().await.unwrap().is_compressed());
+
+    let mut frame = Frame::text(text.as_bytes().to_vec().into());
+    frame.set_compressed(false);
+    client.write_frame(frame).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert!(!frame.is_compressed());
+    assert_eq!(frame.as_text(), Some(text.as_str()));
+  }
+}
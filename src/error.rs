@@ -28,19 +28,113 @@ pub enum WebSocketError {
   ControlFrameFragmented,
   #[error("Ping frame too large")]
   PingFrameTooLarge,
+  #[error("Control frame too large")]
+  ControlFrameTooLarge,
   #[error("Frame too large")]
   FrameTooLarge,
+  #[error("Frame from client is not masked")]
+  UnmaskedFrame,
+  #[error("Memory limit exceeded")]
+  MemoryLimitExceeded,
+  #[error("Connection memory budget exceeded")]
+  ConnectionMemoryExceeded,
+  #[error("Too many fragments in a message")]
+  TooManyFragments,
+  #[error("Fragmented message too large")]
+  FragmentedMessageTooLarge,
+  #[error("Frame rejected by policy: {0}")]
+  FrameRejected(crate::CloseCode),
   #[error("Sec-Websocket-Version must be 13")]
   InvalidSecWebsocketVersion,
   #[error("Invalid value")]
   InvalidValue,
   #[error("Sec-WebSocket-Key header is missing")]
   MissingSecWebSocketKey,
+  #[error("Invalid extension parameters")]
+  InvalidExtensionParameters,
+  #[cfg(feature = "upgrade")]
+  #[error("Invalid PROXY protocol header")]
+  InvalidProxyHeader,
+  #[cfg(feature = "upgrade")]
+  #[error("Unexpected Sec-WebSocket-Protocol in the server response")]
+  UnexpectedSubprotocol,
+  #[cfg(feature = "upgrade")]
+  #[error("Required subprotocol was not negotiated")]
+  MissingSubprotocol,
+  #[cfg(feature = "upgrade")]
+  #[error("Selected subprotocol was not offered by the client")]
+  InvalidSubprotocol,
+  #[cfg(feature = "upgrade")]
+  #[error("Origin is not allowed")]
+  OriginNotAllowed,
+  #[cfg(feature = "upgrade")]
+  #[error("Sec-WebSocket-Accept does not match the key")]
+  InvalidSecWebSocketAccept,
+  #[cfg(feature = "upgrade")]
+  #[error("Handshake response rejected: {0}")]
+  HandshakeRejected(String),
+  #[cfg(feature = "upgrade")]
+  #[error("Invalid SOCKS5 proxy response")]
+  InvalidSocks5Response,
+  #[cfg(feature = "upgrade")]
+  #[error("SOCKS5 proxy authentication failed")]
+  Socks5AuthenticationFailed,
+  #[cfg(feature = "upgrade")]
+  #[error("SOCKS5 proxy could not connect: reply code {0}")]
+  Socks5ConnectFailed(u8),
+  #[cfg(feature = "upgrade")]
+  #[error("Invalid or unsupported websocket URL")]
+  InvalidUrl,
+  #[cfg(feature = "connect")]
+  #[error("Too many redirects")]
+  TooManyRedirects,
+  #[cfg(feature = "connect")]
+  #[error("Redirect to another origin")]
+  CrossOriginRedirect,
+  #[cfg(any(feature = "tls-rustls", feature = "tls-native"))]
+  #[error("Invalid TLS server name")]
+  InvalidServerName,
+  #[cfg(feature = "deflate")]
+  #[error("Invalid permessage-deflate parameters")]
+  InvalidDeflateParameters,
+  #[cfg(feature = "deflate")]
+  #[error("Invalid compressed data")]
+  InvalidCompressedData,
+  #[cfg(feature = "brotli")]
+  #[error("Unexpected permessage-brotli extension in the server response")]
+  UnexpectedBrotliExtension,
+  #[cfg(feature = "zstd")]
+  #[error("Invalid permessage-zstd parameters")]
+  InvalidZstdParameters,
+  #[cfg(feature = "keepalive")]
+  #[error("No pong received before the keepalive timeout")]
+  KeepAliveTimeout,
+  #[cfg(feature = "keepalive")]
+  #[error("No frame received before the read timeout")]
+  ReadTimeout,
+  #[cfg(feature = "keepalive")]
+  #[error("Write did not complete before the write timeout")]
+  WriteTimeout,
+  #[cfg(feature = "keepalive")]
+  #[error("No close frame received before the close timeout")]
+  CloseTimeout,
+  #[cfg(feature = "wasm")]
+  #[error("Browser WebSocket error: {0}")]
+  BrowserError(String),
+  #[cfg(feature = "wasm")]
+  #[error("Browsers do not let applications send ping or pong messages")]
+  BrowserControlMessage,
+  #[cfg(feature = "testing")]
+  #[error("Mock server expectation failed: {0}")]
+  MockExpectationFailed(String),
   #[error(transparent)]
   IoError(#[from] std::io::Error),
   #[cfg(feature = "upgrade")]
   #[error(transparent)]
   HTTPError(#[from] hyper::Error),
+  #[cfg(feature = "tls-native")]
+  #[error(transparent)]
+  NativeTlsError(#[from] native_tls::Error),
   #[cfg(feature = "unstable-split")]
   #[error("Failed to send frame")]
   SendError(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
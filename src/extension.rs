@@ -0,0 +1,170 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use crate::Frame;
+use crate::WebSocketError;
+
+/// A custom websocket extension (RFC 6455 Section 9), negotiated in the `Sec-WebSocket-Extensions` header and given
+/// every frame of the connection.
+///
+/// An extension claims RSV bits with [`Extension::reserved_bits`], so that incoming frames with those bits set are
+/// not rejected with `WebSocketError::ReservedBitsNotZero`. It must not claim RSV1 if compression is negotiated too.
+///
+/// The methods take `&self`, since the extension of a connection is shared by its read and write halves, so an
+/// extension that keeps state per connection uses interior mutability and a new instance for every connection.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{Extension, Frame, OpCode, WebSocketError};
+///
+/// /// Marks data frames whose payload is reversed with RSV2.
+/// struct Reverse;
+///
+/// impl Extension for Reverse {
+///   fn name(&self) -> &str {
+///     "x-reverse"
+///   }
+///
+///   fn reserved_bits(&self) -> u8 {
+///     0x20
+///   }
+///
+///   fn encode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError> {
+///     if matches!(frame.opcode, OpCode::Text | OpCode::Binary | OpCode::Continuation) {
+///       frame.payload.to_mut().reverse();
+///       frame.set_rsv_bits(frame.rsv_bits() | 0x20);
+///     }
+///     Ok(())
+///   }
+///
+///   fn decode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError> {
+///     if frame.rsv_bits() & 0x20 != 0 {
+///       frame.payload.to_mut().reverse();
+///       frame.set_rsv_bits(frame.rsv_bits() & !0x20);
+///     }
+///     Ok(())
+///   }
+/// }
+/// ```
+pub trait Extension: Send + Sync {
+  /// The name the extension is negotiated under.
+  fn name(&self) -> &str;
+
+  /// The RSV bits the extension uses, in their positions in the first byte of the frame header: `0x40` for RSV1,
+  /// `0x20` for RSV2 and `0x10` for RSV3.
+  fn reserved_bits(&self) -> u8;
+
+  /// The parameters a client offers the extension with, such as `max_size=10`, or an empty string for none.
+  fn offer(&self) -> String {
+    String::new()
+  }
+
+  /// Chooses among a client's offers of the extension on a server. `offers` holds the parameters of each offer, in the
+  /// client's order of preference. Returns the parameters to accept the extension with, or `None` to decline it.
+  ///
+  /// By default the first offer is accepted without parameters.
+  fn accept(&self, offers: &[&str]) -> Option<String> {
+    offers.first().map(|_| String::new())
+  }
+
+  /// Configures the extension with the parameters a server accepted it with, on a client. Fails the handshake if
+  /// they are not acceptable, with `WebSocketError::InvalidExtensionParameters` for example.
+  fn configure(&self, params: &str) -> Result<(), WebSocketError> {
+    let _ = params;
+    Ok(())
+  }
+
+  /// Transforms an outgoing frame before it is masked and written, after it has been compressed.
+  fn encode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError>;
+
+  /// Transforms an incoming frame once it has been unmasked, before it is decompressed. The extension clears the
+  /// RSV bits it handled, so that they are not mistaken for compression.
+  fn decode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError>;
+}
+
+impl fmt::Debug for dyn Extension {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Extension")
+      .field("name", &self.name())
+      .finish_non_exhaustive()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use crate::Role;
+  use crate::WebSocket;
+  use std::sync::Arc;
+
+  struct Reverse;
+
+  impl Extension for Reverse {
+    fn name(&self) -> &str {
+      "x-reverse"
+    }
+
+    fn reserved_bits(&self) -> u8 {
+      0x20
+    }
+
+    fn encode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError> {
+      if matches!(frame.opcode, OpCode::Text | OpCode::Binary) {
+        frame.payload.to_mut().reverse();
+        frame.set_rsv_bits(frame.rsv_bits() | 0x20);
+      }
+      Ok(())
+    }
+
+    fn decode(&self, frame: &mut Frame<'_>) -> Result<(), WebSocketError> {
+      if frame.rsv_bits() & 0x20 != 0 {
+        frame.payload.to_mut().reverse();
+        frame.set_rsv_bits(frame.rsv_bits() & !0x20);
+      }
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn frames_pass_through_the_extension() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_extension(Some(Arc::new(Reverse)));
+    server.set_extension(Some(Arc::new(Reverse)));
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    assert_eq!(frame.rsv_bits(), 0);
+
+    // Without the extension, RSV2 is a protocol error.
+    server.set_extension(None);
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::ReservedBitsNotZero)
+    ));
+  }
+}
@@ -0,0 +1,218 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::future::Future;
+use std::pin::pin;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocketError;
+use crate::WebSocketRead;
+use crate::WebSocketWrite;
+
+/// Relays frames from `from` to `to` until a close frame has been forwarded, as the building block of a websocket
+/// reverse proxy.
+///
+/// Frames are forwarded one by one as they arrive, without collecting fragmented messages. The masking key chosen by
+/// the peer of `from` is dropped, so when `to` is a client the payload is masked again in place with a fresh random
+/// key, as RFC 6455 Section 10.3 requires, rather than one the peer could choose.
+///
+/// Automatic pongs and close replies are disabled on `from`, so pings, pongs and close frames are forwarded as well
+/// and the endpoints answer each other's pings and carry out the close handshake themselves. To relay compressed
+/// messages without decompressing them, enable `set_compression_passthrough` on both connections.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{forward_bidirectional, WebSocket, WebSocketError};
+/// use tokio::net::TcpStream;
+///
+/// async fn proxy(
+///   client: WebSocket<TcpStream>,
+///   upstream: WebSocket<TcpStream>,
+/// ) -> Result<(), WebSocketError> {
+///   let (mut client_rx, mut client_tx) = client.split(tokio::io::split);
+///   let (mut upstream_rx, mut upstream_tx) = upstream.split(tokio::io::split);
+///   forward_bidirectional(
+///     (&mut client_rx, &mut client_tx),
+///     (&mut upstream_rx, &mut upstream_tx),
+///   )
+///   .await
+/// }
+/// ```
+pub async fn forward<R, W>(
+  from: &mut WebSocketRead<R>,
+  to: &mut WebSocketWrite<W>,
+) -> Result<(), WebSocketError>
+where
+  R: AsyncRead + Unpin,
+  W: AsyncWrite + Unpin,
+{
+  from.set_auto_pong(false);
+  from.set_auto_close(false);
+  loop {
+    let frame = from.read_frame_queued().await?;
+    let rsv = frame.rsv_bits();
+    let mut frame = Frame::new(frame.fin, frame.opcode, None, frame.payload);
+    frame.set_rsv_bits(rsv);
+    let close = frame.opcode == OpCode::Close;
+    to.write_frame(frame).await?;
+    if close {
+      return Ok(());
+    }
+  }
+}
+
+/// Relays frames in both directions between two connections with [`forward`], until close frames have been
+/// forwarded both ways or either direction fails.
+pub async fn forward_bidirectional<RA, WA, RB, WB>(
+  a: (&mut WebSocketRead<RA>, &mut WebSocketWrite<WA>),
+  b: (&mut WebSocketRead<RB>, &mut WebSocketWrite<WB>),
+) -> Result<(), WebSocketError>
+where
+  RA: AsyncRead + Unpin,
+  WA: AsyncWrite + Unpin,
+  RB: AsyncRead + Unpin,
+  WB: AsyncWrite + Unpin,
+{
+  let mut a_to_b = pin!(forward(a.0, b.1));
+  let mut b_to_a = pin!(forward(b.0, a.1));
+  let (mut a_done, mut b_done) = (false, false);
+  poll_fn(|cx| {
+    if !a_done {
+      if let Poll::Ready(res) = a_to_b.as_mut().poll(cx) {
+        res?;
+        a_done = true;
+      }
+    }
+    if !b_done {
+      if let Poll::Ready(res) = b_to_a.as_mut().poll(cx) {
+        res?;
+        b_done = true;
+      }
+    }
+    if a_done && b_done {
+      Poll::Ready(Ok(()))
+    } else {
+      Poll::Pending
+    }
+  })
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+  use crate::after_handshake_split;
+  use crate::Role;
+
+  type Half<S> = (
+    WebSocketRead<tokio::io::ReadHalf<S>>,
+    WebSocketWrite<tokio::io::WriteHalf<S>>,
+  );
+
+  fn pair(
+    role: Role,
+  ) -> (Half<tokio::io::DuplexStream>, tokio::io::DuplexStream) {
+    let (local, remote) = tokio::io::duplex(4096);
+    let (r, w) = tokio::io::split(local);
+    (after_handshake_split(r, w, role), remote)
+  }
+
+  #[tokio::test]
+  async fn relays_messages_pings_and_close() {
+    // The proxy is the server of the downstream client and the client of the upstream server.
+    let ((mut down_rx, mut down_tx), client) = pair(Role::Server);
+    let ((mut up_rx, mut up_tx), server) = pair(Role::Client);
+    let relay = tokio::spawn(async move {
+      forward_bidirectional(
+        (&mut down_rx, &mut down_tx),
+        (&mut up_rx, &mut up_tx),
+      )
+      .await
+    });
+
+    let mut client = crate::WebSocket::after_handshake(client, Role::Client);
+    let mut server = crate::WebSocket::after_handshake(server, Role::Server);
+    client.set_auto_pong(false);
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    // The upstream server answers the ping, not the proxy.
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"after".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("after"));
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!((frame.opcode, &*frame.payload), (OpCode::Pong, &b"hi"[..]));
+
+    server
+      .write_frame(Frame::binary(vec![7; 1000].into()))
+      .await
+      .unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, &[7; 1000][..]);
+
+    // The close handshake passes through in both directions and ends the relay.
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    server.flush().await.unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    relay.await.unwrap().unwrap();
+  }
+
+  #[tokio::test]
+  async fn upstream_frames_get_a_fresh_masking_key() {
+    let ((mut down_rx, _down_tx), client) = pair(Role::Server);
+    let ((_up_rx, mut up_tx), mut upstream) = pair(Role::Client);
+    let relay =
+      tokio::spawn(async move { forward(&mut down_rx, &mut up_tx).await });
+
+    let mut client = crate::WebSocket::after_handshake(client, Role::Client);
+    let key = [1, 2, 3, 4];
+    let frame = Frame::text(b"hello".to_vec().into()).with_mask_key(key);
+    client.write_frame(frame).await.unwrap();
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    relay.await.unwrap().unwrap();
+
+    let mut head = [0; 11];
+    upstream.read_exact(&mut head).await.unwrap();
+    assert_eq!(head[..2], [0x81, 0x80 | 5]);
+    let mask = [head[2], head[3], head[4], head[5]];
+    assert_ne!(mask, key);
+    let mut payload = head[6..].to_vec();
+    crate::unmask(&mut payload, mask);
+    assert_eq!(payload, b"hello");
+  }
+}
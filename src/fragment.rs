@@ -14,30 +14,49 @@
 
 #[cfg(feature = "unstable-split")]
 use std::future::Future;
+#[cfg(feature = "spill")]
+use std::path::Path;
+#[cfg(feature = "spill")]
+use std::path::PathBuf;
 
 use crate::error::WebSocketError;
 use crate::frame::Frame;
+use crate::frame::FrameHeader;
+use crate::limit::MemoryPermit;
+use crate::spill::Collected;
+#[cfg(feature = "spill")]
+use crate::spill::SpillConfig;
+#[cfg(feature = "spill")]
+use crate::spill::SpillFile;
+use crate::CloseCode;
+use crate::FrameInfo;
+use crate::MemoryLimiter;
+use crate::Message;
 use crate::OpCode;
-use crate::ReadHalf;
 use crate::WebSocket;
 #[cfg(feature = "unstable-split")]
 use crate::WebSocketRead;
-use crate::WriteHalf;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 
-pub enum Fragment {
-  Text(Option<utf8::Incomplete>, Vec<u8>),
-  Binary(Vec<u8>),
+/// Storage for the payload of a message that is being collected.
+pub enum Buffer {
+  Memory(Vec<u8>),
+  #[cfg(feature = "spill")]
+  Spilled(SpillFile),
 }
 
-impl Fragment {
-  /// Returns the payload of the fragment.
-  fn take_buffer(self) -> Vec<u8> {
+impl Buffer {
+  async fn extend_from_slice(
+    &mut self,
+    data: &[u8],
+  ) -> Result<(), WebSocketError> {
     match self {
-      Fragment::Text(_, buffer) => buffer,
-      Fragment::Binary(buffer) => buffer,
+      Buffer::Memory(buffer) => buffer.extend_from_slice(data),
+      #[cfg(feature = "spill")]
+      Buffer::Spilled(file) => file.write_all(data).await?,
     }
+    Ok(())
   }
 }
 
@@ -75,9 +94,7 @@ impl Fragment {
 /// ```
 ///
 pub struct FragmentCollector<S> {
-  stream: S,
-  read_half: ReadHalf,
-  write_half: WriteHalf,
+  ws: WebSocket<S>,
   fragments: Fragments,
 }
 
@@ -87,26 +104,302 @@ impl<'f, S> FragmentCollector<S> {
   where
     S: AsyncRead + AsyncWrite + Unpin,
   {
-    let (stream, read_half, write_half) = ws.into_parts_internal();
-    FragmentCollector {
-      stream,
-      read_half,
-      write_half,
-      fragments: Fragments::new(),
-    }
+    let fragments = Fragments::new(
+      ws.read_half.memory_limiter.clone(),
+      ws.read_half.connection_memory.clone(),
+    );
+    FragmentCollector { ws, fragments }
   }
 
   /// Reads a WebSocket frame, collecting fragmented messages until the final frame is received and returns the completed message.
   ///
   /// Text frames payload is guaranteed to be valid UTF-8.
   pub async fn read_frame(&mut self) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    match self.collect_message(false).await? {
+      Collected::Frame(frame) => Ok(frame),
+      #[cfg(feature = "spill")]
+      Collected::Spilled(_) => unreachable!(),
+    }
+  }
+
+  /// Like `read_frame`, but fragmented messages that grow beyond the spill threshold are written to a temporary file
+  /// instead of being buffered in memory.
+  ///
+  /// See `FragmentCollector::set_spill_threshold`.
+  #[cfg(feature = "spill")]
+  pub async fn read_collected(
+    &mut self,
+  ) -> Result<Collected<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.collect_message(true).await
+  }
+
+  /// Reads a complete message, collecting fragmented messages like `read_frame`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{FragmentCollector, Message};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn echo(ws: &mut FragmentCollector<TcpStream>) -> Result<()> {
+  ///   loop {
+  ///     match ws.read_message().await? {
+  ///       Message::Text(text) => ws.send_text(&text).await?,
+  ///       Message::Binary(data) => ws.send_binary(&data).await?,
+  ///       Message::Close(_) => return Ok(()),
+  ///       Message::Ping(_) | Message::Pong(_) => {}
+  ///     }
+  ///   }
+  /// }
+  /// ```
+  pub async fn read_message(&mut self) -> Result<Message, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    Message::try_from(self.read_frame().await?)
+  }
+
+  /// Sets the size in bytes above which a fragmented message read with `read_collected` is moved to a temporary file.
+  ///
+  /// Frames that are not fragmented are always returned in memory; their size is bounded by `set_max_message_size`.
+  /// The file is written with `tokio::fs`, which needs a tokio runtime.
+  ///
+  /// Default: `None` (never spill)
+  #[cfg(feature = "spill")]
+  pub fn set_spill_threshold(&mut self, threshold: Option<usize>) {
+    self.fragments.spill.threshold = threshold;
+  }
+
+  /// Sets the directory temporary files are created in.
+  ///
+  /// Default: `std::env::temp_dir()`
+  #[cfg(feature = "spill")]
+  pub fn set_spill_dir(&mut self, dir: impl Into<PathBuf>) {
+    self.fragments.spill.dir = dir.into();
+  }
+
+  /// Sets the maximum size in bytes of a message spilled to a temporary file, in place of
+  /// `set_max_fragmented_message_size`. If a message grows larger, the connection is closed with status code 1009 and
+  /// the read fails with `WebSocketError::FragmentedMessageTooLarge`.
+  ///
+  /// Default: 1 GiB
+  #[cfg(feature = "spill")]
+  pub fn set_max_spill_size(&mut self, max_size: usize) {
+    self.fragments.spill.max_size = max_size;
+  }
+
+  /// Sets the maximum number of frames in a fragmented message. If a message has more, the connection is closed with
+  /// status code 1009 and the read fails with `WebSocketError::TooManyFragments`.
+  ///
+  /// Default: 65536
+  pub fn set_max_fragments(&mut self, max_fragments: usize) {
+    self.fragments.max_fragments = max_fragments;
+  }
+
+  /// Sets the maximum size in bytes of a fragmented message buffered in memory. If a message grows larger, the
+  /// connection is closed with status code 1009 and the read fails with `WebSocketError::FragmentedMessageTooLarge`.
+  ///
+  /// `WebSocket::set_max_message_size` only limits the size of each frame. Messages spilled to a temporary file are
+  /// limited by `set_max_spill_size` instead.
+  ///
+  /// Default: 64 MiB
+  pub fn set_max_fragmented_message_size(&mut self, max_size: usize) {
+    self.fragments.max_size = max_size;
+  }
+
+  /// See `WebSocket::set_keepalive`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_keepalive(
+    &mut self,
+    interval: std::time::Duration,
+    timeout: std::time::Duration,
+  ) {
+    self.ws.read_half.keepalive = Some(crate::keepalive::KeepAlive::new(
+      interval,
+      timeout,
+      &*self.ws.read_half.timer,
+    ));
+  }
+
+  /// See `WebSocket::set_read_timeout`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_read_timeout(&mut self, timeout: std::time::Duration) {
+    self.ws.read_half.read_timeout =
+      Some(crate::keepalive::ReadTimeout::new(timeout));
+  }
+
+  /// See `WebSocket::set_write_timeout`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_write_timeout(&mut self, timeout: std::time::Duration) {
+    self.ws.write_half.write_timeout = Some(timeout);
+  }
+
+  /// See `WebSocket::set_close_timeout`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_close_timeout(&mut self, timeout: std::time::Duration) {
+    self.ws.read_half.close_timeout = Some(timeout);
+  }
+
+  /// See `WebSocket::set_timer`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_timer(&mut self, timer: impl crate::Timer + 'static) {
+    self.ws.set_timer(timer);
+  }
+
+  /// See `WebSocket::set_writev`.
+  pub fn set_writev(&mut self, vectored: bool) {
+    self.ws.set_writev(vectored);
+  }
+
+  /// See `WebSocket::set_writev_threshold`.
+  pub fn set_writev_threshold(&mut self, threshold: usize) {
+    self.ws.set_writev_threshold(threshold);
+  }
+
+  /// See `WebSocket::set_max_write_frame_size`.
+  pub fn set_max_write_frame_size(
+    &mut self,
+    max_write_frame_size: Option<usize>,
+  ) {
+    self.ws.set_max_write_frame_size(max_write_frame_size);
+  }
+
+  /// See `WebSocket::set_auto_close`.
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.ws.set_auto_close(auto_close);
+  }
+
+  /// See `WebSocket::set_auto_pong`.
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.ws.set_auto_pong(auto_pong);
+  }
+
+  /// See `WebSocket::set_max_message_size`. It limits the size of each frame; see also
+  /// `set_max_fragmented_message_size`.
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.ws.set_max_message_size(max_message_size);
+  }
+
+  /// See `WebSocket::set_memory_limiter`. A message that is being collected keeps accounting against the limiter it
+  /// started with.
+  pub fn set_memory_limiter(&mut self, limiter: MemoryLimiter) {
+    self.ws.set_memory_limiter(limiter.clone());
+    self.fragments.memory_limiter = Some(limiter);
+  }
+
+  /// See `WebSocket::set_frame_policy`.
+  pub fn set_frame_policy(
+    &mut self,
+    policy: impl FnMut(&FrameInfo) -> Result<(), CloseCode> + Send + 'static,
+  ) {
+    self.ws.set_frame_policy(policy);
+  }
+
+  /// See `WebSocket::set_read_buffer_high_water_mark`.
+  pub fn set_read_buffer_high_water_mark(
+    &mut self,
+    high_water_mark: Option<usize>,
+  ) {
+    self.ws.set_read_buffer_high_water_mark(high_water_mark);
+  }
+
+  /// See `WebSocket::set_accept_unmasked_frames`.
+  pub fn set_accept_unmasked_frames(&mut self, accept_unmasked_frames: bool) {
+    self.ws.set_accept_unmasked_frames(accept_unmasked_frames);
+  }
+
+  /// See `WebSocket::set_auto_apply_mask`.
+  pub fn set_auto_apply_mask(&mut self, auto_apply_mask: bool) {
+    self.ws.set_auto_apply_mask(auto_apply_mask);
+  }
+
+  /// Returns the size above which fragmented messages are moved to a temporary file.
+  #[cfg(feature = "spill")]
+  pub fn spill_threshold(&self) -> Option<usize> {
+    self.fragments.spill.threshold
+  }
+
+  /// Returns the directory temporary files are created in.
+  #[cfg(feature = "spill")]
+  pub fn spill_dir(&self) -> &Path {
+    &self.fragments.spill.dir
+  }
+
+  /// Returns the maximum size in bytes of a message spilled to a temporary file.
+  #[cfg(feature = "spill")]
+  pub fn max_spill_size(&self) -> usize {
+    self.fragments.spill.max_size
+  }
+
+  /// Returns the maximum number of frames in a fragmented message.
+  pub fn max_fragments(&self) -> usize {
+    self.fragments.max_fragments
+  }
+
+  /// Returns the maximum size in bytes of a fragmented message.
+  pub fn max_fragmented_message_size(&self) -> usize {
+    self.fragments.max_size
+  }
+
+  /// Returns whether close frames are answered automatically.
+  pub fn auto_close(&self) -> bool {
+    self.ws.auto_close()
+  }
+
+  /// Returns whether ping frames are answered automatically.
+  pub fn auto_pong(&self) -> bool {
+    self.ws.auto_pong()
+  }
+
+  /// Returns the maximum message size in bytes.
+  pub fn max_message_size(&self) -> usize {
+    self.ws.max_message_size()
+  }
+
+  /// Returns the shared `MemoryLimiter`, if one is set.
+  pub fn memory_limiter(&self) -> Option<&MemoryLimiter> {
+    self.ws.memory_limiter()
+  }
+
+  /// Returns a reference to the `WebSocket`.
+  pub fn get_ref(&self) -> &WebSocket<S> {
+    &self.ws
+  }
+
+  /// Returns a mutable reference to the `WebSocket`, for settings that have no counterpart on the
+  /// `FragmentCollector`. Frames read through it while a message is being collected are missing from the message.
+  pub fn get_mut(&mut self) -> &mut WebSocket<S> {
+    &mut self.ws
+  }
+
+  async fn collect_message(
+    &mut self,
+    allow_spill: bool,
+  ) -> Result<Collected<'f>, WebSocketError>
   where
     S: AsyncRead + AsyncWrite + Unpin,
   {
     loop {
-      let (res, obligated_send) =
-        self.read_half.read_frame_inner(&mut self.stream).await;
-      let is_closed = self.write_half.closed;
+      #[cfg(feature = "keepalive")]
+      let (res, obligated_send) = self
+        .ws
+        .read_half
+        .read_frame_keepalive(&mut self.ws.write_half, &mut self.ws.stream)
+        .await;
+      #[cfg(not(feature = "keepalive"))]
+      let (res, obligated_send) = self
+        .ws
+        .read_half
+        .read_frame_inner(&mut self.ws.stream)
+        .await;
+      let is_closed = self.ws.write_half.closed;
       if let Some(obligated_send) = obligated_send {
         if !is_closed {
           self.write_frame(obligated_send).await?;
@@ -118,8 +411,22 @@ impl<'f, S> FragmentCollector<S> {
       if is_closed && frame.opcode != OpCode::Close {
         return Err(WebSocketError::ConnectionClosed);
       }
-      if let Some(frame) = self.fragments.accumulate(frame)? {
-        return Ok(frame);
+      match self.fragments.accumulate(frame, allow_spill).await {
+        Ok(Some(message)) => return Ok(message),
+        Ok(None) => {}
+        Err(WebSocketError::MemoryLimitExceeded) => {
+          self.write_frame(Frame::close(1013, b"")).await?;
+          return Err(WebSocketError::MemoryLimitExceeded);
+        }
+        Err(
+          e @ (WebSocketError::ConnectionMemoryExceeded
+          | WebSocketError::TooManyFragments
+          | WebSocketError::FragmentedMessageTooLarge),
+        ) => {
+          self.write_frame(Frame::close(1009, b"")).await?;
+          return Err(e);
+        }
+        Err(e) => return Err(e),
       }
     }
   }
@@ -132,21 +439,155 @@ impl<'f, S> FragmentCollector<S> {
   where
     S: AsyncRead + AsyncWrite + Unpin,
   {
-    self.write_half.write_frame(&mut self.stream, frame).await?;
+    self
+      .ws
+      .write_half
+      .write_frame(&mut self.ws.stream, frame)
+      .await?;
+    Ok(())
+  }
+
+  /// See `WebSocket::write_frame_ref`.
+  pub async fn write_frame_ref(
+    &mut self,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .ws
+      .write_half
+      .write_frame_ref(&mut self.ws.stream, frame)
+      .await
+  }
+
+  /// See `WebSocket::ping_with_payload`.
+  pub async fn ping_with_payload(
+    &mut self,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let ping = Frame::new(true, OpCode::Ping, None, payload.into());
+    let sent = self.ws.read_half.now();
+    self
+      .ws
+      .write_half
+      .write_frame_ref(&mut self.ws.stream, &ping)
+      .await?;
+    self.ws.read_half.pings.sent(payload, sent);
     Ok(())
   }
 
+  /// See `WebSocket::write_message`.
+  pub async fn write_message(
+    &mut self,
+    message: Message,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(message.into()).await
+  }
+
+  /// See `WebSocket::send_text`.
+  pub async fn send_text(&mut self, text: &str) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(Frame::text(text.as_bytes().into())).await
+  }
+
+  /// See `WebSocket::send_binary`.
+  pub async fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(Frame::binary(data.into())).await
+  }
+
+  /// See `WebSocket::send_ping`.
+  pub async fn send_ping(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_frame(Frame::new(true, OpCode::Ping, None, data.into()))
+      .await
+  }
+
+  /// See `WebSocket::latency`.
+  pub fn latency(&self) -> Option<std::time::Duration> {
+    self.ws.read_half.pings.latency()
+  }
+
+  /// See `WebSocket::flush`.
+  pub async fn flush(&mut self) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.ws.write_half.flush(&mut self.ws.stream).await
+  }
+
+  /// Whether a close frame has been written.
+  pub fn is_closed(&self) -> bool {
+    self.ws.write_half.closed
+  }
+
+  /// See `WebSocket::close`.
+  pub async fn close(
+    &mut self,
+    code: CloseCode,
+    reason: &str,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    crate::close_handshake(
+      &mut self.ws.read_half,
+      &mut self.ws.write_half,
+      &mut self.ws.stream,
+      code,
+      reason,
+    )
+    .await
+  }
+
+  /// See `WebSocket::write_with_header`.
+  pub async fn write_with_header(
+    &mut self,
+    header: &FrameHeader,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .ws
+      .write_half
+      .write_with_header(&mut self.ws.stream, header, payload)
+      .await
+  }
+
   /// Consumes the `FragmentCollector` and returns the underlying stream.
   #[inline]
   pub fn into_inner(self) -> S {
-    self.stream
+    self.ws.stream
+  }
+
+  /// Consumes the `FragmentCollector` and returns the `WebSocket`, for example to close it by hand or to switch
+  /// protocols. A message that is partially collected is dropped.
+  #[inline]
+  pub fn into_websocket(self) -> WebSocket<S> {
+    self.ws
   }
 }
 
 #[cfg(feature = "unstable-split")]
 pub struct FragmentCollectorRead<S> {
-  stream: S,
-  read_half: ReadHalf,
+  ws: WebSocketRead<S>,
   fragments: Fragments,
 }
 
@@ -157,12 +598,11 @@ impl<'f, S> FragmentCollectorRead<S> {
   where
     S: AsyncRead + Unpin,
   {
-    let (stream, read_half) = ws.into_parts_internal();
-    FragmentCollectorRead {
-      stream,
-      read_half,
-      fragments: Fragments::new(),
-    }
+    let fragments = Fragments::new(
+      ws.read_half.memory_limiter.clone(),
+      ws.read_half.connection_memory.clone(),
+    );
+    FragmentCollectorRead { ws, fragments }
   }
 
   /// Reads a WebSocket frame, collecting fragmented messages until the final frame is received and returns the completed message.
@@ -176,14 +616,238 @@ impl<'f, S> FragmentCollectorRead<S> {
     &mut self,
     send_fn: &mut impl FnMut(Frame<'f>) -> R,
   ) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+    E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    R: Future<Output = Result<(), E>>,
+  {
+    match self.collect_message(send_fn, false).await? {
+      Collected::Frame(frame) => Ok(frame),
+      #[cfg(feature = "spill")]
+      Collected::Spilled(_) => unreachable!(),
+    }
+  }
+
+  /// Like `read_frame`, but obligated frames are queued for the matching `WebSocketWrite` instead of being passed to a
+  /// `send_fn`. See `WebSocketRead::read_frame_queued`.
+  pub async fn read_frame_queued(&mut self) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    let control = self.ws.control.clone();
+    let mut send_fn = |frame| {
+      control.push(frame);
+      std::future::ready(Ok::<_, WebSocketError>(()))
+    };
+    match self.collect_message(&mut send_fn, false).await? {
+      Collected::Frame(frame) => Ok(frame),
+      #[cfg(feature = "spill")]
+      Collected::Spilled(_) => unreachable!(),
+    }
+  }
+
+  /// Like `read_frame`, but fragmented messages that grow beyond the spill threshold are written to a temporary file
+  /// instead of being buffered in memory.
+  ///
+  /// See `FragmentCollector::set_spill_threshold`.
+  #[cfg(feature = "spill")]
+  pub async fn read_collected<R, E>(
+    &mut self,
+    send_fn: &mut impl FnMut(Frame<'f>) -> R,
+  ) -> Result<Collected<'f>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+    E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    R: Future<Output = Result<(), E>>,
+  {
+    self.collect_message(send_fn, true).await
+  }
+
+  /// See `FragmentCollector::set_spill_threshold`.
+  #[cfg(feature = "spill")]
+  pub fn set_spill_threshold(&mut self, threshold: Option<usize>) {
+    self.fragments.spill.threshold = threshold;
+  }
+
+  /// See `FragmentCollector::set_spill_dir`.
+  #[cfg(feature = "spill")]
+  pub fn set_spill_dir(&mut self, dir: impl Into<PathBuf>) {
+    self.fragments.spill.dir = dir.into();
+  }
+
+  /// See `FragmentCollector::set_max_spill_size`.
+  #[cfg(feature = "spill")]
+  pub fn set_max_spill_size(&mut self, max_size: usize) {
+    self.fragments.spill.max_size = max_size;
+  }
+
+  /// See `FragmentCollector::set_max_fragments`.
+  pub fn set_max_fragments(&mut self, max_fragments: usize) {
+    self.fragments.max_fragments = max_fragments;
+  }
+
+  /// See `FragmentCollector::set_max_fragmented_message_size`.
+  pub fn set_max_fragmented_message_size(&mut self, max_size: usize) {
+    self.fragments.max_size = max_size;
+  }
+
+  /// Returns the size above which fragmented messages are moved to a temporary file.
+  #[cfg(feature = "spill")]
+  pub fn spill_threshold(&self) -> Option<usize> {
+    self.fragments.spill.threshold
+  }
+
+  /// Returns the directory temporary files are created in.
+  #[cfg(feature = "spill")]
+  pub fn spill_dir(&self) -> &Path {
+    &self.fragments.spill.dir
+  }
+
+  /// Returns the maximum size in bytes of a message spilled to a temporary file.
+  #[cfg(feature = "spill")]
+  pub fn max_spill_size(&self) -> usize {
+    self.fragments.spill.max_size
+  }
+
+  /// Returns the maximum number of frames in a fragmented message.
+  pub fn max_fragments(&self) -> usize {
+    self.fragments.max_fragments
+  }
+
+  /// Returns the maximum size in bytes of a fragmented message.
+  pub fn max_fragmented_message_size(&self) -> usize {
+    self.fragments.max_size
+  }
+
+  /// See `WebSocketRead::set_auto_close`.
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.ws.read_half.auto_close = auto_close;
+  }
+
+  /// See `WebSocketRead::set_auto_pong`.
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.ws.read_half.auto_pong = auto_pong;
+  }
+
+  /// See `WebSocketRead::set_max_message_size`.
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.ws.read_half.max_message_size = max_message_size;
+  }
+
+  /// See `WebSocketRead::set_memory_limiter`. A message that is being collected keeps accounting against the limiter
+  /// it started with.
+  pub fn set_memory_limiter(&mut self, limiter: MemoryLimiter) {
+    self.ws.read_half.memory_limiter = Some(limiter.clone());
+    self.fragments.memory_limiter = Some(limiter);
+  }
+
+  /// See `WebSocketRead::set_frame_policy`.
+  pub fn set_frame_policy(
+    &mut self,
+    policy: impl FnMut(&FrameInfo) -> Result<(), CloseCode> + Send + 'static,
+  ) {
+    self.ws.read_half.frame_policy = Some(Box::new(policy));
+  }
+
+  /// See `WebSocketRead::set_read_buffer_high_water_mark`.
+  pub fn set_read_buffer_high_water_mark(
+    &mut self,
+    high_water_mark: Option<usize>,
+  ) {
+    self.ws.read_half.read_buffer_high_water_mark = high_water_mark;
+  }
+
+  /// See `WebSocketRead::set_accept_unmasked_frames`.
+  pub fn set_accept_unmasked_frames(&mut self, accept_unmasked_frames: bool) {
+    self.ws.read_half.accept_unmasked_frames = accept_unmasked_frames;
+  }
+
+  /// See `WebSocketRead::set_auto_apply_mask`.
+  pub fn set_auto_apply_mask(&mut self, auto_apply_mask: bool) {
+    self.ws.read_half.auto_apply_mask = auto_apply_mask;
+  }
+
+  /// Returns whether close frames are answered automatically.
+  pub fn auto_close(&self) -> bool {
+    self.ws.read_half.auto_close
+  }
+
+  /// Returns whether ping frames are answered automatically.
+  pub fn auto_pong(&self) -> bool {
+    self.ws.read_half.auto_pong
+  }
+
+  /// Returns the maximum message size in bytes.
+  pub fn max_message_size(&self) -> usize {
+    self.ws.read_half.max_message_size
+  }
+
+  /// Returns the shared `MemoryLimiter`, if one is set.
+  pub fn memory_limiter(&self) -> Option<&MemoryLimiter> {
+    self.ws.read_half.memory_limiter.as_ref()
+  }
+
+  /// Returns the size above which the read buffer is released after a frame has been read.
+  pub fn read_buffer_high_water_mark(&self) -> Option<usize> {
+    self.ws.read_half.read_buffer_high_water_mark
+  }
+
+  /// Returns whether a server accepts frames that the client did not mask.
+  pub fn accept_unmasked_frames(&self) -> bool {
+    self.ws.read_half.accept_unmasked_frames
+  }
+
+  /// Returns whether the mask is applied to frame payloads automatically.
+  pub fn auto_apply_mask(&self) -> bool {
+    self.ws.read_half.auto_apply_mask
+  }
+
+  /// Returns a reference to the `WebSocketRead`.
+  pub fn get_ref(&self) -> &WebSocketRead<S> {
+    &self.ws
+  }
+
+  /// Returns a mutable reference to the `WebSocketRead`. See `FragmentCollector::get_mut`.
+  pub fn get_mut(&mut self) -> &mut WebSocketRead<S> {
+    &mut self.ws
+  }
+
+  /// Consumes the `FragmentCollectorRead` and returns the underlying read half of the stream.
+  #[inline]
+  pub fn into_inner(self) -> S {
+    self.ws.stream
+  }
+
+  /// Consumes the `FragmentCollectorRead` and returns the `WebSocketRead`. A message that is partially collected is
+  /// dropped.
+  #[inline]
+  pub fn into_websocket(self) -> WebSocketRead<S> {
+    self.ws
+  }
+
+  async fn collect_message<R, E>(
+    &mut self,
+    send_fn: &mut impl FnMut(Frame<'f>) -> R,
+    allow_spill: bool,
+  ) -> Result<Collected<'f>, WebSocketError>
   where
     S: AsyncRead + Unpin,
     E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
     R: Future<Output = Result<(), E>>,
   {
     loop {
-      let (res, obligated_send) =
-        self.read_half.read_frame_inner(&mut self.stream).await;
+      #[cfg(feature = "keepalive")]
+      let (res, obligated_send) = self
+        .ws
+        .read_half
+        .read_frame_timed(&mut self.ws.stream)
+        .await;
+      #[cfg(not(feature = "keepalive"))]
+      let (res, obligated_send) = self
+        .ws
+        .read_half
+        .read_frame_inner(&mut self.ws.stream)
+        .await;
       if let Some(frame) = obligated_send {
         let res = send_fn(frame).await;
         res.map_err(|e| WebSocketError::SendError(e.into()))?;
@@ -191,56 +855,171 @@ impl<'f, S> FragmentCollectorRead<S> {
       let Some(frame) = res? else {
         continue;
       };
-      if let Some(frame) = self.fragments.accumulate(frame)? {
-        return Ok(frame);
+      match self.fragments.accumulate(frame, allow_spill).await {
+        Ok(Some(message)) => return Ok(message),
+        Ok(None) => {}
+        Err(WebSocketError::MemoryLimitExceeded) => {
+          let res = send_fn(Frame::close(1013, b"")).await;
+          res.map_err(|e| WebSocketError::SendError(e.into()))?;
+          return Err(WebSocketError::MemoryLimitExceeded);
+        }
+        Err(
+          e @ (WebSocketError::ConnectionMemoryExceeded
+          | WebSocketError::TooManyFragments
+          | WebSocketError::FragmentedMessageTooLarge),
+        ) => {
+          let res = send_fn(Frame::close(1009, b"")).await;
+          res.map_err(|e| WebSocketError::SendError(e.into()))?;
+          return Err(e);
+        }
+        Err(e) => return Err(e),
       }
     }
   }
 }
 
+/// Validates the opcode sequence of fragmented messages for applications that read raw frames instead of using
+/// `FragmentCollector`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{FragmentState, OpCode, WebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn handle(ws: &mut WebSocket<TcpStream>) -> Result<()> {
+///   let mut state = FragmentState::new();
+///   loop {
+///     let frame = ws.read_frame().await?;
+///     state.check(&frame)?;
+///     if frame.opcode == OpCode::Close {
+///       break;
+///     }
+///     // Process the frame...
+///   }
+///   Ok(())
+/// }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FragmentState {
+  opcode: Option<OpCode>,
+}
+
+impl FragmentState {
+  /// Creates a new `FragmentState` with no message in progress.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Checks that `frame` may follow the frames seen so far and updates the state.
+  ///
+  /// Returns `WebSocketError::InvalidFragment` for a new data frame while a fragmented message is in progress, and
+  /// `WebSocketError::InvalidContinuationFrame` for a continuation frame outside of one. Control frames may appear
+  /// anywhere.
+  pub fn check(&mut self, frame: &Frame<'_>) -> Result<(), WebSocketError> {
+    match frame.opcode {
+      OpCode::Text | OpCode::Binary => {
+        if self.opcode.is_some() {
+          return Err(WebSocketError::InvalidFragment);
+        }
+        if !frame.fin {
+          self.opcode = Some(frame.opcode);
+        }
+      }
+      OpCode::Continuation => {
+        if self.opcode.is_none() {
+          return Err(WebSocketError::InvalidContinuationFrame);
+        }
+        if frame.fin {
+          self.opcode = None;
+        }
+      }
+      OpCode::Close | OpCode::Ping | OpCode::Pong => {}
+    }
+    Ok(())
+  }
+
+  /// The opcode of the fragmented message in progress, if any.
+  pub fn message_opcode(&self) -> Option<OpCode> {
+    self.opcode
+  }
+}
+
 /// Accumulates potentially fragmented [`Frame`]s to defragment the incoming WebSocket stream.
 struct Fragments {
-  fragments: Option<Fragment>,
+  fragments: Option<Buffer>,
   opcode: OpCode,
+  #[cfg(feature = "spill")]
+  spill: SpillConfig,
+  memory_limiter: Option<MemoryLimiter>,
+  permit: Option<MemoryPermit>,
+  connection_memory: Option<MemoryLimiter>,
+  connection_permit: Option<MemoryPermit>,
+  max_fragments: usize,
+  max_size: usize,
+  /// Frames and payload bytes of the message in progress.
+  count: usize,
+  size: usize,
 }
 
 impl Fragments {
-  pub fn new() -> Self {
+  pub fn new(
+    memory_limiter: Option<MemoryLimiter>,
+    connection_memory: Option<MemoryLimiter>,
+  ) -> Self {
     Self {
       fragments: None,
       opcode: OpCode::Close,
+      #[cfg(feature = "spill")]
+      spill: SpillConfig {
+        threshold: None,
+        dir: std::env::temp_dir(),
+        max_size: 1 << 30,
+      },
+      memory_limiter,
+      permit: None,
+      connection_memory,
+      connection_permit: None,
+      max_fragments: 1 << 16,
+      max_size: 64 << 20,
+      count: 0,
+      size: 0,
     }
   }
 
-  pub fn accumulate<'f>(
+  pub async fn accumulate<'f>(
     &mut self,
     frame: Frame<'f>,
-  ) -> Result<Option<Frame<'f>>, WebSocketError> {
+    allow_spill: bool,
+  ) -> Result<Option<Collected<'f>>, WebSocketError> {
+    let buffered = match frame.opcode {
+      OpCode::Text | OpCode::Binary => !frame.fin,
+      OpCode::Continuation => {
+        matches!(self.fragments.as_ref(), Some(Buffer::Memory(_)))
+      }
+      _ => false,
+    };
+    self.check_limits(&frame, allow_spill)?;
+    if buffered {
+      self.account(frame.payload.len())?;
+    }
+
     match frame.opcode {
       OpCode::Text | OpCode::Binary => {
         if frame.fin {
           if self.fragments.is_some() {
             return Err(WebSocketError::InvalidFragment);
           }
-          return Ok(Some(Frame::new(true, frame.opcode, None, frame.payload)));
+          return Ok(Some(Collected::Frame(Frame::new(
+            true,
+            frame.opcode,
+            None,
+            frame.payload,
+          ))));
         } else {
-          self.fragments = match frame.opcode {
-            OpCode::Text => match utf8::decode(&frame.payload) {
-              Ok(text) => Some(Fragment::Text(None, text.as_bytes().to_vec())),
-              Err(utf8::DecodeError::Incomplete {
-                valid_prefix,
-                incomplete_suffix,
-              }) => Some(Fragment::Text(
-                Some(incomplete_suffix),
-                valid_prefix.as_bytes().to_vec(),
-              )),
-              Err(utf8::DecodeError::Invalid { .. }) => {
-                return Err(WebSocketError::InvalidUTF8);
-              }
-            },
-            OpCode::Binary => Some(Fragment::Binary(frame.payload.into())),
-            _ => unreachable!(),
-          };
+          // `ReadHalf` has checked that text fragments are valid UTF-8 so far.
+          self.fragments = Some(Buffer::Memory(frame.payload.into()));
           self.opcode = frame.opcode;
         }
       }
@@ -248,68 +1027,403 @@ impl Fragments {
         None => {
           return Err(WebSocketError::InvalidContinuationFrame);
         }
-        Some(Fragment::Text(data, input)) => {
-          let mut tail = &frame.payload[..];
-          if let Some(mut incomplete) = data.take() {
-            if let Some((result, rest)) =
-              incomplete.try_complete(&frame.payload)
-            {
-              tail = rest;
-              match result {
-                Ok(text) => {
-                  input.extend_from_slice(text.as_bytes());
-                }
-                Err(_) => {
-                  return Err(WebSocketError::InvalidUTF8);
-                }
-              }
-            } else {
-              tail = &[];
-              data.replace(incomplete);
-            }
-          }
+        Some(buffer) => {
+          buffer.extend_from_slice(&frame.payload).await?;
+        }
+      },
+      _ => return Ok(Some(Collected::Frame(frame))),
+    }
 
-          match utf8::decode(tail) {
-            Ok(text) => {
-              input.extend_from_slice(text.as_bytes());
-            }
-            Err(utf8::DecodeError::Incomplete {
-              valid_prefix,
-              incomplete_suffix,
-            }) => {
-              input.extend_from_slice(valid_prefix.as_bytes());
-              *data = Some(incomplete_suffix);
-            }
-            Err(utf8::DecodeError::Invalid { valid_prefix, .. }) => {
-              input.extend_from_slice(valid_prefix.as_bytes());
-              return Err(WebSocketError::InvalidUTF8);
-            }
-          }
+    #[cfg(feature = "spill")]
+    if allow_spill {
+      self.maybe_spill().await?;
+    }
+    #[cfg(not(feature = "spill"))]
+    let _ = allow_spill;
 
-          if frame.fin {
-            return Ok(Some(Frame::new(
-              true,
-              self.opcode,
-              None,
-              self.fragments.take().unwrap().take_buffer().into(),
-            )));
-          }
+    if frame.fin {
+      self.permit = None;
+      self.connection_permit = None;
+      let buffer = self.fragments.take().unwrap();
+      let message = match buffer {
+        Buffer::Memory(buffer) => {
+          Collected::Frame(Frame::new(true, self.opcode, None, buffer.into()))
         }
-        Some(Fragment::Binary(data)) => {
-          data.extend_from_slice(&frame.payload);
-          if frame.fin {
-            return Ok(Some(Frame::new(
-              true,
-              self.opcode,
-              None,
-              self.fragments.take().unwrap().take_buffer().into(),
-            )));
-          }
+        #[cfg(feature = "spill")]
+        Buffer::Spilled(file) => {
+          Collected::Spilled(file.finish(self.opcode).await?)
         }
-      },
-      _ => return Ok(Some(frame)),
+      };
+      return Ok(Some(message));
     }
 
     Ok(None)
   }
+
+  /// Moves the in-progress message to a temporary file once it exceeds the spill threshold.
+  #[cfg(feature = "spill")]
+  async fn maybe_spill(&mut self) -> Result<(), WebSocketError> {
+    let Some(threshold) = self.spill.threshold else {
+      return Ok(());
+    };
+    let Some(buffer) = self.fragments.as_mut() else {
+      return Ok(());
+    };
+    if let Buffer::Memory(data) = buffer {
+      if data.len() > threshold {
+        let mut file = SpillFile::create(&self.spill.dir).await?;
+        file.write_all(data).await?;
+        *buffer = Buffer::Spilled(file);
+        self.permit = None;
+        self.connection_permit = None;
+      }
+    }
+    Ok(())
+  }
+
+  /// Accounts `size` more buffered bytes against the shared `MemoryLimiter` and the connection memory budget.
+  fn account(&mut self, size: usize) -> Result<(), WebSocketError> {
+    let result = if !grow(&self.memory_limiter, &mut self.permit, size) {
+      Err(WebSocketError::MemoryLimitExceeded)
+    } else if !grow(&self.connection_memory, &mut self.connection_permit, size)
+    {
+      Err(WebSocketError::ConnectionMemoryExceeded)
+    } else {
+      Ok(())
+    };
+    if result.is_err() {
+      self.discard();
+    }
+    result
+  }
+
+  /// Counts the frames and bytes of a fragmented message against `max_fragments` and `max_size`, or the spill limit
+  /// once the message is moved to a temporary file.
+  fn check_limits(
+    &mut self,
+    frame: &Frame<'_>,
+    allow_spill: bool,
+  ) -> Result<(), WebSocketError> {
+    let len = frame.payload.len();
+    let (count, size) = match frame.opcode {
+      OpCode::Text | OpCode::Binary if !frame.fin => (1, len),
+      OpCode::Continuation if self.fragments.is_some() => {
+        (self.count + 1, self.size.saturating_add(len))
+      }
+      _ => return Ok(()),
+    };
+    let result = if count > self.max_fragments {
+      Err(WebSocketError::TooManyFragments)
+    } else if size > self.size_limit(size, allow_spill) {
+      Err(WebSocketError::FragmentedMessageTooLarge)
+    } else {
+      self.count = count;
+      self.size = size;
+      return Ok(());
+    };
+    self.discard();
+    result
+  }
+
+  /// The size limit of a message of `size` bytes, which depends on whether it is spilled.
+  fn size_limit(&self, size: usize, allow_spill: bool) -> usize {
+    #[cfg(feature = "spill")]
+    if let (true, Some(threshold)) = (allow_spill, self.spill.threshold) {
+      if size > threshold {
+        return self.spill.max_size;
+      }
+    }
+    #[cfg(not(feature = "spill"))]
+    let _ = (size, allow_spill);
+    self.max_size
+  }
+
+  /// Drops the message in progress and releases its memory.
+  fn discard(&mut self) {
+    self.fragments = None;
+    self.permit = None;
+    self.connection_permit = None;
+  }
+}
+
+fn grow(
+  limiter: &Option<MemoryLimiter>,
+  permit: &mut Option<MemoryPermit>,
+  size: usize,
+) -> bool {
+  let Some(limiter) = limiter else {
+    return true;
+  };
+  match permit {
+    Some(permit) => permit.try_grow(size),
+    None => {
+      *permit = limiter.try_acquire(size);
+      permit.is_some()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(feature = "spill")]
+  #[tokio::test]
+  async fn spill_fragmented_text() {
+    use tokio::io::AsyncReadExt;
+
+    let mut fragments = Fragments::new(None, None);
+    fragments.spill.threshold = Some(4);
+    // Spilled messages are not limited by the in-memory limit.
+    fragments.max_size = 5;
+
+    let first =
+      Frame::new(false, OpCode::Text, None, b"hel\xc3".to_vec().into());
+    assert!(fragments.accumulate(first, true).await.unwrap().is_none());
+    let last =
+      Frame::new(true, OpCode::Continuation, None, b"\xa9lo".to_vec().into());
+    let Some(Collected::Spilled(mut message)) =
+      fragments.accumulate(last, true).await.unwrap()
+    else {
+      panic!("expected a spilled message");
+    };
+    assert_eq!(message.opcode(), OpCode::Text);
+    assert_eq!(message.len(), 7);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let metadata = message.file().metadata().await.unwrap();
+      assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    let path = message.path().to_owned();
+    let mut contents = String::new();
+    message.file().read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "hel\u{e9}lo");
+    drop(message);
+    // The file is removed on the blocking thread pool.
+    for _ in 0..100 {
+      if !path.exists() {
+        break;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!path.exists());
+  }
+
+  #[cfg(feature = "spill")]
+  #[tokio::test]
+  async fn spill_limit() {
+    let mut fragments = Fragments::new(None, None);
+    fragments.spill.threshold = Some(2);
+    fragments.spill.max_size = 6;
+    fragments.max_size = 4;
+    let frame =
+      |fin, opcode, len| Frame::new(fin, opcode, None, vec![0; len].into());
+
+    assert!(fragments
+      .accumulate(frame(false, OpCode::Binary, 3), true)
+      .await
+      .unwrap()
+      .is_none());
+    assert!(fragments
+      .accumulate(frame(false, OpCode::Continuation, 3), true)
+      .await
+      .unwrap()
+      .is_none());
+    assert!(matches!(
+      fragments
+        .accumulate(frame(true, OpCode::Continuation, 1), true)
+        .await,
+      Err(WebSocketError::FragmentedMessageTooLarge)
+    ));
+
+    // Messages read without spilling keep the in-memory limit.
+    assert!(matches!(
+      fragments
+        .accumulate(frame(false, OpCode::Binary, 5), false)
+        .await,
+      Err(WebSocketError::FragmentedMessageTooLarge)
+    ));
+  }
+
+  #[cfg(feature = "spill")]
+  #[tokio::test]
+  async fn no_spill_without_opt_in() {
+    let mut fragments = Fragments::new(None, None);
+    fragments.spill.threshold = Some(1);
+
+    let first = Frame::new(false, OpCode::Binary, None, vec![1, 2].into());
+    assert!(fragments.accumulate(first, false).await.unwrap().is_none());
+    let last = Frame::new(true, OpCode::Continuation, None, vec![3].into());
+    let Some(Collected::Frame(frame)) =
+      fragments.accumulate(last, false).await.unwrap()
+    else {
+      panic!("expected an in-memory frame");
+    };
+    assert_eq!(frame.payload, &[1, 2, 3]);
+  }
+
+  #[tokio::test]
+  async fn connection_budget_rejects_growing_message() {
+    let budget = MemoryLimiter::new(4);
+    let mut fragments = Fragments::new(None, Some(budget.clone()));
+
+    let first = Frame::new(false, OpCode::Binary, None, vec![0; 3].into());
+    assert!(fragments.accumulate(first, false).await.unwrap().is_none());
+    assert_eq!(budget.used(), 3);
+    let next = Frame::new(false, OpCode::Continuation, None, vec![0; 3].into());
+    assert!(matches!(
+      fragments.accumulate(next, false).await,
+      Err(WebSocketError::ConnectionMemoryExceeded)
+    ));
+    assert_eq!(budget.used(), 0);
+  }
+
+  #[tokio::test]
+  async fn fragment_limits() {
+    let mut fragments = Fragments::new(None, None);
+    fragments.max_fragments = 2;
+    fragments.max_size = 4;
+    let frame =
+      |fin, opcode, len| Frame::new(fin, opcode, None, vec![0; len].into());
+
+    assert!(fragments
+      .accumulate(frame(false, OpCode::Binary, 2), false)
+      .await
+      .unwrap()
+      .is_none());
+    assert!(fragments
+      .accumulate(frame(false, OpCode::Continuation, 0), false)
+      .await
+      .unwrap()
+      .is_none());
+    assert!(matches!(
+      fragments
+        .accumulate(frame(false, OpCode::Continuation, 0), false)
+        .await,
+      Err(WebSocketError::TooManyFragments)
+    ));
+
+    // Unfragmented messages are only limited by `max_message_size`.
+    assert!(fragments
+      .accumulate(frame(true, OpCode::Binary, 8), false)
+      .await
+      .unwrap()
+      .is_some());
+
+    assert!(fragments
+      .accumulate(frame(false, OpCode::Binary, 2), false)
+      .await
+      .unwrap()
+      .is_none());
+    assert!(matches!(
+      fragments
+        .accumulate(frame(true, OpCode::Continuation, 3), false)
+        .await,
+      Err(WebSocketError::FragmentedMessageTooLarge)
+    ));
+  }
+
+  #[tokio::test]
+  async fn too_many_fragments_closes_with_1009() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, crate::Role::Client);
+    let server = WebSocket::after_handshake(server, crate::Role::Server);
+    let mut server = FragmentCollector::new(server);
+    server.set_max_fragments(2);
+
+    client
+      .write_frame(Frame::new(false, OpCode::Text, None, b"a"[..].into()))
+      .await
+      .unwrap();
+    for _ in 0..2 {
+      client
+        .write_frame(Frame::new(
+          false,
+          OpCode::Continuation,
+          None,
+          b""[..].into(),
+        ))
+        .await
+        .unwrap();
+    }
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::TooManyFragments)
+    ));
+    let close = client.read_frame().await.unwrap();
+    assert_eq!(close.as_close().unwrap().unwrap().code, CloseCode::Size);
+  }
+
+  #[tokio::test]
+  async fn configure_and_unwrap() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, crate::Role::Client);
+    let server = WebSocket::after_handshake(server, crate::Role::Server);
+    let mut server = FragmentCollector::new(server);
+
+    server.set_auto_pong(false);
+    server.set_max_message_size(16);
+    assert!(!server.get_ref().auto_pong());
+    server.get_mut().set_auto_close(false);
+    assert!(!server.auto_close());
+    assert_eq!(server.max_message_size(), 16);
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b""[..].into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Ping);
+
+    let mut server = server.into_websocket();
+    server
+      .write_frame(Frame::close_with(CloseCode::Normal, "bye"))
+      .await
+      .unwrap();
+    let close = client.read_frame().await.unwrap();
+    assert_eq!(close.as_close().unwrap().unwrap().reason, "bye");
+  }
+
+  #[test]
+  fn fragment_state_sequencing() {
+    let mut state = FragmentState::new();
+    let frame = |fin, opcode| Frame::new(fin, opcode, None, vec![].into());
+
+    assert!(matches!(
+      state.check(&frame(true, OpCode::Continuation)),
+      Err(WebSocketError::InvalidContinuationFrame)
+    ));
+    state.check(&frame(false, OpCode::Text)).unwrap();
+    assert_eq!(state.message_opcode(), Some(OpCode::Text));
+    state.check(&frame(true, OpCode::Ping)).unwrap();
+    assert!(matches!(
+      state.check(&frame(true, OpCode::Binary)),
+      Err(WebSocketError::InvalidFragment)
+    ));
+    state.check(&frame(true, OpCode::Continuation)).unwrap();
+    assert_eq!(state.message_opcode(), None);
+    state.check(&frame(true, OpCode::Binary)).unwrap();
+  }
+
+  #[cfg(feature = "unstable-split")]
+  #[tokio::test]
+  async fn limits_can_be_raised_after_split() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, crate::Role::Client);
+    let mut server = WebSocket::after_handshake(server, crate::Role::Server);
+    server.set_max_message_size(4);
+    let (rx, _tx) = server.split(tokio::io::split);
+    let mut rx = FragmentCollectorRead::new(rx);
+
+    rx.set_max_message_size(16);
+    assert_eq!(rx.max_message_size(), 16);
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = rx.read_frame_queued().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+  }
 }
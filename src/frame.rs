@@ -12,11 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use tokio::io::AsyncWriteExt;
+use tokio::io::AsyncWrite;
 
+use bytes::Bytes;
 use bytes::BytesMut;
 use core::ops::Deref;
 
+use crate::codec;
+pub(crate) use crate::codec::MAX_HEAD_SIZE;
+use crate::io::WsWrite;
+use crate::truncate_close_reason;
+use crate::CloseCode;
+use crate::CloseFrame;
 use crate::WebSocketError;
 
 macro_rules! repr_u8 {
@@ -38,6 +45,12 @@ macro_rules! repr_u8 {
           }
         }
       }
+
+      impl From<$name> for u8 {
+        fn from(v: $name) -> u8 {
+          v as u8
+        }
+      }
     }
 }
 
@@ -85,6 +98,21 @@ impl From<Vec<u8>> for Payload<'_> {
   }
 }
 
+impl From<Bytes> for Payload<'_> {
+  fn from(bytes: Bytes) -> Self {
+    Payload::Bytes(bytes.into())
+  }
+}
+
+impl From<Payload<'_>> for Bytes {
+  fn from(payload: Payload<'_>) -> Self {
+    match payload {
+      Payload::Bytes(b) => b.freeze(),
+      payload => Vec::from(payload).into(),
+    }
+  }
+}
+
 impl From<Payload<'_>> for Vec<u8> {
   fn from(cow: Payload<'_>) -> Self {
     match cow {
@@ -114,6 +142,27 @@ impl Payload<'_> {
   }
 }
 
+impl Clone for Payload<'_> {
+  /// Clones the payload. A mutably borrowed payload is copied into an owned buffer.
+  fn clone(&self) -> Self {
+    match self {
+      Payload::BorrowedMut(payload) => Payload::Owned(payload.to_vec()),
+      Payload::Borrowed(payload) => Payload::Borrowed(payload),
+      Payload::Owned(payload) => Payload::Owned(payload.clone()),
+      Payload::Bytes(payload) => Payload::Bytes(payload.clone()),
+    }
+  }
+}
+
+/// Payloads compare by content, regardless of how they are stored.
+impl PartialEq for Payload<'_> {
+  fn eq(&self, other: &Self) -> bool {
+    self.deref() == other.deref()
+  }
+}
+
+impl Eq for Payload<'_> {}
+
 impl<'a> PartialEq<&'_ [u8]> for Payload<'a> {
   fn eq(&self, other: &&'_ [u8]) -> bool {
     self.deref() == *other
@@ -127,6 +176,9 @@ impl<'a, const N: usize> PartialEq<&'_ [u8; N]> for Payload<'a> {
 }
 
 /// Represents a WebSocket frame.
+///
+/// Frames compare equal when their header fields, masking key and payload contents are equal.
+#[derive(Clone, PartialEq, Eq)]
 pub struct Frame<'f> {
   /// Indicates if this is the final frame in a message.
   pub fin: bool,
@@ -134,11 +186,108 @@ pub struct Frame<'f> {
   pub opcode: OpCode,
   /// The masking key of the frame, if any.
   mask: Option<[u8; 4]>,
+  /// Whether the frame has the RSV1 bit set, which marks a message compressed with permessage-deflate.
+  pub(crate) rsv1: bool,
+  /// The RSV2 and RSV3 bits, which are only set by an `Extension` that claims them.
+  pub(crate) rsv2: bool,
+  pub(crate) rsv3: bool,
+  /// Whether the message this frame starts may be compressed when it is written.
+  pub(crate) compress: bool,
   /// The payload of the frame.
   pub payload: Payload<'f>,
 }
 
-const MAX_HEAD_SIZE: usize = 16;
+/// Number of payload bytes shown by the `Debug` and `Display` implementations of `Frame` by default.
+const PREVIEW_LEN: usize = 32;
+
+impl core::fmt::Debug for Frame<'_> {
+  /// Shows the header fields and a preview of the first 32 payload bytes. Use `{:#?}` to show the whole payload.
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let limit = if f.alternate() {
+      None
+    } else {
+      Some(PREVIEW_LEN)
+    };
+    f.debug_struct("Frame")
+      .field("fin", &self.fin)
+      .field("opcode", &self.opcode)
+      .field("masked", &self.mask.is_some())
+      .field("len", &self.payload.len())
+      .field("payload", &PayloadPreview::new(self, limit))
+      .finish()
+  }
+}
+
+impl core::fmt::Display for Frame<'_> {
+  /// Formats the frame on a single line, e.g. `Text (fin, 5 bytes) "hello"`. The precision sets how many payload
+  /// bytes are shown (`{:.100}`) and defaults to 32.
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let limit = f.precision().unwrap_or(PREVIEW_LEN);
+    write!(
+      f,
+      "{:?} ({}, {} bytes) {:?}",
+      self.opcode,
+      if self.fin { "fin" } else { "not fin" },
+      self.payload.len(),
+      PayloadPreview::new(self, Some(limit)),
+    )
+  }
+}
+
+/// Renders an unmasked text payload as a quoted string and anything else as hex, truncated to `limit` bytes.
+struct PayloadPreview<'a> {
+  text: bool,
+  payload: &'a [u8],
+  limit: Option<usize>,
+}
+
+impl<'a> PayloadPreview<'a> {
+  fn new(frame: &'a Frame<'_>, limit: Option<usize>) -> Self {
+    Self {
+      text: frame.opcode == OpCode::Text && frame.mask.is_none(),
+      payload: &frame.payload,
+      limit,
+    }
+  }
+}
+
+impl core::fmt::Debug for PayloadPreview<'_> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    let shown = match self.limit {
+      Some(limit) => &self.payload[..self.payload.len().min(limit)],
+      None => self.payload,
+    };
+
+    // A truncated preview may end in the middle of a character.
+    let text = match std::str::from_utf8(shown) {
+      Ok(text) => Some(text),
+      Err(e) if e.error_len().is_none() => {
+        std::str::from_utf8(&shown[..e.valid_up_to()]).ok()
+      }
+      Err(_) => None,
+    };
+    let shown_len = match text {
+      Some(text) if self.text => {
+        write!(f, "{:?}", text)?;
+        text.len()
+      }
+      _ => {
+        for (i, byte) in shown.iter().enumerate() {
+          if i > 0 {
+            f.write_str(" ")?;
+          }
+          write!(f, "{:02x}", byte)?;
+        }
+        shown.len()
+      }
+    };
+
+    if shown_len < self.payload.len() {
+      write!(f, "... (+{} bytes)", self.payload.len() - shown_len)?;
+    }
+    Ok(())
+  }
+}
 
 impl<'f> Frame<'f> {
   /// Creates a new WebSocket `Frame`.
@@ -153,6 +302,10 @@ impl<'f> Frame<'f> {
       opcode,
       mask,
       payload,
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
@@ -167,6 +320,10 @@ impl<'f> Frame<'f> {
       opcode: OpCode::Text,
       mask: None,
       payload,
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
@@ -179,6 +336,10 @@ impl<'f> Frame<'f> {
       opcode: OpCode::Binary,
       mask: None,
       payload,
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
@@ -187,6 +348,9 @@ impl<'f> Frame<'f> {
   /// This is a convenience method for `Frame::new(true, OpCode::Close, None, payload)`.
   ///
   /// This method does not check if `code` is a valid close code and `reason` is valid UTF-8.
+  ///
+  /// `reason` must be at most `MAX_CLOSE_REASON_LEN` bytes, otherwise writing the frame fails with
+  /// `WebSocketError::ControlFrameTooLarge`. Use `truncate_close_reason` to shorten it safely.
   pub fn close(code: u16, reason: &[u8]) -> Self {
     let mut payload = Vec::with_capacity(2 + reason.len());
     payload.extend_from_slice(&code.to_be_bytes());
@@ -197,9 +361,21 @@ impl<'f> Frame<'f> {
       opcode: OpCode::Close,
       mask: None,
       payload: payload.into(),
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
+  /// Create a new WebSocket close `Frame` with a status code and a reason. The reason is truncated to
+  /// `MAX_CLOSE_REASON_LEN` bytes with `truncate_close_reason`.
+  ///
+  /// This method does not check if `code` may be sent in a close frame.
+  pub fn close_with(code: CloseCode, reason: &str) -> Self {
+    Self::close(code.into(), truncate_close_reason(reason).as_bytes())
+  }
+
   /// Create a new WebSocket close `Frame` with a raw payload.
   ///
   /// This is a convenience method for `Frame::new(true, OpCode::Close, None, payload)`.
@@ -211,6 +387,10 @@ impl<'f> Frame<'f> {
       opcode: OpCode::Close,
       mask: None,
       payload,
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
@@ -223,6 +403,10 @@ impl<'f> Frame<'f> {
       opcode: OpCode::Pong,
       mask: None,
       payload,
+      rsv1: false,
+      rsv2: false,
+      rsv3: false,
+      compress: true,
     }
   }
 
@@ -235,12 +419,95 @@ impl<'f> Frame<'f> {
     return std::str::from_utf8(&self.payload).is_ok();
   }
 
+  /// Returns the payload as a string slice if this is a text frame with a valid UTF-8 payload.
+  ///
+  /// Text frames returned by `read_frame` with FIN set are always valid UTF-8.
+  pub fn as_text(&self) -> Option<&str> {
+    if self.opcode != OpCode::Text {
+      return None;
+    }
+
+    #[cfg(feature = "simd")]
+    return simdutf8::basic::from_utf8(&self.payload).ok();
+
+    #[cfg(not(feature = "simd"))]
+    return std::str::from_utf8(&self.payload).ok();
+  }
+
+  /// Consumes the frame and returns the payload as a `String` if this is a text frame with a valid UTF-8 payload.
+  pub fn into_text(self) -> Option<String> {
+    self.as_text()?;
+    let payload: Vec<u8> = self.payload.into();
+    // SAFETY: validated by `as_text` above.
+    Some(unsafe { String::from_utf8_unchecked(payload) })
+  }
+
+  /// Returns the status code and reason if this is a close frame that has a payload, or `None` for other frames and
+  /// close frames without a status code.
+  ///
+  /// Fails like `validate` if the payload is not a valid close payload: a single byte, a status code that must not be
+  /// sent, or a reason that is not UTF-8.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{CloseCode, Frame};
+  ///
+  /// let frame = Frame::close_with(CloseCode::Away, "restarting");
+  /// let close = frame.as_close().unwrap().unwrap();
+  /// assert_eq!(close.code, CloseCode::Away);
+  /// assert_eq!(close.reason, "restarting");
+  /// ```
+  pub fn as_close(&self) -> Result<Option<CloseFrame>, WebSocketError> {
+    if self.opcode != OpCode::Close || self.payload.is_empty() {
+      return Ok(None);
+    }
+    validate_close_payload(&self.payload)?;
+    let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+    let reason = self.payload[2..].to_vec();
+    Ok(Some(CloseFrame {
+      code: CloseCode::from(code),
+      // SAFETY: validated by `validate_close_payload` above.
+      reason: unsafe { String::from_utf8_unchecked(reason) },
+    }))
+  }
+
+  /// Checks the frame against the rules RFC 6455 places on a single frame:
+  ///
+  /// - control frames must not be fragmented and must have a payload of at most 125 bytes,
+  /// - text frames with FIN set must be valid UTF-8,
+  /// - close frames must carry either no payload, or an allowed status code followed by a UTF-8 reason.
+  ///
+  /// The payload must be unmasked. Rules that span several frames, such as continuation sequencing, are not checked.
+  pub fn validate(&self) -> Result<(), WebSocketError> {
+    if is_control(self.opcode) {
+      if !self.fin {
+        return Err(WebSocketError::ControlFrameFragmented);
+      }
+      if self.payload.len() > 125 {
+        return Err(match self.opcode {
+          OpCode::Ping => WebSocketError::PingFrameTooLarge,
+          _ => WebSocketError::ControlFrameTooLarge,
+        });
+      }
+    }
+
+    match self.opcode {
+      OpCode::Text if self.fin && !self.is_utf8() => {
+        Err(WebSocketError::InvalidUTF8)
+      }
+      OpCode::Close => validate_close_payload(&self.payload),
+      _ => Ok(()),
+    }
+  }
+
+  /// Masks the payload in-place with the frame's masking key, generating a random key if it has none.
   pub fn mask(&mut self) {
     if let Some(mask) = self.mask {
-      crate::mask::unmask(self.payload.to_mut(), mask);
+      crate::unmask(self.payload.to_mut(), mask);
     } else {
       let mask: [u8; 4] = rand::random();
-      crate::mask::unmask(self.payload.to_mut(), mask);
+      crate::unmask(self.payload.to_mut(), mask);
       self.mask = Some(mask);
     }
   }
@@ -250,7 +517,7 @@ impl<'f> Frame<'f> {
   /// Note: By default, the frame payload is unmasked by `WebSocket::read_frame`.
   pub fn unmask(&mut self) {
     if let Some(mask) = self.mask {
-      crate::mask::unmask(self.payload.to_mut(), mask);
+      crate::unmask(self.payload.to_mut(), mask);
     }
   }
 
@@ -259,97 +526,497 @@ impl<'f> Frame<'f> {
   /// # Panics
   ///
   /// This method panics if the head buffer is not at least n-bytes long, where n is the size of the length field (0, 2, 4, or 10)
-  pub fn fmt_head(&mut self, head: &mut [u8]) -> usize {
-    head[0] = (self.fin as u8) << 7 | (self.opcode as u8);
-
-    let len = self.payload.len();
-    let size = if len < 126 {
-      head[1] = len as u8;
-      2
-    } else if len < 65536 {
-      head[1] = 126;
-      head[2..4].copy_from_slice(&(len as u16).to_be_bytes());
-      4
-    } else {
-      head[1] = 127;
-      head[2..10].copy_from_slice(&(len as u64).to_be_bytes());
-      10
+  pub fn fmt_head(&self, head: &mut [u8]) -> usize {
+    self.fmt_head_with_mask(head, self.mask)
+  }
+
+  pub(crate) fn fmt_head_with_mask(
+    &self,
+    head: &mut [u8],
+    mask: Option<[u8; 4]>,
+  ) -> usize {
+    let fields = codec::Head {
+      fin: self.fin,
+      rsv: self.rsv_bits(),
+      opcode: self.opcode.into(),
+      mask,
+      payload_len: self.payload.len() as u64,
     };
+    codec::encode_head(&fields, head)
+  }
 
-    if let Some(mask) = self.mask {
-      head[1] |= 0x80;
-      head[size..size + 4].copy_from_slice(&mask);
-      size + 4
-    } else {
-      size
-    }
+  pub async fn writev<S>(&self, stream: &mut S) -> Result<(), std::io::Error>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.writev_to(stream).await
   }
 
-  pub async fn writev<S>(
-    &mut self,
+  pub(crate) async fn writev_to<S>(
+    &self,
     stream: &mut S,
   ) -> Result<(), std::io::Error>
   where
-    S: AsyncWriteExt + Unpin,
+    S: WsWrite,
   {
-    use std::io::IoSlice;
+    let mut head = [0; MAX_HEAD_SIZE];
+    let size = self.fmt_head(&mut head);
+    writev_parts(stream, &head[..size], &self.payload).await
+  }
 
+  /// Writes the frame to the buffer and returns a slice of the buffer containing the frame.
+  ///
+  /// The buffer is cleared first. Only its spare capacity is written to, so it is never zero-filled.
+  pub fn write<'a>(&self, buf: &'a mut Vec<u8>) -> &'a [u8] {
     let mut head = [0; MAX_HEAD_SIZE];
     let size = self.fmt_head(&mut head);
+    write_parts(buf, &head[..size], &self.payload)
+  }
 
-    let total = size + self.payload.len();
+  /// Like `write`, but masks the copy in the buffer with `mask` and leaves the frame itself untouched.
+  pub(crate) fn write_masked<'a>(
+    &self,
+    mask: [u8; 4],
+    buf: &'a mut Vec<u8>,
+  ) -> &'a [u8] {
+    let mut head = [0; MAX_HEAD_SIZE];
+    let size = self.fmt_head_with_mask(&mut head, Some(mask));
+    write_masked_parts(buf, &head[..size], &self.payload, mask)
+  }
 
-    let mut b = [IoSlice::new(&head[..size]), IoSlice::new(&self.payload)];
+  /// Encodes a frame with a payload of at most `SMALL_FRAME_SIZE` bytes on the stack. If `mask` is given, it is
+  /// used in place of the frame's masking key and the copied payload is masked with it.
+  pub(crate) fn write_small(&self, mask: Option<[u8; 4]>) -> SmallFrame {
+    let mut head = [0; MAX_HEAD_SIZE];
+    let size = self.fmt_head_with_mask(&mut head, mask.or(self.mask));
+    SmallFrame::new(&head[..size], &self.payload, mask)
+  }
 
-    let mut n = stream.write_vectored(&b).await?;
-    if n == total {
-      return Ok(());
-    }
+  /// Encodes the header of this frame so it can be reused for other frames of the same shape.
+  ///
+  /// The masking key is not part of the cached header; it is added when the frame is written.
+  pub fn header(&self) -> FrameHeader {
+    FrameHeader::new(self.fin, self.opcode, self.payload.len())
+  }
+
+  /// Whether the RSV1 bit is set, which marks the first frame of a message compressed with permessage-deflate. Frames
+  /// only keep it, with their payload compressed, when read with `WebSocket::set_compression_passthrough` enabled.
+  pub fn is_compressed(&self) -> bool {
+    self.rsv1
+  }
+
+  /// The RSV1, RSV2 and RSV3 bits of the frame, in their positions in the first byte of the header: `0x40`, `0x20`
+  /// and `0x10`.
+  pub fn rsv_bits(&self) -> u8 {
+    (self.rsv1 as u8) << 6 | (self.rsv2 as u8) << 5 | (self.rsv3 as u8) << 4
+  }
 
-    // Slightly more optimized than (unstable) write_all_vectored for 2 iovecs.
-    while n <= size {
-      b[0] = IoSlice::new(&head[n..size]);
-      n += stream.write_vectored(&b).await?;
+  /// Sets the RSV bits of the frame, for an `Extension` that claims them. Bits of `bits` other than `0x40`, `0x20` and
+  /// `0x10` are ignored.
+  pub fn set_rsv_bits(&mut self, bits: u8) {
+    self.rsv1 = bits & 0x40 != 0;
+    self.rsv2 = bits & 0x20 != 0;
+    self.rsv3 = bits & 0x10 != 0;
+  }
+
+  /// Sets whether the message this frame starts may be compressed when it is written, to send a specific message
+  /// uncompressed. Only the first frame of a message decides; it has no effect if compression is not negotiated.
+  ///
+  /// Default: `true`
+  pub fn set_compressed(&mut self, compressed: bool) {
+    self.compress = compressed;
+  }
+
+  /// The masking key of the frame, if any.
+  pub fn mask_key(&self) -> Option<[u8; 4]> {
+    self.mask
+  }
+
+  /// Sets the masking key a client uses for this frame instead of a random one. The payload must not be masked yet.
+  ///
+  /// This makes the bytes on the wire reproducible for conformance tooling and differential testing. Outside of tests
+  /// the key should be left to the random generator, as RFC 6455 Section 10.3 requires it to be unpredictable.
+  pub fn with_mask_key(mut self, mask: [u8; 4]) -> Self {
+    self.mask = Some(mask);
+    self
+  }
+
+  /// Detaches the frame from any borrowed buffer, copying the payload if needed.
+  #[cfg(feature = "unstable-split")]
+  pub(crate) fn into_owned(self) -> Frame<'static> {
+    let rsv = self.rsv_bits();
+    let payload = match self.payload {
+      Payload::Bytes(b) => Payload::Bytes(b),
+      Payload::Owned(v) => Payload::Owned(v),
+      Payload::Borrowed(b) => Payload::Owned(b.to_vec()),
+      Payload::BorrowedMut(b) => Payload::Owned(b.to_vec()),
+    };
+    let mut frame = Frame::new(self.fin, self.opcode, self.mask, payload);
+    frame.set_rsv_bits(rsv);
+    frame.compress = self.compress;
+    frame
+  }
+}
+
+/// Checks that a close frame payload is empty or an allowed status code followed by a UTF-8 reason.
+pub(crate) fn validate_close_payload(
+  payload: &[u8],
+) -> Result<(), WebSocketError> {
+  match payload.len() {
+    0 => Ok(()),
+    1 => Err(WebSocketError::InvalidCloseFrame),
+    _ => {
+      let code =
+        CloseCode::from(u16::from_be_bytes(payload[0..2].try_into().unwrap()));
+
+      #[cfg(feature = "simd")]
+      if simdutf8::basic::from_utf8(&payload[2..]).is_err() {
+        return Err(WebSocketError::InvalidUTF8);
+      };
+
+      #[cfg(not(feature = "simd"))]
+      if std::str::from_utf8(&payload[2..]).is_err() {
+        return Err(WebSocketError::InvalidUTF8);
+      };
+
+      if !code.is_allowed() {
+        return Err(WebSocketError::InvalidCloseCode);
+      }
+      Ok(())
     }
+  }
+}
 
-    // Header out of the way.
-    if n < total && n > size {
-      stream.write_all(&self.payload[n - size..]).await?;
+/// A precomputed frame header for sending many frames with the same FIN bit, opcode and payload length.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{FrameHeader, OpCode, WebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn send_ticks(
+///   ws: &mut WebSocket<TcpStream>,
+///   ticks: &[[u8; 32]],
+/// ) -> Result<()> {
+///   let header = FrameHeader::new(true, OpCode::Binary, 32);
+///   for tick in ticks {
+///     ws.write_with_header(&header, tick).await?;
+///   }
+///   Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+  head: [u8; MAX_HEAD_SIZE],
+  size: usize,
+  fin: bool,
+  opcode: OpCode,
+  payload_len: usize,
+}
+
+impl FrameHeader {
+  /// Encodes an unmasked frame header.
+  pub fn new(fin: bool, opcode: OpCode, payload_len: usize) -> Self {
+    let mut head = [0; MAX_HEAD_SIZE];
+    let size = encode_head(&mut head, fin, opcode, payload_len, None);
+    Self {
+      head,
+      size,
+      fin,
+      opcode,
+      payload_len,
     }
+  }
 
-    Ok(())
+  /// Indicates if frames with this header are the final frame in a message.
+  pub fn fin(&self) -> bool {
+    self.fin
   }
 
-  /// Writes the frame to the buffer and returns a slice of the buffer containing the frame.
-  pub fn write<'a>(&mut self, buf: &'a mut Vec<u8>) -> &'a [u8] {
-    fn reserve_enough(buf: &mut Vec<u8>, len: usize) {
-      if buf.len() < len {
-        buf.resize(len, 0);
-      }
+  /// The opcode of frames with this header.
+  pub fn opcode(&self) -> OpCode {
+    self.opcode
+  }
+
+  /// The payload length frames with this header must have.
+  pub fn payload_len(&self) -> usize {
+    self.payload_len
+  }
+
+  /// The encoded header, without a masking key.
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.head[..self.size]
+  }
+
+  /// The encoded header with the mask bit set and `mask` appended.
+  pub(crate) fn masked(&self, mask: [u8; 4]) -> ([u8; MAX_HEAD_SIZE], usize) {
+    let mut head = self.head;
+    head[1] |= 0x80;
+    head[self.size..self.size + 4].copy_from_slice(&mask);
+    (head, self.size + 4)
+  }
+}
+
+/// Frames with payloads up to this size are encoded on the stack and written with a single call, instead of going
+/// through the write buffer or a vectored write.
+pub(crate) const SMALL_FRAME_SIZE: usize = 256;
+
+/// A complete frame with a payload of at most `SMALL_FRAME_SIZE` bytes.
+pub(crate) struct SmallFrame {
+  buf: [u8; MAX_HEAD_SIZE + SMALL_FRAME_SIZE],
+  len: usize,
+}
+
+impl SmallFrame {
+  /// Copies an encoded header and a payload, masking the copied payload if `mask` is given.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the payload is longer than `SMALL_FRAME_SIZE`.
+  pub fn new(head: &[u8], payload: &[u8], mask: Option<[u8; 4]>) -> Self {
+    let mut buf = [0; MAX_HEAD_SIZE + SMALL_FRAME_SIZE];
+    let len = head.len() + payload.len();
+    buf[..head.len()].copy_from_slice(head);
+    buf[head.len()..len].copy_from_slice(payload);
+    if let Some(mask) = mask {
+      crate::unmask(&mut buf[head.len()..len], mask);
     }
-    let len = self.payload.len();
-    reserve_enough(buf, len + MAX_HEAD_SIZE);
+    Self { buf, len }
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.buf[..self.len]
+  }
+}
+
+/// Formats a frame header into `head`. Returns the size of the header.
+fn encode_head(
+  head: &mut [u8],
+  fin: bool,
+  opcode: OpCode,
+  len: usize,
+  mask: Option<[u8; 4]>,
+) -> usize {
+  let fields = codec::Head {
+    fin,
+    rsv: 0,
+    opcode: opcode.into(),
+    mask,
+    payload_len: len as u64,
+  };
+  codec::encode_head(&fields, head)
+}
+
+/// Writes an encoded header and a payload with vectored writes.
+pub(crate) async fn writev_parts<S>(
+  stream: &mut S,
+  head: &[u8],
+  payload: &[u8],
+) -> Result<(), std::io::Error>
+where
+  S: WsWrite,
+{
+  use std::io::IoSlice;
+
+  let size = head.len();
+  let total = size + payload.len();
+
+  let mut b = [IoSlice::new(head), IoSlice::new(payload)];
+
+  let mut n = stream.send_vectored(&b).await?;
+  if n == total {
+    return Ok(());
+  }
 
-    let size = self.fmt_head(buf);
-    buf[size..size + len].copy_from_slice(&self.payload);
-    &buf[..size + len]
+  // Slightly more optimized than (unstable) write_all_vectored for 2 iovecs.
+  while n <= size {
+    b[0] = IoSlice::new(&head[n..size]);
+    n += stream.send_vectored(&b).await?;
   }
+
+  // Header out of the way.
+  if n < total && n > size {
+    stream.send_all(&payload[n - size..]).await?;
+  }
+
+  Ok(())
+}
+
+/// Copies an encoded header and a payload into `buf`.
+pub(crate) fn write_parts<'a>(
+  buf: &'a mut Vec<u8>,
+  head: &[u8],
+  payload: &[u8],
+) -> &'a [u8] {
+  buf.clear();
+  buf.reserve_exact(head.len() + payload.len());
+  buf.extend_from_slice(head);
+  buf.extend_from_slice(payload);
+  buf
+}
+
+/// Copies an encoded header and a payload into `buf`, masking the copied payload.
+pub(crate) fn write_masked_parts<'a>(
+  buf: &'a mut Vec<u8>,
+  head: &[u8],
+  payload: &[u8],
+  mask: [u8; 4],
+) -> &'a [u8] {
+  write_parts(buf, head, payload);
+  crate::unmask(&mut buf[head.len()..], mask);
+  buf
 }
 
 repr_u8! {
+    /// The opcode of a WebSocket frame (RFC 6455, Section 5.2).
+    ///
+    /// The discriminants are the on-the-wire values. Use `OpCode::try_from(u8)` and `u8::from(OpCode)` to convert;
+    /// reserved opcodes fail to convert with `WebSocketError::InvalidValue`.
     #[repr(u8)]
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
     pub enum OpCode {
+        /// `0x0`: continues a fragmented message.
         Continuation = 0x0,
+        /// `0x1`: UTF-8 text data.
         Text = 0x1,
+        /// `0x2`: binary data.
         Binary = 0x2,
+        /// `0x8`: connection close.
         Close = 0x8,
+        /// `0x9`: ping.
         Ping = 0x9,
+        /// `0xA`: pong.
         Pong = 0xA,
     }
 }
 
+impl OpCode {
+  /// Returns `true` for `Close`, `Ping` and `Pong`.
+  #[inline]
+  pub fn is_control(self) -> bool {
+    is_control(self)
+  }
+
+  /// Returns `true` for `Continuation`, `Text` and `Binary`.
+  #[inline]
+  pub fn is_data(self) -> bool {
+    !is_control(self)
+  }
+}
+
 #[inline]
 pub fn is_control(opcode: OpCode) -> bool {
   matches!(opcode, OpCode::Close | OpCode::Ping | OpCode::Pong)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn close_frames() {
+    let frame = Frame::close_with(CloseCode::Normal, &"\u{e9}".repeat(100));
+    let close = frame.as_close().unwrap().unwrap();
+    assert_eq!(close.code, CloseCode::Normal);
+    assert_eq!(close.reason.len(), 122);
+
+    assert!(Frame::close_raw(b"".as_ref().into())
+      .as_close()
+      .unwrap()
+      .is_none());
+    assert!(Frame::text(b"hi".as_ref().into())
+      .as_close()
+      .unwrap()
+      .is_none());
+    assert!(matches!(
+      Frame::close(1005, b"").as_close(),
+      Err(WebSocketError::InvalidCloseCode)
+    ));
+    assert!(matches!(
+      Frame::close(1000, b"\xff").as_close(),
+      Err(WebSocketError::InvalidUTF8)
+    ));
+  }
+
+  #[test]
+  fn validate() {
+    assert!(Frame::text(b"hello".as_ref().into()).validate().is_ok());
+    assert!(matches!(
+      Frame::text(b"\xff".as_ref().into()).validate(),
+      Err(WebSocketError::InvalidUTF8)
+    ));
+    // Fragments of a text message may split a code point.
+    assert!(
+      Frame::new(false, OpCode::Text, None, b"\xc3".as_ref().into())
+        .validate()
+        .is_ok()
+    );
+    assert!(matches!(
+      Frame::new(false, OpCode::Ping, None, b"".as_ref().into()).validate(),
+      Err(WebSocketError::ControlFrameFragmented)
+    ));
+    assert!(matches!(
+      Frame::pong(vec![0; 126].into()).validate(),
+      Err(WebSocketError::ControlFrameTooLarge)
+    ));
+    assert!(Frame::close(1000, b"bye").validate().is_ok());
+    assert!(matches!(
+      Frame::close_raw(vec![3].into()).validate(),
+      Err(WebSocketError::InvalidCloseFrame)
+    ));
+    assert!(matches!(
+      Frame::close(1005, b"").validate(),
+      Err(WebSocketError::InvalidCloseCode)
+    ));
+  }
+
+  #[test]
+  fn text_accessors() {
+    let frame = Frame::text(b"hello".as_ref().into());
+    assert_eq!(frame.as_text(), Some("hello"));
+    assert_eq!(frame.into_text().as_deref(), Some("hello"));
+    assert_eq!(Frame::binary(b"hello".as_ref().into()).as_text(), None);
+    assert_eq!(Frame::text(b"\xff".as_ref().into()).into_text(), None);
+  }
+
+  #[test]
+  fn formatting() {
+    let frame = Frame::text(b"hello".as_ref().into());
+    assert_eq!(frame.to_string(), r#"Text (fin, 5 bytes) "hello""#);
+    assert_eq!(
+      format!("{:?}", frame),
+      r#"Frame { fin: true, opcode: Text, masked: false, len: 5, payload: "hello" }"#
+    );
+
+    let frame = Frame::new(false, OpCode::Binary, None, vec![0xab; 40].into());
+    assert_eq!(
+      format!("{:.2}", frame),
+      "Binary (not fin, 40 bytes) ab ab... (+38 bytes)"
+    );
+    assert!(format!("{:?}", frame).ends_with("... (+8 bytes) }"));
+    assert!(!format!("{:#?}", frame).contains("..."));
+
+    // Truncation never splits a character.
+    let frame = Frame::text("ééé".as_bytes().into());
+    assert_eq!(
+      format!("{:.3}", frame),
+      r#"Text (fin, 6 bytes) "é"... (+4 bytes)"#
+    );
+  }
+
+  #[test]
+  fn clone_and_eq() {
+    let mut data = *b"hello";
+    let borrowed = Frame::text(Payload::BorrowedMut(&mut data));
+    let owned = Frame::text(b"hello".to_vec().into());
+    assert_eq!(borrowed.clone(), owned);
+    assert_eq!(
+      Frame::binary(Payload::Bytes(BytesMut::from(&b"hello"[..]))),
+      Frame::binary(b"hello".as_ref().into())
+    );
+    assert_ne!(owned, Frame::binary(b"hello".as_ref().into()));
+    assert_ne!(owned, Frame::text(b"hell".as_ref().into()));
+  }
+}
@@ -0,0 +1,144 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::CloseCode;
+use crate::Frame;
+use crate::WebSocket;
+
+/// A `WebSocket` that sends a close frame with status code 1001 (going away) when it is dropped before a close
+/// frame has been sent, so that handlers returning early do not leave the peer with an abnormal closure (1006).
+///
+/// The close frame is written by a task spawned on the current tokio runtime. Without a runtime, as when the
+/// runtime itself is shutting down, the connection is dropped as before. The peer's close frame is not awaited;
+/// use `WebSocket::close` for that.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{GracefulWebSocket, WebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn handle(ws: WebSocket<TcpStream>) -> Result<()> {
+///   let mut ws = GracefulWebSocket::new(ws);
+///   let frame = ws.read_frame().await?;
+///   // Returning with an error here still closes the connection properly.
+///   ws.write_frame(frame).await?;
+///   Ok(())
+/// }
+/// ```
+pub struct GracefulWebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  ws: Option<WebSocket<S>>,
+}
+
+impl<S> GracefulWebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  /// Wraps `ws` to close it when dropped.
+  pub fn new(ws: WebSocket<S>) -> Self {
+    Self { ws: Some(ws) }
+  }
+
+  /// Returns the `WebSocket`, which is no longer closed when dropped.
+  pub fn into_inner(mut self) -> WebSocket<S> {
+    self.ws.take().unwrap()
+  }
+}
+
+impl<S> Deref for GracefulWebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  type Target = WebSocket<S>;
+
+  fn deref(&self) -> &WebSocket<S> {
+    self.ws.as_ref().unwrap()
+  }
+}
+
+impl<S> DerefMut for GracefulWebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  fn deref_mut(&mut self) -> &mut WebSocket<S> {
+    self.ws.as_mut().unwrap()
+  }
+}
+
+impl<S> Drop for GracefulWebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  fn drop(&mut self) {
+    let Some(mut ws) = self.ws.take() else {
+      return;
+    };
+    if ws.is_closed() {
+      return;
+    }
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+      return;
+    };
+    runtime.spawn(async move {
+      // Finish a frame started with `poll_write_frame` first, so the close frame is not written into its middle.
+      poll_fn(|cx| ws.poll_flush(cx)).await?;
+      let frame = Frame::close_with(CloseCode::Away, "");
+      ws.write_frame(frame).await?;
+      ws.flush().await
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+  use crate::Role;
+
+  #[tokio::test]
+  async fn sends_going_away_when_dropped() {
+    let (server, mut client) = tokio::io::duplex(1024);
+    let ws = WebSocket::after_handshake(server, Role::Server);
+    drop(GracefulWebSocket::new(ws));
+
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x88, 2, 0x03, 0xe9]);
+  }
+
+  #[tokio::test]
+  async fn explicit_close_is_not_repeated() {
+    let (server, mut client) = tokio::io::duplex(1024);
+    let ws = WebSocket::after_handshake(server, Role::Server);
+    let mut ws = GracefulWebSocket::new(ws);
+    ws.write_frame(Frame::close(1000, b"")).await.unwrap();
+    drop(ws);
+
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x88, 2, 0x03, 0xe8]);
+  }
+}
@@ -0,0 +1,88 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The IO operations `ReadHalf` and `WriteHalf` need from a transport.
+//!
+//! The protocol core only talks to these traits, so another runtime's IO traits can be supported with an adapter
+//! type implementing them, rather than another copy of the read and write paths. Streams implementing tokio's
+//! `AsyncRead` and `AsyncWrite` implement them directly.
+//!
+//! `compat::FuturesIo` is that adapter for streams implementing the `futures-io` traits. It implements tokio's traits,
+//! which only needs tokio's IO traits and no tokio runtime. The `embedded-io-async` traits have async methods that
+//! cannot back tokio's poll-based traits, so `compat::EmbeddedWebSocket` implements these traits for its stream
+//! instead. Streams of completion-based runtimes take ownership of the buffers, so `OwnedWebSocket` implements them
+//! over the public `OwnedRead` and `OwnedWrite` traits, which `compat::UringWebSocket` implements for `tokio-uring`.
+
+use std::io;
+use std::io::IoSlice;
+
+use bytes::BufMut;
+use bytes::BytesMut;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+/// The reading side of a transport.
+pub(crate) trait WsRead {
+  /// Appends at most `limit` bytes to `buf`, reading into its spare capacity. Returns the number of bytes read,
+  /// which is 0 at the end of the stream.
+  async fn read_into(
+    &mut self,
+    buf: &mut BytesMut,
+    limit: usize,
+  ) -> io::Result<usize>;
+}
+
+/// The writing side of a transport.
+pub(crate) trait WsWrite {
+  /// Writes all of `buf`.
+  async fn send_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+  /// Writes from `bufs` in order and returns the number of bytes written, which may be less than their total.
+  async fn send_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize>;
+}
+
+/// A stream that never has data, for parsing a frame that is already in the read buffer.
+pub(crate) struct Drained;
+
+impl WsRead for Drained {
+  async fn read_into(
+    &mut self,
+    _buf: &mut BytesMut,
+    _limit: usize,
+  ) -> io::Result<usize> {
+    Err(io::ErrorKind::WouldBlock.into())
+  }
+}
+
+impl<S: AsyncRead + Unpin> WsRead for S {
+  async fn read_into(
+    &mut self,
+    buf: &mut BytesMut,
+    limit: usize,
+  ) -> io::Result<usize> {
+    AsyncReadExt::read_buf(self, &mut buf.limit(limit)).await
+  }
+}
+
+impl<S: AsyncWrite + Unpin> WsWrite for S {
+  async fn send_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    AsyncWriteExt::write_all(self, buf).await
+  }
+
+  async fn send_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    AsyncWriteExt::write_vectored(self, bufs).await
+  }
+}
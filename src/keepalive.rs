@@ -0,0 +1,370 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::Frame;
+use crate::FrameRead;
+use crate::OpCode;
+use crate::Payload;
+use crate::ReadHalf;
+use crate::Sleep;
+use crate::Timer;
+use crate::WebSocketError;
+use crate::WriteHalf;
+
+/// Keepalive state of a `ReadHalf`, set with `WebSocket::set_keepalive`.
+pub(crate) struct KeepAlive {
+  interval: Duration,
+  timeout: Duration,
+  /// Created on the first read, so that the keepalive can be set outside of a runtime.
+  timer: Option<Sleep>,
+  last_frame: Instant,
+  /// Whether a ping has been sent and no frame has been received since.
+  awaiting_pong: bool,
+}
+
+impl KeepAlive {
+  pub(crate) fn new(
+    interval: Duration,
+    timeout: Duration,
+    timer: &dyn Timer,
+  ) -> Self {
+    Self {
+      interval,
+      timeout,
+      timer: None,
+      last_frame: timer.now(),
+      awaiting_pong: false,
+    }
+  }
+}
+
+/// Read timeout state of a `ReadHalf`, set with `WebSocket::set_read_timeout`.
+pub(crate) struct ReadTimeout {
+  duration: Duration,
+  /// Created on the first read, like the keepalive timer.
+  timer: Option<Sleep>,
+}
+
+impl ReadTimeout {
+  pub(crate) fn new(duration: Duration) -> Self {
+    Self {
+      duration,
+      timer: None,
+    }
+  }
+}
+
+/// What woke up a read with a keepalive or a read timeout.
+enum Wake<'f> {
+  Frame(FrameRead<'f>),
+  Ping,
+  KeepAliveTimedOut,
+  ReadTimedOut,
+}
+
+impl ReadHalf {
+  /// Like `read_frame_inner`, but while no frame arrives, pings are written to `write_half` as the keepalive
+  /// requires. When the keepalive times out, the error comes with a close frame owed to the peer.
+  ///
+  /// The read timeout applies as in `read_frame_timed`.
+  pub(crate) async fn read_frame_keepalive<'f, S>(
+    &mut self,
+    write_half: &mut WriteHalf,
+    stream: &mut S,
+  ) -> FrameRead<'f>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    if self.keepalive.is_none() {
+      return self.read_frame_timed(stream).await;
+    }
+    self.start_read_timeout();
+    loop {
+      match poll_fn(|cx| self.poll_timers(cx, stream, true)).await {
+        Wake::Frame(read) => return read,
+        Wake::Ping if !write_half.closed => {
+          let ping =
+            Frame::new(true, OpCode::Ping, None, Payload::Borrowed(&[]));
+          if let Err(e) = write_half.write_frame(stream, ping).await {
+            return (Err(e), None);
+          }
+        }
+        Wake::Ping => {}
+        Wake::KeepAliveTimedOut => {
+          return (
+            Err(WebSocketError::KeepAliveTimeout),
+            Some(Frame::close(1001, b"")),
+          )
+        }
+        Wake::ReadTimedOut => return (Err(WebSocketError::ReadTimeout), None),
+      }
+    }
+  }
+
+  /// Like `read_frame_inner`, but fails with `WebSocketError::ReadTimeout` if no frame arrives within the read
+  /// timeout. Bytes of a frame that has not arrived completely stay buffered for the next read.
+  pub(crate) async fn read_frame_timed<'f, S>(
+    &mut self,
+    stream: &mut S,
+  ) -> FrameRead<'f>
+  where
+    S: AsyncRead + Unpin,
+  {
+    if self.read_timeout.is_none() {
+      return self.read_frame_inner(stream).await;
+    }
+    self.start_read_timeout();
+    match poll_fn(|cx| self.poll_timers(cx, stream, false)).await {
+      Wake::Frame(read) => read,
+      Wake::ReadTimedOut => (Err(WebSocketError::ReadTimeout), None),
+      Wake::Ping | Wake::KeepAliveTimedOut => unreachable!(),
+    }
+  }
+
+  fn start_read_timeout(&mut self) {
+    let Some(read_timeout) = &mut self.read_timeout else {
+      return;
+    };
+    let deadline = self.timer.now() + read_timeout.duration;
+    read_timeout.timer = Some(self.timer.sleep_until(deadline));
+  }
+
+  fn poll_timers<'f, S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+    keepalive: bool,
+  ) -> Poll<Wake<'f>>
+  where
+    S: AsyncRead + Unpin,
+  {
+    if let Poll::Ready(read) = self.poll_read_frame_inner(cx, stream) {
+      if let Some(keepalive) = &mut self.keepalive {
+        keepalive.last_frame = self.timer.now();
+        keepalive.awaiting_pong = false;
+      }
+      return Poll::Ready(Wake::Frame(read));
+    }
+    if let Some(timer) = self
+      .read_timeout
+      .as_mut()
+      .and_then(|read_timeout| read_timeout.timer.as_mut())
+    {
+      if timer.as_mut().poll(cx).is_ready() {
+        return Poll::Ready(Wake::ReadTimedOut);
+      }
+    }
+    let Some(keepalive) = self.keepalive.as_mut().filter(|_| keepalive) else {
+      return Poll::Pending;
+    };
+    let idle_until = keepalive.last_frame + keepalive.interval;
+    let timer = keepalive
+      .timer
+      .get_or_insert_with(|| self.timer.sleep_until(idle_until));
+    loop {
+      ready!(timer.as_mut().poll(cx));
+      if keepalive.awaiting_pong {
+        return Poll::Ready(Wake::KeepAliveTimedOut);
+      }
+      // The timer is only moved when it fires, not on every frame.
+      let idle_until = keepalive.last_frame + keepalive.interval;
+      let now = self.timer.now();
+      if idle_until > now {
+        *timer = self.timer.sleep_until(idle_until);
+        continue;
+      }
+      keepalive.awaiting_pong = true;
+      *timer = self.timer.sleep_until(now + keepalive.timeout);
+      return Poll::Ready(Wake::Ping);
+    }
+  }
+}
+
+impl WriteHalf {
+  /// The result of a write that was given the write timeout. A write that timed out may have left part of a frame
+  /// on the wire, so nothing can be written after it.
+  pub(crate) fn timed_write(
+    &mut self,
+    write: Option<Result<(), WebSocketError>>,
+  ) -> Result<(), WebSocketError> {
+    write.unwrap_or_else(|| {
+      self.closed = true;
+      self.write_timed_out = true;
+      Err(WebSocketError::WriteTimeout)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+  use std::sync::Mutex;
+  use std::time::Duration;
+  use std::time::Instant;
+
+  use tokio::io::AsyncReadExt;
+  use tokio::io::AsyncWriteExt;
+
+  use crate::CloseCode;
+  use crate::Frame;
+  use crate::OpCode;
+  use crate::Role;
+  use crate::Sleep;
+  use crate::Timer;
+  use crate::WebSocket;
+  use crate::WebSocketError;
+
+  /// Skips ahead to each deadline that is waited for.
+  struct VirtualClock(Arc<Mutex<Instant>>);
+
+  impl Timer for VirtualClock {
+    fn now(&self) -> Instant {
+      *self.0.lock().unwrap()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+      let now = self.0.clone();
+      Box::pin(async move {
+        let mut now = now.lock().unwrap();
+        *now = (*now).max(deadline);
+      })
+    }
+  }
+
+  #[tokio::test]
+  async fn times_out_without_pong() {
+    let (server, mut client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_keepalive(Duration::from_millis(20), Duration::from_millis(20));
+
+    let result = ws.read_frame().await;
+    assert!(matches!(result, Err(WebSocketError::KeepAliveTimeout)));
+
+    // A ping, then a close frame with status code 1001.
+    let mut buf = [0; 6];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x89, 0, 0x88, 2, 0x03, 0xe9]);
+  }
+
+  #[tokio::test]
+  async fn keepalive_uses_the_timer() {
+    let (server, mut client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    let start = Instant::now();
+    let clock = Arc::new(Mutex::new(start));
+    ws.set_timer(VirtualClock(clock.clone()));
+    let hour = Duration::from_secs(3600);
+    ws.set_keepalive(hour, hour);
+
+    let result = ws.read_frame().await;
+    assert!(matches!(result, Err(WebSocketError::KeepAliveTimeout)));
+    assert_eq!(*clock.lock().unwrap(), start + 2 * hour);
+    let mut buf = [0; 6];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x89, 0, 0x88, 2, 0x03, 0xe9]);
+
+    ws.set_close_timeout(hour);
+    let result = ws.close(CloseCode::Normal, "").await;
+    assert!(matches!(result, Err(WebSocketError::CloseTimeout)));
+    assert_eq!(*clock.lock().unwrap(), start + 3 * hour);
+  }
+
+  #[tokio::test]
+  async fn latency_uses_the_timer() {
+    let (server, client) = tokio::io::duplex(1024);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    tokio::spawn(async move { while server.read_frame().await.is_ok() {} });
+    let mut ws = WebSocket::after_handshake(client, Role::Client);
+    let clock = Arc::new(Mutex::new(Instant::now()));
+    ws.set_timer(VirtualClock(clock.clone()));
+
+    ws.ping_with_payload(b"1").await.unwrap();
+    *clock.lock().unwrap() += Duration::from_secs(5);
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Pong);
+    assert_eq!(ws.latency(), Some(Duration::from_secs(5)));
+  }
+
+  #[tokio::test]
+  async fn pongs_keep_the_connection_alive() {
+    let (server, client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_keepalive(Duration::from_millis(10), Duration::from_millis(50));
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    tokio::spawn(async move { while client.read_frame().await.is_ok() {} });
+
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(200);
+    let mut pongs = 0;
+    while let Ok(frame) =
+      tokio::time::timeout_at(deadline, ws.read_frame()).await
+    {
+      assert_eq!(frame.unwrap().opcode, OpCode::Pong);
+      pongs += 1;
+    }
+    assert!(pongs >= 3);
+  }
+
+  #[tokio::test]
+  async fn read_timeout_keeps_partial_frames() {
+    let (server, mut client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_read_timeout(Duration::from_millis(20));
+
+    // Half of a masked text frame.
+    client
+      .write_all(&[0x81, 0x82, 0, 0, 0, 0, b'h'])
+      .await
+      .unwrap();
+    let result = ws.read_frame().await;
+    assert!(matches!(result, Err(WebSocketError::ReadTimeout)));
+
+    client.write_all(b"i").await.unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hi"));
+  }
+
+  #[tokio::test]
+  async fn write_timeout_closes_the_connection() {
+    // The peer never reads, so the duplex buffer fills up.
+    let (server, _client) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_write_timeout(Duration::from_millis(20));
+
+    let frame = Frame::binary(vec![0; 1024].into());
+    let result = ws.write_frame(frame).await;
+    assert!(matches!(result, Err(WebSocketError::WriteTimeout)));
+    assert!(ws.is_closed());
+    let result = ws.write_frame(Frame::close(1000, b"")).await;
+    assert!(matches!(result, Err(WebSocketError::ConnectionClosed)));
+  }
+
+  #[tokio::test]
+  async fn close_timeout() {
+    let (server, _client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_close_timeout(Duration::from_millis(20));
+    let result = ws.close(CloseCode::Normal, "").await;
+    assert!(matches!(result, Err(WebSocketError::CloseTimeout)));
+  }
+}
@@ -0,0 +1,564 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use hyper::http::request::Parts;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use pin_project::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::upgrade;
+use crate::upgrade::UpgradeFut;
+use crate::WebSocketError;
+
+/// A tower [`Layer`] that serves websocket upgrades next to an existing service.
+///
+/// Upgrade requests accepted by the predicate are answered with `101 Switching Protocols`, and the handler is
+/// spawned with the [`UpgradeFut`] and the request parts. Every other request goes to the inner service, so the
+/// layer can sit anywhere in a middleware stack: layers outside it see upgrade requests too, for example to
+/// authenticate them.
+///
+/// The handler is a closure taking the `UpgradeFut` and the request parts, or a [`ProtocolRouter`] that picks one by
+/// subprotocol. Upgrade requests the handler rejects, or with invalid `Sec-WebSocket-Key` or `Sec-WebSocket-Version`
+/// headers, are answered with `400 Bad Request`. Responses use the default body of the inner service's response type.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::upgrade::UpgradeFut;
+/// use fastwebsockets::WebSocketLayer;
+/// use hyper::http::request::Parts;
+///
+/// async fn handle(fut: UpgradeFut, _parts: Parts) {
+///   let Ok(mut ws) = fut.await else { return };
+///   while let Ok(frame) = ws.read_frame().await {
+///     if frame.opcode == fastwebsockets::OpCode::Close {
+///       break;
+///     }
+///   }
+/// }
+///
+/// let app: axum::Router = axum::Router::new()
+///   .route("/", axum::routing::get(|| async { "hello" }))
+///   .layer(WebSocketLayer::path("/ws", handle));
+/// ```
+#[derive(Clone, Debug)]
+pub struct WebSocketLayer<P, H> {
+  predicate: P,
+  handler: H,
+  allowed_origins: Option<Arc<[String]>>,
+}
+
+impl<P, H> WebSocketLayer<P, H> {
+  /// Routes the upgrade requests for which `predicate` returns `true` to `handler`.
+  pub fn new(predicate: P, handler: H) -> Self {
+    Self {
+      predicate,
+      handler,
+      allowed_origins: None,
+    }
+  }
+
+  /// Answers the routed upgrade requests of browsers on other origins than `origins`, such as
+  /// `https://app.example.com`, with `403 Forbidden`. See [`upgrade::check_origin`].
+  ///
+  /// Default: all origins are allowed
+  pub fn allowed_origins<I>(mut self, origins: I) -> Self
+  where
+    I: IntoIterator,
+    I::Item: Into<String>,
+  {
+    self.allowed_origins = Some(origins.into_iter().map(Into::into).collect());
+    self
+  }
+}
+
+impl<H> WebSocketLayer<PathPredicate, H> {
+  /// Routes the upgrade requests for `path` to `handler`.
+  pub fn path(path: impl Into<String>, handler: H) -> Self {
+    Self::new(PathPredicate { path: path.into() }, handler)
+  }
+}
+
+impl<S, P, H> Layer<S> for WebSocketLayer<P, H>
+where
+  P: Clone,
+  H: Clone,
+{
+  type Service = WebSocketService<S, P, H>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    WebSocketService {
+      inner,
+      predicate: self.predicate.clone(),
+      handler: self.handler.clone(),
+      allowed_origins: self.allowed_origins.clone(),
+    }
+  }
+}
+
+/// Matches requests by URI path. See [`WebSocketLayer::path`].
+#[derive(Clone, Debug)]
+pub struct PathPredicate {
+  path: String,
+}
+
+/// Decides whether an upgrade request is routed to the websocket handler.
+pub trait UpgradePredicate {
+  /// Returns `true` if the upgrade request is handled by the layer.
+  fn matches(&self, parts: &Parts) -> bool;
+}
+
+impl UpgradePredicate for PathPredicate {
+  fn matches(&self, parts: &Parts) -> bool {
+    parts.uri.path() == self.path
+  }
+}
+
+impl<F> UpgradePredicate for F
+where
+  F: Fn(&Parts) -> bool,
+{
+  fn matches(&self, parts: &Parts) -> bool {
+    self(parts)
+  }
+}
+
+/// Serves the connections upgraded by [`WebSocketLayer`].
+///
+/// Closures taking the [`UpgradeFut`] and the request parts implement it, accepting upgrades without a subprotocol,
+/// and so does [`ProtocolRouter`].
+pub trait UpgradeHandler {
+  /// Selects the subprotocol of a connection from the ones the client offers, in its order of preference. Returning
+  /// an error rejects the upgrade.
+  ///
+  /// Default: no subprotocol
+  fn select_protocol<'a>(
+    &self,
+    offered: &[&'a str],
+  ) -> Result<Option<&'a str>, WebSocketError> {
+    let _ = offered;
+    Ok(None)
+  }
+
+  /// Starts serving a connection upgraded with the selected subprotocol. The connection is available once the
+  /// response has been sent, so this must not wait for `fut`.
+  fn serve(&self, fut: UpgradeFut, parts: Parts, protocol: Option<&str>);
+}
+
+impl<F, Fut> UpgradeHandler for F
+where
+  F: Fn(UpgradeFut, Parts) -> Fut,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  fn serve(&self, fut: UpgradeFut, parts: Parts, _protocol: Option<&str>) {
+    tokio::spawn(self(fut, parts));
+  }
+}
+
+type BoxHandler = Arc<
+  dyn Fn(UpgradeFut, Parts) -> Pin<Box<dyn Future<Output = ()> + Send>>
+    + Send
+    + Sync,
+>;
+
+/// Dispatches upgraded connections to handlers registered by subprotocol.
+///
+/// The subprotocol is the first one offered by the client that has a handler. Clients that offer none of them go to
+/// the fallback handler without a subprotocol, or are rejected if there is none.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::upgrade::UpgradeFut;
+/// use fastwebsockets::{ProtocolRouter, WebSocketLayer};
+/// use hyper::http::request::Parts;
+///
+/// async fn graphql(fut: UpgradeFut, _parts: Parts) {
+///   // ...
+/// }
+///
+/// async fn chat(fut: UpgradeFut, _parts: Parts) {
+///   // ...
+/// }
+///
+/// let layer = WebSocketLayer::path(
+///   "/ws",
+///   ProtocolRouter::new()
+///     .route("graphql-transport-ws", graphql)
+///     .route("chat.v2", chat),
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct ProtocolRouter {
+  routes: Vec<(String, BoxHandler)>,
+  fallback: Option<BoxHandler>,
+}
+
+impl ProtocolRouter {
+  /// Creates a router without handlers.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Serves the connections that select `protocol` with `handler`.
+  pub fn route<H, Fut>(
+    mut self,
+    protocol: impl Into<String>,
+    handler: H,
+  ) -> Self
+  where
+    H: Fn(UpgradeFut, Parts) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+  {
+    self.routes.push((protocol.into(), boxed(handler)));
+    self
+  }
+
+  /// Serves the connections of clients that offer none of the routed subprotocols with `handler`.
+  ///
+  /// Default: such upgrades are rejected
+  pub fn fallback<H, Fut>(mut self, handler: H) -> Self
+  where
+    H: Fn(UpgradeFut, Parts) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+  {
+    self.fallback = Some(boxed(handler));
+    self
+  }
+
+  fn handler(&self, protocol: Option<&str>) -> Option<&BoxHandler> {
+    match protocol {
+      Some(protocol) => self
+        .routes
+        .iter()
+        .find(|(route, _)| route == protocol)
+        .map(|(_, handler)| handler),
+      None => self.fallback.as_ref(),
+    }
+  }
+}
+
+impl std::fmt::Debug for ProtocolRouter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ProtocolRouter")
+      .field(
+        "routes",
+        &self
+          .routes
+          .iter()
+          .map(|(route, _)| route)
+          .collect::<Vec<_>>(),
+      )
+      .field("fallback", &self.fallback.is_some())
+      .finish()
+  }
+}
+
+impl UpgradeHandler for ProtocolRouter {
+  fn select_protocol<'a>(
+    &self,
+    offered: &[&'a str],
+  ) -> Result<Option<&'a str>, WebSocketError> {
+    let routed = offered
+      .iter()
+      .find(|offered| self.handler(Some(offered)).is_some());
+    match routed {
+      Some(protocol) => Ok(Some(*protocol)),
+      None if self.fallback.is_some() => Ok(None),
+      None => Err(WebSocketError::MissingSubprotocol),
+    }
+  }
+
+  fn serve(&self, fut: UpgradeFut, parts: Parts, protocol: Option<&str>) {
+    if let Some(handler) = self.handler(protocol) {
+      tokio::spawn(handler(fut, parts));
+    }
+  }
+}
+
+fn boxed<H, Fut>(handler: H) -> BoxHandler
+where
+  H: Fn(UpgradeFut, Parts) -> Fut + Send + Sync + 'static,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  Arc::new(move |fut, parts| Box::pin(handler(fut, parts)))
+}
+
+/// The service produced by [`WebSocketLayer`].
+#[derive(Clone, Debug)]
+pub struct WebSocketService<S, P, H> {
+  inner: S,
+  predicate: P,
+  handler: H,
+  allowed_origins: Option<Arc<[String]>>,
+}
+
+impl<S, P, H, ReqBody, ResBody> Service<Request<ReqBody>>
+  for WebSocketService<S, P, H>
+where
+  S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+  P: UpgradePredicate,
+  H: UpgradeHandler,
+  ResBody: Default,
+{
+  type Response = Response<ResBody>;
+  type Error = S::Error;
+  type Future = WebSocketFuture<S::Future, ResBody>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+    if !upgrade::is_upgrade_request(&request) {
+      return WebSocketFuture::Inner(self.inner.call(request));
+    }
+    let (parts, body) = request.into_parts();
+    if !self.predicate.matches(&parts) {
+      return WebSocketFuture::Inner(
+        self.inner.call(Request::from_parts(parts, body)),
+      );
+    }
+    if let Some(origins) = &self.allowed_origins {
+      if upgrade::origin_allowed(&parts.headers, origins).is_err() {
+        let mut response = Response::new(ResBody::default());
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        return WebSocketFuture::Upgrade(Some(response));
+      }
+    }
+
+    let offered: Vec<&str> =
+      upgrade::offered_protocols(&parts.headers).collect();
+    let protocol = self
+      .handler
+      .select_protocol(&offered)
+      .map(|protocol| protocol.map(str::to_owned));
+    let mut request = Request::from_parts(parts, ());
+    let response = match (protocol, upgrade::upgrade(&mut request)) {
+      (Ok(protocol), Ok((mut response, fut))) => {
+        if let Some(protocol) = &protocol {
+          response.headers_mut().insert(
+            hyper::header::SEC_WEBSOCKET_PROTOCOL,
+            hyper::header::HeaderValue::from_str(protocol)
+              .expect("bug: invalid subprotocol"),
+          );
+        }
+        let fut = fut.negotiated(&response);
+        let (parts, _) = request.into_parts();
+        self.handler.serve(fut, parts, protocol.as_deref());
+        response.map(|_| ResBody::default())
+      }
+      _ => {
+        let mut response = Response::new(ResBody::default());
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        response
+      }
+    };
+    WebSocketFuture::Upgrade(Some(response))
+  }
+}
+
+/// The response future of [`WebSocketService`].
+#[pin_project(project = WebSocketFutureProj)]
+#[derive(Debug)]
+pub enum WebSocketFuture<F, B> {
+  #[doc(hidden)]
+  Inner(#[pin] F),
+  #[doc(hidden)]
+  Upgrade(Option<Response<B>>),
+}
+
+impl<F, B, E> Future for WebSocketFuture<F, B>
+where
+  F: Future<Output = Result<Response<B>, E>>,
+{
+  type Output = Result<Response<B>, E>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    match self.project() {
+      WebSocketFutureProj::Inner(future) => future.poll(cx),
+      WebSocketFutureProj::Upgrade(response) => {
+        Poll::Ready(Ok(response.take().expect("polled after completion")))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::convert::Infallible;
+
+  #[derive(Clone)]
+  struct Inner;
+
+  impl Service<Request<()>> for Inner {
+    type Response = Response<String>;
+    type Error = Infallible;
+    type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+
+    fn poll_ready(
+      &mut self,
+      _: &mut Context<'_>,
+    ) -> Poll<Result<(), Infallible>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<()>) -> Self::Future {
+      std::future::ready(Ok(Response::new(request.uri().path().to_owned())))
+    }
+  }
+
+  fn upgrade_request(path: &str, key: Option<&str>) -> Request<()> {
+    let mut request = Request::builder()
+      .uri(path)
+      .header("Connection", "upgrade")
+      .header("Upgrade", "websocket")
+      .header("Sec-WebSocket-Version", "13");
+    if let Some(key) = key {
+      request = request.header("Sec-WebSocket-Key", key);
+    }
+    request.body(()).unwrap()
+  }
+
+  #[tokio::test]
+  async fn routes_matching_upgrades() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let layer = WebSocketLayer::path("/ws", move |_fut, parts: Parts| {
+      let tx = tx.clone();
+      async move {
+        tx.send(parts.uri.path().to_owned()).unwrap();
+      }
+    });
+    let mut service = layer.layer(Inner);
+
+    let response = service
+      .call(upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ==")))
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(
+      response.headers()["Sec-WebSocket-Accept"],
+      "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+    assert_eq!(response.body(), "");
+    assert_eq!(rx.recv().await.unwrap(), "/ws");
+
+    let response = service.call(upgrade_request("/ws", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Other paths and plain requests go to the inner service.
+    let response = service
+      .call(upgrade_request("/other", Some("dGhlIHNhbXBsZSBub25jZQ==")))
+      .await
+      .unwrap();
+    assert_eq!(response.body(), "/other");
+    let request = Request::builder().uri("/ws").body(()).unwrap();
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.body(), "/ws");
+    assert!(rx.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn rejects_other_origins() {
+    let layer = WebSocketLayer::path("/ws", |_fut, _parts| async {})
+      .allowed_origins(["https://app.example.com"]);
+    let mut service = layer.layer(Inner);
+
+    let mut request = upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    request
+      .headers_mut()
+      .insert("Origin", "https://evil.example".parse().unwrap());
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut request = upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    request
+      .headers_mut()
+      .insert("Origin", "https://app.example.com".parse().unwrap());
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+  }
+
+  #[tokio::test]
+  async fn closure_predicate() {
+    let layer = WebSocketLayer::new(
+      |parts: &Parts| parts.uri.path().starts_with("/rooms/"),
+      |_fut, _parts| async {},
+    );
+    let mut service = layer.layer(Inner);
+
+    let response = service
+      .call(upgrade_request(
+        "/rooms/1",
+        Some("dGhlIHNhbXBsZSBub25jZQ=="),
+      ))
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    let response = service
+      .call(upgrade_request("/lobby", Some("dGhlIHNhbXBsZSBub25jZQ==")))
+      .await
+      .unwrap();
+    assert_eq!(response.body(), "/lobby");
+  }
+
+  #[tokio::test]
+  async fn routes_by_subprotocol() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (graphql, chat) = (tx.clone(), tx);
+    let router = ProtocolRouter::new()
+      .route("graphql-transport-ws", move |_fut, _parts| {
+        let tx = graphql.clone();
+        async move { tx.send("graphql").unwrap() }
+      })
+      .route("chat.v2", move |_fut, _parts| {
+        let tx = chat.clone();
+        async move { tx.send("chat").unwrap() }
+      });
+    let mut service = WebSocketLayer::path("/ws", router.clone()).layer(Inner);
+
+    let mut request = upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    request.headers_mut().insert(
+      "Sec-WebSocket-Protocol",
+      "chat.v1, chat.v2, graphql-transport-ws".parse().unwrap(),
+    );
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(upgrade::selected_protocol(&response), Some("chat.v2"));
+    assert_eq!(rx.recv().await.unwrap(), "chat");
+
+    // Without a fallback, clients must offer a routed subprotocol.
+    let request = upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let router = router.fallback(|_fut, _parts| async {});
+    let mut service = WebSocketLayer::path("/ws", router).layer(Inner);
+    let request = upgrade_request("/ws", Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    let response = service.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(upgrade::selected_protocol(&response), None);
+    assert!(rx.try_recv().is_err());
+  }
+}
@@ -73,7 +73,28 @@
 //! }
 //! ```
 //!
-//! _permessage-deflate is not supported yet._
+//! Enable the `deflate` feature for permessage-deflate compression (RFC 7692). See `DeflateConfig`.
+//!
+//! Enable the `futures` feature for `Stream` and `Sink` implementations of `WebSocket` and its split halves.
+//!
+//! Enable the `futures-io` feature to run a `WebSocket` on async-std, smol and other runtimes whose streams
+//! implement the `futures-io` traits. See the `compat` module.
+//!
+//! `OwnedWebSocket` runs over streams of completion-based runtimes, such as monoio and glommio, which take ownership
+//! of the buffers they read into and write from. Implement `OwnedRead` and `OwnedWrite` for the stream. Enable the
+//! `io-uring` feature on Linux for `compat::UringWebSocket`, which is an `OwnedWebSocket` over a `tokio-uring` stream.
+//!
+//! Enable the `wasm` feature for `BrowserWebSocket`, a client for `wasm32-unknown-unknown` that sends and receives
+//! messages through the browser's `WebSocket`. It has the `read_message`, `write_message` and `close` methods of
+//! `WebSocket`, so messaging code can be shared between native and browser clients.
+//!
+//! Enable the `tungstenite-compat` feature for `compat::tungstenite::WebSocketStream`, which has the `read`, `send`
+//! and `next` methods of tokio-tungstenite, to move existing code over one call site at a time.
+//!
+//! Enable the `connect` feature for `connect`, which opens a client connection to a `ws://` URL in one call.
+//!
+//! Enable the `tls-rustls` or `tls-native` feature for the TLS connectors in the `tls` module, which `connect` also
+//! uses for `wss://` URLs. The `tls-rustls` feature also adds an acceptor for servers that terminate TLS themselves.
 //!
 //! ## HTTP Upgrades
 //!
@@ -147,45 +168,247 @@
 //!   }
 //! }
 //! ```
+//!
+//! Enable the `autobahn` feature to check the client role of your own wrappers against the Autobahn|Testsuite
+//! with `autobahn::AutobahnClient`.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+/// Autobahn|Testsuite client runner.
+#[cfg(feature = "autobahn")]
+#[cfg_attr(docsrs, doc(cfg(feature = "autobahn")))]
+pub mod autobahn;
+#[cfg(feature = "brotli")]
+mod brotli;
+#[cfg(feature = "wasm")]
+mod browser;
+mod byte_stream;
+mod capture;
 mod close;
+/// Frame codec on byte slices, using only `core`.
+pub mod codec;
+/// Adapters for runtimes other than tokio and for code written against tokio-tungstenite.
+#[cfg(any(
+  feature = "embedded-io",
+  feature = "futures-io",
+  all(feature = "io-uring", target_os = "linux"),
+  feature = "tungstenite-compat"
+))]
+#[cfg_attr(
+  docsrs,
+  doc(cfg(any(
+    feature = "embedded-io",
+    feature = "futures-io",
+    feature = "io-uring",
+    feature = "tungstenite-compat"
+  )))
+)]
+pub mod compat;
+/// Protocol test vectors.
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod conformance;
+#[cfg(feature = "connect")]
+mod connect;
+#[cfg(feature = "deflate")]
+mod deflate;
 mod error;
+mod extension;
+#[cfg(feature = "unstable-split")]
+mod forward;
 mod fragment;
 mod frame;
+#[cfg(feature = "close-on-drop")]
+mod graceful;
 /// Client handshake.
 #[cfg(feature = "upgrade")]
 #[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
 pub mod handshake;
-mod mask;
+mod io;
+#[cfg(feature = "keepalive")]
+mod keepalive;
+#[cfg(feature = "tower")]
+mod layer;
+mod limit;
+mod message;
+mod message_stream;
+mod message_writer;
+/// MQTT over WebSocket.
+#[cfg(feature = "upgrade")]
+#[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
+pub mod mqtt;
+#[cfg(feature = "unstable-split")]
+mod obligated;
+mod owned;
+mod ping;
+mod policy;
+/// Sans-io protocol state machine.
+pub mod proto;
+/// PROXY protocol support.
+#[cfg(feature = "upgrade")]
+#[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
+pub mod proxy;
+/// SOCKS5 proxy client.
+#[cfg(feature = "upgrade")]
+#[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
+pub mod socks;
+mod spill;
+#[cfg(feature = "futures")]
+mod stream;
+/// Blocking websockets without an async runtime.
+pub mod sync;
+mod tap;
+/// Test utilities.
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "keepalive")]
+mod timer;
+/// TLS connectors and acceptors.
+#[cfg(any(feature = "tls-rustls", feature = "tls-native"))]
+#[cfg_attr(
+  docsrs,
+  doc(cfg(any(feature = "tls-rustls", feature = "tls-native")))
+)]
+pub mod tls;
 /// HTTP upgrades.
 #[cfg(feature = "upgrade")]
 #[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
 pub mod upgrade;
+mod validate;
+#[cfg(feature = "zstd")]
+mod zstd;
 
 use bytes::Buf;
-
+use bytes::Bytes;
 use bytes::BytesMut;
-#[cfg(feature = "unstable-split")]
 use std::future::Future;
+use std::pin::pin;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
 
 use tokio::io::AsyncRead;
-use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
 
+#[cfg(feature = "brotli")]
+use crate::brotli::BrotliDecoder;
+#[cfg(feature = "brotli")]
+use crate::brotli::BrotliEncoder;
+#[cfg(feature = "deflate")]
+use crate::deflate::looks_incompressible;
+#[cfg(feature = "deflate")]
+use crate::deflate::Compressor;
+#[cfg(feature = "deflate")]
+use crate::deflate::Decompressor;
+#[cfg(feature = "deflate")]
+use crate::deflate::Deflater;
+#[cfg(feature = "deflate")]
+use crate::deflate::Inflater;
+use crate::io::WsRead;
+use crate::io::WsWrite;
+#[cfg(feature = "keepalive")]
+use crate::keepalive::KeepAlive;
+#[cfg(feature = "keepalive")]
+use crate::keepalive::ReadTimeout;
+use crate::limit::MemoryPermit;
+#[cfg(feature = "unstable-split")]
+use crate::obligated::ControlQueue;
+use crate::ping::PingTracker;
+use crate::policy::FramePolicy;
+use crate::tap::Tapped;
+use crate::tap::WireTap;
+use crate::validate::Utf8Validator;
+#[cfg(feature = "zstd")]
+use crate::zstd::ZstdDecoder;
+#[cfg(feature = "zstd")]
+use crate::zstd::ZstdEncoder;
+
+#[cfg(feature = "brotli")]
+pub use crate::brotli::BrotliConfig;
+#[cfg(feature = "wasm")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm")))]
+pub use crate::browser::BrowserWebSocket;
+pub use crate::byte_stream::WsByteStream;
+pub use crate::capture::CaptureReader;
+pub use crate::capture::CapturedFrame;
+pub use crate::capture::FrameDirection;
+pub use crate::capture::FrameRecorder;
+pub use crate::capture::ReplayStream;
+pub use crate::close::truncate_close_reason;
 pub use crate::close::CloseCode;
+pub use crate::close::CloseFrame;
+pub use crate::close::MAX_CLOSE_REASON_LEN;
+#[cfg(feature = "connect")]
+pub use crate::connect::connect;
+#[cfg(feature = "connect")]
+pub use crate::connect::ConnectOptions;
+#[cfg(feature = "deflate")]
+pub use crate::deflate::DeflateConfig;
+#[cfg(feature = "deflate")]
+pub use crate::deflate::DeflateOffer;
 pub use crate::error::WebSocketError;
+pub use crate::extension::Extension;
+#[cfg(feature = "unstable-split")]
+pub use crate::forward::forward;
+#[cfg(feature = "unstable-split")]
+pub use crate::forward::forward_bidirectional;
 pub use crate::fragment::FragmentCollector;
 #[cfg(feature = "unstable-split")]
 pub use crate::fragment::FragmentCollectorRead;
+pub use crate::fragment::FragmentState;
 pub use crate::frame::Frame;
+pub use crate::frame::FrameHeader;
 pub use crate::frame::OpCode;
 pub use crate::frame::Payload;
-pub use crate::mask::unmask;
-
-#[derive(Copy, Clone, PartialEq)]
+#[cfg(feature = "close-on-drop")]
+pub use crate::graceful::GracefulWebSocket;
+#[cfg(feature = "tower")]
+pub use crate::layer::PathPredicate;
+#[cfg(feature = "tower")]
+pub use crate::layer::ProtocolRouter;
+#[cfg(feature = "tower")]
+pub use crate::layer::UpgradeHandler;
+#[cfg(feature = "tower")]
+pub use crate::layer::UpgradePredicate;
+#[cfg(feature = "tower")]
+pub use crate::layer::WebSocketFuture;
+#[cfg(feature = "tower")]
+pub use crate::layer::WebSocketLayer;
+#[cfg(feature = "tower")]
+pub use crate::layer::WebSocketService;
+pub use crate::limit::MemoryLimiter;
+pub use crate::message::Message;
+pub use crate::message_stream::MessageStream;
+pub use crate::message_writer::MessageWriter;
+#[cfg(feature = "unstable-split")]
+pub use crate::obligated::obligated_channel;
+#[cfg(feature = "unstable-split")]
+pub use crate::obligated::ObligatedReceiver;
+#[cfg(feature = "unstable-split")]
+pub use crate::obligated::ObligatedSender;
+pub use crate::owned::OwnedRead;
+pub use crate::owned::OwnedWebSocket;
+pub use crate::owned::OwnedWrite;
+pub use crate::policy::FrameInfo;
+#[cfg(feature = "spill")]
+pub use crate::spill::Collected;
+#[cfg(feature = "spill")]
+pub use crate::spill::SpilledMessage;
+#[cfg(feature = "keepalive")]
+pub use crate::timer::Sleep;
+#[cfg(feature = "keepalive")]
+pub use crate::timer::Timer;
+#[cfg(feature = "keepalive")]
+pub use crate::timer::TokioTimer;
+#[cfg(feature = "zstd")]
+pub use crate::zstd::ZstdConfig;
+pub use fastwebsockets_codec::unmask;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Role {
   Server,
   Client,
@@ -197,7 +420,53 @@ pub(crate) struct WriteHalf {
   vectored: bool,
   auto_apply_mask: bool,
   writev_threshold: usize,
+  max_write_frame_size: Option<usize>,
   write_buffer: Vec<u8>,
+  connection_memory: Option<MemoryLimiter>,
+  write_buffer_permit: Option<MemoryPermit>,
+  recorder: Option<FrameRecorder>,
+  wire_tap: Option<WireTap>,
+  /// The frame started by `poll_write_frame`, until it is written.
+  poll_write: Option<PollWrite>,
+  extension: Option<Arc<dyn Extension>>,
+  #[cfg(feature = "deflate")]
+  compressor: Option<Compressor>,
+  #[cfg(feature = "deflate")]
+  compression_level: u32,
+  #[cfg(feature = "deflate")]
+  skip_incompressible: bool,
+  #[cfg(feature = "deflate")]
+  min_compress_size: usize,
+  #[cfg(feature = "deflate")]
+  compression_enabled: bool,
+  #[cfg(feature = "deflate")]
+  compress_text: bool,
+  #[cfg(feature = "deflate")]
+  compress_binary: bool,
+  #[cfg(feature = "deflate")]
+  deflate_dictionary: Option<Arc<[u8]>>,
+  /// Whether the message being written is compressed.
+  #[cfg(feature = "deflate")]
+  deflating: bool,
+  /// Whether compressed messages are passed through without inflating or deflating them.
+  #[cfg(feature = "deflate")]
+  compression_passthrough: bool,
+  #[cfg(feature = "keepalive")]
+  write_timeout: Option<std::time::Duration>,
+  /// Whether a write has timed out, leaving part of a frame on the wire.
+  #[cfg(feature = "keepalive")]
+  write_timed_out: bool,
+  #[cfg(feature = "keepalive")]
+  timer: Arc<dyn Timer>,
+}
+
+/// Progress of a frame written with `poll_write_frame`. The encoded frame is in the write buffer, or in `overflow`
+/// if the connection memory budget does not allow growing the write buffer.
+struct PollWrite {
+  written: usize,
+  overflow: Option<Vec<u8>>,
+  /// A payload written after the encoded header with vectored writes instead of being copied into the buffer.
+  payload: Option<Bytes>,
 }
 
 pub(crate) struct ReadHalf {
@@ -207,6 +476,44 @@ pub(crate) struct ReadHalf {
   auto_pong: bool,
   writev_threshold: usize,
   max_message_size: usize,
+  memory_limiter: Option<MemoryLimiter>,
+  connection_memory: Option<MemoryLimiter>,
+  read_buffer_high_water_mark: Option<usize>,
+  accept_unmasked_frames: bool,
+  lenient: bool,
+  frame_policy: Option<FramePolicy>,
+  recorder: Option<FrameRecorder>,
+  wire_tap: Option<WireTap>,
+  extension: Option<Arc<dyn Extension>>,
+  /// Whether a `Stream` implementation has returned a close frame, which ends the stream.
+  #[cfg(feature = "futures")]
+  stream_ended: bool,
+  #[cfg(feature = "deflate")]
+  decompressor: Option<Decompressor>,
+  #[cfg(feature = "deflate")]
+  deflate_dictionary: Option<Arc<[u8]>>,
+  /// Whether the message being read is compressed.
+  #[cfg(feature = "deflate")]
+  inflating: bool,
+  /// Whether compressed messages are passed through without inflating or deflating them.
+  #[cfg(feature = "deflate")]
+  compression_passthrough: bool,
+  /// Bytes the message being read has decompressed to so far.
+  #[cfg(feature = "deflate")]
+  inflated: usize,
+  #[cfg(feature = "keepalive")]
+  keepalive: Option<KeepAlive>,
+  #[cfg(feature = "keepalive")]
+  read_timeout: Option<ReadTimeout>,
+  #[cfg(feature = "keepalive")]
+  close_timeout: Option<std::time::Duration>,
+  #[cfg(feature = "keepalive")]
+  timer: Arc<dyn Timer>,
+  /// Whether a close frame has been received.
+  close_received: bool,
+  /// Validates the fragmented text message being read, if any.
+  utf8: Option<Utf8Validator>,
+  pings: PingTracker,
   buffer: BytesMut,
 }
 
@@ -214,12 +521,16 @@ pub(crate) struct ReadHalf {
 pub struct WebSocketRead<S> {
   stream: S,
   read_half: ReadHalf,
+  control: Arc<ControlQueue>,
 }
 
 #[cfg(feature = "unstable-split")]
 pub struct WebSocketWrite<S> {
   stream: S,
   write_half: WriteHalf,
+  control: Arc<ControlQueue>,
+  /// Whether the frame being written by `poll_write_frame` is the caller's rather than a queued control frame.
+  poll_writing_frame: bool,
 }
 
 #[cfg(feature = "unstable-split")]
@@ -233,26 +544,24 @@ where
   R: AsyncRead + Unpin,
   W: AsyncWrite + Unpin,
 {
+  let control = Arc::new(ControlQueue::default());
   (
     WebSocketRead {
       stream: read,
       read_half: ReadHalf::after_handshake(role),
+      control: control.clone(),
     },
     WebSocketWrite {
       stream: write,
       write_half: WriteHalf::after_handshake(role),
+      control,
+      poll_writing_frame: false,
     },
   )
 }
 
 #[cfg(feature = "unstable-split")]
 impl<'f, S> WebSocketRead<S> {
-  /// Consumes the `WebSocketRead` and returns the underlying stream.
-  #[inline]
-  pub(crate) fn into_parts_internal(self) -> (S, ReadHalf) {
-    (self.stream, self.read_half)
-  }
-
   pub fn set_writev_threshold(&mut self, threshold: usize) {
     self.read_half.writev_threshold = threshold;
   }
@@ -278,6 +587,127 @@ impl<'f, S> WebSocketRead<S> {
     self.read_half.max_message_size = max_message_size;
   }
 
+  /// Sets a `MemoryLimiter` shared with other connections. Each incoming payload is accounted against it while the
+  /// frame is being read, and frames that do not fit are rejected with close code 1013. Returned frames are not
+  /// accounted.
+  ///
+  /// Default: `None`
+  pub fn set_memory_limiter(&mut self, limiter: MemoryLimiter) {
+    self.read_half.memory_limiter = Some(limiter);
+  }
+
+  /// Sets a policy that is consulted with the header of every incoming frame before its payload is read. Returning
+  /// `Err(code)` rejects the frame: the connection is closed with `code` and `read_frame` fails with
+  /// `WebSocketError::FrameRejected`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{CloseCode, OpCode, WebSocket};
+  /// use tokio::net::TcpStream;
+  ///
+  /// fn text_only(ws: &mut WebSocket<TcpStream>) {
+  ///   ws.set_frame_policy(|info| match info.opcode {
+  ///     OpCode::Binary => Err(CloseCode::Unsupported),
+  ///     _ if info.payload_len > 1024 => Err(CloseCode::Size),
+  ///     _ => Ok(()),
+  ///   });
+  /// }
+  /// ```
+  pub fn set_frame_policy(
+    &mut self,
+    policy: impl FnMut(&FrameInfo) -> Result<(), CloseCode> + Send + 'static,
+  ) {
+    self.read_half.frame_policy = Some(Box::new(policy));
+  }
+
+  /// See `WebSocket::set_read_timeout`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_read_timeout(&mut self, timeout: std::time::Duration) {
+    self.read_half.read_timeout = Some(ReadTimeout::new(timeout));
+  }
+
+  /// See `WebSocket::set_timer`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_timer(&mut self, timer: impl Timer + 'static) {
+    self.read_half.timer = Arc::new(timer);
+  }
+
+  /// See `WebSocket::set_recorder`. Share a clone of the recorder with the write half to capture both directions.
+  pub fn set_recorder(&mut self, recorder: Option<FrameRecorder>) {
+    self.read_half.recorder = recorder;
+  }
+
+  /// See `WebSocket::set_wire_tap`.
+  pub fn set_wire_tap(
+    &mut self,
+    tap: impl Fn(FrameDirection, &[u8]) + Send + Sync + 'static,
+  ) {
+    self.read_half.wire_tap = Some(Arc::new(tap));
+  }
+
+  /// See `WebSocket::set_extension`.
+  pub fn set_extension(&mut self, extension: Option<Arc<dyn Extension>>) {
+    self.read_half.extension = extension;
+  }
+
+  /// Sets whether a server accepts frames that the client did not mask.
+  ///
+  /// RFC 6455 requires clients to mask every frame, and unmasked frames are rejected with
+  /// `WebSocketError::UnmaskedFrame` by default. Only enable this for trusted peers, such as internal proxies that
+  /// skip masking for performance, since masking protects intermediaries from cache poisoning attacks.
+  ///
+  /// Default: `false`
+  pub fn set_accept_unmasked_frames(&mut self, accept_unmasked_frames: bool) {
+    self.read_half.accept_unmasked_frames = accept_unmasked_frames;
+  }
+
+  /// See `WebSocket::set_lenient`.
+  pub fn set_lenient(&mut self, lenient: bool) {
+    self.read_half.lenient = lenient;
+  }
+
+  /// Sets the size in bytes above which the read buffer is released after a frame has been read, instead of being kept
+  /// around for the next one. This returns memory to the allocator after a burst of large messages.
+  ///
+  /// Default: `None` (never shrink)
+  pub fn set_read_buffer_high_water_mark(
+    &mut self,
+    high_water_mark: Option<usize>,
+  ) {
+    self.read_half.read_buffer_high_water_mark = high_water_mark;
+  }
+
+  /// See `WebSocket::set_deflate`.
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate(&mut self, config: Option<DeflateConfig>) {
+    self.read_half.set_deflate(config.as_ref());
+  }
+
+  /// See `WebSocket::set_deflate_dictionary`.
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    self.read_half.set_deflate_dictionary(dictionary);
+  }
+
+  /// See `WebSocket::set_compression_passthrough`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_passthrough(&mut self, passthrough: bool) {
+    self.read_half.compression_passthrough = passthrough;
+  }
+
+  /// See `WebSocket::set_brotli`.
+  #[cfg(feature = "brotli")]
+  pub fn set_brotli(&mut self, config: Option<BrotliConfig>) {
+    self.read_half.set_brotli(config.as_ref());
+  }
+
+  /// See `WebSocket::set_zstd`.
+  #[cfg(feature = "zstd")]
+  pub fn set_zstd(&mut self, config: Option<ZstdConfig>) {
+    self.read_half.set_zstd(config.as_ref());
+  }
+
   /// Sets whether to automatically apply the mask to the frame payload.
   ///
   /// Default: `true`
@@ -285,6 +715,62 @@ impl<'f, S> WebSocketRead<S> {
     self.read_half.auto_apply_mask = auto_apply_mask;
   }
 
+  /// Number of bytes received from the stream that have not been returned as frames yet.
+  pub fn buffered_bytes(&self) -> usize {
+    self.read_half.buffered_bytes()
+  }
+
+  /// Returns the role this end of the connection was created with.
+  pub fn role(&self) -> Role {
+    self.read_half.role
+  }
+
+  /// Returns the size above which frames are written with vectored writes.
+  pub fn writev_threshold(&self) -> usize {
+    self.read_half.writev_threshold
+  }
+
+  /// Returns whether close frames are answered automatically.
+  pub fn auto_close(&self) -> bool {
+    self.read_half.auto_close
+  }
+
+  /// Returns whether ping frames are answered automatically.
+  pub fn auto_pong(&self) -> bool {
+    self.read_half.auto_pong
+  }
+
+  /// Returns the maximum message size in bytes.
+  pub fn max_message_size(&self) -> usize {
+    self.read_half.max_message_size
+  }
+
+  /// Returns the shared `MemoryLimiter`, if one is set.
+  pub fn memory_limiter(&self) -> Option<&MemoryLimiter> {
+    self.read_half.memory_limiter.as_ref()
+  }
+
+  /// Returns the size above which the read buffer is released after a frame has been read.
+  pub fn read_buffer_high_water_mark(&self) -> Option<usize> {
+    self.read_half.read_buffer_high_water_mark
+  }
+
+  /// Returns whether a server accepts frames that the client did not mask.
+  pub fn accept_unmasked_frames(&self) -> bool {
+    self.read_half.accept_unmasked_frames
+  }
+
+  /// Returns whether frames that can be skipped without losing track of the stream are skipped instead of failing
+  /// the connection.
+  pub fn lenient(&self) -> bool {
+    self.read_half.lenient
+  }
+
+  /// Returns whether the mask is applied to frame payloads automatically.
+  pub fn auto_apply_mask(&self) -> bool {
+    self.read_half.auto_apply_mask
+  }
+
   /// Reads a frame from the stream.
   pub async fn read_frame<R, E>(
     &mut self,
@@ -296,6 +782,10 @@ impl<'f, S> WebSocketRead<S> {
     R: Future<Output = Result<(), E>>,
   {
     loop {
+      #[cfg(feature = "keepalive")]
+      let (res, obligated_send) =
+        self.read_half.read_frame_timed(&mut self.stream).await;
+      #[cfg(not(feature = "keepalive"))]
       let (res, obligated_send) =
         self.read_half.read_frame_inner(&mut self.stream).await;
       if let Some(frame) = obligated_send {
@@ -307,6 +797,31 @@ impl<'f, S> WebSocketRead<S> {
       }
     }
   }
+
+  /// Reads a frame from the stream. Obligated pong and close frames are queued instead of being passed to a
+  /// `send_fn`, and are written by the matching `WebSocketWrite` before its next frame or by
+  /// `WebSocketWrite::pump_control`.
+  ///
+  /// Only the most recent pong is kept if several pings arrive before the writer catches up.
+  pub async fn read_frame_queued(&mut self) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    loop {
+      #[cfg(feature = "keepalive")]
+      let (res, obligated_send) =
+        self.read_half.read_frame_timed(&mut self.stream).await;
+      #[cfg(not(feature = "keepalive"))]
+      let (res, obligated_send) =
+        self.read_half.read_frame_inner(&mut self.stream).await;
+      if let Some(frame) = obligated_send {
+        self.control.push(frame);
+      }
+      if let Some(frame) = res? {
+        break Ok(frame);
+      }
+    }
+  }
 }
 
 #[cfg(feature = "unstable-split")]
@@ -322,6 +837,111 @@ impl<'f, S> WebSocketWrite<S> {
     self.write_half.writev_threshold = threshold;
   }
 
+  /// See `WebSocket::set_max_write_frame_size`.
+  pub fn set_max_write_frame_size(
+    &mut self,
+    max_write_frame_size: Option<usize>,
+  ) {
+    self.write_half.max_write_frame_size =
+      max_write_frame_size.map(|max| max.max(1));
+  }
+
+  /// See `WebSocket::set_write_timeout`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_write_timeout(&mut self, timeout: std::time::Duration) {
+    self.write_half.write_timeout = Some(timeout);
+  }
+
+  /// See `WebSocket::set_timer`.
+  #[cfg(feature = "keepalive")]
+  pub fn set_timer(&mut self, timer: impl Timer + 'static) {
+    self.write_half.timer = Arc::new(timer);
+  }
+
+  /// See `WebSocket::set_recorder`.
+  pub fn set_recorder(&mut self, recorder: Option<FrameRecorder>) {
+    self.write_half.recorder = recorder;
+  }
+
+  /// See `WebSocket::set_wire_tap`.
+  pub fn set_wire_tap(
+    &mut self,
+    tap: impl Fn(FrameDirection, &[u8]) + Send + Sync + 'static,
+  ) {
+    self.write_half.wire_tap = Some(Arc::new(tap));
+  }
+
+  /// See `WebSocket::set_extension`.
+  pub fn set_extension(&mut self, extension: Option<Arc<dyn Extension>>) {
+    self.write_half.extension = extension;
+  }
+
+  /// See `WebSocket::set_deflate`.
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate(&mut self, config: Option<DeflateConfig>) {
+    self.write_half.set_deflate(config.as_ref());
+  }
+
+  /// See `WebSocket::set_compression_level`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_level(&mut self, level: u32) {
+    self.write_half.set_compression_level(level);
+  }
+
+  /// See `WebSocket::set_skip_incompressible`.
+  #[cfg(feature = "deflate")]
+  pub fn set_skip_incompressible(&mut self, skip: bool) {
+    self.write_half.skip_incompressible = skip;
+  }
+
+  /// See `WebSocket::set_min_compress_size`.
+  #[cfg(feature = "deflate")]
+  pub fn set_min_compress_size(&mut self, size: usize) {
+    self.write_half.min_compress_size = size;
+  }
+
+  /// See `WebSocket::set_compression_enabled`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_enabled(&mut self, enabled: bool) {
+    self.write_half.compression_enabled = enabled;
+  }
+
+  /// See `WebSocket::set_compression_passthrough`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_passthrough(&mut self, passthrough: bool) {
+    self.write_half.compression_passthrough = passthrough;
+  }
+
+  /// See `WebSocket::set_compress_text`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compress_text(&mut self, compress: bool) {
+    self.write_half.compress_text = compress;
+  }
+
+  /// See `WebSocket::set_compress_binary`.
+  #[cfg(feature = "deflate")]
+  pub fn set_compress_binary(&mut self, compress: bool) {
+    self.write_half.compress_binary = compress;
+  }
+
+  /// See `WebSocket::set_deflate_dictionary`.
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    self.write_half.set_deflate_dictionary(dictionary);
+  }
+
+  /// See `WebSocket::set_brotli`.
+  #[cfg(feature = "brotli")]
+  pub fn set_brotli(&mut self, config: Option<BrotliConfig>) {
+    self.write_half.set_brotli(config.as_ref());
+  }
+
+  /// See `WebSocket::set_zstd`.
+  #[cfg(feature = "zstd")]
+  pub fn set_zstd(&mut self, config: Option<ZstdConfig>) {
+    self.write_half.set_zstd(config.as_ref());
+  }
+
   /// Sets whether to automatically apply the mask to the frame payload.
   ///
   /// Default: `true`
@@ -333,6 +953,80 @@ impl<'f, S> WebSocketWrite<S> {
     self.write_half.closed
   }
 
+  /// Number of payload bytes in control frames queued by `WebSocketRead::read_frame_queued` that have not been
+  /// written yet.
+  pub fn queued_bytes(&self) -> usize {
+    self.control.queued_bytes()
+  }
+
+  /// Returns the role this end of the connection was created with.
+  pub fn role(&self) -> Role {
+    self.write_half.role
+  }
+
+  /// Returns whether vectored writes are enabled.
+  pub fn writev(&self) -> bool {
+    self.write_half.vectored
+  }
+
+  /// Returns the size above which frames are written with vectored writes.
+  pub fn writev_threshold(&self) -> usize {
+    self.write_half.writev_threshold
+  }
+
+  /// Returns the maximum payload size of outgoing frames.
+  pub fn max_write_frame_size(&self) -> Option<usize> {
+    self.write_half.max_write_frame_size
+  }
+
+  /// Returns whether the mask is applied to frame payloads automatically.
+  pub fn auto_apply_mask(&self) -> bool {
+    self.write_half.auto_apply_mask
+  }
+
+  /// Returns the compression level of outgoing messages.
+  #[cfg(feature = "deflate")]
+  pub fn compression_level(&self) -> u32 {
+    self.write_half.compression_level
+  }
+
+  /// Returns whether messages that look incompressible are sent uncompressed.
+  #[cfg(feature = "deflate")]
+  pub fn skip_incompressible(&self) -> bool {
+    self.write_half.skip_incompressible
+  }
+
+  /// Returns the payload size below which messages are sent uncompressed.
+  #[cfg(feature = "deflate")]
+  pub fn min_compress_size(&self) -> usize {
+    self.write_half.min_compress_size
+  }
+
+  /// Returns whether outgoing messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compression_enabled(&self) -> bool {
+    self.write_half.compression_enabled
+  }
+
+  /// Returns whether compressed messages are passed through as they are.
+  #[cfg(feature = "deflate")]
+  pub fn compression_passthrough(&self) -> bool {
+    self.write_half.compression_passthrough
+  }
+
+  /// Returns whether outgoing text messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compress_text(&self) -> bool {
+    self.write_half.compress_text
+  }
+
+  /// Returns whether outgoing binary messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compress_binary(&self) -> bool {
+    self.write_half.compress_binary
+  }
+
+  /// Writes a frame to the stream, preceded by any control frames queued by `WebSocketRead::read_frame_queued`.
   pub async fn write_frame(
     &mut self,
     frame: Frame<'f>,
@@ -340,14 +1034,139 @@ impl<'f, S> WebSocketWrite<S> {
   where
     S: AsyncWrite + Unpin,
   {
+    self.pump_control().await?;
     self.write_half.write_frame(&mut self.stream, frame).await
   }
 
+  /// See `WebSocket::write_frame_ref`.
+  pub async fn write_frame_ref(
+    &mut self,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.pump_control().await?;
+    self
+      .write_half
+      .write_frame_ref(&mut self.stream, frame)
+      .await
+  }
+
+  /// See `WebSocket::write_with_header`.
+  pub async fn write_with_header(
+    &mut self,
+    header: &FrameHeader,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.pump_control().await?;
+    self
+      .write_half
+      .write_with_header(&mut self.stream, header, payload)
+      .await
+  }
+
+  /// Writes the pong and close frames queued by `WebSocketRead::read_frame_queued`, if any. Call this when the
+  /// writer is otherwise idle so that pings are answered promptly.
+  pub async fn pump_control(&mut self) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    for frame in self.control.take().into_iter().flatten() {
+      if self.write_half.closed {
+        break;
+      }
+      self.write_half.write_frame(&mut self.stream, frame).await?;
+    }
+    Ok(())
+  }
+
   pub async fn flush(&mut self) -> Result<(), WebSocketError>
   where
     S: AsyncWrite + Unpin,
   {
-    flush(&mut self.stream).await
+    self.write_half.flush(&mut self.stream).await
+  }
+
+  /// See `WebSocket::poll_write_frame`. Control frames queued by `WebSocketRead::read_frame_queued` are written
+  /// first.
+  pub fn poll_write_frame(
+    &mut self,
+    cx: &mut Context<'_>,
+    frame: &Frame<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    if !self.poll_writing_frame {
+      ready!(self.poll_control(cx))?;
+      self.poll_writing_frame = true;
+    }
+    let result =
+      ready!(self
+        .write_half
+        .poll_write_frame(cx, &mut self.stream, frame));
+    self.poll_writing_frame = false;
+    Poll::Ready(result)
+  }
+
+  /// See `WebSocket::poll_flush`.
+  pub fn poll_flush(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    let result = ready!(self.write_half.poll_flush(cx, &mut self.stream));
+    self.poll_writing_frame = false;
+    Poll::Ready(result)
+  }
+
+  /// See `WebSocket::poll_close`. Control frames queued by `WebSocketRead::read_frame_queued` are written first, so
+  /// a close frame echoed for the peer takes the place of the normal closure.
+  pub fn poll_close(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    ready!(self.write_half.poll_pending(cx, &mut self.stream))?;
+    self.poll_writing_frame = false;
+    ready!(self.poll_control(cx))?;
+    self.write_half.poll_close(cx, &mut self.stream)
+  }
+
+  /// Polls writing the control frames queued by `WebSocketRead::read_frame_queued`, like `pump_control`.
+  fn poll_control(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    loop {
+      ready!(self.write_half.poll_pending(cx, &mut self.stream))?;
+      // The close frame goes back to the queue until the pong is written.
+      let frame = match self.control.take() {
+        [Some(pong), close] => {
+          if let Some(close) = close {
+            self.control.push(close);
+          }
+          pong
+        }
+        [None, Some(close)] => close,
+        [None, None] => return Poll::Ready(Ok(())),
+      };
+      if self.write_half.closed {
+        return Poll::Ready(Ok(()));
+      }
+      self.write_half.start_poll_write(&frame)?;
+    }
   }
 }
 
@@ -359,6 +1178,38 @@ where
   stream.flush().await.map_err(WebSocketError::IoError)
 }
 
+/// Sends a close frame unless one has been sent, reads until the peer's close frame, discarding the frames before
+/// it, and shuts down the stream.
+async fn close_handshake<S>(
+  read_half: &mut ReadHalf,
+  write_half: &mut WriteHalf,
+  stream: &mut S,
+  code: CloseCode,
+  reason: &str,
+) -> Result<(), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  if !write_half.closed {
+    let frame = Frame::close_with(code, reason);
+    write_half.write_frame(stream, frame).await?;
+  }
+  write_half.flush(stream).await?;
+
+  #[cfg(feature = "keepalive")]
+  if let Some(timeout) = read_half.close_timeout {
+    let timer = read_half.timer.clone();
+    let drain =
+      timer::timeout(&*timer, timeout, read_half.read_until_close(stream))
+        .await;
+    stream.shutdown().await?;
+    return drain.ok_or(WebSocketError::CloseTimeout)?;
+  }
+  read_half.read_until_close(stream).await?;
+  stream.shutdown().await?;
+  Ok(())
+}
+
 /// WebSocket protocol implementation over an async stream.
 pub struct WebSocket<S> {
   stream: S,
@@ -412,14 +1263,18 @@ impl<'f, S> WebSocket<S> {
   {
     let (stream, read, write) = self.into_parts_internal();
     let (r, w) = split_fn(stream);
+    let control = Arc::new(ControlQueue::default());
     (
       WebSocketRead {
         stream: r,
         read_half: read,
+        control: control.clone(),
       },
       WebSocketWrite {
+        poll_writing_frame: write.is_poll_writing(),
         stream: w,
         write_half: write,
+        control,
       },
     )
   }
@@ -432,6 +1287,7 @@ impl<'f, S> WebSocket<S> {
   }
 
   /// Consumes the `WebSocket` and returns the underlying stream.
+  #[cfg(feature = "unstable-split")]
   #[inline]
   pub(crate) fn into_parts_internal(self) -> (S, ReadHalf, WriteHalf) {
     (self.stream, self.read_half, self.write_half)
@@ -470,39 +1326,501 @@ impl<'f, S> WebSocket<S> {
     self.read_half.max_message_size = max_message_size;
   }
 
-  /// Sets whether to automatically apply the mask to the frame payload.
+  /// Sets the maximum payload size in bytes of outgoing frames, for intermediaries that limit the frame size. Data
+  /// frames written with `write_frame` that are larger are split into a frame with their opcode followed by
+  /// continuation frames. With compression, the limit applies to the payload before it is compressed.
   ///
-  /// Default: `true`
-  pub fn set_auto_apply_mask(&mut self, auto_apply_mask: bool) {
-    self.read_half.auto_apply_mask = auto_apply_mask;
-    self.write_half.auto_apply_mask = auto_apply_mask;
+  /// Default: `None` (no limit)
+  pub fn set_max_write_frame_size(
+    &mut self,
+    max_write_frame_size: Option<usize>,
+  ) {
+    self.write_half.max_write_frame_size =
+      max_write_frame_size.map(|max| max.max(1));
   }
 
-  pub fn is_closed(&self) -> bool {
-    self.write_half.closed
+  /// Sets a `MemoryLimiter` shared with other connections. Each incoming payload is accounted against it while the
+  /// frame is being read, and frames that do not fit are rejected with close code 1013. Returned frames are not
+  /// accounted.
+  ///
+  /// Default: `None`
+  pub fn set_memory_limiter(&mut self, limiter: MemoryLimiter) {
+    self.read_half.memory_limiter = Some(limiter);
   }
 
-  /// Writes a frame to the stream.
+  /// Sets a policy that is consulted with the header of every incoming frame before its payload is read. Returning
+  /// `Err(code)` rejects the frame: the connection is closed with `code` and `read_frame` fails with
+  /// `WebSocketError::FrameRejected`.
   ///
   /// # Example
   ///
   /// ```
-  /// use fastwebsockets::{WebSocket, Frame, OpCode};
+  /// use fastwebsockets::{CloseCode, OpCode, WebSocket};
   /// use tokio::net::TcpStream;
-  /// use anyhow::Result;
   ///
-  /// async fn send(
-  ///   ws: &mut WebSocket<TcpStream>
-  /// ) -> Result<()> {
-  ///   let mut frame = Frame::binary(vec![0x01, 0x02, 0x03].into());
-  ///   ws.write_frame(frame).await?;
-  ///   Ok(())
+  /// fn text_only(ws: &mut WebSocket<TcpStream>) {
+  ///   ws.set_frame_policy(|info| match info.opcode {
+  ///     OpCode::Binary => Err(CloseCode::Unsupported),
+  ///     _ if info.payload_len > 1024 => Err(CloseCode::Size),
+  ///     _ => Ok(()),
+  ///   });
   /// }
   /// ```
-  pub async fn write_frame(
+  pub fn set_frame_policy(
     &mut self,
-    frame: Frame<'f>,
-  ) -> Result<(), WebSocketError>
+    policy: impl FnMut(&FrameInfo) -> Result<(), CloseCode> + Send + 'static,
+  ) {
+    self.read_half.frame_policy = Some(Box::new(policy));
+  }
+
+  /// Sets a keepalive: when no frame has been received for `interval`, `read_frame` sends a ping, and when no frame
+  /// follows within `timeout`, it sends a close frame with status code 1001 and returns
+  /// `WebSocketError::KeepAliveTimeout`. Any frame counts, not only the pong, and pongs are still returned.
+  ///
+  /// Pings are only sent while `read_frame` is waiting for a frame, so the connection has to be read continuously.
+  /// `FragmentCollector` keeps the keepalive; the split halves and the `Stream` implementation ignore it.
+  ///
+  /// Default: disabled
+  #[cfg(feature = "keepalive")]
+  pub fn set_keepalive(
+    &mut self,
+    interval: std::time::Duration,
+    timeout: std::time::Duration,
+  ) {
+    self.read_half.keepalive =
+      Some(KeepAlive::new(interval, timeout, &*self.read_half.timer));
+  }
+
+  /// Sets how long `read_frame` waits for a frame before failing with `WebSocketError::ReadTimeout`. The connection
+  /// stays usable: part of a frame that arrived in time is kept for the next call.
+  ///
+  /// Default: disabled
+  #[cfg(feature = "keepalive")]
+  pub fn set_read_timeout(&mut self, timeout: std::time::Duration) {
+    self.read_half.read_timeout = Some(ReadTimeout::new(timeout));
+  }
+
+  /// Sets how long `close` waits for the peer's close frame. When the time is up, the stream is shut down and
+  /// `close` fails with `WebSocketError::CloseTimeout`.
+  ///
+  /// Default: disabled
+  #[cfg(feature = "keepalive")]
+  pub fn set_close_timeout(&mut self, timeout: std::time::Duration) {
+    self.read_half.close_timeout = Some(timeout);
+  }
+
+  /// Sets how long a write, including the pongs and close frames written by `read_frame`, or a flush may take
+  /// before it fails with `WebSocketError::WriteTimeout`, so that peers that stopped reading can be dropped. Part of
+  /// a frame may have been written by then, so the connection is closed and later writes fail with
+  /// `WebSocketError::ConnectionClosed`. The `poll_*` methods are not covered.
+  ///
+  /// Default: disabled
+  #[cfg(feature = "keepalive")]
+  pub fn set_write_timeout(&mut self, timeout: std::time::Duration) {
+    self.write_half.write_timeout = Some(timeout);
+  }
+
+  /// Sets the clock of the keepalive, the read, write and close timeouts and the round-trip times of `latency`, for
+  /// tests that control time and for runtimes other than tokio. The keepalive measures idle time from when it is set, so set the timer first.
+  ///
+  /// Default: `TokioTimer`
+  #[cfg(feature = "keepalive")]
+  pub fn set_timer(&mut self, timer: impl Timer + 'static) {
+    let timer: Arc<dyn Timer> = Arc::new(timer);
+    self.read_half.timer = timer.clone();
+    self.write_half.timer = timer;
+  }
+
+  /// Sets a recorder that captures every frame read and written, to reproduce interop problems later with
+  /// `ReplayStream`. Inbound frames are recorded before they are decompressed, outbound frames after.
+  ///
+  /// Default: `None`
+  pub fn set_recorder(&mut self, recorder: Option<FrameRecorder>) {
+    self.read_half.recorder = recorder.clone();
+    self.write_half.recorder = recorder;
+  }
+
+  /// Sets a function that observes the raw bytes read from and written to the stream, as they are transferred. The
+  /// bytes are those of the WebSocket protocol, after TLS has been terminated, so traffic can be mirrored to
+  /// debugging tools without wrapping the stream. The tap runs on the task driving the connection and must not
+  /// block.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::FrameDirection;
+  /// use fastwebsockets::WebSocket;
+  /// use tokio::net::TcpStream;
+  ///
+  /// fn tap(ws: &mut WebSocket<TcpStream>) {
+  ///   ws.set_wire_tap(|direction, bytes| match direction {
+  ///     FrameDirection::Inbound => eprintln!("<< {:02x?}", bytes),
+  ///     FrameDirection::Outbound => eprintln!(">> {:02x?}", bytes),
+  ///   });
+  /// }
+  /// ```
+  pub fn set_wire_tap(
+    &mut self,
+    tap: impl Fn(FrameDirection, &[u8]) + Send + Sync + 'static,
+  ) {
+    let tap: WireTap = Arc::new(tap);
+    self.read_half.wire_tap = Some(tap.clone());
+    self.write_half.wire_tap = Some(tap);
+  }
+
+  /// Sets the custom extension that incoming and outgoing frames pass through, or removes it with `None`.
+  ///
+  /// Incoming frames with the RSV bits the extension claims set are accepted and passed to `Extension::decode`,
+  /// and frames written with `write_frame` and `poll_write_frame` are passed to `Extension::encode`. Frames written
+  /// with `write_frame_ref` and `write_with_header` are sent as they are. Servers negotiate the extension with
+  /// `upgrade::upgrade_with_extension` and clients with `handshake::client_with_extension`, which set it.
+  ///
+  /// Default: `None`
+  pub fn set_extension(&mut self, extension: Option<Arc<dyn Extension>>) {
+    self.read_half.extension = extension.clone();
+    self.write_half.extension = extension;
+  }
+
+  /// Sets whether a server accepts frames that the client did not mask.
+  ///
+  /// RFC 6455 requires clients to mask every frame, and unmasked frames are rejected with
+  /// `WebSocketError::UnmaskedFrame` by default. Only enable this for trusted peers, such as internal proxies that
+  /// skip masking for performance, since masking protects intermediaries from cache poisoning attacks.
+  ///
+  /// Default: `false`
+  pub fn set_accept_unmasked_frames(&mut self, accept_unmasked_frames: bool) {
+    self.read_half.accept_unmasked_frames = accept_unmasked_frames;
+  }
+
+  /// Sets whether frames that break the protocol in ways that do not lose track of the stream are skipped instead
+  /// of failing the connection, for peers with known bugs that cannot be fixed, such as embedded clients in the field.
+  ///
+  /// Pings and pongs with more than 125 bytes of payload and frames with a reserved opcode are discarded, and
+  /// `read_frame` returns `WebSocketError::PingFrameTooLarge`, `WebSocketError::ControlFrameTooLarge` or
+  /// `WebSocketError::InvalidValue` respectively without sending a close frame. The connection stays usable and
+  /// reading can continue with the next frame. Other protocol violations still fail the connection, and a skipped
+  /// frame must still be smaller than `max_message_size`.
+  ///
+  /// Default: `false`
+  pub fn set_lenient(&mut self, lenient: bool) {
+    self.read_half.lenient = lenient;
+  }
+
+  /// Sets the size in bytes above which the read buffer is released after a frame has been read, instead of being kept
+  /// around for the next one. This returns memory to the allocator after a burst of large messages.
+  ///
+  /// Default: `None` (never shrink)
+  pub fn set_read_buffer_high_water_mark(
+    &mut self,
+    high_water_mark: Option<usize>,
+  ) {
+    self.read_half.read_buffer_high_water_mark = high_water_mark;
+  }
+
+  /// Sets the maximum number of bytes this connection may hold across the frame being read, partially received
+  /// messages and the write buffer. A peer that exceeds it is disconnected with close code 1009. Frames returned by
+  /// `read_frame` are not counted.
+  ///
+  /// Outgoing frames that would grow the write buffer past the budget are written with vectored writes instead.
+  ///
+  /// The budget is shared by both halves, so it has to be set before `split`.
+  ///
+  /// Default: `None`
+  pub fn set_max_connection_memory(&mut self, max_connection_memory: usize) {
+    let budget = MemoryLimiter::new(max_connection_memory);
+    self.read_half.connection_memory = Some(budget.clone());
+    self.write_half.connection_memory = Some(budget);
+    self.write_half.write_buffer_permit = None;
+    self.write_half.write_buffer = Vec::new();
+  }
+
+  /// Enables permessage-deflate compression with parameters negotiated during the handshake, or disables it with
+  /// `None`. Servers using `upgrade::upgrade_with_deflate` and clients using `handshake::client` have this set
+  /// automatically.
+  ///
+  /// Data messages written with `write_frame` are compressed, while `write_frame_ref` and `write_with_header` send
+  /// them uncompressed. Compressed incoming messages are decompressed before they are returned.
+  ///
+  /// Default: `None`
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate(&mut self, config: Option<DeflateConfig>) {
+    self.read_half.set_deflate(config.as_ref());
+    self.write_half.set_deflate(config.as_ref());
+  }
+
+  /// Sets the zlib compression level of outgoing messages when permessage-deflate is enabled, from 0 (stored
+  /// uncompressed) to 9 (smallest output). Larger values are clamped to 9. `set_deflate` replaces it with
+  /// `DeflateConfig::level` if that is set.
+  ///
+  /// Low levels keep the CPU cost of small, latency-sensitive messages down, while high levels pay off for large
+  /// repetitive payloads. The level can be changed at any time and applies from the next frame written, although
+  /// the compressor may have to drop the history it kept from earlier messages. The memory used by the compressor is
+  /// bounded by the negotiated `server_max_window_bits` or `client_max_window_bits`; zlib's memory level is fixed at
+  /// its default of 8.
+  ///
+  /// Default: 6
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_level(&mut self, level: u32) {
+    self.write_half.set_compression_level(level);
+  }
+
+  /// Sets whether to send messages uncompressed when their first frame looks incompressible, such as images or
+  /// archives that are already compressed. The decision is based on the byte entropy of a sample from the start of
+  /// the payload and saves the CPU time of compressing data that would not shrink.
+  ///
+  /// Default: `true`
+  #[cfg(feature = "deflate")]
+  pub fn set_skip_incompressible(&mut self, skip: bool) {
+    self.write_half.skip_incompressible = skip;
+  }
+
+  /// Sets the payload size in bytes below which messages are sent uncompressed, since compressing small messages
+  /// costs CPU time and saves few or no bytes. The size of the first frame of a message decides. Single messages can
+  /// also be sent uncompressed with `Frame::set_compressed`.
+  ///
+  /// Default: 0
+  #[cfg(feature = "deflate")]
+  pub fn set_min_compress_size(&mut self, size: usize) {
+    self.write_half.min_compress_size = size;
+  }
+
+  /// Sets whether outgoing messages are compressed, for example to save CPU time under load without renegotiating
+  /// the extension. The peer needs no notice, since messages without RSV1 are always valid; incoming messages are
+  /// still decompressed. A change applies from the next message, a fragmented message is finished the way it started.
+  ///
+  /// Default: `true`
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_enabled(&mut self, enabled: bool) {
+    self.write_half.compression_enabled = enabled;
+  }
+
+  /// Sets whether compressed messages are passed through as they are, for proxies that relay messages between two
+  /// peers without inspecting them and should not spend CPU time decompressing and compressing them again.
+  ///
+  /// The first frame of an incoming compressed message has `Frame::is_compressed` set, and the payloads of all its
+  /// frames are left compressed and are not checked for valid UTF-8, so read them with `read_frame` rather than a
+  /// `FragmentCollector`. Outgoing frames are written as they are, so such frames can be forwarded to another
+  /// connection, and messages the application writes itself are sent uncompressed. Both connections must have
+  /// negotiated compression with the same parameters, and compressed messages must be forwarded whole and in order,
+  /// as the compression context carries over from one message to the next.
+  ///
+  /// Default: `false`
+  #[cfg(feature = "deflate")]
+  pub fn set_compression_passthrough(&mut self, passthrough: bool) {
+    self.read_half.compression_passthrough = passthrough;
+    self.write_half.compression_passthrough = passthrough;
+  }
+
+  /// Sets whether outgoing text messages are compressed.
+  ///
+  /// Default: `true`
+  #[cfg(feature = "deflate")]
+  pub fn set_compress_text(&mut self, compress: bool) {
+    self.write_half.compress_text = compress;
+  }
+
+  /// Sets whether outgoing binary messages are compressed. Disable it when binary payloads are already compressed,
+  /// such as protobuf encoded with snappy, so that text messages alone pay for compression.
+  ///
+  /// Default: `true`
+  #[cfg(feature = "deflate")]
+  pub fn set_compress_binary(&mut self, compress: bool) {
+    self.write_half.compress_binary = compress;
+  }
+
+  /// Sets a preset dictionary that compressed messages in both directions are encoded against, such as the common
+  /// prefix of the JSON documents an application exchanges. This greatly improves the ratio of small messages.
+  ///
+  /// Preset dictionaries are not part of RFC 7692 and are not negotiated, so both peers have to be configured with
+  /// the same dictionary, before the first compressed message. Only its last `2^max_window_bits` bytes are used.
+  /// Setting it resets the compression context.
+  ///
+  /// Default: `None`
+  #[cfg(feature = "deflate")]
+  pub fn set_deflate_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    self.read_half.set_deflate_dictionary(dictionary.clone());
+    self.write_half.set_deflate_dictionary(dictionary);
+  }
+
+  /// Enables compression with the non-standard `permessage-brotli` extension, or disables compression with `None`.
+  /// It replaces permessage-deflate, since both extensions use the RSV1 bit. Servers using
+  /// `upgrade::upgrade_with_brotli` and clients using `handshake::client` have this set automatically.
+  ///
+  /// Default: `None`
+  #[cfg(feature = "brotli")]
+  pub fn set_brotli(&mut self, config: Option<BrotliConfig>) {
+    self.read_half.set_brotli(config.as_ref());
+    self.write_half.set_brotli(config.as_ref());
+  }
+
+  /// Enables compression with the experimental, non-standard `permessage-zstd` extension using parameters negotiated
+  /// during the handshake, or disables compression with `None`. It replaces the other compression extensions, since
+  /// they all use the RSV1 bit. Servers using `upgrade::upgrade_with_zstd` and clients using `handshake::client` have
+  /// this set automatically.
+  ///
+  /// Default: `None`
+  #[cfg(feature = "zstd")]
+  pub fn set_zstd(&mut self, config: Option<ZstdConfig>) {
+    self.read_half.set_zstd(config.as_ref());
+    self.write_half.set_zstd(config.as_ref());
+  }
+
+  /// Sets whether to automatically apply the mask to the frame payload.
+  ///
+  /// Default: `true`
+  pub fn set_auto_apply_mask(&mut self, auto_apply_mask: bool) {
+    self.read_half.auto_apply_mask = auto_apply_mask;
+    self.write_half.auto_apply_mask = auto_apply_mask;
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.write_half.closed
+  }
+
+  /// Number of bytes received from the stream that have not been returned as frames yet.
+  pub fn buffered_bytes(&self) -> usize {
+    self.read_half.buffered_bytes()
+  }
+
+  /// Returns the role this end of the connection was created with.
+  pub fn role(&self) -> Role {
+    self.read_half.role
+  }
+
+  /// Returns whether vectored writes are enabled.
+  pub fn writev(&self) -> bool {
+    self.write_half.vectored
+  }
+
+  /// Returns the size above which frames are written with vectored writes.
+  pub fn writev_threshold(&self) -> usize {
+    self.write_half.writev_threshold
+  }
+
+  /// Returns the maximum payload size of outgoing frames.
+  pub fn max_write_frame_size(&self) -> Option<usize> {
+    self.write_half.max_write_frame_size
+  }
+
+  /// Returns whether close frames are answered automatically.
+  pub fn auto_close(&self) -> bool {
+    self.read_half.auto_close
+  }
+
+  /// Returns whether ping frames are answered automatically.
+  pub fn auto_pong(&self) -> bool {
+    self.read_half.auto_pong
+  }
+
+  /// Returns the maximum message size in bytes.
+  pub fn max_message_size(&self) -> usize {
+    self.read_half.max_message_size
+  }
+
+  /// Returns the shared `MemoryLimiter`, if one is set.
+  pub fn memory_limiter(&self) -> Option<&MemoryLimiter> {
+    self.read_half.memory_limiter.as_ref()
+  }
+
+  /// Returns the size above which the read buffer is released after a frame has been read.
+  pub fn read_buffer_high_water_mark(&self) -> Option<usize> {
+    self.read_half.read_buffer_high_water_mark
+  }
+
+  /// Returns whether a server accepts frames that the client did not mask.
+  pub fn accept_unmasked_frames(&self) -> bool {
+    self.read_half.accept_unmasked_frames
+  }
+
+  /// Returns whether frames that can be skipped without losing track of the stream are skipped instead of failing
+  /// the connection.
+  pub fn lenient(&self) -> bool {
+    self.read_half.lenient
+  }
+
+  /// Returns the round-trip time of the most recent ping sent with `ping_with_payload` that has been answered, or
+  /// `None` if none has.
+  pub fn latency(&self) -> Option<std::time::Duration> {
+    self.read_half.pings.latency()
+  }
+
+  /// Returns whether the mask is applied to frame payloads automatically.
+  pub fn auto_apply_mask(&self) -> bool {
+    self.read_half.auto_apply_mask
+  }
+
+  /// Returns the compression level of outgoing messages.
+  #[cfg(feature = "deflate")]
+  pub fn compression_level(&self) -> u32 {
+    self.write_half.compression_level
+  }
+
+  /// Returns whether messages that look incompressible are sent uncompressed.
+  #[cfg(feature = "deflate")]
+  pub fn skip_incompressible(&self) -> bool {
+    self.write_half.skip_incompressible
+  }
+
+  /// Returns the payload size below which messages are sent uncompressed.
+  #[cfg(feature = "deflate")]
+  pub fn min_compress_size(&self) -> usize {
+    self.write_half.min_compress_size
+  }
+
+  /// Returns whether outgoing messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compression_enabled(&self) -> bool {
+    self.write_half.compression_enabled
+  }
+
+  /// Returns whether compressed messages are passed through as they are.
+  #[cfg(feature = "deflate")]
+  pub fn compression_passthrough(&self) -> bool {
+    self.write_half.compression_passthrough
+  }
+
+  /// Returns whether outgoing text messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compress_text(&self) -> bool {
+    self.write_half.compress_text
+  }
+
+  /// Returns whether outgoing binary messages are compressed.
+  #[cfg(feature = "deflate")]
+  pub fn compress_binary(&self) -> bool {
+    self.write_half.compress_binary
+  }
+
+  /// Returns the per-connection memory budget in bytes, if one is set.
+  pub fn max_connection_memory(&self) -> Option<usize> {
+    self
+      .read_half
+      .connection_memory
+      .as_ref()
+      .map(MemoryLimiter::limit)
+  }
+
+  /// Writes a frame to the stream.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{WebSocket, Frame, OpCode};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn send(
+  ///   ws: &mut WebSocket<TcpStream>
+  /// ) -> Result<()> {
+  ///   let mut frame = Frame::binary(vec![0x01, 0x02, 0x03].into());
+  ///   ws.write_frame(frame).await?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn write_frame(
+    &mut self,
+    frame: Frame<'f>,
+  ) -> Result<(), WebSocketError>
   where
     S: AsyncRead + AsyncWrite + Unpin,
   {
@@ -510,6 +1828,127 @@ impl<'f, S> WebSocket<S> {
     Ok(())
   }
 
+  /// Writes a frame to the stream without consuming it, so a prebuilt frame can be sent repeatedly.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{WebSocket, Frame};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn heartbeat(
+  ///   ws: &mut WebSocket<TcpStream>
+  /// ) -> Result<()> {
+  ///   let frame = Frame::text(b"heartbeat".as_ref().into());
+  ///   loop {
+  ///     ws.write_frame_ref(&frame).await?;
+  ///     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+  ///   }
+  /// }
+  /// ```
+  pub async fn write_frame_ref(
+    &mut self,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .write_frame_ref(&mut self.stream, frame)
+      .await
+  }
+
+  /// Sends a ping with `payload`, at most 125 bytes, and measures the round-trip time when the matching pong is
+  /// read. The payload should tell pings apart, such as a counter; see `latency`.
+  ///
+  /// Pongs are matched as they are read, so the connection has to be read for the measurement to complete.
+  pub async fn ping_with_payload(
+    &mut self,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let ping = Frame::new(true, OpCode::Ping, None, payload.into());
+    let sent = self.read_half.now();
+    self
+      .write_half
+      .write_frame_ref(&mut self.stream, &ping)
+      .await?;
+    self.read_half.pings.sent(payload, sent);
+    Ok(())
+  }
+
+  /// Writes a message as a single frame.
+  pub async fn write_message(
+    &mut self,
+    message: Message,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(message.into()).await
+  }
+
+  /// Writes a text frame.
+  pub async fn send_text(&mut self, text: &str) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(Frame::text(text.as_bytes().into())).await
+  }
+
+  /// Writes a binary frame.
+  pub async fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_frame(Frame::binary(data.into())).await
+  }
+
+  /// Writes a ping frame. See `ping_with_payload` to measure the round-trip time.
+  pub async fn send_ping(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_frame(Frame::new(true, OpCode::Ping, None, data.into()))
+      .await
+  }
+
+  /// Returns a `MessageWriter` that writes one message with `opcode`, fragmented into frames as its bytes are
+  /// written. The message ends when the writer is shut down.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `opcode` is not `OpCode::Text` or `OpCode::Binary`.
+  pub fn message_writer(&mut self, opcode: OpCode) -> MessageWriter<'_, S>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    MessageWriter::new(self, opcode)
+  }
+
+  /// Writes a frame using a precomputed `FrameHeader`, skipping header encoding. Clients still mask every frame with
+  /// a fresh key.
+  ///
+  /// Returns `WebSocketError::InvalidValue` if `payload` does not have the length the header was built for.
+  pub async fn write_with_header(
+    &mut self,
+    header: &FrameHeader,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .write_with_header(&mut self.stream, header, payload)
+      .await
+  }
+
   /// Flushes the data from the underlying stream.
   ///
   /// if the underlying stream is buffered (i.e: TlsStream<TcpStream>), it is needed to call flush
@@ -519,7 +1958,102 @@ impl<'f, S> WebSocket<S> {
   where
     S: AsyncWrite + Unpin,
   {
-    flush(&mut self.stream).await
+    self.write_half.flush(&mut self.stream).await
+  }
+
+  /// Polls writing a frame, for event loops written as `Future::poll` implementations rather than async fns.
+  ///
+  /// The frame is encoded on the first call, masked and compressed like with `write_frame` but without consuming
+  /// it. If `Poll::Pending` is returned, the frame has been taken and this must be polled again, with the same
+  /// frame, until it returns `Poll::Ready`. Calling the async write methods in between is not supported.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{Frame, WebSocket, WebSocketError};
+  /// use std::future::Future;
+  /// use std::pin::Pin;
+  /// use std::task::{Context, Poll};
+  /// use tokio::net::TcpStream;
+  ///
+  /// struct Greet<'a> {
+  ///   ws: &'a mut WebSocket<TcpStream>,
+  ///   frame: Frame<'static>,
+  /// }
+  ///
+  /// impl Future for Greet<'_> {
+  ///   type Output = Result<(), WebSocketError>;
+  ///
+  ///   fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+  ///     let this = &mut *self;
+  ///     this.ws.poll_write_frame(cx, &this.frame)
+  ///   }
+  /// }
+  /// ```
+  pub fn poll_write_frame(
+    &mut self,
+    cx: &mut Context<'_>,
+    frame: &Frame<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .poll_write_frame(cx, &mut self.stream, frame)
+  }
+
+  /// Polls finishing a frame started by `poll_write_frame`, if any, and flushing the stream.
+  pub fn poll_flush(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.write_half.poll_flush(cx, &mut self.stream)
+  }
+
+  /// Polls reading a frame, like `read_frame`. Pongs and close frames owed to the peer are written like frames
+  /// started by `poll_write_frame`, so this must not be used while such a frame is pending.
+  pub(crate) fn poll_read_frame(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<Frame<'f>, WebSocketError>>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    loop {
+      ready!(self.write_half.poll_pending(cx, &mut self.stream))?;
+      let (res, obligated_send) =
+        ready!(self.read_half.poll_read_frame_inner(cx, &mut self.stream));
+      let is_closed = self.write_half.closed;
+      if let Some(frame) = obligated_send {
+        if !is_closed {
+          self.write_half.start_poll_write(&frame)?;
+        }
+      }
+      if let Some(frame) = res? {
+        if is_closed && frame.opcode != OpCode::Close {
+          return Poll::Ready(Err(WebSocketError::ConnectionClosed));
+        }
+        return Poll::Ready(Ok(frame));
+      }
+    }
+  }
+
+  /// Polls closing the connection: finishes a frame started by `poll_write_frame`, writes a close frame with status
+  /// code 1000 unless a close frame has already been written, and flushes the stream.
+  ///
+  /// The stream is not shut down, so that the peer's close frame can still be read.
+  pub fn poll_close(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.write_half.poll_close(cx, &mut self.stream)
   }
 
   /// Reads a frame from the stream.
@@ -553,6 +2087,12 @@ impl<'f, S> WebSocket<S> {
     S: AsyncRead + AsyncWrite + Unpin,
   {
     loop {
+      #[cfg(feature = "keepalive")]
+      let (res, obligated_send) = self
+        .read_half
+        .read_frame_keepalive(&mut self.write_half, &mut self.stream)
+        .await;
+      #[cfg(not(feature = "keepalive"))]
       let (res, obligated_send) =
         self.read_half.read_frame_inner(&mut self.stream).await;
       let is_closed = self.write_half.closed;
@@ -569,13 +2109,94 @@ impl<'f, S> WebSocket<S> {
       }
     }
   }
+
+  /// Reads the next text or binary message as a `MessageStream`, which yields its payload as the fragments arrive
+  /// instead of buffering it like `FragmentCollector`. Pings and pongs that arrive first are skipped.
+  ///
+  /// Returns `None` once the peer's close frame has been received.
+  pub async fn read_message_stream(
+    &mut self,
+  ) -> Result<Option<MessageStream<'_, S>>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    loop {
+      let frame = self.read_frame().await?;
+      match frame.opcode {
+        OpCode::Text | OpCode::Binary => {
+          return Ok(Some(MessageStream::new(self, frame)))
+        }
+        OpCode::Close => return Ok(None),
+        OpCode::Continuation => {
+          return Err(WebSocketError::InvalidContinuationFrame)
+        }
+        OpCode::Ping | OpCode::Pong => {}
+      }
+    }
+  }
+
+  /// Closes the connection gracefully: sends a close frame with `code` and `reason`, reads until the peer's close
+  /// frame, discarding any frames that arrive first, and shuts down the stream. The reason is truncated to
+  /// `MAX_CLOSE_REASON_LEN` bytes.
+  ///
+  /// If a close frame has already been sent or received, the corresponding step is skipped. With the `keepalive`
+  /// feature, `set_close_timeout` bounds the wait for the peer.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{CloseCode, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn shutdown(ws: &mut WebSocket<TcpStream>) -> Result<()> {
+  ///   ws.close(CloseCode::Away, "server restarting").await?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn close(
+    &mut self,
+    code: CloseCode,
+    reason: &str,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    close_handshake(
+      &mut self.read_half,
+      &mut self.write_half,
+      &mut self.stream,
+      code,
+      reason,
+    )
+    .await
+  }
 }
 
-const MAX_HEADER_SIZE: usize = 14;
+const READ_BUFFER_SIZE: usize = 8192;
+/// Payloads larger than this are read into a dedicated buffer instead of the shared read buffer.
+const DIRECT_READ_THRESHOLD: usize = 64 << 10;
+
+/// A frame read by `ReadHalf`, or `None` if it was a ping answered automatically, and the frame owed to the peer in
+/// response, if any.
+type FrameRead<'f> =
+  (Result<Option<Frame<'f>>, WebSocketError>, Option<Frame<'f>>);
 
 impl ReadHalf {
+  pub fn buffered_bytes(&self) -> usize {
+    self.buffer.len()
+  }
+
+  /// The current time on the clock set with `set_timer`, for round-trip times.
+  pub(crate) fn now(&self) -> std::time::Instant {
+    #[cfg(feature = "keepalive")]
+    return self.timer.now();
+    #[cfg(not(feature = "keepalive"))]
+    std::time::Instant::now()
+  }
+
   pub fn after_handshake(role: Role) -> Self {
-    let buffer = BytesMut::with_capacity(8192);
+    let buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
 
     Self {
       role,
@@ -584,10 +2205,120 @@ impl ReadHalf {
       auto_pong: true,
       writev_threshold: 1024,
       max_message_size: 64 << 20,
+      memory_limiter: None,
+      connection_memory: None,
+      read_buffer_high_water_mark: None,
+      accept_unmasked_frames: false,
+      lenient: false,
+      frame_policy: None,
+      recorder: None,
+      wire_tap: None,
+      extension: None,
+      #[cfg(feature = "futures")]
+      stream_ended: false,
+      #[cfg(feature = "deflate")]
+      decompressor: None,
+      #[cfg(feature = "deflate")]
+      deflate_dictionary: None,
+      #[cfg(feature = "deflate")]
+      inflating: false,
+      #[cfg(feature = "deflate")]
+      compression_passthrough: false,
+      #[cfg(feature = "deflate")]
+      inflated: 0,
+      #[cfg(feature = "keepalive")]
+      keepalive: None,
+      #[cfg(feature = "keepalive")]
+      read_timeout: None,
+      #[cfg(feature = "keepalive")]
+      close_timeout: None,
+      #[cfg(feature = "keepalive")]
+      timer: Arc::new(TokioTimer),
+      close_received: false,
+      utf8: None,
+      pings: PingTracker::default(),
       buffer,
     }
   }
 
+  /// Polls reading a frame, for callers that cannot keep the `read_frame_inner` future across polls.
+  ///
+  /// The stream is read into the read buffer until it holds a whole frame, which `read_frame_inner` then parses
+  /// without reading again. Unlike dropping `read_frame_inner` halfway, returning `Poll::Pending` loses nothing.
+  pub(crate) fn poll_read_frame_inner<'f, S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+  ) -> Poll<FrameRead<'f>>
+  where
+    S: AsyncRead + Unpin,
+  {
+    while let Some(missing) = self.missing_frame_bytes() {
+      self.buffer.reserve(missing.max(codec::MAX_HEAD_SIZE));
+      let mut stream = Tapped::new(stream, self.wire_tap.clone());
+      // Reading into the buffer can be cancelled at any point.
+      let read = pin!(stream.read_into(&mut self.buffer, usize::MAX));
+      match ready!(read.poll(cx)) {
+        Ok(0) => {
+          return Poll::Ready((Err(WebSocketError::UnexpectedEOF), None))
+        }
+        Ok(_) => {}
+        Err(e) => return Poll::Ready((Err(e.into()), None)),
+      }
+    }
+    match pin!(self.read_frame_inner(&mut io::Drained)).poll(cx) {
+      Poll::Ready(result) => Poll::Ready(result),
+      Poll::Pending => unreachable!("parsing a buffered frame does not wait"),
+    }
+  }
+
+  /// The number of bytes missing from the read buffer for it to hold the next frame, or `None` if it holds the
+  /// frame or enough of it for the frame to be rejected.
+  fn missing_frame_bytes(&self) -> Option<usize> {
+    let buf = &self.buffer[..];
+    if buf.len() < 2 {
+      return Some(2 - buf.len());
+    }
+    let masked = buf[1] & 0b10000000 != 0;
+    let length_code = buf[1] & 0x7F;
+    let extra = match length_code {
+      126 => 2,
+      127 => 8,
+      _ => 0,
+    };
+    let head = 2 + extra + masked as usize * 4;
+    if buf.len() < head {
+      return Some(head - buf.len());
+    }
+    let payload_len = match extra {
+      0 => u64::from(length_code),
+      2 => u64::from(u16::from_be_bytes([buf[2], buf[3]])),
+      _ => u64::from_be_bytes(buf[2..10].try_into().unwrap()),
+    };
+    // Frames this large are rejected before their payload is read.
+    if payload_len >= self.max_message_size as u64 {
+      return None;
+    }
+    let len = head + payload_len as usize;
+    len.checked_sub(buf.len()).filter(|&missing| missing > 0)
+  }
+
+  /// Reads and discards frames until a close frame has been received. Nothing is sent in response, since a close
+  /// frame has been sent already.
+  async fn read_until_close<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    while !self.close_received {
+      let (res, _) = self.read_frame_inner(stream).await;
+      res?;
+    }
+    Ok(())
+  }
+
   /// Attempt to read a single frame from from the incoming stream, returning any send obligations if
   /// `auto_close` or `auto_pong` are enabled. Callers to this function are obligated to send the
   /// frame in the latter half of the tuple if one is specified, unless the write half of this socket
@@ -597,71 +2328,273 @@ impl ReadHalf {
   pub(crate) async fn read_frame_inner<'f, S>(
     &mut self,
     stream: &mut S,
-  ) -> (Result<Option<Frame<'f>>, WebSocketError>, Option<Frame<'f>>)
+  ) -> FrameRead<'f>
   where
-    S: AsyncRead + Unpin,
+    S: WsRead,
   {
-    let mut frame = match self.parse_frame_header(stream).await {
+    let mut stream = Tapped::new(stream, self.wire_tap.clone());
+    let mut frame = match self.parse_frame_header(&mut stream).await {
       Ok(frame) => frame,
+      Err(WebSocketError::MemoryLimitExceeded) => {
+        return (
+          Err(WebSocketError::MemoryLimitExceeded),
+          Some(Frame::close(1013, b"")),
+        )
+      }
+      Err(WebSocketError::ConnectionMemoryExceeded) => {
+        return (
+          Err(WebSocketError::ConnectionMemoryExceeded),
+          Some(Frame::close(1009, b"")),
+        )
+      }
+      Err(WebSocketError::FrameRejected(code)) => {
+        return (
+          Err(WebSocketError::FrameRejected(code)),
+          Some(Frame::close(code.into(), b"")),
+        )
+      }
       Err(e) => return (Err(e), None),
     };
 
+    if let Some(recorder) = &self.recorder {
+      recorder.record_frame(FrameDirection::Inbound, &frame);
+    }
+
     if self.role == Role::Server && self.auto_apply_mask {
       frame.unmask()
     };
 
+    if let Some(extension) = &self.extension {
+      if let Err(e) = extension.decode(&mut frame) {
+        return (Err(e), None);
+      }
+    }
+
+    #[cfg(feature = "deflate")]
+    if let Err(e) = self.inflate(&mut frame) {
+      return (Err(e), None);
+    }
+
+    if frame.opcode == OpCode::Close {
+      self.close_received = true;
+    }
+
     match frame.opcode {
       OpCode::Close if self.auto_close => {
-        match frame.payload.len() {
-          0 => {}
-          1 => return (Err(WebSocketError::InvalidCloseFrame), None),
-          _ => {
-            let code = close::CloseCode::from(u16::from_be_bytes(
-              frame.payload[0..2].try_into().unwrap(),
-            ));
-
-            #[cfg(feature = "simd")]
-            if simdutf8::basic::from_utf8(&frame.payload[2..]).is_err() {
-              return (Err(WebSocketError::InvalidUTF8), None);
-            };
-
-            #[cfg(not(feature = "simd"))]
-            if std::str::from_utf8(&frame.payload[2..]).is_err() {
-              return (Err(WebSocketError::InvalidUTF8), None);
-            };
-
-            if !code.is_allowed() {
-              return (
-                Err(WebSocketError::InvalidCloseCode),
-                Some(Frame::close(1002, &frame.payload[2..])),
-              );
-            }
+        match frame::validate_close_payload(&frame.payload) {
+          Ok(()) => {}
+          Err(WebSocketError::InvalidCloseCode) => {
+            return (
+              Err(WebSocketError::InvalidCloseCode),
+              Some(Frame::close(1002, &frame.payload[2..])),
+            );
           }
-        };
+          Err(e) => return (Err(e), None),
+        }
 
-        let obligated_send = Frame::close_raw(frame.payload.to_owned().into());
+        let obligated_send = Frame::close_raw(frame.payload.to_vec().into());
         (Ok(Some(frame)), Some(obligated_send))
       }
       OpCode::Ping if self.auto_pong => {
         (Ok(None), Some(Frame::pong(frame.payload)))
       }
-      OpCode::Text => {
-        if frame.fin && !frame.is_utf8() {
+      OpCode::Pong => {
+        let now = self.now();
+        self.pings.received_pong(&frame.payload, now);
+        (Ok(Some(frame)), None)
+      }
+      OpCode::Text if frame.fin => {
+        self.utf8 = None;
+        // Only a frame passed through compressed still has RSV1 set.
+        if !frame.rsv1 && !frame.is_utf8() {
           (Err(WebSocketError::InvalidUTF8), None)
         } else {
           (Ok(Some(frame)), None)
         }
       }
+      OpCode::Text => {
+        self.utf8 = (!frame.rsv1).then(Utf8Validator::default);
+        self.validate_utf8(frame)
+      }
+      OpCode::Binary => {
+        self.utf8 = None;
+        (Ok(Some(frame)), None)
+      }
+      OpCode::Continuation => self.validate_utf8(frame),
       _ => (Ok(Some(frame)), None),
     }
   }
 
+  /// Checks the next fragment of a text message for valid UTF-8, so that an invalid message fails as soon as the
+  /// invalid fragment arrives.
+  fn validate_utf8<'f>(&mut self, frame: Frame<'f>) -> FrameRead<'f> {
+    let Some(validator) = &mut self.utf8 else {
+      return (Ok(Some(frame)), None);
+    };
+    let result = validator.feed(&frame.payload, frame.fin);
+    if frame.fin || result.is_err() {
+      self.utf8 = None;
+    }
+    (result.map(|()| Some(frame)), None)
+  }
+
+  /// Whether the RSV1 bit may be set on a frame, which is the case for the first frame of a data message when
+  /// permessage-deflate is enabled.
+  fn compressed_frames_allowed(&self, opcode: OpCode) -> bool {
+    #[cfg(feature = "deflate")]
+    {
+      self.decompressor.is_some()
+        && matches!(opcode, OpCode::Text | OpCode::Binary)
+    }
+    #[cfg(not(feature = "deflate"))]
+    {
+      let _ = opcode;
+      false
+    }
+  }
+
+  /// Replaces the payload of a compressed data frame with its decompressed contents.
+  #[cfg(feature = "deflate")]
+  fn inflate(&mut self, frame: &mut Frame) -> Result<(), WebSocketError> {
+    let Some(decompressor) = &mut self.decompressor else {
+      return Ok(());
+    };
+    if self.compression_passthrough {
+      return Ok(());
+    }
+    match frame.opcode {
+      OpCode::Text | OpCode::Binary => {
+        self.inflating = frame.rsv1;
+        self.inflated = 0;
+      }
+      OpCode::Continuation => {}
+      _ => return Ok(()),
+    }
+    if !self.inflating {
+      return Ok(());
+    }
+
+    // The limit applies to the whole message, so a peer cannot inflate an unbounded amount of data by fragmenting it.
+    let payload = decompressor
+      .decompress(
+        &frame.payload,
+        frame.fin,
+        self.max_message_size.saturating_sub(self.inflated),
+      )
+      .inspect_err(|_| self.inflating = false)?;
+    self.inflated += payload.len();
+    frame.payload = Payload::Owned(payload);
+    frame.rsv1 = false;
+    if frame.fin {
+      self.inflating = false;
+    }
+    Ok(())
+  }
+
+  #[cfg(feature = "deflate")]
+  fn set_deflate(&mut self, config: Option<&DeflateConfig>) {
+    self.decompressor = config.map(|config| {
+      Decompressor::Deflate(Inflater::new(
+        config,
+        self.role,
+        self.deflate_dictionary.clone(),
+      ))
+    });
+    self.inflating = false;
+  }
+
+  #[cfg(feature = "zstd")]
+  fn set_zstd(&mut self, config: Option<&ZstdConfig>) {
+    self.decompressor = config
+      .map(|config| Decompressor::Zstd(ZstdDecoder::new(config, self.role)));
+    self.inflating = false;
+  }
+
+  #[cfg(feature = "brotli")]
+  fn set_brotli(&mut self, config: Option<&BrotliConfig>) {
+    self.decompressor =
+      config.map(|_| Decompressor::Brotli(Box::new(BrotliDecoder::new())));
+    self.inflating = false;
+  }
+
+  #[cfg(feature = "deflate")]
+  fn set_deflate_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    if let Some(decompressor) = &mut self.decompressor {
+      decompressor.set_dictionary(dictionary.clone());
+    }
+    self.deflate_dictionary = dictionary;
+  }
+
+  /// The error for the frame at the start of the read buffer if it breaks the protocol but can be skipped without
+  /// losing track of the stream.
+  fn recoverable_violation(&self) -> Option<WebSocketError> {
+    let length_code = self.buffer[1] & 0x7F;
+    match frame::OpCode::try_from(self.buffer[0] & 0b00001111) {
+      Err(e) => Some(e),
+      Ok(OpCode::Ping) if length_code > 125 => {
+        Some(WebSocketError::PingFrameTooLarge)
+      }
+      Ok(OpCode::Pong) if length_code > 125 => {
+        Some(WebSocketError::ControlFrameTooLarge)
+      }
+      Ok(_) => None,
+    }
+  }
+
+  /// Discards the frame at the start of the read buffer, so that the next read starts at the following frame.
+  async fn skip_frame<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsRead,
+  {
+    let masked = self.buffer[1] & 0b10000000 != 0;
+    let length_code = self.buffer[1] & 0x7F;
+    let extra = match length_code {
+      126 => 2,
+      127 => 8,
+      _ => 0,
+    };
+    self.buffer.advance(2);
+    while self.buffer.remaining() < extra + masked as usize * 4 {
+      if stream.read_into(&mut self.buffer, usize::MAX).await? == 0 {
+        return Err(WebSocketError::UnexpectedEOF);
+      }
+    }
+    let payload_len = match extra {
+      0 => u64::from(length_code),
+      2 => u64::from(self.buffer.get_u16()),
+      _ => self.buffer.get_u64(),
+    };
+    if masked {
+      self.buffer.advance(4);
+    }
+    if payload_len >= self.max_message_size as u64 {
+      return Err(WebSocketError::FrameTooLarge);
+    }
+
+    let mut remaining = payload_len as usize;
+    loop {
+      let n = remaining.min(self.buffer.len());
+      self.buffer.advance(n);
+      remaining -= n;
+      if remaining == 0 {
+        return Ok(());
+      }
+      self.buffer.reserve(remaining.min(READ_BUFFER_SIZE));
+      if stream.read_into(&mut self.buffer, remaining).await? == 0 {
+        return Err(WebSocketError::UnexpectedEOF);
+      }
+    }
+  }
+
   async fn parse_frame_header<'a, S>(
     &mut self,
     stream: &mut S,
   ) -> Result<Frame<'a>, WebSocketError>
   where
-    S: AsyncRead + Unpin,
+    S: WsRead,
   {
     macro_rules! eof {
       ($n:expr) => {{
@@ -673,7 +2606,7 @@ impl ReadHalf {
 
     // Read the first two bytes
     while self.buffer.remaining() < 2 {
-      eof!(stream.read_buf(&mut self.buffer).await?);
+      eof!(stream.read_into(&mut self.buffer, usize::MAX).await?);
     }
 
     let fin = self.buffer[0] & 0b10000000 != 0;
@@ -681,11 +2614,22 @@ impl ReadHalf {
     let rsv2 = self.buffer[0] & 0b00100000 != 0;
     let rsv3 = self.buffer[0] & 0b00010000 != 0;
 
-    if rsv1 || rsv2 || rsv3 {
-      return Err(WebSocketError::ReservedBitsNotZero);
+    if self.lenient {
+      if let Some(e) = self.recoverable_violation() {
+        self.skip_frame(stream).await?;
+        return Err(e);
+      }
     }
 
     let opcode = frame::OpCode::try_from(self.buffer[0] & 0b00001111)?;
+
+    let claimed = self.extension.as_ref().map_or(0, |e| e.reserved_bits());
+    if (rsv1 && claimed & 0x40 == 0 && !self.compressed_frames_allowed(opcode))
+      || (rsv2 && claimed & 0x20 == 0)
+      || (rsv3 && claimed & 0x10 == 0)
+    {
+      return Err(WebSocketError::ReservedBitsNotZero);
+    }
     let masked = self.buffer[1] & 0b10000000 != 0;
 
     let length_code = self.buffer[1] & 0x7F;
@@ -697,7 +2641,7 @@ impl ReadHalf {
 
     self.buffer.advance(2);
     while self.buffer.remaining() < extra + masked as usize * 4 {
-      eof!(stream.read_buf(&mut self.buffer).await?);
+      eof!(stream.read_into(&mut self.buffer, usize::MAX).await?);
     }
 
     let payload_len: usize = match extra {
@@ -724,6 +2668,10 @@ impl ReadHalf {
       None
     };
 
+    if self.role == Role::Server && !masked && !self.accept_unmasked_frames {
+      return Err(WebSocketError::UnmaskedFrame);
+    }
+
     if frame::is_control(opcode) && !fin {
       return Err(WebSocketError::ControlFrameFragmented);
     }
@@ -732,19 +2680,84 @@ impl ReadHalf {
       return Err(WebSocketError::PingFrameTooLarge);
     }
 
+    if frame::is_control(opcode) && payload_len > 125 {
+      return Err(WebSocketError::ControlFrameTooLarge);
+    }
+
     if payload_len >= self.max_message_size {
       return Err(WebSocketError::FrameTooLarge);
     }
 
-    // Reserve a bit more to try to get next frame header and avoid a syscall to read it next time
-    self.buffer.reserve(payload_len + MAX_HEADER_SIZE);
-    while payload_len > self.buffer.remaining() {
-      eof!(stream.read_buf(&mut self.buffer).await?);
+    if let Some(policy) = &mut self.frame_policy {
+      let info = FrameInfo {
+        fin,
+        opcode,
+        payload_len,
+        masked,
+      };
+      policy(&info).map_err(WebSocketError::FrameRejected)?;
     }
 
-    // if we read too much it will stay in the buffer, for the next call to this method
-    let payload = self.buffer.split_to(payload_len);
-    let frame = Frame::new(fin, opcode, mask, Payload::Bytes(payload));
+    // Held until the payload has been split off the read buffer.
+    let _permit = match &self.memory_limiter {
+      Some(limiter) => Some(
+        limiter
+          .try_acquire(payload_len)
+          .ok_or(WebSocketError::MemoryLimitExceeded)?,
+      ),
+      None => None,
+    };
+    let _connection_permit = match &self.connection_memory {
+      Some(budget) => Some(
+        budget
+          .try_acquire(payload_len + codec::MAX_HEAD_SIZE)
+          .ok_or(WebSocketError::ConnectionMemoryExceeded)?,
+      ),
+      None => None,
+    };
+
+    let payload = if payload_len > DIRECT_READ_THRESHOLD
+      && payload_len > self.buffer.remaining()
+    {
+      // Large payloads get their own allocation and are read straight into it, so the read buffer keeps its size
+      // and nothing is copied after the read. tokio's `AsyncRead` has no vectored reads, so the bytes following the
+      // payload are picked up by the next call instead of being scattered into the read buffer.
+      let mut payload = BytesMut::with_capacity(payload_len);
+      payload.extend_from_slice(&self.buffer);
+      self.buffer.clear();
+      while payload.len() < payload_len {
+        let remaining = payload_len - payload.len();
+        eof!(stream.read_into(&mut payload, remaining).await?);
+      }
+      payload
+    } else {
+      // Reserve a bit more to try to get next frame header and avoid a syscall to read it next time.
+      // `read_into` reads into the spare capacity, so this never memsets.
+      self.buffer.reserve(payload_len + codec::MAX_HEAD_SIZE);
+      while payload_len > self.buffer.remaining() {
+        eof!(stream.read_into(&mut self.buffer, usize::MAX).await?);
+      }
+
+      // if we read too much it will stay in the buffer, for the next call to this method
+      let payload = self.buffer.split_to(payload_len);
+
+      // The remaining buffer still shares the allocation with `payload`. Move it to a fresh one so the large
+      // allocation is freed once the application drops the frame.
+      if self
+        .read_buffer_high_water_mark
+        .is_some_and(|mark| payload_len + codec::MAX_HEAD_SIZE > mark)
+      {
+        let mut buffer =
+          BytesMut::with_capacity(READ_BUFFER_SIZE.max(self.buffer.len()));
+        buffer.extend_from_slice(&self.buffer);
+        self.buffer = buffer;
+      }
+      payload
+    };
+    let mut frame = Frame::new(fin, opcode, mask, Payload::Bytes(payload));
+    frame.rsv1 = rsv1;
+    frame.rsv2 = rsv2;
+    frame.rsv3 = rsv3;
     Ok(frame)
   }
 }
@@ -757,43 +2770,641 @@ impl WriteHalf {
       auto_apply_mask: true,
       vectored: true,
       writev_threshold: 1024,
+      max_write_frame_size: None,
       write_buffer: Vec::with_capacity(2),
+      connection_memory: None,
+      write_buffer_permit: None,
+      recorder: None,
+      wire_tap: None,
+      poll_write: None,
+      extension: None,
+      #[cfg(feature = "deflate")]
+      compressor: None,
+      #[cfg(feature = "deflate")]
+      compression_level: 6,
+      #[cfg(feature = "deflate")]
+      skip_incompressible: true,
+      #[cfg(feature = "deflate")]
+      min_compress_size: 0,
+      #[cfg(feature = "deflate")]
+      compression_enabled: true,
+      #[cfg(feature = "deflate")]
+      compress_text: true,
+      #[cfg(feature = "deflate")]
+      compress_binary: true,
+      #[cfg(feature = "deflate")]
+      deflate_dictionary: None,
+      #[cfg(feature = "deflate")]
+      deflating: false,
+      #[cfg(feature = "deflate")]
+      compression_passthrough: false,
+      #[cfg(feature = "keepalive")]
+      write_timeout: None,
+      #[cfg(feature = "keepalive")]
+      write_timed_out: false,
+      #[cfg(feature = "keepalive")]
+      timer: Arc::new(TokioTimer),
+    }
+  }
+
+  /// Compresses the payload of a data frame if permessage-deflate is enabled.
+  ///
+  /// Payloads of clients that mask frames themselves cannot be compressed, so those messages are sent uncompressed.
+  #[cfg(feature = "deflate")]
+  fn deflate<'a>(
+    &mut self,
+    frame: Frame<'a>,
+  ) -> Result<Frame<'a>, WebSocketError> {
+    let Some(compressor) = &mut self.compressor else {
+      return Ok(frame);
+    };
+    if self.compression_passthrough {
+      return Ok(frame);
+    }
+    match frame.opcode {
+      OpCode::Text | OpCode::Binary => {
+        let enabled = match frame.opcode {
+          OpCode::Text => self.compress_text,
+          _ => self.compress_binary,
+        };
+        self.deflating = self.compression_enabled
+          && enabled
+          && frame.compress
+          && frame.payload.len() >= self.min_compress_size
+          && (self.role == Role::Server || self.auto_apply_mask)
+          && !(self.skip_incompressible
+            && looks_incompressible(&frame.payload));
+      }
+      OpCode::Continuation => {}
+      _ => return Ok(frame),
+    }
+    if !self.deflating {
+      return Ok(frame);
+    }
+
+    let payload = compressor.compress(&frame.payload, frame.fin)?;
+    if frame.fin {
+      self.deflating = false;
+    }
+    let mut compressed =
+      Frame::new(frame.fin, frame.opcode, frame.mask_key(), payload.into());
+    compressed.rsv1 = frame.opcode != OpCode::Continuation;
+    Ok(compressed)
+  }
+
+  #[cfg(feature = "deflate")]
+  fn set_deflate(&mut self, config: Option<&DeflateConfig>) {
+    if let Some(level) = config.and_then(|config| config.level) {
+      self.compression_level = level.min(9);
+    }
+    self.compressor = config.map(|config| {
+      Compressor::Deflate(Deflater::new(
+        config,
+        self.role,
+        self.compression_level,
+        self.deflate_dictionary.clone(),
+      ))
+    });
+    self.deflating = false;
+  }
+
+  #[cfg(feature = "zstd")]
+  fn set_zstd(&mut self, config: Option<&ZstdConfig>) {
+    self.compressor = config
+      .map(|config| Compressor::Zstd(ZstdEncoder::new(config, self.role)));
+    self.deflating = false;
+  }
+
+  #[cfg(feature = "brotli")]
+  fn set_brotli(&mut self, config: Option<&BrotliConfig>) {
+    self.compressor = config
+      .map(|config| Compressor::Brotli(Box::new(BrotliEncoder::new(config))));
+    self.deflating = false;
+  }
+
+  #[cfg(feature = "deflate")]
+  fn set_deflate_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+    if let Some(compressor) = &mut self.compressor {
+      compressor.set_dictionary(dictionary.clone());
+    }
+    self.deflate_dictionary = dictionary;
+  }
+
+  #[cfg(feature = "deflate")]
+  fn set_compression_level(&mut self, level: u32) {
+    self.compression_level = level.min(9);
+    if let Some(compressor) = &mut self.compressor {
+      compressor.set_level(self.compression_level);
     }
   }
 
+  /// Flushes the provided stream, within the write timeout.
+  pub async fn flush<S>(&mut self, stream: &mut S) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    #[cfg(feature = "keepalive")]
+    if let Some(timeout) = self.write_timeout {
+      let flush = timer::timeout(&*self.timer, timeout, flush(stream)).await;
+      return self.timed_write(flush);
+    }
+    flush(stream).await
+  }
+
   /// Writes a frame to the provided stream.
   pub async fn write_frame<'a, S>(
     &'a mut self,
     stream: &mut S,
-    mut frame: Frame<'a>,
+    frame: Frame<'a>,
   ) -> Result<(), WebSocketError>
   where
-    S: AsyncWrite + Unpin,
+    S: WsWrite,
+  {
+    #[cfg(feature = "keepalive")]
+    if let Some(timeout) = self.write_timeout {
+      let timer = self.timer.clone();
+      let write =
+        timer::timeout(&*timer, timeout, self.write_frame_inner(stream, frame))
+          .await;
+      return self.timed_write(write);
+    }
+    self.write_frame_inner(stream, frame).await
+  }
+
+  async fn write_frame_inner<'a, S>(
+    &'a mut self,
+    stream: &mut S,
+    frame: Frame<'a>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    match self.max_write_frame_size {
+      Some(max)
+        if frame.payload.len() > max && !frame::is_control(frame.opcode) =>
+      {
+        self.write_fragmented(stream, frame, max).await
+      }
+      _ => self.write_single_frame(stream, frame).await,
+    }
+  }
+
+  /// Splits a data frame into frames with at most `max` payload bytes. Only the first keeps the opcode and the RSV
+  /// bits, and only the last the FIN bit.
+  async fn write_fragmented<S>(
+    &mut self,
+    stream: &mut S,
+    frame: Frame<'_>,
+    max: usize,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
   {
+    let mut chunks = frame.payload.chunks(max).peekable();
+    let mut opcode = frame.opcode;
+    let mut rsv_bits = frame.rsv_bits();
+    let mut offset = 0;
+    while let Some(chunk) = chunks.next() {
+      let fin = frame.fin && chunks.peek().is_none();
+      // A chunk starting `offset` bytes into the payload is masked with the key continued at that offset, so the bytes
+      // on the wire are the same as if the frame had been masked whole.
+      let mask = frame
+        .mask_key()
+        .map(|key| std::array::from_fn(|i| key[(offset + i) % key.len()]));
+      let mut fragment =
+        Frame::new(fin, opcode, mask, Payload::Borrowed(chunk));
+      fragment.set_rsv_bits(rsv_bits);
+      fragment.compress = frame.compress;
+      self.write_single_frame(stream, fragment).await?;
+      opcode = OpCode::Continuation;
+      rsv_bits = 0;
+      offset += chunk.len();
+    }
+    Ok(())
+  }
+
+  async fn write_single_frame<'a, S>(
+    &'a mut self,
+    stream: &mut S,
+    frame: Frame<'a>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    let stream = &mut Tapped::new(stream, self.wire_tap.clone());
+    #[cfg(feature = "deflate")]
+    let mut frame = self.deflate(frame)?;
+    #[cfg(not(feature = "deflate"))]
+    let mut frame = frame;
+    if let Some(extension) = &self.extension {
+      extension.encode(&mut frame)?;
+    }
+
     if self.role == Role::Client && self.auto_apply_mask {
       frame.mask();
     }
 
-    if frame.opcode == OpCode::Close {
-      self.closed = true;
-    } else if self.closed {
-      return Err(WebSocketError::ConnectionClosed);
+    self.start_frame(frame.opcode, frame.payload.len())?;
+    if let Some(recorder) = &self.recorder {
+      recorder.record_frame(FrameDirection::Outbound, &frame);
+    }
+
+    let len = frame.payload.len();
+    if len <= frame::SMALL_FRAME_SIZE {
+      stream.send_all(frame.write_small(None).as_bytes()).await?;
+    } else if (self.vectored && len > self.writev_threshold)
+      || !self.reserve_write_buffer(len)
+    {
+      frame.writev_to(stream).await?;
+    } else {
+      let text = frame.write(&mut self.write_buffer);
+      stream.send_all(text).await?;
+    }
+
+    Ok(())
+  }
+
+  /// Writes a frame to the provided stream without consuming it.
+  ///
+  /// For clients the payload is masked in a copy, so the same frame can be written any number of times.
+  pub async fn write_frame_ref<S>(
+    &mut self,
+    stream: &mut S,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    #[cfg(feature = "keepalive")]
+    if let Some(timeout) = self.write_timeout {
+      let timer = self.timer.clone();
+      let write = timer::timeout(
+        &*timer,
+        timeout,
+        self.write_frame_ref_inner(stream, frame),
+      )
+      .await;
+      return self.timed_write(write);
     }
+    self.write_frame_ref_inner(stream, frame).await
+  }
 
-    if self.vectored && frame.payload.len() > self.writev_threshold {
-      frame.writev(stream).await?;
+  async fn write_frame_ref_inner<S>(
+    &mut self,
+    stream: &mut S,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    let stream = &mut Tapped::new(stream, self.wire_tap.clone());
+    self.start_frame(frame.opcode, frame.payload.len())?;
+
+    let len = frame.payload.len();
+    let mask = (self.role == Role::Client && self.auto_apply_mask)
+      .then(|| frame.mask_key().unwrap_or_else(rand::random));
+    self.record_copy(frame, mask);
+    if len <= frame::SMALL_FRAME_SIZE {
+      stream.send_all(frame.write_small(mask).as_bytes()).await?;
+      return Ok(());
+    }
+    let reserved = self.reserve_write_buffer(len);
+    if let Some(mask) = mask {
+      if reserved {
+        stream
+          .send_all(frame.write_masked(mask, &mut self.write_buffer))
+          .await?;
+      } else {
+        stream
+          .send_all(frame.write_masked(mask, &mut Vec::new()))
+          .await?;
+      }
+    } else if (self.vectored && len > self.writev_threshold) || !reserved {
+      frame.writev_to(stream).await?;
     } else {
       let text = frame.write(&mut self.write_buffer);
-      stream.write_all(text).await?;
+      stream.send_all(text).await?;
+    }
+
+    Ok(())
+  }
+
+  /// Writes a frame made of a precomputed header and a payload of matching length.
+  pub async fn write_with_header<S>(
+    &mut self,
+    stream: &mut S,
+    header: &FrameHeader,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    #[cfg(feature = "keepalive")]
+    if let Some(timeout) = self.write_timeout {
+      let timer = self.timer.clone();
+      let write = timer::timeout(
+        &*timer,
+        timeout,
+        self.write_with_header_inner(stream, header, payload),
+      )
+      .await;
+      return self.timed_write(write);
+    }
+    self.write_with_header_inner(stream, header, payload).await
+  }
+
+  async fn write_with_header_inner<S>(
+    &mut self,
+    stream: &mut S,
+    header: &FrameHeader,
+    payload: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: WsWrite,
+  {
+    if payload.len() != header.payload_len() {
+      return Err(WebSocketError::InvalidValue);
+    }
+    let stream = &mut Tapped::new(stream, self.wire_tap.clone());
+    self.start_frame(header.opcode(), header.payload_len())?;
+
+    let len = payload.len();
+    let mask = (self.role == Role::Client && self.auto_apply_mask)
+      .then(rand::random::<[u8; 4]>);
+    if let Some(recorder) = &self.recorder {
+      let head = header.as_bytes()[0];
+      recorder.record(FrameDirection::Outbound, head, mask, payload, false);
+    }
+    if len <= frame::SMALL_FRAME_SIZE {
+      let small = match mask {
+        Some(mask) => {
+          let (head, size) = header.masked(mask);
+          frame::SmallFrame::new(&head[..size], payload, Some(mask))
+        }
+        None => frame::SmallFrame::new(header.as_bytes(), payload, None),
+      };
+      stream.send_all(small.as_bytes()).await?;
+      return Ok(());
+    }
+    let reserved = self.reserve_write_buffer(len);
+    if let Some(mask) = mask {
+      let (head, size) = header.masked(mask);
+      if reserved {
+        let buf = &mut self.write_buffer;
+        let text = frame::write_masked_parts(buf, &head[..size], payload, mask);
+        stream.send_all(text).await?;
+      } else {
+        let buf = &mut Vec::new();
+        let text = frame::write_masked_parts(buf, &head[..size], payload, mask);
+        stream.send_all(text).await?;
+      }
+    } else if (self.vectored && len > self.writev_threshold) || !reserved {
+      frame::writev_parts(stream, header.as_bytes(), payload).await?;
+    } else {
+      let buf = &mut self.write_buffer;
+      let text = frame::write_parts(buf, header.as_bytes(), payload);
+      stream.send_all(text).await?;
+    }
+
+    Ok(())
+  }
+
+  /// Polls writing a frame. The frame is encoded into the write buffer on the first call and ignored by the calls
+  /// that follow until the encoded frame has been written.
+  pub fn poll_write_frame<S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+    frame: &Frame<'_>,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    if self.poll_write.is_none() {
+      self.start_poll_write(frame)?;
+    }
+    self.poll_pending(cx, stream)
+  }
+
+  /// Polls writing the rest of the frame started by `poll_write_frame`, if any.
+  pub fn poll_pending<S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    while let Some(pending) = &mut self.poll_write {
+      let head = pending.overflow.as_deref().unwrap_or(&self.write_buffer);
+      let payload = pending.payload.as_deref().unwrap_or_default();
+      let (buf, rest) = match head.get(pending.written..) {
+        Some(buf) if !buf.is_empty() => (buf, payload),
+        _ => (&payload[pending.written - head.len()..], &[][..]),
+      };
+      if buf.is_empty() {
+        self.poll_write = None;
+        break;
+      }
+      let write = if rest.is_empty() {
+        Pin::new(&mut *stream).poll_write(cx, buf)
+      } else {
+        let bufs = [std::io::IoSlice::new(buf), std::io::IoSlice::new(rest)];
+        Pin::new(&mut *stream).poll_write_vectored(cx, &bufs)
+      };
+      let n = match ready!(write) {
+        Ok(0) => Err(std::io::ErrorKind::WriteZero.into()),
+        result => result,
+      };
+      let n = match n {
+        Ok(n) => n,
+        Err(e) => {
+          self.poll_write = None;
+          return Poll::Ready(Err(WebSocketError::IoError(e)));
+        }
+      };
+      if let Some(tap) = &self.wire_tap {
+        let split = n.min(buf.len());
+        tap(FrameDirection::Outbound, &buf[..split]);
+        if n > split {
+          tap(FrameDirection::Outbound, &rest[..n - split]);
+        }
+      }
+      pending.written += n;
+    }
+    Poll::Ready(Ok(()))
+  }
+
+  /// Polls finishing the pending frame, writing a close frame with status code 1000 unless one has been written,
+  /// and flushing the stream.
+  pub fn poll_close<S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    ready!(self.poll_pending(cx, stream))?;
+    if !self.closed {
+      self.start_poll_write(&Frame::close(1000, &[]))?;
+      ready!(self.poll_pending(cx, stream))?;
+    }
+    self.poll_flush(cx, stream)
+  }
+
+  /// Polls finishing the pending frame and flushing the stream.
+  pub fn poll_flush<S>(
+    &mut self,
+    cx: &mut Context<'_>,
+    stream: &mut S,
+  ) -> Poll<Result<(), WebSocketError>>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    ready!(self.poll_pending(cx, stream))?;
+    Pin::new(stream)
+      .poll_flush(cx)
+      .map_err(WebSocketError::IoError)
+  }
+
+  /// Whether a frame started by `poll_write_frame` is not completely written yet.
+  #[cfg(feature = "unstable-split")]
+  fn is_poll_writing(&self) -> bool {
+    self.poll_write.is_some()
+  }
+
+  /// Encodes a frame for `poll_pending` to write. Like `write_frame_ref`, the frame is masked in the copy; unlike it,
+  /// data frames are compressed if compression is enabled.
+  fn start_poll_write(
+    &mut self,
+    frame: &Frame<'_>,
+  ) -> Result<(), WebSocketError> {
+    let mut copy = Frame::new(
+      frame.fin,
+      frame.opcode,
+      frame.mask_key(),
+      Payload::Borrowed(&frame.payload),
+    );
+    copy.set_rsv_bits(frame.rsv_bits());
+    copy.compress = frame.compress;
+    self.start_poll_send(copy)
+  }
+
+  /// Like `start_poll_write`, but takes the frame. An owned payload above `writev_threshold` is masked in place and
+  /// written after the header with vectored writes, without being copied into the write buffer.
+  fn start_poll_send(
+    &mut self,
+    frame: Frame<'_>,
+  ) -> Result<(), WebSocketError> {
+    #[cfg(feature = "deflate")]
+    let mut frame = self.deflate(frame)?;
+    #[cfg(not(feature = "deflate"))]
+    let mut frame = frame;
+    if let Some(extension) = &self.extension {
+      extension.encode(&mut frame)?;
+    }
+    self.start_frame(frame.opcode, frame.payload.len())?;
+
+    let mask = (self.role == Role::Client && self.auto_apply_mask)
+      .then(|| frame.mask_key().unwrap_or_else(rand::random));
+    self.record_copy(&frame, mask);
+    let len = frame.payload.len();
+    let owned = matches!(frame.payload, Payload::Owned(_) | Payload::Bytes(_));
+    if owned && self.vectored && len > self.writev_threshold {
+      if let Some(mask) = mask {
+        crate::unmask(frame.payload.to_mut(), mask);
+      }
+      let mut head = [0; codec::MAX_HEAD_SIZE];
+      let size = frame.fmt_head_with_mask(&mut head, mask.or(frame.mask_key()));
+      self.write_buffer.clear();
+      self.write_buffer.extend_from_slice(&head[..size]);
+      self.poll_write = Some(PollWrite {
+        written: 0,
+        overflow: None,
+        payload: Some(frame.payload.into()),
+      });
+      return Ok(());
+    }
+
+    let mut overflow = (!self.reserve_write_buffer(len)).then(Vec::new);
+    let buf = overflow.as_mut().unwrap_or(&mut self.write_buffer);
+    match mask {
+      Some(mask) => frame.write_masked(mask, buf),
+      None => frame.write(buf),
+    };
+    self.poll_write = Some(PollWrite {
+      written: 0,
+      overflow,
+      payload: None,
+    });
+    Ok(())
+  }
+
+  /// Records an outgoing frame that is written as a copy masked with `mask`.
+  fn record_copy(&self, frame: &Frame<'_>, mask: Option<[u8; 4]>) {
+    let Some(recorder) = &self.recorder else {
+      return;
+    };
+    match mask {
+      Some(mask) => recorder.record(
+        FrameDirection::Outbound,
+        capture::head_byte(frame),
+        Some(mask),
+        &frame.payload,
+        false,
+      ),
+      None => recorder.record_frame(FrameDirection::Outbound, frame),
+    }
+  }
+
+  /// Checks an outgoing frame and tracks the close state.
+  fn start_frame(
+    &mut self,
+    opcode: OpCode,
+    payload_len: usize,
+  ) -> Result<(), WebSocketError> {
+    if frame::is_control(opcode) && payload_len > 125 {
+      return Err(WebSocketError::ControlFrameTooLarge);
     }
 
+    #[cfg(feature = "keepalive")]
+    if self.write_timed_out {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+    if opcode == OpCode::Close {
+      self.closed = true;
+    } else if self.closed {
+      return Err(WebSocketError::ConnectionClosed);
+    }
     Ok(())
   }
+
+  /// Accounts for growing the write buffer to fit a `payload_len` frame. Returns `false` if the connection memory
+  /// budget does not allow it.
+  fn reserve_write_buffer(&mut self, payload_len: usize) -> bool {
+    let Some(budget) = &self.connection_memory else {
+      return true;
+    };
+    let needed = payload_len + codec::MAX_HEAD_SIZE;
+    let current = self.write_buffer.capacity();
+    if needed <= current {
+      return true;
+    }
+    match &mut self.write_buffer_permit {
+      Some(permit) => permit.try_grow(needed - current),
+      None => {
+        self.write_buffer_permit = budget.try_acquire(needed);
+        self.write_buffer_permit.is_some()
+      }
+    }
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use tokio::io::AsyncReadExt;
 
   const _: () = {
     const fn assert_unsync<S>() {
@@ -819,4 +3430,482 @@ mod tests {
     }
     assert_unsync::<WebSocket<tokio::net::TcpStream>>();
   };
+
+  #[tokio::test]
+  async fn close_waits_for_the_peer() {
+    let (server, client) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let peer = tokio::spawn(async move {
+      client
+        .write_frame(Frame::text(b"late".to_vec().into()))
+        .await?;
+      let frame = client.read_frame().await?;
+      let close = frame.as_close()?.unwrap();
+      assert_eq!(
+        (close.code, close.reason.as_str()),
+        (CloseCode::Normal, "bye")
+      );
+      // The close frame has been echoed; the server shuts down afterwards.
+      let mut rest = Vec::new();
+      client.into_inner().read_to_end(&mut rest).await?;
+      assert!(rest.is_empty());
+      Ok::<_, WebSocketError>(())
+    });
+
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.close(CloseCode::Normal, "bye").await.unwrap();
+    assert!(ws.is_closed());
+    peer.await.unwrap().unwrap();
+  }
+
+  #[tokio::test]
+  async fn unmasked_frames_require_opt_in() {
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    client.write_all(&[0x81, 0x02, b'h', b'i']).await.unwrap();
+    assert!(matches!(
+      ws.read_frame().await,
+      Err(WebSocketError::UnmaskedFrame)
+    ));
+
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    ws.set_accept_unmasked_frames(true);
+    client.write_all(&[0x81, 0x02, b'h', b'i']).await.unwrap();
+    assert_eq!(ws.read_frame().await.unwrap().as_text(), Some("hi"));
+  }
+
+  #[tokio::test]
+  async fn lenient_mode_skips_recoverable_frames() {
+    let (mut server, client) = tokio::io::duplex(1024);
+    let mut ws = WebSocket::after_handshake(client, Role::Client);
+    ws.set_lenient(true);
+    let mut bytes = vec![0x89, 126, 0, 200];
+    bytes.extend([0; 200]);
+    bytes.extend([0x83, 3, 1, 2, 3]);
+    bytes.extend([0x8a, 126, 0, 130]);
+    bytes.extend([0; 130]);
+    bytes.extend([0x81, 0x02, b'h', b'i']);
+    server.write_all(&bytes).await.unwrap();
+
+    assert!(matches!(
+      ws.read_frame().await,
+      Err(WebSocketError::PingFrameTooLarge)
+    ));
+    assert!(matches!(
+      ws.read_frame().await,
+      Err(WebSocketError::InvalidValue)
+    ));
+    assert!(matches!(
+      ws.read_frame().await,
+      Err(WebSocketError::ControlFrameTooLarge)
+    ));
+    assert_eq!(ws.read_frame().await.unwrap().as_text(), Some("hi"));
+    assert!(!ws.is_closed());
+  }
+
+  #[tokio::test]
+  async fn wire_tap() {
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(server, Role::Server);
+    let tapped = Arc::new(std::sync::Mutex::new(vec![]));
+    let log = tapped.clone();
+    ws.set_wire_tap(move |direction, bytes| {
+      log.lock().unwrap().push((direction, bytes.to_vec()));
+    });
+
+    let masked = [0x81, 0x82, 0, 0, 0, 0, b'h', b'i'];
+    client.write_all(&masked).await.unwrap();
+    assert_eq!(ws.read_frame().await.unwrap().as_text(), Some("hi"));
+    ws.write_frame(Frame::text(b"ok".to_vec().into()))
+      .await
+      .unwrap();
+
+    let tapped = tapped.lock().unwrap();
+    let bytes = |direction| {
+      let chunks = tapped.iter().filter(|(d, _)| *d == direction);
+      chunks
+        .flat_map(|(_, bytes)| bytes.clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(bytes(FrameDirection::Inbound), masked);
+    assert_eq!(bytes(FrameDirection::Outbound), [0x81, 0x02, b'o', b'k']);
+  }
+
+  #[tokio::test]
+  async fn explicit_mask_key() {
+    let (client, mut server) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(client, Role::Client);
+    let frame = Frame::text(b"hi".to_vec().into()).with_mask_key([1, 2, 3, 4]);
+    ws.write_frame_ref(&frame).await.unwrap();
+    ws.write_frame(frame).await.unwrap();
+
+    let mut buf = [0; 16];
+    server.read_exact(&mut buf).await.unwrap();
+    let expected = [0x81, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'i' ^ 2];
+    assert_eq!(buf[..8], expected);
+    assert_eq!(buf[8..], expected);
+  }
+
+  #[tokio::test]
+  async fn small_frames_use_one_write() {
+    /// Records each `poll_write` and `poll_write_vectored` call with the number of bytes written.
+    struct CountingStream {
+      inner: tokio::io::DuplexStream,
+      writes: Arc<std::sync::Mutex<Vec<(&'static str, usize)>>>,
+    }
+
+    impl AsyncRead for CountingStream {
+      fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+      ) -> Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_read(cx, buf)
+      }
+    }
+
+    impl AsyncWrite for CountingStream {
+      fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+      ) -> Poll<std::io::Result<usize>> {
+        let written =
+          ready!(std::pin::Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.writes.lock().unwrap().push(("write", written));
+        Poll::Ready(Ok(written))
+      }
+
+      fn poll_write_vectored(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+      ) -> Poll<std::io::Result<usize>> {
+        let written = ready!(
+          std::pin::Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+        )?;
+        self.writes.lock().unwrap().push(("writev", written));
+        Poll::Ready(Ok(written))
+      }
+
+      fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+      ) -> Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+      }
+
+      fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+      ) -> Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+      }
+    }
+
+    let (client, server) = tokio::io::duplex(4096);
+    let writes = Arc::new(std::sync::Mutex::new(vec![]));
+    let client = CountingStream {
+      inner: client,
+      writes: writes.clone(),
+    };
+    let mut ws = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    ws.set_writev_threshold(0);
+
+    let payload = vec![b'a'; frame::SMALL_FRAME_SIZE];
+    ws.write_frame(Frame::text(payload.as_slice().into()))
+      .await
+      .unwrap();
+    ws.write_frame_ref(&Frame::binary(payload.as_slice().into()))
+      .await
+      .unwrap();
+    let header = FrameHeader::new(true, OpCode::Text, payload.len());
+    ws.write_with_header(&header, &payload).await.unwrap();
+    let head = 4 + 4;
+    assert_eq!(
+      *writes.lock().unwrap(),
+      [("write", head + payload.len()); 3]
+    );
+    for _ in 0..3 {
+      assert_eq!(&*server.read_frame().await.unwrap().payload, payload);
+    }
+
+    // Larger frames still take the vectored path, with one call for the head and the payload.
+    writes.lock().unwrap().clear();
+    let payload = vec![b'a'; frame::SMALL_FRAME_SIZE + 1];
+    ws.write_frame(Frame::binary(payload.as_slice().into()))
+      .await
+      .unwrap();
+    assert_eq!(*writes.lock().unwrap(), [("writev", head + payload.len())]);
+    assert_eq!(&*server.read_frame().await.unwrap().payload, payload);
+  }
+
+  #[test]
+  fn getters_reflect_setters() {
+    let (stream, _) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(stream, Role::Client);
+    assert!(ws.role() == Role::Client);
+    assert!(ws.writev() && ws.auto_close() && ws.auto_pong());
+    assert_eq!(ws.max_connection_memory(), None);
+
+    ws.set_writev(false);
+    ws.set_writev_threshold(4096);
+    ws.set_auto_close(false);
+    ws.set_auto_pong(false);
+    ws.set_max_message_size(1024);
+    ws.set_read_buffer_high_water_mark(Some(1 << 20));
+    ws.set_max_connection_memory(1 << 16);
+    ws.set_auto_apply_mask(false);
+
+    assert!(!ws.writev());
+    assert_eq!(ws.writev_threshold(), 4096);
+    assert!(!ws.auto_close());
+    assert!(!ws.auto_pong());
+    assert_eq!(ws.max_message_size(), 1024);
+    assert_eq!(ws.read_buffer_high_water_mark(), Some(1 << 20));
+    assert_eq!(ws.max_connection_memory(), Some(1 << 16));
+    assert!(!ws.auto_apply_mask());
+  }
+
+  #[tokio::test]
+  async fn large_payload_followed_by_small_frame() {
+    let (client, server) = tokio::io::duplex(1 << 20);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let large = vec![7u8; DIRECT_READ_THRESHOLD * 2 + 3];
+    client
+      .write_frame(Frame::binary(large.clone().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"tail".to_vec().into()))
+      .await
+      .unwrap();
+
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Binary);
+    assert_eq!(frame.payload.len(), large.len());
+    assert!(frame.payload.iter().all(|b| *b == 7));
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.payload, b"tail");
+  }
+
+  #[tokio::test]
+  async fn write_frame_ref_reuses_frame() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let frame = Frame::text(b"heartbeat".as_ref().into());
+    client.write_frame_ref(&frame).await.unwrap();
+    client.write_frame_ref(&frame).await.unwrap();
+    assert_eq!(frame.payload, b"heartbeat");
+
+    for _ in 0..2 {
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(frame.payload, b"heartbeat");
+    }
+  }
+
+  #[tokio::test]
+  async fn poll_write_frame_across_pending() {
+    // A small pipe makes the writes return `Poll::Pending` until the reader catches up.
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let writer = tokio::spawn(async move {
+      let frame = Frame::binary(vec![7; 1000].into());
+      std::future::poll_fn(|cx| client.poll_write_frame(cx, &frame)).await?;
+      assert_eq!(&frame.payload[..], &[7; 1000]);
+      std::future::poll_fn(|cx| client.poll_close(cx)).await?;
+      assert!(client.is_closed());
+      // Closing again does not write a second close frame.
+      std::future::poll_fn(|cx| client.poll_close(cx)).await?;
+      Ok::<_, WebSocketError>(client)
+    });
+
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Binary);
+    assert_eq!(&frame.payload[..], &[7; 1000]);
+    let close = server.read_frame().await.unwrap();
+    assert_eq!(close.as_close().unwrap().unwrap().code, CloseCode::Normal);
+
+    let mut client = writer.await.unwrap().unwrap();
+    let frame = Frame::text(b"late".as_ref().into());
+    let result = std::future::poll_fn(|cx| client.poll_write_frame(cx, &frame));
+    assert!(matches!(
+      result.await,
+      Err(WebSocketError::ConnectionClosed)
+    ));
+  }
+
+  #[tokio::test]
+  async fn write_with_cached_header() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let header = FrameHeader::new(true, OpCode::Binary, 4);
+    assert_eq!(header.as_bytes(), &[0x82, 4]);
+    client
+      .write_with_header(&header, &[1, 2, 3, 4])
+      .await
+      .unwrap();
+    client
+      .write_with_header(&header, &[5, 6, 7, 8])
+      .await
+      .unwrap();
+    assert!(client.write_with_header(&header, &[1]).await.is_err());
+
+    assert_eq!(server.read_frame().await.unwrap().payload, &[1, 2, 3, 4]);
+    assert_eq!(server.read_frame().await.unwrap().payload, &[5, 6, 7, 8]);
+  }
+
+  #[tokio::test]
+  async fn frame_policy_rejects_before_payload() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    server.set_frame_policy(|info| match info.opcode {
+      OpCode::Binary => Err(CloseCode::Unsupported),
+      _ => Ok(()),
+    });
+
+    client
+      .write_frame(Frame::binary(vec![1, 2, 3].into()))
+      .await
+      .unwrap();
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::FrameRejected(CloseCode::Unsupported))
+    ));
+
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert_eq!(frame.payload, &1003u16.to_be_bytes());
+  }
+
+  #[tokio::test]
+  async fn oversized_close_reason_is_rejected() {
+    let (client, _server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+
+    let reason = [b'a'; MAX_CLOSE_REASON_LEN + 1];
+    assert!(matches!(
+      client.write_frame(Frame::close(1000, &reason)).await,
+      Err(WebSocketError::ControlFrameTooLarge)
+    ));
+    assert!(!client.is_closed());
+  }
+
+  #[tokio::test]
+  async fn oversized_frames_are_split() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_max_write_frame_size(Some(4));
+
+    client
+      .write_frame(Frame::text(b"hello world"[..].into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::binary(b"tiny"[..].into()))
+      .await
+      .unwrap();
+
+    let mut frames = Vec::new();
+    for _ in 0..4 {
+      let frame = server.read_frame().await.unwrap();
+      frames.push((frame.fin, frame.opcode, frame.payload.to_vec()));
+    }
+    assert_eq!(
+      frames,
+      [
+        (false, OpCode::Text, b"hell".to_vec()),
+        (false, OpCode::Continuation, b"o wo".to_vec()),
+        (true, OpCode::Continuation, b"rld".to_vec()),
+        (true, OpCode::Binary, b"tiny".to_vec()),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn split_frames_keep_the_mask_key() {
+    let key = [1, 2, 3, 4];
+    let mut masked = b"hello world".to_vec();
+    crate::unmask(&mut masked, key);
+
+    let (client, mut server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    client.set_max_write_frame_size(Some(3));
+    client
+      .write_frame(Frame::text(b"hello world"[..].into()).with_mask_key(key))
+      .await
+      .unwrap();
+    drop(client);
+
+    let mut wire = Vec::new();
+    server.read_to_end(&mut wire).await.unwrap();
+    let mut payload = Vec::new();
+    let mut keys = Vec::new();
+    let mut rest = &wire[..];
+    while !rest.is_empty() {
+      let len = (rest[1] & 0x7f) as usize;
+      keys.push(<[u8; 4]>::try_from(&rest[2..6]).unwrap());
+      payload.extend_from_slice(&rest[6..6 + len]);
+      rest = &rest[6 + len..];
+    }
+    assert_eq!(
+      keys,
+      [[1, 2, 3, 4], [4, 1, 2, 3], [3, 4, 1, 2], [2, 3, 4, 1]]
+    );
+    assert_eq!(payload, masked);
+
+    // A payload masked by the caller is split the same way.
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_max_write_frame_size(Some(3));
+    client.set_auto_apply_mask(false);
+    client
+      .write_frame(Frame::text(masked.into()).with_mask_key(key))
+      .await
+      .unwrap();
+    let mut message = Vec::new();
+    for _ in 0..4 {
+      message.extend_from_slice(&server.read_frame().await.unwrap().payload);
+    }
+    assert_eq!(message, b"hello world");
+  }
+
+  #[tokio::test]
+  async fn invalid_utf8_fails_at_the_fragment() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    // "é" split between fragments is fine, an invalid byte fails before the message is finished.
+    for (fin, opcode, payload) in [
+      (false, OpCode::Text, &b"caf\xc3"[..]),
+      (false, OpCode::Continuation, b"\xa9"),
+      (false, OpCode::Continuation, b"\xff"),
+    ] {
+      client
+        .write_frame(Frame::new(fin, opcode, None, payload.into()))
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(&server.read_frame().await.unwrap().payload[..], b"caf\xc3");
+    assert_eq!(&server.read_frame().await.unwrap().payload[..], b"\xa9");
+    assert!(matches!(
+      server.read_frame().await,
+      Err(WebSocketError::InvalidUTF8)
+    ));
+  }
 }
@@ -0,0 +1,141 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// A memory budget shared by any number of connections.
+///
+/// Every `WebSocket` and `FragmentCollector` configured with a clone of the same limiter accounts incoming payloads
+/// against one global counter. Once the budget is exhausted, new incoming messages are rejected with
+/// `WebSocketError::MemoryLimitExceeded` and the connection is closed with status code 1013 (Try Again Later).
+///
+/// A `WebSocket` accounts a frame's payload only while the frame is being read. Once the frame is returned, its bytes
+/// belong to the application and are no longer accounted. A `FragmentCollector` also accounts the fragments of a
+/// message until the message is complete.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{MemoryLimiter, WebSocket, Role};
+/// use tokio::net::TcpStream;
+///
+/// fn configure(ws: &mut WebSocket<TcpStream>, limiter: &MemoryLimiter) {
+///   ws.set_memory_limiter(limiter.clone());
+/// }
+///
+/// // 512 MiB for all connections combined.
+/// let limiter = MemoryLimiter::new(512 << 20);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MemoryLimiter {
+  inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+  limit: usize,
+  used: AtomicUsize,
+}
+
+impl MemoryLimiter {
+  /// Creates a new limiter with a budget of `limit` bytes.
+  pub fn new(limit: usize) -> Self {
+    Self {
+      inner: Arc::new(Inner {
+        limit,
+        used: AtomicUsize::new(0),
+      }),
+    }
+  }
+
+  /// The total budget in bytes.
+  pub fn limit(&self) -> usize {
+    self.inner.limit
+  }
+
+  /// The number of bytes currently accounted across all connections.
+  pub fn used(&self) -> usize {
+    self.inner.used.load(Ordering::Relaxed)
+  }
+
+  /// Reserves `size` bytes, returning `None` if that would exceed the budget.
+  pub(crate) fn try_acquire(&self, size: usize) -> Option<MemoryPermit> {
+    if !self.reserve(size) {
+      return None;
+    }
+    Some(MemoryPermit {
+      limiter: self.clone(),
+      size,
+    })
+  }
+
+  fn reserve(&self, size: usize) -> bool {
+    let limit = self.inner.limit;
+    self
+      .inner
+      .used
+      .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+        used.checked_add(size).filter(|total| *total <= limit)
+      })
+      .is_ok()
+  }
+}
+
+/// Bytes reserved from a `MemoryLimiter`, released when dropped.
+#[derive(Debug)]
+pub(crate) struct MemoryPermit {
+  limiter: MemoryLimiter,
+  size: usize,
+}
+
+impl MemoryPermit {
+  /// Reserves `additional` more bytes, returning `false` if that would exceed the budget.
+  pub fn try_grow(&mut self, additional: usize) -> bool {
+    if !self.limiter.reserve(additional) {
+      return false;
+    }
+    self.size += additional;
+    true
+  }
+}
+
+impl Drop for MemoryPermit {
+  fn drop(&mut self) {
+    self
+      .limiter
+      .inner
+      .used
+      .fetch_sub(self.size, Ordering::AcqRel);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn permits_are_released_on_drop() {
+    let limiter = MemoryLimiter::new(10);
+    let mut a = limiter.try_acquire(6).unwrap();
+    assert!(limiter.try_acquire(5).is_none());
+    assert!(a.try_grow(4));
+    assert!(!a.try_grow(1));
+    assert_eq!(limiter.used(), 10);
+    drop(a);
+    assert_eq!(limiter.used(), 0);
+    assert!(limiter.try_acquire(10).is_some());
+  }
+}
@@ -0,0 +1,201 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+
+use crate::CloseFrame;
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocketError;
+
+/// A complete message, for applications that do not need to deal with frames.
+///
+/// Read messages with `FragmentCollector::read_message`, which collects fragmented messages, and write them with
+/// `write_message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+  Text(String),
+  Binary(Bytes),
+  Ping(Bytes),
+  Pong(Bytes),
+  /// A close message, with `None` if the peer sent no status code.
+  Close(Option<CloseFrame>),
+}
+
+impl Message {
+  /// Creates a text message.
+  pub fn text(text: impl Into<String>) -> Self {
+    Message::Text(text.into())
+  }
+
+  /// Creates a binary message.
+  pub fn binary(data: impl Into<Bytes>) -> Self {
+    Message::Binary(data.into())
+  }
+
+  /// Returns the payload, or the reason of a close message.
+  pub fn into_data(self) -> Bytes {
+    match self {
+      Message::Text(text) => text.into(),
+      Message::Binary(data) | Message::Ping(data) | Message::Pong(data) => data,
+      Message::Close(Some(close)) => close.reason.into(),
+      Message::Close(None) => Bytes::new(),
+    }
+  }
+
+  /// Returns the text if this is a text message.
+  pub fn as_text(&self) -> Option<&str> {
+    match self {
+      Message::Text(text) => Some(text),
+      _ => None,
+    }
+  }
+
+  /// Returns whether this is a text message.
+  pub fn is_text(&self) -> bool {
+    matches!(self, Message::Text(_))
+  }
+
+  /// Returns whether this is a binary message.
+  pub fn is_binary(&self) -> bool {
+    matches!(self, Message::Binary(_))
+  }
+
+  /// Returns whether this is a close message.
+  pub fn is_close(&self) -> bool {
+    matches!(self, Message::Close(_))
+  }
+}
+
+impl From<String> for Message {
+  fn from(text: String) -> Self {
+    Message::Text(text)
+  }
+}
+
+impl From<&str> for Message {
+  fn from(text: &str) -> Self {
+    Message::Text(text.to_owned())
+  }
+}
+
+impl From<Bytes> for Message {
+  fn from(data: Bytes) -> Self {
+    Message::Binary(data)
+  }
+}
+
+impl From<Vec<u8>> for Message {
+  fn from(data: Vec<u8>) -> Self {
+    Message::Binary(data.into())
+  }
+}
+
+impl From<Message> for Frame<'static> {
+  fn from(message: Message) -> Self {
+    match message {
+      Message::Text(text) => Frame::text(text.into_bytes().into()),
+      Message::Binary(data) => Frame::binary(data.into()),
+      Message::Ping(data) => Frame::new(true, OpCode::Ping, None, data.into()),
+      Message::Pong(data) => Frame::pong(data.into()),
+      Message::Close(Some(close)) => {
+        Frame::close_with(close.code, &close.reason)
+      }
+      Message::Close(None) => Frame::close_raw(Vec::new().into()),
+    }
+  }
+}
+
+impl TryFrom<Frame<'_>> for Message {
+  type Error = WebSocketError;
+
+  /// Converts a complete, unfragmented frame. Fails with `WebSocketError::InvalidFragment` for frames that are part
+  /// of a fragmented message, `WebSocketError::InvalidUTF8` for text frames that are not valid UTF-8, and like
+  /// `Frame::as_close` for invalid close frames.
+  fn try_from(frame: Frame<'_>) -> Result<Self, WebSocketError> {
+    if !frame.fin {
+      return Err(WebSocketError::InvalidFragment);
+    }
+    match frame.opcode {
+      OpCode::Text => frame
+        .into_text()
+        .map(Message::Text)
+        .ok_or(WebSocketError::InvalidUTF8),
+      OpCode::Binary => Ok(Message::Binary(frame.payload.into())),
+      OpCode::Ping => Ok(Message::Ping(frame.payload.into())),
+      OpCode::Pong => Ok(Message::Pong(frame.payload.into())),
+      OpCode::Close => Ok(Message::Close(frame.as_close()?)),
+      OpCode::Continuation => Err(WebSocketError::InvalidFragment),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::CloseCode;
+  use crate::FragmentCollector;
+  use crate::Role;
+  use crate::WebSocket;
+
+  #[tokio::test]
+  async fn round_trip() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client =
+      FragmentCollector::new(WebSocket::after_handshake(client, Role::Client));
+    let mut server =
+      FragmentCollector::new(WebSocket::after_handshake(server, Role::Server));
+
+    client.send_text("hello").await.unwrap();
+    client.send_binary(&[1, 2, 3]).await.unwrap();
+    client
+      .write_frame(Frame::new(false, OpCode::Text, None, b"frag"[..].into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        b"mented"[..].into(),
+      ))
+      .await
+      .unwrap();
+    let close = CloseFrame {
+      code: CloseCode::Normal,
+      reason: "done".into(),
+    };
+    client
+      .write_message(Message::Close(Some(close.clone())))
+      .await
+      .unwrap();
+
+    assert_eq!(server.read_message().await.unwrap(), "hello".into());
+    assert_eq!(server.read_message().await.unwrap(), vec![1, 2, 3].into());
+    assert_eq!(server.read_message().await.unwrap(), "fragmented".into());
+    assert_eq!(
+      server.read_message().await.unwrap(),
+      Message::Close(Some(close))
+    );
+  }
+
+  #[test]
+  fn fragments_are_not_messages() {
+    let frame = Frame::new(false, OpCode::Binary, None, b"part"[..].into());
+    assert!(matches!(
+      Message::try_from(frame),
+      Err(WebSocketError::InvalidFragment)
+    ));
+  }
+}
@@ -0,0 +1,248 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Buf;
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+use crate::byte_stream::io_error;
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// The payload of one message, returned by `WebSocket::read_message_stream` and read frame by frame as the
+/// fragments arrive, without buffering the whole message.
+///
+/// Read it with `next_chunk`, which returns the payload of each frame without copying it, or through `AsyncRead`.
+/// With the `futures` feature, it is also a `Stream` of chunks. The stream ends after the final fragment.
+///
+/// Text messages are checked for valid UTF-8 across fragments, although a chunk may end in the middle of a
+/// character. Pings are answered while the message is read. A close frame before the final fragment fails the read
+/// with `WebSocketError::ConnectionClosed`.
+///
+/// The message must be read to the end before the next one is read from the `WebSocket`. The limits of
+/// `FragmentCollector` do not apply, only `WebSocket::set_max_message_size` for each frame.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::WebSocket;
+/// use tokio::fs::File;
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn save_upload(ws: &mut WebSocket<TcpStream>, file: &mut File) -> Result<()> {
+///   if let Some(mut message) = ws.read_message_stream().await? {
+///     tokio::io::copy(&mut message, file).await?;
+///   }
+///   Ok(())
+/// }
+/// ```
+pub struct MessageStream<'a, S> {
+  ws: &'a mut WebSocket<S>,
+  opcode: OpCode,
+  /// Payload that has not been returned yet.
+  chunk: Bytes,
+  /// An error to return once a close frame owed to the peer has been flushed.
+  error: Option<WebSocketError>,
+  done: bool,
+}
+
+impl<'a, S> MessageStream<'a, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  pub(crate) fn new(ws: &'a mut WebSocket<S>, frame: Frame<'_>) -> Self {
+    Self {
+      ws,
+      opcode: frame.opcode,
+      chunk: frame.payload.into(),
+      error: None,
+      done: frame.fin,
+    }
+  }
+
+  /// Returns `OpCode::Text` or `OpCode::Binary`.
+  pub fn opcode(&self) -> OpCode {
+    self.opcode
+  }
+
+  /// Returns the payload of the next frame, or `None` after the final fragment. Empty frames are skipped.
+  pub async fn next_chunk(&mut self) -> Option<Result<Bytes, WebSocketError>> {
+    poll_fn(|cx| self.poll_next_chunk(cx)).await
+  }
+
+  pub(crate) fn poll_next_chunk(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Result<Bytes, WebSocketError>>> {
+    if !self.chunk.is_empty() {
+      return Poll::Ready(Some(Ok(std::mem::take(&mut self.chunk))));
+    }
+    loop {
+      if self.error.is_some() {
+        // Send the close frame owed to the peer, if any, like `WsByteStream` does at the end of the stream.
+        let _ = ready!(self.ws.poll_flush(cx));
+        self.done = true;
+        return Poll::Ready(self.error.take().map(Err));
+      }
+      if self.done {
+        return Poll::Ready(None);
+      }
+      let frame = match ready!(self.ws.poll_read_frame(cx)) {
+        Ok(frame) => frame,
+        Err(e) => {
+          self.error = Some(e);
+          continue;
+        }
+      };
+      match frame.opcode {
+        OpCode::Continuation => {
+          self.done = frame.fin;
+          if !frame.payload.is_empty() {
+            return Poll::Ready(Some(Ok(frame.payload.into())));
+          }
+        }
+        OpCode::Ping | OpCode::Pong => {}
+        OpCode::Close => self.error = Some(WebSocketError::ConnectionClosed),
+        OpCode::Text | OpCode::Binary => {
+          self.error = Some(WebSocketError::InvalidFragment)
+        }
+      }
+    }
+  }
+}
+
+impl<S> AsyncRead for MessageStream<'_, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if this.chunk.is_empty() {
+      match ready!(this.poll_next_chunk(cx)) {
+        Some(Ok(chunk)) => this.chunk = chunk,
+        Some(Err(e)) => return Poll::Ready(Err(io_error(e))),
+        None => return Poll::Ready(Ok(())),
+      }
+    }
+    let n = this.chunk.len().min(buf.remaining());
+    buf.put_slice(&this.chunk[..n]);
+    this.chunk.advance(n);
+    Poll::Ready(Ok(()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncReadExt;
+
+  use super::*;
+  use crate::Role;
+
+  fn fragment(fin: bool, opcode: OpCode, payload: &[u8]) -> Frame<'_> {
+    Frame::new(fin, opcode, None, payload.into())
+  }
+
+  #[tokio::test]
+  async fn reads_fragments_as_they_arrive() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client
+      .write_frame(fragment(false, OpCode::Binary, b"ab"))
+      .await
+      .unwrap();
+    let mut message = server.read_message_stream().await.unwrap().unwrap();
+    assert_eq!(message.opcode(), OpCode::Binary);
+    assert_eq!(message.next_chunk().await.unwrap().unwrap(), "ab");
+
+    client
+      .write_frame(fragment(true, OpCode::Ping, b""))
+      .await
+      .unwrap();
+    client
+      .write_frame(fragment(true, OpCode::Continuation, b"cd"))
+      .await
+      .unwrap();
+    assert_eq!(message.next_chunk().await.unwrap().unwrap(), "cd");
+    assert!(message.next_chunk().await.is_none());
+
+    // The ping was answered.
+    assert_eq!(client.read_frame().await.unwrap().opcode, OpCode::Pong);
+  }
+
+  #[tokio::test]
+  async fn async_read_and_utf8() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    // "é" split between two fragments, then an invalid message.
+    client
+      .write_frame(fragment(false, OpCode::Text, b"caf\xc3"))
+      .await
+      .unwrap();
+    client
+      .write_frame(fragment(true, OpCode::Continuation, b"\xa9!"))
+      .await
+      .unwrap();
+    client
+      .write_frame(fragment(false, OpCode::Text, b"\xc3"))
+      .await
+      .unwrap();
+    client
+      .write_frame(fragment(true, OpCode::Continuation, b"("))
+      .await
+      .unwrap();
+
+    let mut text = String::new();
+    let mut message = server.read_message_stream().await.unwrap().unwrap();
+    message.read_to_string(&mut text).await.unwrap();
+    assert_eq!(text, "café!");
+
+    let mut message = server.read_message_stream().await.unwrap().unwrap();
+    assert_eq!(message.next_chunk().await.unwrap().unwrap(), b"\xc3"[..]);
+    assert!(matches!(
+      message.next_chunk().await,
+      Some(Err(WebSocketError::InvalidUTF8))
+    ));
+  }
+
+  #[tokio::test]
+  async fn ends_on_close() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    assert!(server.read_message_stream().await.unwrap().is_none());
+    assert_eq!(client.read_frame().await.unwrap().opcode, OpCode::Close);
+  }
+}
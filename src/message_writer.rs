@@ -0,0 +1,220 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::byte_stream::io_error;
+use crate::Frame;
+use crate::OpCode;
+use crate::Payload;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Writes one message as a sequence of fragments, returned by `WebSocket::message_writer`.
+///
+/// Bytes written are collected into frames of up to `frame_size` bytes. The first frame carries the message's opcode
+/// and the following ones are continuation frames. `flush` sends the bytes collected so far as a frame, and
+/// `shutdown` sends the final frame with FIN set, which may be empty. The underlying stream is not shut down.
+///
+/// The message is not finished until `shutdown` returns. Dropping the writer earlier leaves the peer waiting for
+/// the rest of the message, so no other data frame can be written on the connection. Text messages must be valid
+/// UTF-8 once complete, but a character may be split between frames.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{OpCode, WebSocket};
+/// use tokio::fs::File;
+/// use tokio::io::AsyncWriteExt;
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn send_file(ws: &mut WebSocket<TcpStream>, mut file: File) -> Result<()> {
+///   let mut writer = ws.message_writer(OpCode::Binary);
+///   tokio::io::copy(&mut file, &mut writer).await?;
+///   writer.shutdown().await?;
+///   Ok(())
+/// }
+/// ```
+pub struct MessageWriter<'a, S> {
+  ws: &'a mut WebSocket<S>,
+  opcode: OpCode,
+  buffer: Vec<u8>,
+  frame_size: usize,
+  /// Whether the first frame has been written.
+  started: bool,
+  /// Whether the final frame has been written.
+  finished: bool,
+}
+
+impl<'a, S> MessageWriter<'a, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  pub(crate) fn new(ws: &'a mut WebSocket<S>, opcode: OpCode) -> Self {
+    assert!(
+      matches!(opcode, OpCode::Text | OpCode::Binary),
+      "message_writer needs OpCode::Text or OpCode::Binary"
+    );
+    Self {
+      ws,
+      opcode,
+      buffer: Vec::new(),
+      frame_size: 64 << 10,
+      started: false,
+      finished: false,
+    }
+  }
+
+  /// Sets the payload size in bytes at which a frame is sent. Smaller writes are collected until it is reached.
+  ///
+  /// Default: 64 KiB
+  pub fn set_frame_size(&mut self, frame_size: usize) {
+    self.frame_size = frame_size.max(1);
+  }
+
+  /// Returns the payload size at which a frame is sent.
+  pub fn frame_size(&self) -> usize {
+    self.frame_size
+  }
+
+  /// Encodes the collected bytes as the next frame. Like `WsByteStream`, the frame has been accepted once it is
+  /// encoded, and the rest of it is written by the next poll.
+  fn poll_start_frame(
+    &mut self,
+    cx: &mut Context<'_>,
+    fin: bool,
+  ) -> Poll<Result<(), WebSocketError>> {
+    ready!(self.ws.write_half.poll_pending(cx, &mut self.ws.stream))?;
+    let opcode = if self.started {
+      OpCode::Continuation
+    } else {
+      self.opcode
+    };
+    let frame = Frame::new(fin, opcode, None, Payload::Borrowed(&self.buffer));
+    self.ws.write_half.start_poll_write(&frame)?;
+    self.buffer.clear();
+    self.started = true;
+    self.finished = fin;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl<S> AsyncWrite for MessageWriter<'_, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let this = self.get_mut();
+    if this.finished {
+      return Poll::Ready(Err(io_error(WebSocketError::ConnectionClosed)));
+    }
+    if buf.is_empty() {
+      return Poll::Ready(Ok(0));
+    }
+    if this.buffer.len() >= this.frame_size {
+      ready!(this.poll_start_frame(cx, false)).map_err(io_error)?;
+    }
+    let n = buf.len().min(this.frame_size - this.buffer.len());
+    this.buffer.extend_from_slice(&buf[..n]);
+    Poll::Ready(Ok(n))
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if !this.buffer.is_empty() {
+      ready!(this.poll_start_frame(cx, false)).map_err(io_error)?;
+    }
+    this.ws.poll_flush(cx).map_err(io_error)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if !this.finished {
+      ready!(this.poll_start_frame(cx, true)).map_err(io_error)?;
+    }
+    this.ws.poll_flush(cx).map_err(io_error)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::io::AsyncWriteExt;
+
+  use super::*;
+  use crate::Role;
+
+  #[tokio::test]
+  async fn fragments_writes() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let mut writer = client.message_writer(OpCode::Text);
+    writer.set_frame_size(4);
+    writer.write_all(b"hello").await.unwrap();
+    writer.flush().await.unwrap();
+    writer.write_all(b"!").await.unwrap();
+    writer.shutdown().await.unwrap();
+    assert!(writer.write(b"more").await.is_err());
+
+    let mut frames = Vec::new();
+    for _ in 0..3 {
+      let frame = server.read_frame().await.unwrap();
+      frames.push((frame.fin, frame.opcode, frame.payload.to_vec()));
+    }
+    assert_eq!(
+      frames,
+      [
+        (false, OpCode::Text, b"hell".to_vec()),
+        (false, OpCode::Continuation, b"o".to_vec()),
+        (true, OpCode::Continuation, b"!".to_vec()),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn empty_message() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client
+      .message_writer(OpCode::Binary)
+      .shutdown()
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert!(frame.fin);
+    assert_eq!(frame.opcode, OpCode::Binary);
+    assert!(frame.payload.is_empty());
+  }
+}
@@ -0,0 +1,114 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MQTT carries its control packets in binary messages, which may hold several packets or parts of one, so an MQTT
+//! client or broker runs over a [`WsByteStream`] once the `mqtt` subprotocol has been negotiated.
+//!
+//! # Example
+//!
+//! ```
+//! use fastwebsockets::mqtt;
+//! use fastwebsockets::WebSocketError;
+//! use fastwebsockets::WsByteStream;
+//! use http_body_util::Empty;
+//! use hyper::{body::{Bytes, Incoming}, Request, Response};
+//!
+//! fn server_upgrade(
+//!   mut req: Request<Incoming>,
+//! ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+//!   let (response, fut) = mqtt::upgrade(&mut req)?;
+//!   tokio::spawn(async move {
+//!     let stream = WsByteStream::new(fut.await?);
+//!     // Hand `stream` to the MQTT broker.
+//!     Ok::<_, WebSocketError>(())
+//!   });
+//!   Ok(response)
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::header::HeaderValue;
+use hyper::header::SEC_WEBSOCKET_PROTOCOL;
+use hyper::upgrade::Upgraded;
+use hyper::Request;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::handshake;
+use crate::upgrade;
+use crate::upgrade::offered_protocols;
+use crate::upgrade::UpgradeFut;
+use crate::WebSocketError;
+use crate::WsByteStream;
+
+/// The subprotocol of MQTT 3.1.1 and 5.0.
+pub const SUBPROTOCOL: &str = "mqtt";
+
+/// The subprotocol MQTT 3.1 clients offer instead of `mqtt`.
+pub const LEGACY_SUBPROTOCOL: &str = "mqttv3.1";
+
+/// Upgrades an MQTT over WebSocket request, selecting the `mqtt` subprotocol, or `mqttv3.1` for MQTT 3.1 clients.
+///
+/// MQTT requires the subprotocol, so a client that offers neither fails the upgrade with
+/// `WebSocketError::MissingSubprotocol`.
+pub fn upgrade<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), WebSocketError> {
+  let request = request.borrow_mut();
+  let (response, fut) = upgrade::upgrade_with_protocol(
+    &mut *request,
+    &[SUBPROTOCOL, LEGACY_SUBPROTOCOL],
+  )?;
+  if upgrade::selected_protocol(&response).is_none() {
+    return Err(WebSocketError::MissingSubprotocol);
+  }
+  Ok((response, fut))
+}
+
+/// Performs the client handshake like `handshake::client`, offering the `mqtt` subprotocol unless the request
+/// already offers it, and returns the connection as a byte stream for an MQTT client.
+///
+/// Fails with `WebSocketError::MissingSubprotocol` if the server does not select a subprotocol.
+pub async fn connect<S, E, B>(
+  executor: &E,
+  mut request: Request<B>,
+  socket: S,
+) -> Result<(WsByteStream<TokioIo<Upgraded>>, Response<Incoming>), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+  E: hyper::rt::Executor<Pin<Box<dyn Future<Output = ()> + Send>>>,
+  B: hyper::body::Body + 'static + Send,
+  B::Data: Send,
+  B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+  if !offered_protocols(request.headers()).any(|p| p == SUBPROTOCOL) {
+    request.headers_mut().append(
+      SEC_WEBSOCKET_PROTOCOL,
+      HeaderValue::from_static(SUBPROTOCOL),
+    );
+  }
+
+  let (ws, response) = handshake::client(executor, request, socket).await?;
+  if upgrade::selected_protocol(&response).is_none() {
+    return Err(WebSocketError::MissingSubprotocol);
+  }
+  Ok((WsByteStream::new(ws), response))
+}
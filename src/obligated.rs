@@ -0,0 +1,277 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use tokio::io::AsyncWrite;
+use tokio::sync::mpsc;
+
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocketError;
+use crate::WebSocketWrite;
+
+/// Creates a channel that carries obligated pong and close frames from a `WebSocketRead` to the task that owns the
+/// matching `WebSocketWrite`.
+///
+/// `capacity` bounds the number of frames waiting to be written. Once it is reached, the read half waits for the
+/// writer to catch up.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{obligated_channel, WebSocket, WebSocketError};
+/// use tokio::io::{AsyncRead, AsyncWrite};
+///
+/// async fn handle<S>(ws: WebSocket<S>) -> Result<(), WebSocketError>
+/// where
+///   S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+/// {
+///   let (mut rx, mut tx) = ws.split(tokio::io::split);
+///   let (sender, receiver) = obligated_channel(16);
+///   tokio::spawn(async move { receiver.pump(&mut tx).await });
+///
+///   loop {
+///     let frame = rx.read_frame(&mut |frame| sender.send(frame)).await?;
+///     // ...
+///   }
+/// }
+/// ```
+pub fn obligated_channel(
+  capacity: usize,
+) -> (ObligatedSender, ObligatedReceiver) {
+  let (tx, rx) = mpsc::channel(capacity);
+  (ObligatedSender { tx }, ObligatedReceiver { rx })
+}
+
+/// The sending side of [`obligated_channel`], meant to be used as the `send_fn` of `WebSocketRead::read_frame`.
+#[derive(Clone, Debug)]
+pub struct ObligatedSender {
+  tx: mpsc::Sender<Frame<'static>>,
+}
+
+impl ObligatedSender {
+  /// Queues `frame` for the writer, copying its payload if it borrows the read buffer.
+  ///
+  /// Fails with `WebSocketError::SendError` if the receiver has been dropped.
+  pub async fn send(&self, frame: Frame<'_>) -> Result<(), WebSocketError> {
+    self
+      .tx
+      .send(frame.into_owned())
+      .await
+      .map_err(|e| WebSocketError::SendError(Box::new(e)))
+  }
+}
+
+/// The receiving side of [`obligated_channel`].
+#[derive(Debug)]
+pub struct ObligatedReceiver {
+  rx: mpsc::Receiver<Frame<'static>>,
+}
+
+impl ObligatedReceiver {
+  /// Receives the next queued frame, or `None` once every sender has been dropped.
+  pub async fn recv(&mut self) -> Option<Frame<'static>> {
+    self.rx.recv().await
+  }
+
+  /// Writes every queued frame to `ws` until every sender has been dropped or a write fails.
+  pub async fn pump<S>(
+    mut self,
+    ws: &mut WebSocketWrite<S>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    while let Some(frame) = self.rx.recv().await {
+      ws.write_frame(frame).await?;
+    }
+    Ok(())
+  }
+}
+
+/// Obligated frames queued by `WebSocketRead::read_frame_queued` until the `WebSocketWrite` of the same connection
+/// writes them.
+///
+/// Only the most recent pong is kept, as allowed by RFC 6455 Section 5.5.3, so the queue never holds more than one
+/// pong and one close frame.
+#[derive(Default)]
+pub(crate) struct ControlQueue {
+  pending: AtomicBool,
+  frames: Mutex<Pending>,
+}
+
+#[derive(Default)]
+struct Pending {
+  pong: Option<Frame<'static>>,
+  close: Option<Frame<'static>>,
+}
+
+impl ControlQueue {
+  pub fn push(&self, frame: Frame<'_>) {
+    let frame = frame.into_owned();
+    let mut frames = self.frames.lock().unwrap();
+    if frame.opcode == OpCode::Close {
+      frames.close = Some(frame);
+    } else {
+      frames.pong = Some(frame);
+    }
+    self.pending.store(true, Ordering::Release);
+  }
+
+  pub fn queued_bytes(&self) -> usize {
+    if !self.pending.load(Ordering::Acquire) {
+      return 0;
+    }
+    let frames = self.frames.lock().unwrap();
+    [&frames.pong, &frames.close]
+      .into_iter()
+      .flatten()
+      .map(|frame| frame.payload.len())
+      .sum()
+  }
+
+  /// Removes the queued frames, pong first.
+  pub fn take(&self) -> [Option<Frame<'static>>; 2] {
+    if !self.pending.swap(false, Ordering::Acquire) {
+      return [None, None];
+    }
+    let mut frames = self.frames.lock().unwrap();
+    [frames.pong.take(), frames.close.take()]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use crate::Role;
+  use crate::WebSocket;
+
+  #[tokio::test]
+  async fn pong_is_written_by_pump() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let server = WebSocket::after_handshake(server, Role::Server);
+    let (mut rx, mut tx) = server.split(tokio::io::split);
+
+    let (sender, receiver) = obligated_channel(4);
+    let pump = tokio::spawn(async move { receiver.pump(&mut tx).await });
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+
+    let frame = rx
+      .read_frame(&mut |frame| sender.send(frame))
+      .await
+      .unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    let pong = client.read_frame().await.unwrap();
+    assert_eq!(pong.opcode, OpCode::Pong);
+    assert_eq!(&*pong.payload, b"hi");
+
+    drop(sender);
+    pump.await.unwrap().unwrap();
+  }
+
+  #[test]
+  fn control_queue_keeps_latest_pong() {
+    let queue = ControlQueue::default();
+    queue.push(Frame::pong(b"1".to_vec().into()));
+    queue.push(Frame::close(1000, b""));
+    queue.push(Frame::pong(b"2".to_vec().into()));
+
+    let [pong, close] = queue.take();
+    assert_eq!(pong, Some(Frame::pong(b"2".to_vec().into())));
+    assert_eq!(close, Some(Frame::close(1000, b"")));
+    assert!(matches!(queue.take(), [None, None]));
+  }
+
+  #[tokio::test]
+  async fn queued_pong_is_written_before_next_frame() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let server = WebSocket::after_handshake(server, Role::Server);
+    let (mut rx, mut tx) = server.split(tokio::io::split);
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"world".to_vec().into()))
+      .await
+      .unwrap();
+
+    let frame = rx.read_frame_queued().await.unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    // The masked "world" frame is still buffered.
+    assert_eq!(rx.buffered_bytes(), 11);
+    assert_eq!(tx.queued_bytes(), 2);
+    tx.write_frame(Frame::text(b"reply".to_vec().into()))
+      .await
+      .unwrap();
+
+    let pong = client.read_frame().await.unwrap();
+    assert_eq!(pong.opcode, OpCode::Pong);
+    assert_eq!(&*pong.payload, b"hi");
+    let reply = client.read_frame().await.unwrap();
+    assert_eq!(reply.as_text(), Some("reply"));
+    assert_eq!(tx.queued_bytes(), 0);
+  }
+
+  #[tokio::test]
+  async fn queued_frames_are_written_by_poll_close() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let server = WebSocket::after_handshake(server, Role::Server);
+    let (mut rx, mut tx) = server.split(tokio::io::split);
+
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"bye".to_vec().into()))
+      .await
+      .unwrap();
+
+    let frame = rx.read_frame_queued().await.unwrap();
+    assert_eq!(frame.as_text(), Some("bye"));
+    assert_eq!(tx.queued_bytes(), 2);
+    std::future::poll_fn(|cx| tx.poll_close(cx)).await.unwrap();
+    assert!(tx.is_closed());
+
+    let pong = client.read_frame().await.unwrap();
+    assert_eq!(pong.opcode, OpCode::Pong);
+    assert_eq!(&*pong.payload, b"hi");
+    let close = client.read_frame().await.unwrap();
+    assert_eq!(close.opcode, OpCode::Close);
+    assert_eq!(&close.payload[..], &1000u16.to_be_bytes());
+    assert_eq!(tx.queued_bytes(), 0);
+  }
+}
@@ -0,0 +1,288 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::io;
+use std::io::IoSlice;
+
+use bytes::BytesMut;
+
+use crate::io::WsRead;
+use crate::io::WsWrite;
+use crate::Frame;
+use crate::ReadHalf;
+use crate::Role;
+use crate::WebSocketError;
+use crate::WriteHalf;
+
+/// The reading side of a transport that takes ownership of the buffer for the duration of a read, as the streams of
+/// completion-based runtimes such as monoio, glommio and tokio-uring do.
+pub trait OwnedRead {
+  /// Reads at most `limit` bytes into the spare capacity of `buf`, after its contents, and returns the number of
+  /// bytes read, which is 0 at the end of the stream, with the buffer. The bytes read are appended to the buffer's
+  /// length.
+  ///
+  /// The buffer is the websocket's read buffer, so frames are parsed where the runtime wrote them.
+  fn read_owned(
+    &mut self,
+    buf: BytesMut,
+    limit: usize,
+  ) -> impl Future<Output = (io::Result<usize>, BytesMut)>;
+}
+
+/// The writing side of a transport that takes ownership of the buffer for the duration of a write.
+pub trait OwnedWrite {
+  /// Writes all of `buf` and returns it.
+  fn write_all_owned(
+    &mut self,
+    buf: Vec<u8>,
+  ) -> impl Future<Output = (io::Result<()>, Vec<u8>)>;
+}
+
+/// Implements the transport traits of the protocol core for a stream with owned-buffer IO.
+struct Owned<S> {
+  stream: S,
+  /// The buffer handed to the stream for writes, kept between them.
+  write_buf: Vec<u8>,
+}
+
+impl<S: OwnedRead> WsRead for Owned<S> {
+  async fn read_into(
+    &mut self,
+    buf: &mut BytesMut,
+    limit: usize,
+  ) -> io::Result<usize> {
+    if buf.capacity() == buf.len() {
+      buf.reserve(1024);
+    }
+    let limit = limit.min(buf.capacity() - buf.len());
+    let (read, owned) =
+      self.stream.read_owned(std::mem::take(buf), limit).await;
+    *buf = owned;
+    read
+  }
+}
+
+impl<S: OwnedWrite> WsWrite for Owned<S> {
+  async fn send_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    self.send_vectored(&[IoSlice::new(buf)]).await.map(drop)
+  }
+
+  async fn send_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    // The slices are borrowed, so they are copied into the owned write buffer and written with one operation.
+    let mut write_buf = std::mem::take(&mut self.write_buf);
+    write_buf.clear();
+    for buf in bufs {
+      write_buf.extend_from_slice(buf);
+    }
+    let len = write_buf.len();
+    let (result, write_buf) = self.stream.write_all_owned(write_buf).await;
+    self.write_buf = write_buf;
+    result.map(|()| len)
+  }
+}
+
+/// A websocket over a stream with owned-buffer IO, for completion-based runtimes. It runs the same protocol core as
+/// `WebSocket`.
+///
+/// Frames are read into the websocket's read buffer, which is handed to [`OwnedRead::read_owned`]. Written frames are
+/// copied into a buffer that is handed to [`OwnedWrite::write_all_owned`] and reused between writes. Keepalive,
+/// timeouts, compression and the other `WebSocket` options are not available.
+///
+/// # Example
+///
+/// A monoio `TcpStream`, whose reads and writes take owned buffers:
+///
+/// ```ignore
+/// use fastwebsockets::{OwnedRead, OwnedWrite};
+/// use monoio::buf::{IoBuf, IoBufMut};
+/// use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+///
+/// struct Monoio(monoio::net::TcpStream);
+///
+/// impl OwnedRead for Monoio {
+///   async fn read_owned(
+///     &mut self,
+///     buf: bytes::BytesMut,
+///     limit: usize,
+///   ) -> (std::io::Result<usize>, bytes::BytesMut) {
+///     let len = buf.len();
+///     let (read, slice) = self.0.read(buf.slice_mut(len..len + limit)).await;
+///     (read, slice.into_inner())
+///   }
+/// }
+///
+/// impl OwnedWrite for Monoio {
+///   async fn write_all_owned(
+///     &mut self,
+///     buf: Vec<u8>,
+///   ) -> (std::io::Result<()>, Vec<u8>) {
+///     let (written, buf) = self.0.write_all(buf).await;
+///     (written.map(drop), buf)
+///   }
+/// }
+/// ```
+pub struct OwnedWebSocket<S> {
+  stream: Owned<S>,
+  read_half: ReadHalf,
+  write_half: WriteHalf,
+}
+
+impl<S> OwnedWebSocket<S> {
+  /// Creates a websocket from a stream that has already completed the handshake.
+  pub fn after_handshake(stream: S, role: Role) -> Self {
+    Self {
+      stream: Owned {
+        stream,
+        write_buf: Vec::new(),
+      },
+      read_half: ReadHalf::after_handshake(role),
+      write_half: WriteHalf::after_handshake(role),
+    }
+  }
+
+  /// Consumes the websocket and returns the underlying stream. Received bytes that have not been read as frames are
+  /// lost.
+  pub fn into_inner(self) -> S {
+    self.stream.stream
+  }
+
+  /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the
+  /// application will have to manually send close frames.
+  ///
+  /// Default: `true`
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.read_half.auto_close = auto_close;
+  }
+
+  /// Sets whether to automatically send a pong frame when a ping frame is received.
+  ///
+  /// Default: `true`
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.read_half.auto_pong = auto_pong;
+  }
+
+  /// Sets the maximum message size in bytes. If a message is received that is larger than this, the connection will
+  /// be closed.
+  ///
+  /// Default: 64 MiB
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.read_half.max_message_size = max_message_size;
+  }
+}
+
+impl<S: OwnedRead + OwnedWrite> OwnedWebSocket<S> {
+  /// Reads a frame. Like `WebSocket::read_frame`, pings are answered and close frames echoed unless `auto_pong` and
+  /// `auto_close` are disabled.
+  pub async fn read_frame<'f>(&mut self) -> Result<Frame<'f>, WebSocketError> {
+    loop {
+      let (res, obligated_send) =
+        self.read_half.read_frame_inner(&mut self.stream).await;
+      let is_closed = self.write_half.closed;
+      if let Some(frame) = obligated_send {
+        if !is_closed {
+          self.write_half.write_frame(&mut self.stream, frame).await?;
+        }
+      }
+      if let Some(frame) = res? {
+        if is_closed && frame.opcode != crate::OpCode::Close {
+          return Err(WebSocketError::ConnectionClosed);
+        }
+        break Ok(frame);
+      }
+    }
+  }
+
+  /// Writes a frame. Clients mask it with a fresh key.
+  pub async fn write_frame(
+    &mut self,
+    frame: Frame<'_>,
+  ) -> Result<(), WebSocketError> {
+    self.write_half.write_frame(&mut self.stream, frame).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use crate::WebSocket;
+  use tokio::io::AsyncReadExt;
+  use tokio::io::AsyncWriteExt;
+
+  /// An in-memory stream with owned-buffer IO, which like a completion-based runtime takes the buffers for the
+  /// duration of each operation. It reads at most 7 bytes at a time.
+  struct OwnedStream(tokio::io::DuplexStream);
+
+  impl OwnedRead for OwnedStream {
+    async fn read_owned(
+      &mut self,
+      mut buf: BytesMut,
+      limit: usize,
+    ) -> (io::Result<usize>, BytesMut) {
+      let mut chunk = [0; 7];
+      let limit = limit.min(chunk.len());
+      let read = self.0.read(&mut chunk[..limit]).await;
+      if let Ok(n) = read {
+        assert!(buf.capacity() - buf.len() >= n);
+        buf.extend_from_slice(&chunk[..n]);
+      }
+      (read, buf)
+    }
+  }
+
+  impl OwnedWrite for OwnedStream {
+    async fn write_all_owned(
+      &mut self,
+      buf: Vec<u8>,
+    ) -> (io::Result<()>, Vec<u8>) {
+      let written = self.0.write_all(&buf).await;
+      (written, buf)
+    }
+  }
+
+  #[tokio::test]
+  async fn websocket_over_owned_buffers() {
+    let (client, server) = tokio::io::duplex(1 << 16);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server =
+      OwnedWebSocket::after_handshake(OwnedStream(server), Role::Server);
+
+    // The frames take many reads of at most 7 bytes.
+    let payload = vec![b'a'; 4096];
+    client
+      .write_frame(Frame::new(true, OpCode::Ping, None, b"p".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::binary(payload.clone().into()))
+      .await
+      .unwrap();
+    // The ping is answered before the binary frame is returned.
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, &payload[..]);
+    server.write_frame(frame).await.unwrap();
+
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Pong);
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, &payload[..]);
+
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+  }
+}
@@ -0,0 +1,92 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Unanswered pings beyond this many are forgotten, oldest first.
+const MAX_PENDING_PINGS: usize = 16;
+
+/// Pings sent with `WebSocket::ping_with_payload` that have not been answered yet, and the last round-trip time.
+#[derive(Default)]
+pub(crate) struct PingTracker {
+  pending: VecDeque<(Box<[u8]>, Instant)>,
+  latency: Option<Duration>,
+}
+
+impl PingTracker {
+  pub(crate) fn sent(&mut self, payload: &[u8], at: Instant) {
+    if self.pending.len() == MAX_PENDING_PINGS {
+      self.pending.pop_front();
+    }
+    self.pending.push_back((payload.into(), at));
+  }
+
+  /// Records the round-trip time if the pong answers a pending ping. Older pings are dropped as well, since peers
+  /// may answer only the most recent of several pings.
+  pub(crate) fn received_pong(&mut self, payload: &[u8], at: Instant) {
+    let Some(i) = self.pending.iter().position(|(p, _)| **p == *payload) else {
+      return;
+    };
+    let (_, sent) = self.pending.drain(..=i).next_back().unwrap();
+    self.latency = Some(at.saturating_duration_since(sent));
+  }
+
+  pub(crate) fn latency(&self) -> Option<Duration> {
+    self.latency
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use crate::Role;
+  use crate::WebSocket;
+
+  #[test]
+  fn matches_pongs_to_pings() {
+    let mut tracker = PingTracker::default();
+    let now = Instant::now();
+    tracker.sent(b"1", now - Duration::from_secs(3));
+    tracker.sent(b"2", now - Duration::from_secs(2));
+    tracker.sent(b"3", now - Duration::from_secs(1));
+
+    tracker.received_pong(b"unsolicited", now);
+    assert_eq!(tracker.latency(), None);
+
+    // Answering the second ping drops the first.
+    tracker.received_pong(b"2", now);
+    assert_eq!(tracker.latency(), Some(Duration::from_secs(2)));
+    tracker.received_pong(b"1", now);
+    assert_eq!(tracker.latency(), Some(Duration::from_secs(2)));
+    assert_eq!(tracker.pending.len(), 1);
+  }
+
+  #[tokio::test]
+  async fn measures_round_trip() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    tokio::spawn(async move { while server.read_frame().await.is_ok() {} });
+
+    let mut ws = WebSocket::after_handshake(client, Role::Client);
+    assert_eq!(ws.latency(), None);
+    ws.ping_with_payload(b"42").await.unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Pong);
+    assert_eq!(&*frame.payload, b"42");
+    assert!(ws.latency().is_some());
+  }
+}
@@ -0,0 +1,32 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::CloseCode;
+use crate::OpCode;
+
+/// The header fields of an incoming frame, as seen by a frame policy before the payload is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+  /// Indicates if this is the final frame in a message.
+  pub fin: bool,
+  /// The opcode of the frame.
+  pub opcode: OpCode,
+  /// The length of the payload in bytes.
+  pub payload_len: usize,
+  /// Whether the payload is masked.
+  pub masked: bool,
+}
+
+pub(crate) type FramePolicy =
+  Box<dyn FnMut(&FrameInfo) -> Result<(), CloseCode> + Send>;
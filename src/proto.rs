@@ -0,0 +1,236 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The protocol state machine without IO, for custom event loops and for tests that work on byte slices.
+//!
+//! A [`Connection`] is fed the bytes received from the peer and returns the frames they hold. The bytes to send,
+//! including automatic pongs and close replies, accumulate in its output buffer until the caller writes them.
+//!
+//! # Example
+//!
+//! ```
+//! use fastwebsockets::proto::Connection;
+//! use fastwebsockets::{Frame, OpCode, Role, WebSocketError};
+//!
+//! fn exchange() -> Result<(), WebSocketError> {
+//!   let mut client = Connection::new(Role::Client);
+//!   let mut server = Connection::new(Role::Server);
+//!
+//!   client.send(Frame::text(b"hello"[..].into()))?;
+//!   server.receive(&client.take_output());
+//!   let frame = server.next_frame()?.unwrap();
+//!   assert_eq!(frame.opcode, OpCode::Text);
+//!   Ok(())
+//! }
+//! # exchange().unwrap();
+//! ```
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::Context;
+use std::task::Poll;
+use std::task::RawWaker;
+use std::task::RawWakerVTable;
+use std::task::Waker;
+
+use crate::io::Drained;
+use crate::Frame;
+use crate::OpCode;
+use crate::ReadHalf;
+use crate::Role;
+use crate::WebSocketError;
+use crate::WriteHalf;
+
+/// A websocket connection that consumes received bytes and produces bytes to send, without doing any IO.
+pub struct Connection {
+  read_half: ReadHalf,
+  write_half: WriteHalf,
+  output: Vec<u8>,
+}
+
+impl Connection {
+  /// Creates a connection whose handshake has already been done.
+  pub fn new(role: Role) -> Self {
+    Self {
+      read_half: ReadHalf::after_handshake(role),
+      write_half: WriteHalf::after_handshake(role),
+      output: Vec::new(),
+    }
+  }
+
+  /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the
+  /// application will have to manually send close frames.
+  ///
+  /// Default: `true`
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.read_half.auto_close = auto_close;
+  }
+
+  /// Sets whether to automatically send a pong frame when a ping frame is received.
+  ///
+  /// Default: `true`
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.read_half.auto_pong = auto_pong;
+  }
+
+  /// Sets the maximum message size in bytes. If a message is received that is larger than this, the connection
+  /// returns `WebSocketError::FrameTooLarge`.
+  ///
+  /// Default: 64 MiB
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.read_half.max_message_size = max_message_size;
+  }
+
+  /// Returns the role of the connection.
+  pub fn role(&self) -> Role {
+    self.read_half.role
+  }
+
+  /// Returns whether a close frame has been sent.
+  pub fn is_closed(&self) -> bool {
+    self.write_half.closed
+  }
+
+  /// Returns the number of received bytes that have not been parsed into frames yet.
+  pub fn buffered_bytes(&self) -> usize {
+    self.read_half.buffered_bytes()
+  }
+
+  /// Appends bytes received from the peer.
+  pub fn receive(&mut self, data: &[u8]) {
+    self.read_half.buffer.extend_from_slice(data);
+  }
+
+  /// Returns the next frame from the received bytes, or `None` if they do not hold a whole frame yet.
+  ///
+  /// Like `WebSocket::read_frame`, pings are answered and not returned when `auto_pong` is enabled, and a close
+  /// frame is echoed when `auto_close` is enabled. The replies are added to the output.
+  pub fn next_frame(
+    &mut self,
+  ) -> Result<Option<Frame<'static>>, WebSocketError> {
+    while self.read_half.missing_frame_bytes().is_none() {
+      let (res, obligated_send) =
+        now(self.read_half.read_frame_inner(&mut Drained));
+      let is_closed = self.write_half.closed;
+      if let Some(frame) = obligated_send {
+        if !is_closed {
+          self.send(frame)?;
+        }
+      }
+      if let Some(frame) = res? {
+        if is_closed && frame.opcode != OpCode::Close {
+          return Err(WebSocketError::ConnectionClosed);
+        }
+        return Ok(Some(frame));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Encodes a frame into the output, masking it for clients.
+  pub fn send(&mut self, frame: Frame<'_>) -> Result<(), WebSocketError> {
+    now(self.write_half.write_frame(&mut self.output, frame))
+  }
+
+  /// Returns the bytes waiting to be sent to the peer.
+  pub fn output(&self) -> &[u8] {
+    &self.output
+  }
+
+  /// Removes the first `n` bytes of the output, once they have been sent.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `n` is larger than the length of the output.
+  pub fn consume_output(&mut self, n: usize) {
+    self.output.drain(..n);
+  }
+
+  /// Takes all bytes waiting to be sent to the peer.
+  pub fn take_output(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.output)
+  }
+}
+
+/// Runs a future that completes without waiting, as reading buffered frames and writing to a `Vec` do.
+fn now<F: Future>(fut: F) -> F::Output {
+  let waker = noop_waker();
+  match pin!(fut).poll(&mut Context::from_waker(&waker)) {
+    Poll::Ready(output) => output,
+    Poll::Pending => unreachable!("IO on buffers does not wait"),
+  }
+}
+
+/// A waker that does nothing, as `Waker::noop` is not available on the minimum supported Rust version.
+fn noop_waker() -> Waker {
+  const VTABLE: RawWakerVTable =
+    RawWakerVTable::new(|_| RAW, |_| {}, |_| {}, |_| {});
+  const RAW: RawWaker = RawWaker::new(std::ptr::null(), &VTABLE);
+  // SAFETY: the vtable functions ignore the data pointer.
+  unsafe { Waker::from_raw(RAW) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn frames_split_across_receives() {
+    let mut client = Connection::new(Role::Client);
+    let mut server = Connection::new(Role::Server);
+
+    client.send(Frame::text(b"hello".to_vec().into())).unwrap();
+    client.send(Frame::binary(vec![7; 300].into())).unwrap();
+    let bytes = client.take_output();
+    for chunk in bytes.chunks(3) {
+      server.receive(chunk);
+      if server.buffered_bytes() < 11 {
+        assert!(server.next_frame().unwrap().is_none());
+      }
+    }
+    let frame = server.next_frame().unwrap().unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    let frame = server.next_frame().unwrap().unwrap();
+    assert_eq!(&*frame.payload, &[7; 300][..]);
+    assert!(server.next_frame().unwrap().is_none());
+    assert_eq!(server.buffered_bytes(), 0);
+  }
+
+  #[test]
+  fn pings_and_close_are_answered_in_the_output() {
+    let mut client = Connection::new(Role::Client);
+    let mut server = Connection::new(Role::Server);
+
+    client
+      .send(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .unwrap();
+    server.receive(&client.take_output());
+    assert!(server.next_frame().unwrap().is_none());
+    client.receive(server.output());
+    let n = server.output().len();
+    server.consume_output(n);
+    let frame = client.next_frame().unwrap().unwrap();
+    assert_eq!((frame.opcode, &*frame.payload), (OpCode::Pong, &b"hi"[..]));
+
+    client.send(Frame::close(1000, b"bye")).unwrap();
+    server.receive(&client.take_output());
+    let frame = server.next_frame().unwrap().unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert!(server.is_closed());
+    client.receive(&server.take_output());
+    let frame = client.next_frame().unwrap().unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert!(client.output().is_empty());
+  }
+}
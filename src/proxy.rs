@@ -0,0 +1,297 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of the HAProxy PROXY protocol header, versions 1 and 2.
+//!
+//! Load balancers operating at layer 4 prepend this header to the connection so the server can learn the address of
+//! the real client. It has to be read before the HTTP request is handed to hyper:
+//!
+//! ```
+//! use fastwebsockets::proxy::read_proxy_header;
+//! use fastwebsockets::upgrade;
+//! use http_body_util::Empty;
+//! use hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn, Request, Response};
+//! use hyper_util::rt::TokioIo;
+//! use tokio::net::TcpStream;
+//! use anyhow::Result;
+//!
+//! async fn serve(mut stream: TcpStream) -> Result<()> {
+//!   let header = read_proxy_header(&mut stream).await?;
+//!   let client = header.source().or(stream.peer_addr().ok());
+//!
+//!   let service = service_fn(move |mut req: Request<Incoming>| async move {
+//!     let (response, fut) = upgrade::upgrade(&mut req)?;
+//!     tokio::spawn(async move {
+//!       println!("{:?} connected", client);
+//!       let ws = fut.await;
+//!     });
+//!     Ok::<Response<Empty<Bytes>>, fastwebsockets::WebSocketError>(response)
+//!   });
+//!   http1::Builder::new()
+//!     .serve_connection(TokioIo::new(stream), service)
+//!     .with_upgrades()
+//!     .await?;
+//!   Ok(())
+//! }
+//! ```
+
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+
+use crate::WebSocketError;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The longest possible version 1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The addresses carried by a PROXY protocol header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+  source: Option<SocketAddr>,
+  destination: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+  /// Address of the client that opened the connection to the proxy.
+  ///
+  /// `None` for health checks sent by the proxy itself (`PROXY UNKNOWN` and the version 2 `LOCAL` command) and for
+  /// address families other than TCP/UDP over IPv4 and IPv6.
+  pub fn source(&self) -> Option<SocketAddr> {
+    self.source
+  }
+
+  /// Address the client connected to on the proxy. See [`ProxyHeader::source`].
+  pub fn destination(&self) -> Option<SocketAddr> {
+    self.destination
+  }
+}
+
+/// Reads a PROXY protocol header from the start of `stream`.
+///
+/// Exactly the bytes of the header are consumed, so the stream can be passed on to hyper afterwards. Both the text
+/// (version 1) and binary (version 2) formats are accepted. A connection that does not start with a header fails with
+/// `WebSocketError::InvalidProxyHeader`; only call this for listeners that sit behind a proxy, since otherwise any
+/// client could forge its address.
+pub async fn read_proxy_header<S>(
+  stream: &mut S,
+) -> Result<ProxyHeader, WebSocketError>
+where
+  S: AsyncRead + Unpin,
+{
+  let mut buf = [0; V1_MAX_LEN];
+  read_exact(stream, &mut buf[..12]).await?;
+
+  if &buf[..12] == V2_SIGNATURE {
+    let mut head = [0; 4];
+    read_exact(stream, &mut head).await?;
+    let len = u16::from_be_bytes([head[2], head[3]]) as usize;
+    let mut body = vec![0; len];
+    read_exact(stream, &mut body).await?;
+    return parse_v2(head[0], head[1], &body);
+  }
+
+  if &buf[..6] != b"PROXY " {
+    return Err(WebSocketError::InvalidProxyHeader);
+  }
+
+  // The header is terminated by CRLF. Read it a byte at a time to avoid consuming the HTTP request that follows.
+  let mut len = 12;
+  while !buf[..len].ends_with(b"\r\n") {
+    if len == V1_MAX_LEN {
+      return Err(WebSocketError::InvalidProxyHeader);
+    }
+    read_exact(stream, &mut buf[len..len + 1]).await?;
+    len += 1;
+  }
+  parse_v1(&buf[..len - 2])
+}
+
+async fn read_exact<S>(
+  stream: &mut S,
+  buf: &mut [u8],
+) -> Result<(), WebSocketError>
+where
+  S: AsyncRead + Unpin,
+{
+  match stream.read_exact(buf).await {
+    Ok(_) => Ok(()),
+    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+      Err(WebSocketError::UnexpectedEOF)
+    }
+    Err(e) => Err(e.into()),
+  }
+}
+
+fn parse_v1(line: &[u8]) -> Result<ProxyHeader, WebSocketError> {
+  let line = std::str::from_utf8(line)
+    .map_err(|_| WebSocketError::InvalidProxyHeader)?;
+  let mut parts = line.split(' ').skip(1);
+  match parts.next() {
+    Some("UNKNOWN") => Ok(ProxyHeader {
+      source: None,
+      destination: None,
+    }),
+    Some(protocol @ ("TCP4" | "TCP6")) => {
+      let mut next = || parts.next().ok_or(WebSocketError::InvalidProxyHeader);
+      let (src, dst, src_port, dst_port) = (next()?, next()?, next()?, next()?);
+      if parts.next().is_some() {
+        return Err(WebSocketError::InvalidProxyHeader);
+      }
+      let ip = |s: &str| -> Result<IpAddr, WebSocketError> {
+        let ip = match protocol {
+          "TCP4" => s.parse::<Ipv4Addr>().map(IpAddr::V4),
+          _ => s.parse::<Ipv6Addr>().map(IpAddr::V6),
+        };
+        ip.map_err(|_| WebSocketError::InvalidProxyHeader)
+      };
+      let port = |s: &str| -> Result<u16, WebSocketError> {
+        s.parse().map_err(|_| WebSocketError::InvalidProxyHeader)
+      };
+      Ok(ProxyHeader {
+        source: Some(SocketAddr::new(ip(src)?, port(src_port)?)),
+        destination: Some(SocketAddr::new(ip(dst)?, port(dst_port)?)),
+      })
+    }
+    _ => Err(WebSocketError::InvalidProxyHeader),
+  }
+}
+
+fn parse_v2(
+  version_command: u8,
+  family: u8,
+  body: &[u8],
+) -> Result<ProxyHeader, WebSocketError> {
+  if version_command >> 4 != 2 {
+    return Err(WebSocketError::InvalidProxyHeader);
+  }
+  let unknown = ProxyHeader {
+    source: None,
+    destination: None,
+  };
+  match version_command & 0x0f {
+    // LOCAL: the connection was opened by the proxy itself.
+    0 => return Ok(unknown),
+    // PROXY
+    1 => {}
+    _ => return Err(WebSocketError::InvalidProxyHeader),
+  }
+
+  // The address block is followed by optional TLVs, which are ignored.
+  match family >> 4 {
+    // AF_INET
+    1 => {
+      let addr = body.get(..12).ok_or(WebSocketError::InvalidProxyHeader)?;
+      let ip = |i: usize| {
+        IpAddr::V4(Ipv4Addr::new(
+          addr[i],
+          addr[i + 1],
+          addr[i + 2],
+          addr[i + 3],
+        ))
+      };
+      let port = |i: usize| u16::from_be_bytes([addr[i], addr[i + 1]]);
+      Ok(ProxyHeader {
+        source: Some(SocketAddr::new(ip(0), port(8))),
+        destination: Some(SocketAddr::new(ip(4), port(10))),
+      })
+    }
+    // AF_INET6
+    2 => {
+      let addr = body.get(..36).ok_or(WebSocketError::InvalidProxyHeader)?;
+      let ip = |i: usize| {
+        let octets: [u8; 16] = addr[i..i + 16].try_into().unwrap();
+        IpAddr::V6(Ipv6Addr::from(octets))
+      };
+      let port = |i: usize| u16::from_be_bytes([addr[i], addr[i + 1]]);
+      Ok(ProxyHeader {
+        source: Some(SocketAddr::new(ip(0), port(32))),
+        destination: Some(SocketAddr::new(ip(16), port(34))),
+      })
+    }
+    // AF_UNSPEC and AF_UNIX
+    0 | 3 => Ok(unknown),
+    _ => Err(WebSocketError::InvalidProxyHeader),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn read(
+    input: &[u8],
+  ) -> (Result<ProxyHeader, WebSocketError>, Vec<u8>) {
+    let mut stream = input;
+    let header = read_proxy_header(&mut stream).await;
+    (header, stream.to_vec())
+  }
+
+  #[tokio::test]
+  async fn v1() {
+    let (header, rest) = read(
+      b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n",
+    )
+    .await;
+    let header = header.unwrap();
+    assert_eq!(header.source(), Some("192.0.2.1:56324".parse().unwrap()));
+    assert_eq!(
+      header.destination(),
+      Some("198.51.100.2:443".parse().unwrap())
+    );
+    assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+    let (header, _) = read(b"PROXY TCP6 ::1 ::2 1 2\r\n").await;
+    assert_eq!(header.unwrap().source(), Some("[::1]:1".parse().unwrap()));
+
+    let (header, _) = read(b"PROXY UNKNOWN\r\n").await;
+    assert_eq!(header.unwrap().source(), None);
+
+    let (header, _) = read(b"PROXY TCP4 ::1 ::2 1 2\r\n").await;
+    assert!(matches!(header, Err(WebSocketError::InvalidProxyHeader)));
+    let (header, _) = read(b"GET / HTTP/1.1\r\nHost: a\r\n").await;
+    assert!(matches!(header, Err(WebSocketError::InvalidProxyHeader)));
+  }
+
+  #[tokio::test]
+  async fn v2() {
+    let mut input = V2_SIGNATURE.to_vec();
+    input.extend_from_slice(&[0x21, 0x11, 0, 12]);
+    input
+      .extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 1, 187]);
+    input.extend_from_slice(b"GET");
+    let (header, rest) = read(&input).await;
+    let header = header.unwrap();
+    assert_eq!(header.source(), Some("192.0.2.1:56324".parse().unwrap()));
+    assert_eq!(
+      header.destination(),
+      Some("198.51.100.2:443".parse().unwrap())
+    );
+    assert_eq!(rest, b"GET");
+
+    let mut input = V2_SIGNATURE.to_vec();
+    input.extend_from_slice(&[0x20, 0x00, 0, 0]);
+    let (header, _) = read(&input).await;
+    assert_eq!(header.unwrap().source(), None);
+
+    let mut input = V2_SIGNATURE.to_vec();
+    input.extend_from_slice(&[0x21, 0x11, 0, 4, 0, 0, 0, 0]);
+    let (header, _) = read(&input).await;
+    assert!(matches!(header, Err(WebSocketError::InvalidProxyHeader)));
+  }
+}
@@ -0,0 +1,319 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SOCKS5 (RFC 1928) client handshake with optional username/password authentication (RFC 1929).
+//!
+//! [`handshake`] asks a proxy to connect to the websocket server over a stream that is already connected to the
+//! proxy. Afterwards the stream reaches the server, for TLS and `handshake::client`. Host names are sent to the
+//! proxy unresolved, so DNS resolution happens on the proxy's side.
+//!
+//! With the `connect` feature, set `ConnectOptions::socks5_proxy` instead to have `connect` do this.
+//!
+//! ```
+//! use fastwebsockets::socks::{self, Credentials};
+//! use tokio::net::TcpStream;
+//! use anyhow::Result;
+//!
+//! async fn open() -> Result<TcpStream> {
+//!   let mut stream = TcpStream::connect("bastion.internal:1080").await?;
+//!   let credentials = Credentials::new("user", "secret");
+//!   socks::handshake(&mut stream, "example.com", 443, Some(&credentials)).await?;
+//!   // Set up TLS and call `handshake::client` over `stream`.
+//!   Ok(stream)
+//! }
+//! ```
+
+use std::net::IpAddr;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::WebSocketError;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A username and password for a SOCKS5 proxy. Both are at most 255 bytes long.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+  username: String,
+  password: String,
+}
+
+impl Credentials {
+  /// Creates credentials for username/password authentication.
+  pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+    Self {
+      username: username.into(),
+      password: password.into(),
+    }
+  }
+
+  /// Returns the username. The password is not exposed, and is left out of the `Debug` output.
+  pub fn username(&self) -> &str {
+    &self.username
+  }
+}
+
+impl std::fmt::Debug for Credentials {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Credentials")
+      .field("username", &self.username)
+      .finish_non_exhaustive()
+  }
+}
+
+/// A SOCKS5 proxy to open client connections through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+  /// The address of the proxy, such as `bastion.internal:1080`.
+  pub addr: String,
+  /// The credentials, if the proxy requires authentication.
+  pub credentials: Option<Credentials>,
+}
+
+impl Socks5Proxy {
+  /// Creates a proxy that does not require authentication.
+  pub fn new(addr: impl Into<String>) -> Self {
+    Self {
+      addr: addr.into(),
+      credentials: None,
+    }
+  }
+
+  /// Sets the username and password to authenticate with.
+  pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+    self.credentials = Some(credentials);
+    self
+  }
+}
+
+/// Asks the SOCKS5 proxy at the other end of `stream` to connect to `host` and `port`.
+///
+/// `host` is sent as an address if it is an IP literal, with or without the brackets of an IPv6 URL authority,
+/// and as a name for the proxy to resolve otherwise. Without credentials only proxies that require no
+/// authentication are accepted.
+///
+/// Fails with `WebSocketError::Socks5AuthenticationFailed` if the proxy accepts none of the offered methods or
+/// rejects the credentials, and with `WebSocketError::Socks5ConnectFailed` carrying the reply code if the proxy
+/// cannot reach the server.
+pub async fn handshake<S>(
+  stream: &mut S,
+  host: &str,
+  port: u16,
+  credentials: Option<&Credentials>,
+) -> Result<(), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let host = host
+    .strip_prefix('[')
+    .and_then(|host| host.strip_suffix(']'))
+    .unwrap_or(host);
+
+  let mut request = Vec::with_capacity(host.len() + 7);
+  request.extend_from_slice(&[VERSION, CMD_CONNECT, 0]);
+  match host.parse::<IpAddr>() {
+    Ok(IpAddr::V4(ip)) => {
+      request.push(ATYP_IPV4);
+      request.extend_from_slice(&ip.octets());
+    }
+    Ok(IpAddr::V6(ip)) => {
+      request.push(ATYP_IPV6);
+      request.extend_from_slice(&ip.octets());
+    }
+    Err(_) => {
+      let len = u8::try_from(host.len())
+        .ok()
+        .filter(|&len| len > 0)
+        .ok_or(WebSocketError::InvalidValue)?;
+      request.extend_from_slice(&[ATYP_DOMAIN, len]);
+      request.extend_from_slice(host.as_bytes());
+    }
+  }
+  request.extend_from_slice(&port.to_be_bytes());
+
+  match credentials {
+    Some(_) => {
+      stream
+        .write_all(&[VERSION, 2, NO_AUTH, USERNAME_PASSWORD])
+        .await?
+    }
+    None => stream.write_all(&[VERSION, 1, NO_AUTH]).await?,
+  }
+  stream.flush().await?;
+  let mut reply = [0; 2];
+  stream.read_exact(&mut reply).await?;
+  match reply {
+    [VERSION, NO_AUTH] => {}
+    [VERSION, USERNAME_PASSWORD] => match credentials {
+      Some(credentials) => authenticate(stream, credentials).await?,
+      None => return Err(WebSocketError::InvalidSocks5Response),
+    },
+    [VERSION, NO_ACCEPTABLE_METHODS] => {
+      return Err(WebSocketError::Socks5AuthenticationFailed)
+    }
+    _ => return Err(WebSocketError::InvalidSocks5Response),
+  }
+
+  stream.write_all(&request).await?;
+  stream.flush().await?;
+  let mut reply = [0; 4];
+  stream.read_exact(&mut reply).await?;
+  if reply[0] != VERSION {
+    return Err(WebSocketError::InvalidSocks5Response);
+  }
+  if reply[1] != 0 {
+    return Err(WebSocketError::Socks5ConnectFailed(reply[1]));
+  }
+  // Skip the address the proxy bound, which clients have no use for.
+  let addr_len = match reply[3] {
+    ATYP_IPV4 => 4,
+    ATYP_IPV6 => 16,
+    ATYP_DOMAIN => usize::from(stream.read_u8().await?),
+    _ => return Err(WebSocketError::InvalidSocks5Response),
+  };
+  let mut bound = [0; 255 + 2];
+  stream.read_exact(&mut bound[..addr_len + 2]).await?;
+  Ok(())
+}
+
+async fn authenticate<S>(
+  stream: &mut S,
+  credentials: &Credentials,
+) -> Result<(), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let username = credentials.username.as_bytes();
+  let password = credentials.password.as_bytes();
+  let (Ok(username_len), Ok(password_len)) =
+    (u8::try_from(username.len()), u8::try_from(password.len()))
+  else {
+    return Err(WebSocketError::InvalidValue);
+  };
+
+  let mut request = Vec::with_capacity(username.len() + password.len() + 3);
+  request.extend_from_slice(&[1, username_len]);
+  request.extend_from_slice(username);
+  request.push(password_len);
+  request.extend_from_slice(password);
+  stream.write_all(&request).await?;
+  stream.flush().await?;
+
+  let mut reply = [0; 2];
+  stream.read_exact(&mut reply).await?;
+  match reply {
+    [1, 0] => Ok(()),
+    [1, _] => Err(WebSocketError::Socks5AuthenticationFailed),
+    _ => Err(WebSocketError::InvalidSocks5Response),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn authenticates_and_sends_the_host_name() {
+    let (mut client, mut proxy) = tokio::io::duplex(1024);
+    let proxy = tokio::spawn(async move {
+      let mut buf = [0; 4];
+      proxy.read_exact(&mut buf).await.unwrap();
+      assert_eq!(buf, [5, 2, 0, 2]);
+      proxy.write_all(&[5, 2]).await.unwrap();
+
+      let mut buf = [0; 13];
+      proxy.read_exact(&mut buf).await.unwrap();
+      assert_eq!(&buf, b"\x01\x04user\x06secret");
+      proxy.write_all(&[1, 0]).await.unwrap();
+
+      let mut buf = [0; 18];
+      proxy.read_exact(&mut buf).await.unwrap();
+      assert_eq!(&buf, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+      proxy
+        .write_all(&[5, 0, 0, 3, 5, b'p', b'r', b'o', b'x', b'y', 0, 80])
+        .await
+        .unwrap();
+      proxy.write_all(b"hello").await.unwrap();
+    });
+
+    let credentials = Credentials::new("user", "secret");
+    handshake(&mut client, "example.com", 443, Some(&credentials))
+      .await
+      .unwrap();
+    // The tunnel starts right after the reply.
+    let mut buf = [0; 5];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+    proxy.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn sends_ip_literals_as_addresses() {
+    let (mut client, mut proxy) = tokio::io::duplex(1024);
+    let proxy = tokio::spawn(async move {
+      let mut buf = [0; 3];
+      proxy.read_exact(&mut buf).await.unwrap();
+      assert_eq!(buf, [5, 1, 0]);
+      proxy.write_all(&[5, 0]).await.unwrap();
+
+      let mut buf = [0; 22];
+      proxy.read_exact(&mut buf).await.unwrap();
+      assert_eq!(&buf[..4], &[5, 1, 0, 4]);
+      assert_eq!(&buf[4..20], &std::net::Ipv6Addr::LOCALHOST.octets());
+      assert_eq!(&buf[20..], &[0x1f, 0x90]);
+      proxy
+        .write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0])
+        .await
+        .unwrap();
+    });
+
+    let result = handshake(&mut client, "[::1]", 8080, None).await;
+    assert!(matches!(
+      result,
+      Err(WebSocketError::Socks5ConnectFailed(5))
+    ));
+    proxy.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn rejected_credentials() {
+    let (mut client, mut proxy) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+      let mut buf = [0; 4];
+      proxy.read_exact(&mut buf).await.unwrap();
+      proxy.write_all(&[5, 2]).await.unwrap();
+      let mut buf = [0; 12];
+      proxy.read_exact(&mut buf).await.unwrap();
+      proxy.write_all(&[1, 1]).await.unwrap();
+    });
+
+    let credentials = Credentials::new("user", "wrong");
+    let result =
+      handshake(&mut client, "example.com", 443, Some(&credentials)).await;
+    assert!(matches!(
+      result,
+      Err(WebSocketError::Socks5AuthenticationFailed)
+    ));
+  }
+}
@@ -0,0 +1,188 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(feature = "spill")]
+use std::io::SeekFrom;
+#[cfg(feature = "spill")]
+use std::path::Path;
+#[cfg(feature = "spill")]
+use std::path::PathBuf;
+
+#[cfg(feature = "spill")]
+use tokio::fs::File;
+#[cfg(feature = "spill")]
+use tokio::fs::OpenOptions;
+#[cfg(feature = "spill")]
+use tokio::io::AsyncSeekExt;
+#[cfg(feature = "spill")]
+use tokio::io::AsyncWriteExt;
+
+use crate::frame::Frame;
+#[cfg(feature = "spill")]
+use crate::OpCode;
+
+/// Configures when `FragmentCollector` moves an incoming message out of memory and into a temporary file.
+#[cfg(feature = "spill")]
+#[derive(Debug, Clone)]
+pub(crate) struct SpillConfig {
+  pub threshold: Option<usize>,
+  pub dir: PathBuf,
+  /// Limits the size of spilled messages in place of `Fragments::max_size`.
+  pub max_size: usize,
+}
+
+/// A temporary file that an oversized message is being written into.
+///
+/// The file is written with `tokio::fs`, which runs the blocking calls on the blocking thread pool. It is removed
+/// when the handle is dropped.
+#[cfg(feature = "spill")]
+pub(crate) struct SpillFile {
+  file: File,
+  path: PathBuf,
+  len: u64,
+  keep: bool,
+}
+
+#[cfg(feature = "spill")]
+impl SpillFile {
+  pub async fn create(dir: &Path) -> std::io::Result<Self> {
+    loop {
+      let name = format!("fastwebsockets-{:016x}.spill", rand::random::<u64>());
+      let path = dir.join(name);
+      let mut options = OpenOptions::new();
+      options.read(true).write(true).create_new(true);
+      // Other users must not be able to read the messages of the connection.
+      #[cfg(unix)]
+      options.mode(0o600);
+      match options.open(&path).await {
+        Ok(file) => {
+          return Ok(Self {
+            file,
+            path,
+            len: 0,
+            keep: false,
+          })
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  pub async fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+    self.file.write_all(buf).await?;
+    self.len += buf.len() as u64;
+    Ok(())
+  }
+
+  pub async fn finish(
+    mut self,
+    opcode: OpCode,
+  ) -> std::io::Result<SpilledMessage> {
+    self.file.flush().await?;
+    self.file.seek(SeekFrom::Start(0)).await?;
+    Ok(SpilledMessage {
+      opcode,
+      inner: self,
+    })
+  }
+}
+
+#[cfg(feature = "spill")]
+impl Drop for SpillFile {
+  fn drop(&mut self) {
+    if self.keep {
+      return;
+    }
+    let path = std::mem::take(&mut self.path);
+    // Remove the file on the blocking thread pool, or right away without a runtime, as when it is shutting down.
+    match tokio::runtime::Handle::try_current() {
+      Ok(runtime) => {
+        runtime.spawn_blocking(move || std::fs::remove_file(path));
+      }
+      Err(_) => {
+        let _ = std::fs::remove_file(path);
+      }
+    }
+  }
+}
+
+/// A complete message whose payload was written to a temporary file instead of being buffered in memory.
+///
+/// The backing file is deleted when this value is dropped. Use [`SpilledMessage::persist`] to keep it.
+#[cfg(feature = "spill")]
+pub struct SpilledMessage {
+  opcode: OpCode,
+  inner: SpillFile,
+}
+
+#[cfg(feature = "spill")]
+impl SpilledMessage {
+  /// The opcode of the message, either `OpCode::Text` or `OpCode::Binary`.
+  ///
+  /// Text payloads have been validated as UTF-8.
+  pub fn opcode(&self) -> OpCode {
+    self.opcode
+  }
+
+  /// Length of the message payload in bytes.
+  pub fn len(&self) -> u64 {
+    self.inner.len
+  }
+
+  /// Returns `true` if the payload is empty.
+  pub fn is_empty(&self) -> bool {
+    self.inner.len == 0
+  }
+
+  /// Path of the temporary file holding the payload.
+  pub fn path(&self) -> &Path {
+    &self.inner.path
+  }
+
+  /// The temporary file, positioned at the start of the payload.
+  pub fn file(&mut self) -> &mut File {
+    &mut self.inner.file
+  }
+
+  /// Moves the payload to `path` so it outlives this value.
+  pub async fn persist(
+    mut self,
+    path: impl AsRef<Path>,
+  ) -> std::io::Result<File> {
+    tokio::fs::rename(&self.inner.path, path.as_ref()).await?;
+    self.inner.keep = true;
+    self.inner.file.try_clone().await
+  }
+}
+
+#[cfg(feature = "spill")]
+impl std::fmt::Debug for SpilledMessage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("SpilledMessage")
+      .field("opcode", &self.opcode)
+      .field("len", &self.inner.len)
+      .field("path", &self.inner.path)
+      .finish()
+  }
+}
+
+/// A message returned by `FragmentCollector::read_collected`.
+pub enum Collected<'f> {
+  /// A frame that was small enough to be collected in memory.
+  Frame(Frame<'f>),
+  /// A message that exceeded the spill threshold and was written to disk.
+  #[cfg(feature = "spill")]
+  Spilled(SpilledMessage),
+}
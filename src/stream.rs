@@ -0,0 +1,306 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Stream` and `Sink` implementations, built on the poll-based reads and writes.
+//!
+//! The streams yield frames like `read_frame` and end after the close frame. The sinks encode a frame into the
+//! write buffer in `start_send`, so borrowed frames can be sent, and `poll_close` sends a close frame with status
+//! code 1000 without shutting the underlying stream down. Owned payloads above the writev threshold are not copied
+//! but written after the header with vectored writes.
+
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::Frame;
+use crate::MessageStream;
+use crate::OpCode;
+use crate::WebSocket;
+use crate::WebSocketError;
+#[cfg(feature = "unstable-split")]
+use crate::WebSocketRead;
+#[cfg(feature = "unstable-split")]
+use crate::WebSocketWrite;
+
+impl<S> Stream for WebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  type Item = Result<Frame<'static>, WebSocketError>;
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    let this = self.get_mut();
+    if this.read_half.stream_ended {
+      // Send the close frame echoed for the peer before ending the stream.
+      return this.poll_flush(cx).map(|result| result.err().map(Err));
+    }
+    let frame = ready!(this.poll_read_frame(cx));
+    if matches!(&frame, Ok(frame) if frame.opcode == OpCode::Close) {
+      this.read_half.stream_ended = true;
+    }
+    Poll::Ready(Some(frame))
+  }
+}
+
+impl<'f, S> Sink<Frame<'f>> for WebSocket<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  type Error = WebSocketError;
+
+  fn poll_ready(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    let this = self.get_mut();
+    this.write_half.poll_pending(cx, &mut this.stream)
+  }
+
+  fn start_send(
+    self: Pin<&mut Self>,
+    frame: Frame<'f>,
+  ) -> Result<(), Self::Error> {
+    self.get_mut().write_half.start_poll_send(frame)
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.get_mut().poll_flush(cx)
+  }
+
+  fn poll_close(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.get_mut().poll_close(cx)
+  }
+}
+
+impl<S> Stream for MessageStream<'_, S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  type Item = Result<Bytes, WebSocketError>;
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    self.get_mut().poll_next_chunk(cx)
+  }
+}
+
+/// Like `WebSocketRead::read_frame_queued`, obligated pong and close frames are queued for the matching
+/// `WebSocketWrite`.
+#[cfg(feature = "unstable-split")]
+impl<S> Stream for WebSocketRead<S>
+where
+  S: AsyncRead + Unpin,
+{
+  type Item = Result<Frame<'static>, WebSocketError>;
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Self::Item>> {
+    let this = self.get_mut();
+    if this.read_half.stream_ended {
+      return Poll::Ready(None);
+    }
+    loop {
+      let (result, obligated_send) =
+        ready!(this.read_half.poll_read_frame_inner(cx, &mut this.stream));
+      if let Some(frame) = obligated_send {
+        this.control.push(frame);
+      }
+      match result {
+        Ok(Some(frame)) => {
+          if frame.opcode == OpCode::Close {
+            this.read_half.stream_ended = true;
+          }
+          return Poll::Ready(Some(Ok(frame)));
+        }
+        Ok(None) => {}
+        Err(e) => return Poll::Ready(Some(Err(e))),
+      }
+    }
+  }
+}
+
+/// Control frames queued by the matching `WebSocketRead` are written before each frame.
+#[cfg(feature = "unstable-split")]
+impl<'f, S> Sink<Frame<'f>> for WebSocketWrite<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  type Error = WebSocketError;
+
+  fn poll_ready(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    let this = self.get_mut();
+    ready!(this.write_half.poll_pending(cx, &mut this.stream))?;
+    this.poll_writing_frame = false;
+    this.poll_control(cx)
+  }
+
+  fn start_send(
+    self: Pin<&mut Self>,
+    frame: Frame<'f>,
+  ) -> Result<(), Self::Error> {
+    self.get_mut().write_half.start_poll_send(frame)
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.get_mut().poll_flush(cx)
+  }
+
+  fn poll_close(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Result<(), Self::Error>> {
+    self.get_mut().poll_close(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Role;
+  use futures_util::SinkExt;
+  use futures_util::StreamExt;
+
+  #[tokio::test]
+  async fn frames_flow_through_stream_and_sink() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client
+      .send(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .send(Frame::binary(b"world"[..].into()))
+      .await
+      .unwrap();
+    SinkExt::close(&mut client).await.unwrap();
+
+    let frames: Vec<_> = (&mut server).collect().await;
+    let frames: Vec<_> = frames.into_iter().map(Result::unwrap).collect();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].as_text(), Some("hello"));
+    assert_eq!(&*frames[1].payload, b"world");
+    assert_eq!(frames[2].opcode, OpCode::Close);
+
+    // The server echoed the close frame before its stream ended.
+    let frame = client.next().await.unwrap().unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert!(client.next().await.is_none());
+  }
+
+  #[cfg(feature = "unstable-split")]
+  #[tokio::test]
+  async fn split_halves() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let server = WebSocket::after_handshake(server, Role::Server);
+    let (mut rx, mut tx) = server.split(tokio::io::split);
+
+    client
+      .send(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .await
+      .unwrap();
+    client
+      .send(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let frame = rx.next().await.unwrap().unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    // The pong queued by the read half goes out before the next frame.
+    tx.send(Frame::text(b"bye".to_vec().into())).await.unwrap();
+    let frame = client.next().await.unwrap().unwrap();
+    assert_eq!((frame.opcode, &*frame.payload), (OpCode::Pong, &b"hi"[..]));
+    let frame = client.next().await.unwrap().unwrap();
+    assert_eq!(frame.as_text(), Some("bye"));
+  }
+
+  #[tokio::test]
+  async fn message_stream_chunks() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client
+      .send(Frame::new(false, OpCode::Binary, None, b"ab"[..].into()))
+      .await
+      .unwrap();
+    client
+      .send(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        b"c"[..].into(),
+      ))
+      .await
+      .unwrap();
+
+    let message = server.read_message_stream().await.unwrap().unwrap();
+    let chunks: Vec<_> = message.map(Result::unwrap).collect().await;
+    assert_eq!(chunks, ["ab", "c"]);
+  }
+
+  #[tokio::test]
+  async fn large_owned_payloads() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let payload: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+    let send = async {
+      client
+        .send(Frame::binary(payload.clone().into()))
+        .await
+        .unwrap();
+      client
+        .send(Frame::binary(Bytes::from(payload.clone()).into()))
+        .await
+        .unwrap();
+    };
+    let receive = async {
+      for _ in 0..2 {
+        let frame = server.next().await.unwrap().unwrap();
+        assert_eq!(&*frame.payload, &payload[..]);
+      }
+    };
+    tokio::join!(send, receive);
+  }
+}
@@ -0,0 +1,192 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A blocking websocket over `std::io::Read` and `std::io::Write` streams, for tools and tests without an async
+//! runtime. It drives a [`Connection`](crate::proto::Connection) with blocking reads and writes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use fastwebsockets::sync::WebSocket;
+//! use fastwebsockets::{Frame, OpCode, Role, WebSocketError};
+//! use std::net::TcpListener;
+//!
+//! fn echo() -> Result<(), WebSocketError> {
+//!   let listener = TcpListener::bind("127.0.0.1:9001")?;
+//!   let (stream, _) = listener.accept()?;
+//!   let mut ws = WebSocket::after_handshake(stream, Role::Server);
+//!   loop {
+//!     let frame = ws.read_frame()?;
+//!     match frame.opcode {
+//!       OpCode::Close => return Ok(()),
+//!       OpCode::Text | OpCode::Binary => ws.write_frame(frame)?,
+//!       _ => {}
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::io::Read;
+use std::io::Write;
+
+use crate::proto::Connection;
+use crate::Frame;
+use crate::Role;
+use crate::WebSocketError;
+
+const READ_SIZE: usize = 8192;
+
+/// A blocking websocket over a stream whose handshake has already been done.
+pub struct WebSocket<S> {
+  stream: S,
+  conn: Connection,
+}
+
+impl<S> WebSocket<S> {
+  /// Creates a websocket from a stream that has already completed the handshake.
+  pub fn after_handshake(stream: S, role: Role) -> Self {
+    Self {
+      stream,
+      conn: Connection::new(role),
+    }
+  }
+
+  /// Consumes the `WebSocket` and returns the underlying stream. Received bytes that have not been read as frames
+  /// are lost.
+  pub fn into_inner(self) -> S {
+    self.stream
+  }
+
+  /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the
+  /// application will have to manually send close frames.
+  ///
+  /// Default: `true`
+  pub fn set_auto_close(&mut self, auto_close: bool) {
+    self.conn.set_auto_close(auto_close);
+  }
+
+  /// Sets whether to automatically send a pong frame when a ping frame is received.
+  ///
+  /// Default: `true`
+  pub fn set_auto_pong(&mut self, auto_pong: bool) {
+    self.conn.set_auto_pong(auto_pong);
+  }
+
+  /// Sets the maximum message size in bytes. If a message is received that is larger than this, `read_frame`
+  /// returns `WebSocketError::FrameTooLarge`.
+  ///
+  /// Default: 64 MiB
+  pub fn set_max_message_size(&mut self, max_message_size: usize) {
+    self.conn.set_max_message_size(max_message_size);
+  }
+
+  /// Returns whether a close frame has been sent.
+  pub fn is_closed(&self) -> bool {
+    self.conn.is_closed()
+  }
+
+  /// Returns the role of the websocket.
+  pub fn role(&self) -> Role {
+    self.conn.role()
+  }
+}
+
+impl<S: Read + Write> WebSocket<S> {
+  /// Reads a frame, blocking until one has been received. Like `WebSocket::read_frame`, pings are answered and
+  /// close frames echoed unless `auto_pong` and `auto_close` are disabled.
+  pub fn read_frame(&mut self) -> Result<Frame<'static>, WebSocketError> {
+    let mut buf = [0; READ_SIZE];
+    loop {
+      let frame = self.conn.next_frame();
+      self.write_output()?;
+      if let Some(frame) = frame? {
+        return Ok(frame);
+      }
+      match self.stream.read(&mut buf)? {
+        0 => return Err(WebSocketError::UnexpectedEOF),
+        n => self.conn.receive(&buf[..n]),
+      }
+    }
+  }
+
+  /// Writes a frame, masking it for clients.
+  pub fn write_frame(
+    &mut self,
+    frame: Frame<'_>,
+  ) -> Result<(), WebSocketError> {
+    self.conn.send(frame)?;
+    self.write_output()
+  }
+
+  /// Flushes the underlying stream.
+  pub fn flush(&mut self) -> Result<(), WebSocketError> {
+    self.stream.flush()?;
+    Ok(())
+  }
+
+  fn write_output(&mut self) -> Result<(), WebSocketError> {
+    if !self.conn.output().is_empty() {
+      self.stream.write_all(self.conn.output())?;
+      let n = self.conn.output().len();
+      self.conn.consume_output(n);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::OpCode;
+  use std::net::TcpListener;
+  use std::net::TcpStream;
+
+  #[test]
+  fn echo_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut ws = WebSocket::after_handshake(stream, Role::Server);
+      loop {
+        let frame = ws.read_frame().unwrap();
+        match frame.opcode {
+          OpCode::Close => break,
+          _ => ws.write_frame(frame).unwrap(),
+        }
+      }
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let mut ws = WebSocket::after_handshake(stream, Role::Client);
+    ws.write_frame(Frame::new(true, OpCode::Ping, None, b"hi".to_vec().into()))
+      .unwrap();
+    ws.write_frame(Frame::text(b"hello".to_vec().into()))
+      .unwrap();
+    ws.write_frame(Frame::binary(vec![7; 100_000].into()))
+      .unwrap();
+
+    let frame = ws.read_frame().unwrap();
+    assert_eq!((frame.opcode, &*frame.payload), (OpCode::Pong, &b"hi"[..]));
+    let frame = ws.read_frame().unwrap();
+    assert_eq!(frame.as_text(), Some("hello"));
+    let frame = ws.read_frame().unwrap();
+    assert_eq!(&*frame.payload, &[7; 100_000][..]);
+
+    ws.write_frame(Frame::close(1000, b"")).unwrap();
+    let frame = ws.read_frame().unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    server.join().unwrap();
+  }
+}
@@ -0,0 +1,77 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::io::IoSlice;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+
+use crate::io::WsRead;
+use crate::io::WsWrite;
+use crate::FrameDirection;
+
+pub(crate) type WireTap = Arc<dyn Fn(FrameDirection, &[u8]) + Send + Sync>;
+
+/// Passes the bytes read from and written to a stream to a wire tap, as they are transferred.
+pub(crate) struct Tapped<'a, S> {
+  stream: &'a mut S,
+  tap: Option<WireTap>,
+}
+
+impl<'a, S> Tapped<'a, S> {
+  pub fn new(stream: &'a mut S, tap: Option<WireTap>) -> Self {
+    Self { stream, tap }
+  }
+}
+
+impl<S: WsRead> WsRead for Tapped<'_, S> {
+  async fn read_into(
+    &mut self,
+    buf: &mut BytesMut,
+    limit: usize,
+  ) -> io::Result<usize> {
+    let n = self.stream.read_into(buf, limit).await?;
+    if let Some(tap) = &self.tap {
+      tap(FrameDirection::Inbound, &buf[buf.len() - n..]);
+    }
+    Ok(n)
+  }
+}
+
+impl<S: WsWrite> WsWrite for Tapped<'_, S> {
+  async fn send_all(&mut self, buf: &[u8]) -> io::Result<()> {
+    self.stream.send_all(buf).await?;
+    if let Some(tap) = &self.tap {
+      tap(FrameDirection::Outbound, buf);
+    }
+    Ok(())
+  }
+
+  async fn send_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    let n = self.stream.send_vectored(bufs).await?;
+    if let Some(tap) = &self.tap {
+      let mut remaining = n;
+      for buf in bufs {
+        if remaining == 0 {
+          break;
+        }
+        let len = buf.len().min(remaining);
+        tap(FrameDirection::Outbound, &buf[..len]);
+        remaining -= len;
+      }
+    }
+    Ok(n)
+  }
+}
@@ -0,0 +1,543 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::time::Sleep;
+
+use crate::upgrade;
+use crate::FragmentCollector;
+use crate::Frame;
+use crate::OpCode;
+use crate::WebSocketError;
+
+/// A stream wrapper that misbehaves like a real network, to test how an application handles it.
+///
+/// It can delay every read and write, return fewer bytes than asked for so that frames arrive split at arbitrary
+/// boundaries, accept only part of every write, and flip bits in the data it reads. Every option is off by default.
+/// The randomness comes from a seed, so a failing run can be reproduced with [`ChaosStream::with_seed`].
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::testing::ChaosStream;
+/// use fastwebsockets::Frame;
+/// use fastwebsockets::Role;
+/// use fastwebsockets::WebSocket;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), fastwebsockets::WebSocketError> {
+/// let (client, server) = tokio::io::duplex(1024);
+/// let mut server = ChaosStream::with_seed(server, 42);
+/// server.set_max_read(3);
+///
+/// let mut client = WebSocket::after_handshake(client, Role::Client);
+/// let mut server = WebSocket::after_handshake(server, Role::Server);
+/// client.write_frame(Frame::text(b"hello".to_vec().into())).await?;
+/// assert_eq!(server.read_frame().await?.as_text(), Some("hello"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChaosStream<S> {
+  inner: S,
+  rng: StdRng,
+  latency: Duration,
+  max_read: usize,
+  max_write: usize,
+  bit_flip_rate: f64,
+  read_delay: Option<Pin<Box<Sleep>>>,
+  write_delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ChaosStream<S> {
+  /// Wraps `inner` with a random seed.
+  pub fn new(inner: S) -> Self {
+    Self::with_seed(inner, rand::random())
+  }
+
+  /// Wraps `inner`, making the same decisions on every run with the same `seed`.
+  pub fn with_seed(inner: S, seed: u64) -> Self {
+    Self {
+      inner,
+      rng: StdRng::seed_from_u64(seed),
+      latency: Duration::ZERO,
+      max_read: usize::MAX,
+      max_write: usize::MAX,
+      bit_flip_rate: 0.0,
+      read_delay: None,
+      write_delay: None,
+    }
+  }
+
+  /// Sets how long every read and write waits before it is passed on to the inner stream.
+  ///
+  /// Default: zero
+  pub fn set_latency(&mut self, latency: Duration) {
+    self.latency = latency;
+  }
+
+  /// Sets the largest number of bytes a read returns. Every read returns a random number of bytes up to this limit,
+  /// so frame headers and payloads are split at arbitrary points.
+  ///
+  /// Default: unlimited
+  pub fn set_max_read(&mut self, max: usize) {
+    self.max_read = max.max(1);
+  }
+
+  /// Sets the largest number of bytes a write accepts. Every write accepts a random number of bytes up to this
+  /// limit, which exercises the handling of short writes.
+  ///
+  /// Default: unlimited
+  pub fn set_max_write(&mut self, max: usize) {
+    self.max_write = max.max(1);
+  }
+
+  /// Sets the probability, between 0 and 1, that a byte read has one of its bits flipped.
+  ///
+  /// Default: 0
+  pub fn set_bit_flip_rate(&mut self, rate: f64) {
+    self.bit_flip_rate = rate.clamp(0.0, 1.0);
+  }
+
+  /// Returns a reference to the inner stream.
+  pub fn get_ref(&self) -> &S {
+    &self.inner
+  }
+
+  /// Returns a mutable reference to the inner stream.
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.inner
+  }
+
+  /// Unwraps the inner stream.
+  pub fn into_inner(self) -> S {
+    self.inner
+  }
+}
+
+/// Waits out the latency before an operation. The elapsed timer is kept until the operation completes, so an
+/// operation that has to wait for the inner stream is not delayed again.
+fn poll_delay(
+  delay: &mut Option<Pin<Box<Sleep>>>,
+  latency: Duration,
+  cx: &mut Context<'_>,
+) -> Poll<()> {
+  if latency.is_zero() {
+    return Poll::Ready(());
+  }
+  delay
+    .get_or_insert_with(|| Box::pin(tokio::time::sleep(latency)))
+    .as_mut()
+    .poll(cx)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ChaosStream<S> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    let this = self.get_mut();
+    ready!(poll_delay(&mut this.read_delay, this.latency, cx));
+
+    let limit = match this.max_read {
+      usize::MAX => buf.remaining(),
+      max => this.rng.gen_range(1..=max).min(buf.remaining()),
+    };
+    let mut limited = ReadBuf::new(buf.initialize_unfilled_to(limit));
+    ready!(Pin::new(&mut this.inner).poll_read(cx, &mut limited))?;
+    this.read_delay = None;
+
+    let read = limited.filled().len();
+    if this.bit_flip_rate > 0.0 {
+      for byte in limited.filled_mut() {
+        if this.rng.gen_bool(this.bit_flip_rate) {
+          *byte ^= 1 << this.rng.gen_range(0..8);
+        }
+      }
+    }
+    buf.advance(read);
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ChaosStream<S> {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<std::io::Result<usize>> {
+    let this = self.get_mut();
+    ready!(poll_delay(&mut this.write_delay, this.latency, cx));
+
+    let limit = match this.max_write {
+      usize::MAX => buf.len(),
+      max => this.rng.gen_range(1..=max).min(buf.len()),
+    };
+    let n = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..limit]))?;
+    this.write_delay = None;
+    Poll::Ready(Ok(n))
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+  }
+}
+
+/// A WebSocket server that follows a script, to test client applications without a real server.
+///
+/// The server accepts a single connection on a local port, performs the handshake and then runs the script in
+/// order: every expected message must be the next message the client sends, and every reply is sent as soon as
+/// the steps before it have completed. Fragmented messages are reassembled and pings are answered automatically.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::testing::MockServer;
+///
+/// # async fn run() -> Result<(), fastwebsockets::WebSocketError> {
+/// let server = MockServer::bind()
+///   .await?
+///   .expect_text("hello")
+///   .send_binary(vec![1, 2, 3])
+///   .close(1000, "");
+/// let url = server.url();
+/// let server = tokio::spawn(server.run());
+///
+/// // Connect the client under test to `url`.
+///
+/// server.await.unwrap()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockServer {
+  listener: TcpListener,
+  script: Vec<Step>,
+}
+
+enum Step {
+  Expect(OpCode, Vec<u8>),
+  ExpectClose(u16),
+  Send(OpCode, Vec<u8>),
+  Close(u16, String),
+}
+
+impl MockServer {
+  /// Binds the server to a free port on the loopback interface.
+  pub async fn bind() -> Result<Self, WebSocketError> {
+    Ok(Self {
+      listener: TcpListener::bind("127.0.0.1:0").await?,
+      script: Vec::new(),
+    })
+  }
+
+  /// Returns the address the server is listening on.
+  pub fn local_addr(&self) -> SocketAddr {
+    self.listener.local_addr().unwrap()
+  }
+
+  /// Returns a `ws://` URL for the server.
+  pub fn url(&self) -> String {
+    format!("ws://{}/", self.local_addr())
+  }
+
+  /// Expects the client to send a text message.
+  pub fn expect_text(mut self, text: impl Into<String>) -> Self {
+    self
+      .script
+      .push(Step::Expect(OpCode::Text, text.into().into_bytes()));
+    self
+  }
+
+  /// Expects the client to send a binary message.
+  pub fn expect_binary(mut self, data: impl Into<Vec<u8>>) -> Self {
+    self.script.push(Step::Expect(OpCode::Binary, data.into()));
+    self
+  }
+
+  /// Expects the client to close the connection with `code`. The close frame is echoed back.
+  pub fn expect_close(mut self, code: u16) -> Self {
+    self.script.push(Step::ExpectClose(code));
+    self
+  }
+
+  /// Sends a text message.
+  pub fn send_text(mut self, text: impl Into<String>) -> Self {
+    self
+      .script
+      .push(Step::Send(OpCode::Text, text.into().into_bytes()));
+    self
+  }
+
+  /// Sends a binary message.
+  pub fn send_binary(mut self, data: impl Into<Vec<u8>>) -> Self {
+    self.script.push(Step::Send(OpCode::Binary, data.into()));
+    self
+  }
+
+  /// Closes the connection with `code` and `reason`, and waits for the client to acknowledge it.
+  pub fn close(mut self, code: u16, reason: impl Into<String>) -> Self {
+    self.script.push(Step::Close(code, reason.into()));
+    self
+  }
+
+  /// Accepts a connection and runs the script on it.
+  ///
+  /// Returns `MockExpectationFailed` as soon as the client sends something the script does not expect, and
+  /// `Ok(())` once every step has completed.
+  pub async fn run(self) -> Result<(), WebSocketError> {
+    let (stream, _) = self.listener.accept().await?;
+
+    let (tx, rx) = oneshot::channel();
+    let tx = Mutex::new(Some(tx));
+    let service = service_fn(move |mut req: Request<Incoming>| {
+      let result = upgrade::upgrade(&mut req).map(|(response, fut)| {
+        if let Some(tx) = tx.lock().unwrap().take() {
+          let _ = tx.send(fut);
+        }
+        response
+      });
+      async move { result }
+    });
+    tokio::spawn(
+      http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .with_upgrades(),
+    );
+    let fut = rx.await.map_err(|_| WebSocketError::ConnectionClosed)?;
+    let mut ws = FragmentCollector::new(fut.await?);
+
+    for step in self.script {
+      match step {
+        Step::Expect(opcode, payload) => {
+          let frame = ws.read_frame().await?;
+          if frame.opcode != opcode || *frame.payload != *payload {
+            let expected = Frame::new(true, opcode, None, payload.into());
+            return Err(unexpected(&expected, &frame));
+          }
+        }
+        Step::ExpectClose(code) => {
+          let frame = ws.read_frame().await?;
+          let received = frame
+            .payload
+            .get(..2)
+            .map(|code| u16::from_be_bytes([code[0], code[1]]));
+          if frame.opcode != OpCode::Close || received != Some(code) {
+            return Err(unexpected(&Frame::close(code, &[]), &frame));
+          }
+        }
+        Step::Send(opcode, payload) => {
+          ws.write_frame(Frame::new(true, opcode, None, payload.into()))
+            .await?;
+        }
+        Step::Close(code, reason) => {
+          ws.write_frame(Frame::close(code, reason.as_bytes()))
+            .await?;
+          while ws.read_frame().await?.opcode != OpCode::Close {}
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn unexpected(expected: &Frame, received: &Frame) -> WebSocketError {
+  WebSocketError::MockExpectationFailed(format!(
+    "expected {}, received {}",
+    expected, received
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::handshake;
+  use crate::Role;
+  use crate::WebSocket;
+  use http_body_util::Empty;
+  use hyper::body::Bytes;
+  use tokio::net::TcpStream;
+
+  #[tokio::test]
+  async fn split_reads_and_writes() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = ChaosStream::with_seed(client, 1);
+    client.set_max_write(3);
+    let mut server = ChaosStream::with_seed(server, 2);
+    server.set_max_read(2);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let payload = vec![7; 300];
+    for _ in 0..3 {
+      client
+        .write_frame(Frame::binary(payload.as_slice().into()))
+        .await
+        .unwrap();
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(&*frame.payload, payload.as_slice());
+    }
+  }
+
+  #[tokio::test]
+  async fn latency() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = ChaosStream::with_seed(client, 1);
+    client.set_latency(Duration::from_millis(20));
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    let start = std::time::Instant::now();
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    assert_eq!(server.read_frame().await.unwrap().as_text(), Some("hello"));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+  }
+
+  #[tokio::test]
+  async fn bit_flips() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut server = ChaosStream::with_seed(server, 3);
+    server.set_bit_flip_rate(1.0);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+
+    client
+      .write_frame(Frame::text(b"hello".to_vec().into()))
+      .await
+      .unwrap();
+    let result = server.read_frame().await;
+    assert!(!matches!(result, Ok(frame) if &*frame.payload == b"hello"));
+
+    // The same seed corrupts the same bits.
+    let mut first = ChaosStream::with_seed(&[0u8; 64][..], 4);
+    let mut second = ChaosStream::with_seed(&[0u8; 64][..], 4);
+    first.set_bit_flip_rate(0.5);
+    second.set_bit_flip_rate(0.5);
+    let (mut a, mut b) = (vec![], vec![]);
+    tokio::io::copy(&mut first, &mut a).await.unwrap();
+    tokio::io::copy(&mut second, &mut b).await.unwrap();
+    assert_eq!(a, b);
+    assert_ne!(a, [0; 64]);
+  }
+
+  struct SpawnExecutor;
+
+  impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+  where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+  {
+    fn execute(&self, fut: Fut) {
+      tokio::task::spawn(fut);
+    }
+  }
+
+  async fn connect(
+    addr: SocketAddr,
+  ) -> WebSocket<TokioIo<hyper::upgrade::Upgraded>> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let request = Request::builder()
+      .uri(format!("ws://{}/", addr))
+      .header("Host", addr.to_string())
+      .header("Upgrade", "websocket")
+      .header("Connection", "upgrade")
+      .header("Sec-WebSocket-Key", handshake::generate_key())
+      .header("Sec-WebSocket-Version", "13")
+      .body(Empty::<Bytes>::new())
+      .unwrap();
+    let (ws, _) = handshake::client(&SpawnExecutor, request, stream)
+      .await
+      .unwrap();
+    ws
+  }
+
+  #[tokio::test]
+  async fn mock_server_script() {
+    let server = MockServer::bind()
+      .await
+      .unwrap()
+      .expect_text("hello")
+      .send_binary(vec![1, 2, 3])
+      .close(1000, "done");
+    let addr = server.local_addr();
+    let server = tokio::spawn(server.run());
+    let mut client = connect(addr).await;
+
+    client
+      .write_frame(Frame::text(b"hello".as_ref().into()))
+      .await
+      .unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Binary);
+    assert_eq!(&*frame.payload, [1, 2, 3]);
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert_eq!(&*frame.payload, b"\x03\xe8done");
+
+    server.await.unwrap().unwrap();
+  }
+
+  #[tokio::test]
+  async fn mock_server_unexpected_message() {
+    let server = MockServer::bind()
+      .await
+      .unwrap()
+      .expect_text("hello")
+      .expect_close(1000);
+    let addr = server.local_addr();
+    let server = tokio::spawn(server.run());
+    let mut client = connect(addr).await;
+
+    client
+      .write_frame(Frame::text(b"goodbye".as_ref().into()))
+      .await
+      .unwrap();
+    let err = server.await.unwrap().unwrap_err();
+    assert!(matches!(err, WebSocketError::MockExpectationFailed(_)));
+    assert!(err.to_string().contains("\"goodbye\""), "{}", err);
+  }
+}
@@ -0,0 +1,94 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::poll_fn;
+use std::future::Future;
+use std::pin::pin;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A future returned by `Timer::sleep_until`.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// The clock behind the keepalive and the read, write and close timeouts, set with `WebSocket::set_timer`.
+///
+/// The default is [`TokioTimer`]. Tests can use a timer they control, and runtimes other than tokio can supply
+/// their own timers.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{Sleep, Timer};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Instant;
+///
+/// /// A virtual clock for tests, which skips ahead to each deadline that is waited for.
+/// #[derive(Clone)]
+/// struct VirtualClock(Arc<Mutex<Instant>>);
+///
+/// impl Timer for VirtualClock {
+///   fn now(&self) -> Instant {
+///     *self.0.lock().unwrap()
+///   }
+///
+///   fn sleep_until(&self, deadline: Instant) -> Sleep {
+///     let now = self.0.clone();
+///     Box::pin(async move {
+///       let mut now = now.lock().unwrap();
+///       *now = (*now).max(deadline);
+///     })
+///   }
+/// }
+/// ```
+pub trait Timer: Send + Sync {
+  /// Returns the current time.
+  fn now(&self) -> Instant;
+
+  /// Returns a future that completes at `deadline`.
+  fn sleep_until(&self, deadline: Instant) -> Sleep;
+}
+
+/// A `Timer` on tokio's clock, which follows `tokio::time::pause`. It needs a tokio runtime with the time driver
+/// enabled.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioTimer;
+
+impl Timer for TokioTimer {
+  fn now(&self) -> Instant {
+    tokio::time::Instant::now().into_std()
+  }
+
+  fn sleep_until(&self, deadline: Instant) -> Sleep {
+    Box::pin(tokio::time::sleep_until(deadline.into()))
+  }
+}
+
+/// Runs `future` until it completes or `duration` has passed on `timer`. Returns `None` on timeout.
+pub(crate) async fn timeout<F: Future>(
+  timer: &dyn Timer,
+  duration: Duration,
+  future: F,
+) -> Option<F::Output> {
+  let mut sleep = timer.sleep_until(timer.now() + duration);
+  let mut future = pin!(future);
+  poll_fn(|cx| {
+    if let Poll::Ready(output) = future.as_mut().poll(cx) {
+      return Poll::Ready(Some(output));
+    }
+    sleep.as_mut().poll(cx).map(|()| None)
+  })
+  .await
+}
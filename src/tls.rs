@@ -0,0 +1,471 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TLS connectors that wrap a connected stream before `handshake::client`: [`RustlsConnector`] with the
+//! `tls-rustls` feature and [`NativeTlsConnector`] with the `tls-native` feature. On the server side,
+//! [`RustlsAcceptor`] terminates TLS before the HTTP connection that serves `upgrade`.
+//!
+//! All of them offer only `http/1.1` over ALPN, since the websocket handshake is an HTTP/1.1 upgrade. The host is
+//! used for SNI and certificate verification; IP literals, with or without the brackets of an IPv6 URL authority,
+//! are verified against the IP addresses of the certificate and are not sent as SNI.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use fastwebsockets::tls::RustlsConnector;
+//! use tokio::net::TcpStream;
+//!
+//! async fn open() -> Result<(), fastwebsockets::WebSocketError> {
+//!   let stream = TcpStream::connect("example.com:443").await?;
+//!   let stream = RustlsConnector::default().connect("example.com", stream).await?;
+//!   // Pass `stream` to `handshake::client`.
+//!   Ok(())
+//! }
+//! ```
+
+use std::fmt;
+#[cfg(feature = "tls-rustls")]
+use std::net::SocketAddr;
+#[cfg(feature = "tls-rustls")]
+use std::sync::Arc;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+#[cfg(feature = "tls-rustls")]
+use tokio::net::TcpListener;
+#[cfg(feature = "tls-rustls")]
+use tokio::net::TcpStream;
+
+use crate::WebSocketError;
+
+#[cfg(feature = "tls-native")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-native")))]
+pub use tokio_native_tls::native_tls;
+#[cfg(feature = "tls-rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-rustls")))]
+pub use tokio_rustls::rustls;
+
+const ALPN_HTTP1: &str = "http/1.1";
+
+/// A TLS connector backed by rustls.
+#[cfg(feature = "tls-rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-rustls")))]
+#[derive(Clone)]
+pub struct RustlsConnector {
+  connector: tokio_rustls::TlsConnector,
+}
+
+#[cfg(feature = "tls-rustls")]
+impl RustlsConnector {
+  /// Creates a connector from a client configuration, such as one with custom root certificates or a client
+  /// certificate. Its ALPN protocols are replaced with `http/1.1`.
+  pub fn new(mut config: rustls::ClientConfig) -> Self {
+    config.alpn_protocols = vec![ALPN_HTTP1.as_bytes().to_vec()];
+    Self {
+      connector: Arc::new(config).into(),
+    }
+  }
+
+  /// Performs the TLS handshake with `host` over `stream`.
+  pub async fn connect<S>(
+    &self,
+    host: &str,
+    stream: S,
+  ) -> Result<tokio_rustls::client::TlsStream<S>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let server_name = rustls::ServerName::try_from(server_name(host))
+      .map_err(|_| WebSocketError::InvalidServerName)?;
+    Ok(self.connector.connect(server_name, stream).await?)
+  }
+}
+
+/// Verifies servers against the Mozilla root certificates from `webpki-roots`, without client authentication.
+#[cfg(feature = "tls-rustls")]
+impl Default for RustlsConnector {
+  fn default() -> Self {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+      |ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+          ta.subject,
+          ta.spki,
+          ta.name_constraints,
+        )
+      },
+    ));
+    let config = rustls::ClientConfig::builder()
+      .with_safe_defaults()
+      .with_root_certificates(roots)
+      .with_no_client_auth();
+    Self::new(config)
+  }
+}
+
+#[cfg(feature = "tls-rustls")]
+impl fmt::Debug for RustlsConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RustlsConnector").finish_non_exhaustive()
+  }
+}
+
+/// A TLS connector backed by the platform TLS library: SChannel on Windows, Security.framework on macOS and
+/// OpenSSL elsewhere.
+#[cfg(feature = "tls-native")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-native")))]
+#[derive(Clone)]
+pub struct NativeTlsConnector {
+  connector: tokio_native_tls::TlsConnector,
+}
+
+#[cfg(feature = "tls-native")]
+impl NativeTlsConnector {
+  /// Creates a connector that verifies servers against the system root certificates.
+  pub fn new() -> Result<Self, WebSocketError> {
+    Self::from_builder(native_tls::TlsConnector::builder())
+  }
+
+  /// Creates a connector from a builder, such as one with extra root certificates or a client identity. Its ALPN
+  /// protocols are replaced with `http/1.1`.
+  pub fn from_builder(
+    mut builder: native_tls::TlsConnectorBuilder,
+  ) -> Result<Self, WebSocketError> {
+    builder.request_alpns(&[ALPN_HTTP1]);
+    Ok(Self {
+      connector: builder.build()?.into(),
+    })
+  }
+
+  /// Performs the TLS handshake with `host` over `stream`.
+  pub async fn connect<S>(
+    &self,
+    host: &str,
+    stream: S,
+  ) -> Result<tokio_native_tls::TlsStream<S>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let host = server_name(host);
+    if host.is_empty() {
+      return Err(WebSocketError::InvalidServerName);
+    }
+    Ok(self.connector.connect(host, stream).await?)
+  }
+}
+
+#[cfg(feature = "tls-native")]
+impl fmt::Debug for NativeTlsConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("NativeTlsConnector").finish_non_exhaustive()
+  }
+}
+
+/// A TLS acceptor backed by rustls, for servers that terminate TLS themselves.
+///
+/// # Example
+///
+/// ```no_run
+/// use fastwebsockets::tls::{ClientCertificates, RustlsAcceptor};
+/// use fastwebsockets::WebSocketError;
+/// use http_body_util::Empty;
+/// use hyper::body::{Bytes, Incoming};
+/// use hyper::server::conn::http1;
+/// use hyper::service::service_fn;
+/// use hyper::{Request, Response};
+/// use hyper_util::rt::TokioIo;
+/// use tokio::net::TcpListener;
+///
+/// async fn serve(
+///   acceptor: RustlsAcceptor,
+///   listener: TcpListener,
+/// ) -> Result<(), WebSocketError> {
+///   loop {
+///     let (stream, _addr) = acceptor.accept_tcp(&listener).await?;
+///     let certificates = ClientCertificates::from_stream(&stream);
+///     let service = service_fn(move |mut req: Request<Incoming>| {
+///       if let Some(certificates) = certificates.clone() {
+///         req.extensions_mut().insert(certificates);
+///       }
+///       // Upgrade the request here. The handler finds the client certificates with
+///       // `req.extensions().get::<ClientCertificates>()`.
+///       async move { Ok::<_, WebSocketError>(Response::new(Empty::<Bytes>::new())) }
+///     });
+///     tokio::spawn(async move {
+///       let conn = http1::Builder::new()
+///         .serve_connection(TokioIo::new(stream), service)
+///         .with_upgrades();
+///       if let Err(e) = conn.await {
+///         eprintln!("Error serving connection: {e}");
+///       }
+///     });
+///   }
+/// }
+/// ```
+#[cfg(feature = "tls-rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-rustls")))]
+#[derive(Clone)]
+pub struct RustlsAcceptor {
+  acceptor: tokio_rustls::TlsAcceptor,
+}
+
+#[cfg(feature = "tls-rustls")]
+impl RustlsAcceptor {
+  /// Creates an acceptor from a server configuration. Its ALPN protocols are replaced with `http/1.1`, so clients
+  /// that only offer other protocols such as `h2` are rejected during the TLS handshake.
+  ///
+  /// To request client certificates, build the configuration with a client certificate verifier such as
+  /// `rustls::server::AllowAnyAuthenticatedClient`.
+  pub fn new(mut config: rustls::ServerConfig) -> Self {
+    config.alpn_protocols = vec![ALPN_HTTP1.as_bytes().to_vec()];
+    Self {
+      acceptor: Arc::new(config).into(),
+    }
+  }
+
+  /// Performs the TLS handshake with a client over `stream`.
+  pub async fn accept<S>(
+    &self,
+    stream: S,
+  ) -> Result<tokio_rustls::server::TlsStream<S>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    Ok(self.acceptor.accept(stream).await?)
+  }
+
+  /// Accepts a TCP connection from `listener` and performs the TLS handshake over it. Returns the stream and the
+  /// address of the client.
+  ///
+  /// A failed TLS handshake is returned as an error, and the listener can be used for the next connection.
+  pub async fn accept_tcp(
+    &self,
+    listener: &TcpListener,
+  ) -> Result<
+    (tokio_rustls::server::TlsStream<TcpStream>, SocketAddr),
+    WebSocketError,
+  > {
+    let (stream, addr) = listener.accept().await?;
+    Ok((self.accept(stream).await?, addr))
+  }
+}
+
+#[cfg(feature = "tls-rustls")]
+impl fmt::Debug for RustlsAcceptor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RustlsAcceptor").finish_non_exhaustive()
+  }
+}
+
+/// The certificate chain a client presented during the TLS handshake, leaf first.
+///
+/// Insert it into the request extensions in the HTTP service so that the upgrade handler can read it with
+/// `req.extensions().get::<ClientCertificates>()`. See [`RustlsAcceptor`].
+#[cfg(feature = "tls-rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls-rustls")))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificates(Arc<[rustls::Certificate]>);
+
+#[cfg(feature = "tls-rustls")]
+impl ClientCertificates {
+  /// Returns the certificates the client of an accepted stream presented, or `None` if it presented none.
+  pub fn from_stream<S>(
+    stream: &tokio_rustls::server::TlsStream<S>,
+  ) -> Option<Self> {
+    match stream.get_ref().1.peer_certificates() {
+      Some(certificates) if !certificates.is_empty() => {
+        Some(Self(certificates.into()))
+      }
+      _ => None,
+    }
+  }
+
+  /// Returns the certificate of the client itself.
+  pub fn leaf(&self) -> &rustls::Certificate {
+    &self.0[0]
+  }
+
+  /// Returns the whole chain, leaf first.
+  pub fn chain(&self) -> &[rustls::Certificate] {
+    &self.0
+  }
+}
+
+/// Returns the name to verify the certificate against: the host without the brackets of an IPv6 literal.
+fn server_name(host: &str) -> &str {
+  host
+    .strip_prefix('[')
+    .and_then(|host| host.strip_suffix(']'))
+    .unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Arc;
+  use tokio::io::AsyncReadExt;
+  use tokio::io::AsyncWriteExt;
+  use tokio::net::TcpListener;
+  use tokio::net::TcpStream;
+
+  static CERT: &[u8] = include_bytes!("../tests/fixtures/localhost.crt");
+  static KEY: &[u8] = include_bytes!("../tests/fixtures/localhost.key");
+
+  /// Starts a TLS echo server for the fixture certificate, which is valid for `localhost`, `127.0.0.1` and `::1`.
+  async fn echo_server() -> TcpStream {
+    use tokio_rustls::rustls;
+
+    let certs = rustls_pemfile::certs(&mut &*CERT).unwrap();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut &*KEY).unwrap();
+    let mut config = rustls::ServerConfig::builder()
+      .with_safe_defaults()
+      .with_no_client_auth()
+      .with_single_cert(
+        certs.into_iter().map(rustls::Certificate).collect(),
+        rustls::PrivateKey(key[0].clone()),
+      )
+      .unwrap();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.unwrap();
+      let mut stream = acceptor.accept(stream).await.unwrap();
+      let mut buf = [0; 5];
+      stream.read_exact(&mut buf).await.unwrap();
+      stream.write_all(&buf).await.unwrap();
+      stream.flush().await.unwrap();
+    });
+    TcpStream::connect(addr).await.unwrap()
+  }
+
+  #[test]
+  fn brackets_are_removed_from_ipv6_literals() {
+    assert_eq!(server_name("[::1]"), "::1");
+    assert_eq!(server_name("127.0.0.1"), "127.0.0.1");
+    assert_eq!(server_name("example.com"), "example.com");
+  }
+
+  #[cfg(feature = "tls-rustls")]
+  #[tokio::test]
+  async fn rustls_connects_to_ip_literal() {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &*CERT).unwrap() {
+      roots.add(&rustls::Certificate(cert)).unwrap();
+    }
+    let config = rustls::ClientConfig::builder()
+      .with_safe_defaults()
+      .with_root_certificates(roots)
+      .with_no_client_auth();
+    let connector = RustlsConnector::new(config);
+
+    let mut stream = connector
+      .connect("127.0.0.1", echo_server().await)
+      .await
+      .unwrap();
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    assert!(matches!(
+      connector
+        .connect("not a name", tokio::io::duplex(64).0)
+        .await,
+      Err(WebSocketError::InvalidServerName)
+    ));
+  }
+
+  #[cfg(feature = "tls-rustls")]
+  #[tokio::test]
+  async fn acceptor_surfaces_client_certificates() {
+    let certs: Vec<_> = rustls_pemfile::certs(&mut &*CERT)
+      .unwrap()
+      .into_iter()
+      .map(rustls::Certificate)
+      .collect();
+    let key = rustls::PrivateKey(
+      rustls_pemfile::pkcs8_private_keys(&mut &*KEY)
+        .unwrap()
+        .remove(0),
+    );
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&certs[0]).unwrap();
+
+    let verifier =
+      rustls::server::AllowAnyAuthenticatedClient::new(roots.clone()).boxed();
+    let config = rustls::ServerConfig::builder()
+      .with_safe_defaults()
+      .with_client_cert_verifier(verifier)
+      .with_single_cert(certs.clone(), key.clone())
+      .unwrap();
+    let acceptor = RustlsAcceptor::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = tokio::spawn(async move {
+      let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)
+        .unwrap();
+      config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+      let stream = TcpStream::connect(addr).await.unwrap();
+      let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+      let server_name = rustls::ServerName::try_from("localhost").unwrap();
+      let mut stream = connector.connect(server_name, stream).await.unwrap();
+      stream.write_all(b"hello").await.unwrap();
+      stream.flush().await.unwrap();
+    });
+
+    let (mut stream, peer) = acceptor.accept_tcp(&listener).await.unwrap();
+    assert_eq!(peer.ip(), addr.ip());
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+    let certificates = ClientCertificates::from_stream(&stream).unwrap();
+    assert_eq!(certificates.chain().len(), 1);
+    assert_eq!(
+      certificates.leaf().0,
+      rustls_pemfile::certs(&mut &*CERT).unwrap()[0]
+    );
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+    client.await.unwrap();
+  }
+
+  #[cfg(feature = "tls-native")]
+  #[tokio::test]
+  async fn native_tls_connects_to_ip_literal() {
+    let mut builder = native_tls::TlsConnector::builder();
+    builder
+      .add_root_certificate(native_tls::Certificate::from_pem(CERT).unwrap());
+    let connector = NativeTlsConnector::from_builder(builder).unwrap();
+
+    let mut stream = connector
+      .connect("127.0.0.1", echo_server().await)
+      .await
+      .unwrap();
+    assert_eq!(
+      stream.get_ref().negotiated_alpn().unwrap().as_deref(),
+      Some(&b"http/1.1"[..])
+    );
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+  }
+}
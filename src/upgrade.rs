@@ -29,15 +29,31 @@ use hyper_util::rt::TokioIo;
 use pin_project::pin_project;
 use sha1::Digest;
 use sha1::Sha1;
+use std::net::IpAddr;
+use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
 
+#[cfg(feature = "deflate")]
+use crate::deflate::parse_offers;
+#[cfg(feature = "brotli")]
+use crate::BrotliConfig;
+#[cfg(feature = "deflate")]
+use crate::DeflateConfig;
+#[cfg(feature = "deflate")]
+use crate::DeflateOffer;
+use crate::Extension;
 use crate::Role;
 use crate::WebSocket;
 use crate::WebSocketError;
+#[cfg(feature = "zstd")]
+use crate::ZstdConfig;
 
-fn sec_websocket_protocol(key: &[u8]) -> String {
+pub(crate) fn sec_websocket_protocol(key: &[u8]) -> String {
   let mut sha1 = Sha1::new();
   sha1.update(key);
   sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11"); // magic string
@@ -50,6 +66,7 @@ type Error = WebSocketError;
 pub struct IncomingUpgrade {
   key: String,
   on_upgrade: hyper::upgrade::OnUpgrade,
+  forwarded: hyper::HeaderMap,
 }
 
 impl IncomingUpgrade {
@@ -64,8 +81,20 @@ impl IncomingUpgrade {
 
     let stream = UpgradeFut {
       inner: self.on_upgrade,
+      extension: None,
+      #[cfg(feature = "deflate")]
+      deflate: None,
+      #[cfg(feature = "brotli")]
+      brotli: None,
+      #[cfg(feature = "zstd")]
+      zstd: None,
+      options: None,
+      protocol: None,
+      extensions: None,
+      forwarded: self.forwarded,
     };
 
+    let stream = stream.negotiated(&response);
     Ok((response, stream))
   }
 }
@@ -101,6 +130,7 @@ where
     Ok(Self {
       on_upgrade,
       key: sec_websocket_protocol(key.as_bytes()),
+      forwarded: forwarding_headers(&parts.headers),
     })
   }
 }
@@ -111,6 +141,253 @@ where
 pub struct UpgradeFut {
   #[pin]
   inner: hyper::upgrade::OnUpgrade,
+  extension: Option<Arc<dyn Extension>>,
+  #[cfg(feature = "deflate")]
+  deflate: Option<DeflateConfig>,
+  #[cfg(feature = "brotli")]
+  brotli: Option<BrotliConfig>,
+  #[cfg(feature = "zstd")]
+  zstd: Option<ZstdConfig>,
+  options: Option<UpgradeOptions>,
+  protocol: Option<String>,
+  extensions: Option<String>,
+  forwarded: hyper::HeaderMap,
+}
+
+impl UpgradeFut {
+  /// Returns the subprotocol selected in the upgrade response, if any, for routing the connection before it is
+  /// available.
+  pub fn protocol(&self) -> Option<&str> {
+    self.protocol.as_deref()
+  }
+
+  /// Returns the `Sec-WebSocket-Extensions` header of the upgrade response, which lists the negotiated extensions
+  /// with their parameters, if any were negotiated.
+  pub fn extensions(&self) -> Option<&str> {
+    self.extensions.as_deref()
+  }
+
+  /// Determines the client from the forwarding headers of the upgrade request, for connections that arrive through
+  /// reverse proxies. See [`forwarded_info`].
+  pub fn forwarded_info(
+    &self,
+    peer: IpAddr,
+    trusted_proxies: &[IpAddr],
+  ) -> ForwardedInfo {
+    resolve_forwarded(&self.forwarded, peer, trusted_proxies)
+  }
+
+  /// Records the subprotocol and extensions negotiated in `response`.
+  pub(crate) fn negotiated<B>(mut self, response: &Response<B>) -> Self {
+    let header = |name| {
+      response
+        .headers()
+        .get(name)
+        .and_then(|value: &hyper::header::HeaderValue| value.to_str().ok())
+        .map(str::to_owned)
+    };
+    self.protocol = header(hyper::header::SEC_WEBSOCKET_PROTOCOL);
+    self.extensions = header(hyper::header::SEC_WEBSOCKET_EXTENSIONS);
+    self
+  }
+
+  /// Applies `options` to the websocket the future resolves to.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::upgrade::{upgrade, UpgradeOptions};
+  /// use fastwebsockets::WebSocketError;
+  /// use http_body_util::Empty;
+  /// use hyper::{body::{Bytes, Incoming}, Request, Response};
+  ///
+  /// const OPTIONS: UpgradeOptions =
+  ///   UpgradeOptions::new().with_max_message_size(1 << 20);
+  ///
+  /// fn server_upgrade(
+  ///   mut req: Request<Incoming>,
+  /// ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  ///   let (response, fut) = upgrade(&mut req)?;
+  ///   let fut = fut.with_options(OPTIONS);
+  ///   tokio::spawn(async move {
+  ///     let ws = fut.await;
+  ///     // ...
+  ///   });
+  ///   Ok(response)
+  /// }
+  /// ```
+  pub fn with_options(mut self, options: UpgradeOptions) -> Self {
+    self.options = Some(options);
+    self
+  }
+
+  /// Returns a future that resolves to the websocket together with the subprotocol, extensions and forwarding
+  /// headers of the upgrade, for handlers that need them once the connection is available.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::upgrade::upgrade_with_protocol;
+  /// use fastwebsockets::WebSocketError;
+  /// use http_body_util::Empty;
+  /// use hyper::{body::{Bytes, Incoming}, Request, Response};
+  /// use std::net::IpAddr;
+  ///
+  /// fn server_upgrade(
+  ///   mut req: Request<Incoming>,
+  ///   peer: IpAddr,
+  /// ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  ///   let (response, fut) = upgrade_with_protocol(&mut req, &["chat"])?;
+  ///   tokio::spawn(async move {
+  ///     let upgraded = fut.with_info().await?;
+  ///     let client = upgraded.forwarded_info(peer, &[]).client();
+  ///     let chat = upgraded.protocol() == Some("chat");
+  ///     let ws = upgraded.into_websocket();
+  ///     // ...
+  ///     Ok::<_, WebSocketError>(())
+  ///   });
+  ///   Ok(response)
+  /// }
+  /// ```
+  pub fn with_info(self) -> UpgradeWithInfo {
+    UpgradeWithInfo { fut: self }
+  }
+}
+
+/// A future that resolves to an [`UpgradedWebSocket`] when the associated HTTP upgrade completes. See
+/// [`UpgradeFut::with_info`].
+#[pin_project]
+#[derive(Debug)]
+pub struct UpgradeWithInfo {
+  #[pin]
+  fut: UpgradeFut,
+}
+
+/// A websocket of an upgraded connection, with what was negotiated in the upgrade.
+pub struct UpgradedWebSocket {
+  ws: WebSocket<TokioIo<hyper::upgrade::Upgraded>>,
+  protocol: Option<String>,
+  extensions: Option<String>,
+  forwarded: hyper::HeaderMap,
+}
+
+impl UpgradedWebSocket {
+  /// See [`UpgradeFut::protocol`].
+  pub fn protocol(&self) -> Option<&str> {
+    self.protocol.as_deref()
+  }
+
+  /// See [`UpgradeFut::extensions`].
+  pub fn extensions(&self) -> Option<&str> {
+    self.extensions.as_deref()
+  }
+
+  /// See [`UpgradeFut::forwarded_info`].
+  pub fn forwarded_info(
+    &self,
+    peer: IpAddr,
+    trusted_proxies: &[IpAddr],
+  ) -> ForwardedInfo {
+    resolve_forwarded(&self.forwarded, peer, trusted_proxies)
+  }
+
+  /// Returns the websocket.
+  pub fn websocket(
+    &mut self,
+  ) -> &mut WebSocket<TokioIo<hyper::upgrade::Upgraded>> {
+    &mut self.ws
+  }
+
+  /// Consumes the value and returns the websocket.
+  pub fn into_websocket(self) -> WebSocket<TokioIo<hyper::upgrade::Upgraded>> {
+    self.ws
+  }
+}
+
+/// Settings for the websocket of an upgraded connection, applied by [`UpgradeFut::with_options`] so that every
+/// handler does not have to configure its connection.
+///
+/// Start from `UpgradeOptions::new` or `Default::default` and change settings with the `with_*` methods. More
+/// settings may be added, so the struct cannot be built with a struct expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UpgradeOptions {
+  /// See `WebSocket::set_max_message_size`.
+  pub max_message_size: usize,
+  /// See `WebSocket::set_auto_close`.
+  pub auto_close: bool,
+  /// See `WebSocket::set_auto_pong`.
+  pub auto_pong: bool,
+  /// See `WebSocket::set_writev`.
+  pub writev: bool,
+  /// See `WebSocket::set_writev_threshold`.
+  pub writev_threshold: usize,
+}
+
+impl Default for UpgradeOptions {
+  /// The defaults of a new `WebSocket`.
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl UpgradeOptions {
+  /// Creates options with the defaults of a new `WebSocket`. Unlike `Default::default`, this can be used in a
+  /// `const`.
+  pub const fn new() -> Self {
+    Self {
+      max_message_size: 64 << 20,
+      auto_close: true,
+      auto_pong: true,
+      writev: true,
+      writev_threshold: 1024,
+    }
+  }
+
+  /// See `WebSocket::set_max_message_size`.
+  pub const fn with_max_message_size(
+    mut self,
+    max_message_size: usize,
+  ) -> Self {
+    self.max_message_size = max_message_size;
+    self
+  }
+
+  /// See `WebSocket::set_auto_close`.
+  pub const fn with_auto_close(mut self, auto_close: bool) -> Self {
+    self.auto_close = auto_close;
+    self
+  }
+
+  /// See `WebSocket::set_auto_pong`.
+  pub const fn with_auto_pong(mut self, auto_pong: bool) -> Self {
+    self.auto_pong = auto_pong;
+    self
+  }
+
+  /// See `WebSocket::set_writev`.
+  pub const fn with_writev(mut self, writev: bool) -> Self {
+    self.writev = writev;
+    self
+  }
+
+  /// See `WebSocket::set_writev_threshold`.
+  pub const fn with_writev_threshold(
+    mut self,
+    writev_threshold: usize,
+  ) -> Self {
+    self.writev_threshold = writev_threshold;
+    self
+  }
+
+  /// Applies the options to `ws`, for websockets that do not come from an `UpgradeFut`.
+  pub fn apply<S>(&self, ws: &mut WebSocket<S>) {
+    ws.set_max_message_size(self.max_message_size);
+    ws.set_auto_close(self.auto_close);
+    ws.set_auto_pong(self.auto_pong);
+    ws.set_writev(self.writev);
+    ws.set_writev_threshold(self.writev_threshold);
+  }
 }
 
 /// Try to upgrade a received `hyper::Request` to a websocket connection.
@@ -127,40 +404,517 @@ pub struct UpgradeFut {
 /// To check if a request is a websocket upgrade request, you can use [`is_upgrade_request`].
 /// Alternatively you can inspect the `Connection` and `Upgrade` headers manually.
 ///
+/// With the `http2` feature, the extended CONNECT requests of RFC 8441 that bootstrap a websocket on an HTTP/2 stream
+/// are accepted too, with a `200` response. They carry no `Sec-WebSocket-Key`. hyper only delivers them if the
+/// server enabled them with `hyper::server::conn::http2::Builder::enable_connect_protocol`.
 pub fn upgrade<B>(
   mut request: impl std::borrow::BorrowMut<Request<B>>,
 ) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
   let request = request.borrow_mut();
+  #[cfg(feature = "http2")]
+  let response = match is_extended_connect(request) {
+    true => connect_accepted(request.headers(), Empty::new())?,
+    false => switching_protocols(request.headers(), Empty::new())?,
+  };
+  #[cfg(not(feature = "http2"))]
+  let response = switching_protocols(request.headers(), Empty::new())?;
+  let forwarded = forwarding_headers(request.headers());
 
-  let key = request
-    .headers()
+  let stream = UpgradeFut {
+    inner: hyper::upgrade::on(request),
+    extension: None,
+    #[cfg(feature = "deflate")]
+    deflate: None,
+    #[cfg(feature = "brotli")]
+    brotli: None,
+    #[cfg(feature = "zstd")]
+    zstd: None,
+    options: None,
+    protocol: None,
+    extensions: None,
+    forwarded,
+  };
+
+  let stream = stream.negotiated(&response);
+  Ok((response, stream))
+}
+
+/// Like [`upgrade`], for servers that use the `http` types but not hyper's upgrade mechanism.
+///
+/// Only the request `parts` are inspected. `io` is supplied by the server and resolves to the connection once the
+/// returned response has been sent; the `IoUpgradeFut` then resolves to the websocket stream over it. Extensions are
+/// not negotiated.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::upgrade_with_io;
+/// use hyper::Request;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), fastwebsockets::WebSocketError> {
+/// let (parts, _) = Request::builder()
+///   .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+///   .header("Sec-WebSocket-Version", "13")
+///   .body(())
+///   .unwrap()
+///   .into_parts();
+/// let (client, server) = tokio::io::duplex(1024);
+///
+/// // The server takes the connection back once it has written the response.
+/// let (response, fut) =
+///   upgrade_with_io(&parts, async { Ok::<_, std::io::Error>(server) })?;
+/// assert_eq!(response.status(), 101);
+/// let ws = fut.await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn upgrade_with_io<F, S, E>(
+  parts: &hyper::http::request::Parts,
+  io: F,
+) -> Result<(Response<()>, IoUpgradeFut<F>), Error>
+where
+  F: std::future::Future<Output = Result<S, E>>,
+  Error: From<E>,
+{
+  let response = switching_protocols(&parts.headers, ())?;
+  Ok((response, IoUpgradeFut { io }))
+}
+
+/// Checks the `Sec-WebSocket-Key` and `Sec-WebSocket-Version` headers of an upgrade request and builds the
+/// `101 Switching Protocols` response.
+fn switching_protocols<B>(
+  headers: &hyper::HeaderMap,
+  body: B,
+) -> Result<Response<B>, Error> {
+  let key = headers
     .get("Sec-WebSocket-Key")
     .ok_or(WebSocketError::MissingSecWebSocketKey)?;
-  if request
-    .headers()
-    .get("Sec-WebSocket-Version")
-    .map(|v| v.as_bytes())
-    != Some(b"13")
-  {
+  if headers.get("Sec-WebSocket-Version").map(|v| v.as_bytes()) != Some(b"13") {
     return Err(WebSocketError::InvalidSecWebsocketVersion);
   }
 
-  let response = Response::builder()
-    .status(hyper::StatusCode::SWITCHING_PROTOCOLS)
-    .header(hyper::header::CONNECTION, "upgrade")
-    .header(hyper::header::UPGRADE, "websocket")
-    .header(
-      "Sec-WebSocket-Accept",
-      &sec_websocket_protocol(key.as_bytes()),
-    )
-    .body(Empty::new())
-    .expect("bug: failed to build response");
+  Ok(
+    Response::builder()
+      .status(hyper::StatusCode::SWITCHING_PROTOCOLS)
+      .header(hyper::header::CONNECTION, "upgrade")
+      .header(hyper::header::UPGRADE, "websocket")
+      .header(
+        "Sec-WebSocket-Accept",
+        &sec_websocket_protocol(key.as_bytes()),
+      )
+      .body(body)
+      .expect("bug: failed to build response"),
+  )
+}
 
-  let stream = UpgradeFut {
-    inner: hyper::upgrade::on(request),
+/// Checks the `Sec-WebSocket-Version` header of an extended CONNECT request and builds the `200` response
+/// (RFC 8441, Section 5).
+#[cfg(feature = "http2")]
+fn connect_accepted<B>(
+  headers: &hyper::HeaderMap,
+  body: B,
+) -> Result<Response<B>, Error> {
+  if headers.get("Sec-WebSocket-Version").map(|v| v.as_bytes()) != Some(b"13") {
+    return Err(WebSocketError::InvalidSecWebsocketVersion);
+  }
+  Ok(Response::new(body))
+}
+
+/// Returns whether `request` is an extended CONNECT request for a websocket on an HTTP/2 stream, which names the
+/// protocol in the `:protocol` pseudo-header instead of the `Upgrade` header.
+#[cfg(feature = "http2")]
+fn is_extended_connect<B>(request: &Request<B>) -> bool {
+  request.method() == hyper::Method::CONNECT
+    && request
+      .extensions()
+      .get::<hyper::ext::Protocol>()
+      .is_some_and(|protocol| {
+        protocol.as_str().eq_ignore_ascii_case("websocket")
+      })
+}
+
+/// Like [`upgrade`], but also negotiates the permessage-deflate extension with the offers in the client's
+/// `Sec-WebSocket-Extensions` header, using `policy` as the server's requirements.
+///
+/// If an offer is accepted, the response carries the agreed parameters and the `WebSocket` returned by the
+/// `UpgradeFut` compresses messages with them. Otherwise the connection is upgraded without compression.
+#[cfg(feature = "deflate")]
+pub fn upgrade_with_deflate<B>(
+  request: impl std::borrow::BorrowMut<Request<B>>,
+  policy: &DeflateConfig,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  upgrade_with_deflate_fn(request, |offers| {
+    offers.iter().find_map(|offer| offer.accept(policy))
+  })
+}
+
+/// Like [`upgrade`], but lets `negotiate` choose the permessage-deflate parameters.
+///
+/// `negotiate` is called with the client's offers, in order of preference, if there are any. It returns the
+/// parameters to accept, or `None` to upgrade the connection without compression. Parameters that none of the offers
+/// allow fail the upgrade with `WebSocketError::InvalidDeflateParameters`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::upgrade_with_deflate_fn;
+/// use fastwebsockets::{DeflateConfig, WebSocketError};
+/// use http_body_util::Empty;
+/// use hyper::{body::{Bytes, Incoming}, Request, Response};
+///
+/// fn server_upgrade(
+///   mut req: Request<Incoming>,
+///   compress: bool,
+/// ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+///   let policy = DeflateConfig {
+///     client_no_context_takeover: true,
+///     ..Default::default()
+///   };
+///   let (response, fut) = upgrade_with_deflate_fn(&mut req, |offers| {
+///     if !compress {
+///       return None;
+///     }
+///     offers.iter().find_map(|offer| offer.accept(&policy))
+///   })?;
+///   tokio::spawn(async move {
+///     let ws = fut.await;
+///     // ...
+///   });
+///   Ok(response)
+/// }
+/// ```
+#[cfg(feature = "deflate")]
+pub fn upgrade_with_deflate_fn<B, F>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  negotiate: F,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error>
+where
+  F: FnOnce(&[DeflateOffer]) -> Option<DeflateConfig>,
+{
+  let request = request.borrow_mut();
+  let offers: Vec<DeflateOffer> = request
+    .headers()
+    .get_all(hyper::header::SEC_WEBSOCKET_EXTENSIONS)
+    .into_iter()
+    .filter_map(|offers| offers.to_str().ok())
+    .flat_map(parse_offers)
+    .collect();
+  let deflate = match offers.is_empty() {
+    true => None,
+    false => negotiate(&offers),
   };
+  if let Some(config) = &deflate {
+    if !offers.iter().any(|offer| offer.permits(config)) {
+      return Err(WebSocketError::InvalidDeflateParameters);
+    }
+  }
 
-  Ok((response, stream))
+  let (mut response, mut fut) = upgrade(request)?;
+  if let Some(config) = deflate {
+    response.headers_mut().insert(
+      hyper::header::SEC_WEBSOCKET_EXTENSIONS,
+      hyper::header::HeaderValue::from_str(&config.response())
+        .expect("bug: invalid extension response"),
+    );
+    fut.deflate = Some(config);
+  }
+  let fut = fut.negotiated(&response);
+  Ok((response, fut))
+}
+
+/// Like [`upgrade`], but also accepts the non-standard `permessage-brotli` extension if the client offers it. The
+/// `WebSocket` returned by the `UpgradeFut` then compresses messages with `config`.
+///
+/// Other extensions in the offer are ignored, so fall back to [`upgrade_with_deflate`] for clients that do not offer
+/// Brotli.
+#[cfg(feature = "brotli")]
+pub fn upgrade_with_brotli<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  config: BrotliConfig,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  let request = request.borrow_mut();
+  let offered = request
+    .headers()
+    .get_all(hyper::header::SEC_WEBSOCKET_EXTENSIONS)
+    .into_iter()
+    .filter_map(|offers| offers.to_str().ok())
+    .any(crate::brotli::is_listed);
+
+  let (mut response, mut fut) = upgrade(request)?;
+  if offered {
+    response.headers_mut().insert(
+      hyper::header::SEC_WEBSOCKET_EXTENSIONS,
+      hyper::header::HeaderValue::from_str(&config.response())
+        .expect("bug: invalid extension response"),
+    );
+    fut.brotli = Some(config);
+  }
+  let fut = fut.negotiated(&response);
+  Ok((response, fut))
+}
+
+/// Like [`upgrade`], but also negotiates the experimental `permessage-zstd` extension with the offers in the client's
+/// `Sec-WebSocket-Extensions` header, using `policy` for the largest windows the server allows and its compression
+/// level.
+///
+/// Other extensions in the offer are ignored, so fall back to [`upgrade_with_deflate`] for clients that do not offer
+/// zstd.
+#[cfg(feature = "zstd")]
+pub fn upgrade_with_zstd<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  policy: &ZstdConfig,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  let request = request.borrow_mut();
+  let zstd = request
+    .headers()
+    .get_all(hyper::header::SEC_WEBSOCKET_EXTENSIONS)
+    .into_iter()
+    .filter_map(|offers| offers.to_str().ok())
+    .find_map(|offers| policy.accept(offers));
+
+  let (mut response, mut fut) = upgrade(request)?;
+  if let Some(config) = zstd {
+    response.headers_mut().insert(
+      hyper::header::SEC_WEBSOCKET_EXTENSIONS,
+      hyper::header::HeaderValue::from_str(&config.response())
+        .expect("bug: invalid extension response"),
+    );
+    fut.zstd = Some(config);
+  }
+  let fut = fut.negotiated(&response);
+  Ok((response, fut))
+}
+
+/// Like [`upgrade`], but also negotiates a custom extension with the client's offers of it in the
+/// `Sec-WebSocket-Extensions` header. If `Extension::accept` accepts one of them, the response lists the extension
+/// with the parameters it returned, and the `WebSocket` returned by the `UpgradeFut` passes its frames through it.
+pub fn upgrade_with_extension<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  extension: Arc<dyn Extension>,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  let request = request.borrow_mut();
+  let offers: Vec<&str> =
+    extension_params(request.headers(), extension.name()).collect();
+  let accepted = match offers.is_empty() {
+    true => None,
+    false => extension.accept(&offers),
+  };
+  let accepted = accepted
+    .map(|params| {
+      hyper::header::HeaderValue::from_str(&extension_element(
+        extension.name(),
+        &params,
+      ))
+      .map_err(|_| WebSocketError::InvalidExtensionParameters)
+    })
+    .transpose()?;
+
+  let (mut response, mut fut) = upgrade(&mut *request)?;
+  if let Some(value) = accepted {
+    response
+      .headers_mut()
+      .insert(hyper::header::SEC_WEBSOCKET_EXTENSIONS, value);
+    fut.extension = Some(extension);
+  }
+  let fut = fut.negotiated(&response);
+  Ok((response, fut))
+}
+
+/// Returns the parameters of each element of the `Sec-WebSocket-Extensions` headers that names the extension `name`,
+/// in order.
+pub(crate) fn extension_params<'a>(
+  headers: &'a hyper::HeaderMap,
+  name: &'a str,
+) -> impl Iterator<Item = &'a str> {
+  headers
+    .get_all(hyper::header::SEC_WEBSOCKET_EXTENSIONS)
+    .into_iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .filter_map(move |element| {
+      let (element_name, params) =
+        element.split_once(';').unwrap_or((element, ""));
+      element_name
+        .trim()
+        .eq_ignore_ascii_case(name)
+        .then(|| params.trim())
+    })
+}
+
+/// Formats an element of the `Sec-WebSocket-Extensions` header.
+pub(crate) fn extension_element(name: &str, params: &str) -> String {
+  match params.is_empty() {
+    true => name.to_owned(),
+    false => format!("{}; {}", name, params),
+  }
+}
+
+/// Like [`upgrade`], but also selects a subprotocol from the client's `Sec-WebSocket-Protocol` header.
+///
+/// The first protocol offered by the client that is in `protocols` is selected and returned in the response. If the
+/// client offers none of them, the connection is upgraded without a subprotocol. Use [`selected_protocol`] on the
+/// response to find out which one applies.
+pub fn upgrade_with_protocol<B>(
+  request: impl std::borrow::BorrowMut<Request<B>>,
+  protocols: &[&str],
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  upgrade_with_protocol_fn(request, |offered| {
+    offered
+      .iter()
+      .copied()
+      .find(|offered| protocols.contains(offered))
+  })
+}
+
+/// Like [`upgrade`], but lets `select` choose the subprotocol.
+///
+/// `select` is called with the protocols in the client's `Sec-WebSocket-Protocol` header, in order of preference, if
+/// there are any. It returns the protocol to select, which is echoed in the response, or `None` to upgrade the
+/// connection without a subprotocol. A protocol the client did not offer fails the upgrade with
+/// `WebSocketError::InvalidSubprotocol`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::upgrade_with_protocol_fn;
+/// use fastwebsockets::WebSocketError;
+/// use http_body_util::Empty;
+/// use hyper::{body::{Bytes, Incoming}, Request, Response};
+///
+/// fn server_upgrade(
+///   mut req: Request<Incoming>,
+/// ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+///   // Pick the newest version of the protocol the client speaks.
+///   let (response, fut) = upgrade_with_protocol_fn(&mut req, |offered| {
+///     offered
+///       .iter()
+///       .copied()
+///       .filter(|protocol| protocol.starts_with("graph.v"))
+///       .max()
+///   })?;
+///   tokio::spawn(async move {
+///     let ws = fut.await;
+///     // ...
+///   });
+///   Ok(response)
+/// }
+/// ```
+pub fn upgrade_with_protocol_fn<B, F>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  select: F,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error>
+where
+  F: for<'a> FnOnce(&[&'a str]) -> Option<&'a str>,
+{
+  let request = request.borrow_mut();
+  let offered: Vec<&str> = offered_protocols(request.headers()).collect();
+  let selected = match offered.is_empty() {
+    true => None,
+    false => select(&offered),
+  };
+  if let Some(protocol) = selected {
+    if !offered.contains(&protocol) {
+      return Err(WebSocketError::InvalidSubprotocol);
+    }
+  }
+  let selected = selected.map(|protocol| {
+    hyper::header::HeaderValue::from_str(protocol)
+      .expect("bug: invalid subprotocol")
+  });
+
+  let (mut response, fut) = upgrade(&mut *request)?;
+  if let Some(value) = selected {
+    response
+      .headers_mut()
+      .insert(hyper::header::SEC_WEBSOCKET_PROTOCOL, value);
+  }
+  let fut = fut.negotiated(&response);
+  Ok((response, fut))
+}
+
+/// Returns the subprotocol selected in the `Sec-WebSocket-Protocol` header of an upgrade response, if any.
+pub fn selected_protocol<B>(response: &Response<B>) -> Option<&str> {
+  response
+    .headers()
+    .get(hyper::header::SEC_WEBSOCKET_PROTOCOL)?
+    .to_str()
+    .ok()
+}
+
+/// Returns the subprotocols listed in the `Sec-WebSocket-Protocol` headers, in order.
+pub(crate) fn offered_protocols(
+  headers: &hyper::HeaderMap,
+) -> impl Iterator<Item = &str> {
+  headers
+    .get_all(hyper::header::SEC_WEBSOCKET_PROTOCOL)
+    .into_iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(str::trim)
+    .filter(|protocol| !protocol.is_empty())
+}
+
+/// Checks the `Origin` header of an upgrade request against the origins allowed to connect, such as
+/// `https://app.example.com`, before upgrading it. Browsers send the origin of the page that opens the websocket, and
+/// do not apply the same-origin policy to websockets, so this keeps other sites from using a visitor's cookies.
+///
+/// Origins are compared case-insensitively. Requests without an `Origin` header come from clients other than
+/// browsers and are allowed. Other requests fail with `WebSocketError::OriginNotAllowed`, which a server answers with
+/// `403 Forbidden`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::{check_origin, upgrade};
+/// use fastwebsockets::WebSocketError;
+/// use http_body_util::Empty;
+/// use hyper::{body::{Bytes, Incoming}, Request, Response, StatusCode};
+///
+/// fn server_upgrade(
+///   mut req: Request<Incoming>,
+/// ) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+///   if check_origin(&req, &["https://app.example.com"]).is_err() {
+///     let mut response = Response::new(Empty::new());
+///     *response.status_mut() = StatusCode::FORBIDDEN;
+///     return Ok(response);
+///   }
+///   let (response, fut) = upgrade(&mut req)?;
+///   tokio::spawn(async move {
+///     let ws = fut.await;
+///     // ...
+///   });
+///   Ok(response)
+/// }
+/// ```
+pub fn check_origin<B, O>(
+  request: &Request<B>,
+  allowed: &[O],
+) -> Result<(), WebSocketError>
+where
+  O: AsRef<str>,
+{
+  origin_allowed(request.headers(), allowed)
+}
+
+/// Checks the `Origin` header like [`check_origin`].
+pub(crate) fn origin_allowed<O: AsRef<str>>(
+  headers: &hyper::HeaderMap,
+  allowed: &[O],
+) -> Result<(), WebSocketError> {
+  let Some(origin) = headers.get(hyper::header::ORIGIN) else {
+    return Ok(());
+  };
+  let origin = origin
+    .to_str()
+    .map_err(|_| WebSocketError::OriginNotAllowed)?;
+  match allowed
+    .iter()
+    .any(|allowed| allowed.as_ref().eq_ignore_ascii_case(origin))
+  {
+    true => Ok(()),
+    false => Err(WebSocketError::OriginNotAllowed),
+  }
 }
 
 /// Check if a request is a websocket upgrade request.
@@ -169,7 +923,13 @@ pub fn upgrade<B>(
 /// this function returns true if of them are `"websocket"`,
 /// If the server supports multiple upgrade protocols,
 /// it would be more appropriate to try each listed protocol in order.
+///
+/// With the `http2` feature, extended CONNECT requests for a websocket on an HTTP/2 stream are upgrade requests too.
 pub fn is_upgrade_request<B>(request: &hyper::Request<B>) -> bool {
+  #[cfg(feature = "http2")]
+  if is_extended_connect(request) {
+    return true;
+  }
   header_contains_value(request.headers(), hyper::header::CONNECTION, "Upgrade")
     && header_contains_value(
       request.headers(),
@@ -178,6 +938,206 @@ pub fn is_upgrade_request<B>(request: &hyper::Request<B>) -> bool {
     )
 }
 
+/// Client information reported by reverse proxies through the `Forwarded` or `X-Forwarded-*` headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedInfo {
+  client: IpAddr,
+  proto: Option<String>,
+  host: Option<String>,
+}
+
+impl ForwardedInfo {
+  /// Address of the client, or of the peer if it could not be determined from trusted headers.
+  pub fn client(&self) -> IpAddr {
+    self.client
+  }
+
+  /// Protocol the client used to connect to the proxy, e.g. `https`.
+  pub fn proto(&self) -> Option<&str> {
+    self.proto.as_deref()
+  }
+
+  /// `Host` header the client sent to the proxy.
+  pub fn host(&self) -> Option<&str> {
+    self.host.as_deref()
+  }
+}
+
+/// Determines the client of an upgrade request that may have passed through reverse proxies.
+///
+/// `peer` is the address of the connection the request arrived on. Forwarding headers are only honored when `peer` is
+/// listed in `trusted_proxies`, since any client can send them. The `Forwarded` header (RFC 7239) is preferred over
+/// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`.
+///
+/// The list of hops is walked from the nearest proxy outwards, and the first address that is not a trusted proxy is
+/// the client. If a hop is obfuscated or malformed, the last trusted proxy is reported instead.
+///
+/// The same information is available from [`UpgradeFut::forwarded_info`] once the request has been upgraded.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::forwarded_info;
+/// use hyper::Request;
+/// use std::net::IpAddr;
+///
+/// let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+/// let req = Request::builder()
+///   .header("Forwarded", "for=192.0.2.60;proto=https")
+///   .body(())
+///   .unwrap();
+///
+/// let info = forwarded_info(&req, proxy, &[proxy]);
+/// assert_eq!(info.client(), "192.0.2.60".parse::<IpAddr>().unwrap());
+/// assert_eq!(info.proto(), Some("https"));
+/// ```
+pub fn forwarded_info<B>(
+  request: &Request<B>,
+  peer: IpAddr,
+  trusted_proxies: &[IpAddr],
+) -> ForwardedInfo {
+  resolve_forwarded(request.headers(), peer, trusted_proxies)
+}
+
+fn resolve_forwarded(
+  headers: &hyper::HeaderMap,
+  peer: IpAddr,
+  trusted_proxies: &[IpAddr],
+) -> ForwardedInfo {
+  let mut info = ForwardedInfo {
+    client: peer,
+    proto: None,
+    host: None,
+  };
+  if !trusted_proxies.contains(&peer) {
+    return info;
+  }
+
+  let hops = if headers.contains_key(hyper::header::FORWARDED) {
+    forwarded_hops(headers)
+  } else {
+    x_forwarded_hops(headers)
+  };
+
+  for hop in hops.into_iter().rev() {
+    let Some(addr) = hop.addr else {
+      break;
+    };
+    info = ForwardedInfo {
+      client: addr,
+      proto: hop.proto,
+      host: hop.host,
+    };
+    if !trusted_proxies.contains(&addr) {
+      break;
+    }
+  }
+  info
+}
+
+const FORWARDING_HEADERS: [&str; 4] = [
+  "forwarded",
+  "x-forwarded-for",
+  "x-forwarded-proto",
+  "x-forwarded-host",
+];
+
+/// Copies the headers read by [`forwarded_info`], so that `UpgradeFut` can resolve the client after the request is
+/// gone. Most requests carry none of them, and an empty `HeaderMap` does not allocate.
+fn forwarding_headers(headers: &hyper::HeaderMap) -> hyper::HeaderMap {
+  let mut forwarded = hyper::HeaderMap::new();
+  for name in FORWARDING_HEADERS {
+    for value in headers.get_all(name) {
+      forwarded.append(name, value.clone());
+    }
+  }
+  forwarded
+}
+
+/// One element of a forwarding header. `addr` is `None` for obfuscated or malformed nodes.
+struct Hop {
+  addr: Option<IpAddr>,
+  proto: Option<String>,
+  host: Option<String>,
+}
+
+fn header_list(
+  headers: &hyper::HeaderMap,
+  header: impl hyper::header::AsHeaderName,
+) -> impl Iterator<Item = &str> {
+  headers
+    .get_all(header)
+    .into_iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(str::trim)
+}
+
+fn forwarded_hops(headers: &hyper::HeaderMap) -> Vec<Hop> {
+  header_list(headers, hyper::header::FORWARDED)
+    .map(|element| {
+      let mut hop = Hop {
+        addr: None,
+        proto: None,
+        host: None,
+      };
+      for pair in element.split(';') {
+        let Some((key, value)) = pair.trim().split_once('=') else {
+          continue;
+        };
+        let value = value.trim_matches('"');
+        if key.eq_ignore_ascii_case("for") {
+          hop.addr = parse_node(value);
+        } else if key.eq_ignore_ascii_case("proto") {
+          hop.proto = Some(value.to_owned());
+        } else if key.eq_ignore_ascii_case("host") {
+          hop.host = Some(value.to_owned());
+        }
+      }
+      hop
+    })
+    .collect()
+}
+
+fn x_forwarded_hops(headers: &hyper::HeaderMap) -> Vec<Hop> {
+  let mut proto: Vec<_> = header_list(headers, "x-forwarded-proto").collect();
+  let mut host: Vec<_> = header_list(headers, "x-forwarded-host").collect();
+  let addrs: Vec<_> = header_list(headers, "x-forwarded-for").collect();
+
+  // Proxies that set `X-Forwarded-Proto` or `X-Forwarded-Host` usually only keep the value they received from the
+  // client, so a single value belongs to the leftmost hop.
+  let mut hops: Vec<_> = addrs
+    .iter()
+    .map(|addr| Hop {
+      addr: parse_node(addr),
+      proto: None,
+      host: None,
+    })
+    .collect();
+  if proto.len() == 1 || proto.len() == hops.len() {
+    for (hop, proto) in hops.iter_mut().zip(proto.drain(..)) {
+      hop.proto = Some(proto.to_owned());
+    }
+  }
+  if host.len() == 1 || host.len() == hops.len() {
+    for (hop, host) in hops.iter_mut().zip(host.drain(..)) {
+      hop.host = Some(host.to_owned());
+    }
+  }
+  hops
+}
+
+/// Parses a node as `ip`, `ip:port`, `[ipv6]` or `[ipv6]:port`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+  if let Some(rest) = node.strip_prefix('[') {
+    return rest.split_once(']')?.0.parse().ok();
+  }
+  node
+    .parse()
+    .ok()
+    .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
 /// Check if there is a header of the given name containing the wanted value.
 fn header_contains_value(
   headers: &hyper::HeaderMap,
@@ -226,9 +1186,581 @@ impl std::future::Future for UpgradeFut {
       Poll::Pending => return Poll::Pending,
       Poll::Ready(x) => x,
     };
-    Poll::Ready(Ok(WebSocket::after_handshake(
-      TokioIo::new(upgraded?),
-      Role::Server,
-    )))
+    let mut ws =
+      WebSocket::after_handshake(TokioIo::new(upgraded?), Role::Server);
+    ws.set_extension(this.extension.take());
+    #[cfg(feature = "deflate")]
+    ws.set_deflate(this.deflate.take());
+    #[cfg(feature = "brotli")]
+    if let Some(config) = this.brotli.take() {
+      ws.set_brotli(Some(config));
+    }
+    #[cfg(feature = "zstd")]
+    if let Some(config) = this.zstd.take() {
+      ws.set_zstd(Some(config));
+    }
+    if let Some(options) = this.options {
+      options.apply(&mut ws);
+    }
+    Poll::Ready(Ok(ws))
+  }
+}
+
+impl std::future::Future for UpgradeWithInfo {
+  type Output = Result<UpgradedWebSocket, Error>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+    let mut fut = self.project().fut;
+    let ws = match fut.as_mut().poll(cx) {
+      Poll::Pending => return Poll::Pending,
+      Poll::Ready(ws) => ws?,
+    };
+    let fut = fut.project();
+    Poll::Ready(Ok(UpgradedWebSocket {
+      ws,
+      protocol: fut.protocol.take(),
+      extensions: fut.extensions.take(),
+      forwarded: std::mem::take(fut.forwarded),
+    }))
+  }
+}
+
+/// A future that resolves to a websocket stream over the connection returned by the future passed to
+/// [`upgrade_with_io`].
+#[pin_project]
+#[derive(Debug)]
+pub struct IoUpgradeFut<F> {
+  #[pin]
+  io: F,
+}
+
+impl<F, S, E> std::future::Future for IoUpgradeFut<F>
+where
+  F: std::future::Future<Output = Result<S, E>>,
+  S: AsyncRead + AsyncWrite + Unpin,
+  Error: From<E>,
+{
+  type Output = Result<WebSocket<S>, Error>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+    let this = self.project();
+    let io = match this.io.poll(cx) {
+      Poll::Pending => return Poll::Pending,
+      Poll::Ready(x) => x,
+    };
+    Poll::Ready(Ok(WebSocket::after_handshake(io?, Role::Server)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(headers: &[(&str, &str)]) -> Request<()> {
+    let mut builder = Request::builder();
+    for (name, value) in headers {
+      builder = builder.header(*name, *value);
+    }
+    builder.body(()).unwrap()
+  }
+
+  fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+  }
+
+  #[tokio::test]
+  async fn upgrade_with_io_parts() {
+    let (parts, _) = request(&[
+      ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+      ("Sec-WebSocket-Version", "13"),
+    ])
+    .into_parts();
+    let (client, server) = tokio::io::duplex(64);
+    let (response, fut) =
+      upgrade_with_io(&parts, async { Ok::<_, std::io::Error>(server) })
+        .unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::SWITCHING_PROTOCOLS);
+    // RFC 6455, section 1.3.
+    assert_eq!(
+      response.headers()["Sec-WebSocket-Accept"],
+      "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+
+    let mut server = fut.await.unwrap();
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    client
+      .write_frame(crate::Frame::text(b"hi".as_ref().into()))
+      .await
+      .unwrap();
+    assert_eq!(server.read_frame().await.unwrap().as_text(), Some("hi"));
+
+    let (parts, _) = request(&[("Sec-WebSocket-Version", "13")]).into_parts();
+    let result = upgrade_with_io(&parts, async {
+      Ok::<_, std::io::Error>(tokio::io::empty())
+    });
+    assert!(matches!(
+      result,
+      Err(WebSocketError::MissingSecWebSocketKey)
+    ));
+  }
+
+  #[test]
+  fn options_apply_to_the_websocket() {
+    let (stream, _) = tokio::io::duplex(64);
+    let mut ws = WebSocket::after_handshake(stream, Role::Server);
+    let defaults = UpgradeOptions::default();
+    assert_eq!(defaults.max_message_size, ws.max_message_size());
+    assert_eq!(defaults.writev, ws.writev());
+    assert_eq!(defaults.writev_threshold, ws.writev_threshold());
+
+    let options = defaults
+      .with_max_message_size(1024)
+      .with_auto_pong(false)
+      .with_writev(false);
+    options.apply(&mut ws);
+    assert_eq!(ws.max_message_size(), 1024);
+    assert!(ws.auto_close());
+    assert!(!ws.auto_pong());
+    assert!(!ws.writev());
+  }
+
+  #[test]
+  fn origin_check() {
+    let allowed = ["https://app.example.com"];
+    let req = request(&[("Origin", "https://APP.example.com")]);
+    assert!(check_origin(&req, &allowed).is_ok());
+    let req = request(&[("Origin", "https://evil.example")]);
+    assert!(matches!(
+      check_origin(&req, &allowed),
+      Err(WebSocketError::OriginNotAllowed)
+    ));
+    let req = request(&[("Origin", "null")]);
+    assert!(check_origin(&req, &allowed).is_err());
+    // Clients other than browsers do not send an origin.
+    let req = request(&[]);
+    assert!(check_origin(&req, &allowed).is_ok());
+  }
+
+  #[test]
+  fn protocol_negotiation() {
+    let key = ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+    let version = ("Sec-WebSocket-Version", "13");
+    let supported = ["mqtt", "chat"];
+
+    let mut req =
+      request(&[key, version, ("Sec-WebSocket-Protocol", "v2.chat, chat")]);
+    let (response, fut) = upgrade_with_protocol(&mut req, &supported).unwrap();
+    assert_eq!(selected_protocol(&response), Some("chat"));
+    assert_eq!(fut.protocol(), Some("chat"));
+    assert_eq!(fut.extensions(), None);
+
+    // The client's order of preference wins.
+    let mut req = request(&[
+      key,
+      version,
+      ("Sec-WebSocket-Protocol", "chat"),
+      ("Sec-WebSocket-Protocol", "mqtt"),
+    ]);
+    let (response, _) = upgrade_with_protocol(&mut req, &supported).unwrap();
+    assert_eq!(selected_protocol(&response), Some("chat"));
+
+    let mut req = request(&[key, version, ("Sec-WebSocket-Protocol", "soap")]);
+    let (response, _) = upgrade_with_protocol(&mut req, &supported).unwrap();
+    assert_eq!(selected_protocol(&response), None);
+
+    let mut req = request(&[
+      key,
+      version,
+      ("Sec-WebSocket-Protocol", "graph.v1, graph.v2"),
+    ]);
+    let (response, _) =
+      upgrade_with_protocol_fn(&mut req, |offered| offered.last().copied())
+        .unwrap();
+    assert_eq!(selected_protocol(&response), Some("graph.v2"));
+
+    // Only an offered protocol can be selected.
+    let mut req =
+      request(&[key, version, ("Sec-WebSocket-Protocol", "graph.v1")]);
+    assert!(matches!(
+      upgrade_with_protocol_fn(&mut req, |_| Some("graph.v3")),
+      Err(WebSocketError::InvalidSubprotocol)
+    ));
+  }
+
+  #[test]
+  fn extension_negotiation() {
+    struct Custom;
+
+    impl Extension for Custom {
+      fn name(&self) -> &str {
+        "x-custom"
+      }
+
+      fn reserved_bits(&self) -> u8 {
+        0x20
+      }
+
+      fn accept(&self, offers: &[&str]) -> Option<String> {
+        assert_eq!(offers, ["level=3", ""]);
+        Some("level=1".to_owned())
+      }
+
+      fn encode(
+        &self,
+        _frame: &mut crate::Frame<'_>,
+      ) -> Result<(), WebSocketError> {
+        Ok(())
+      }
+
+      fn decode(
+        &self,
+        _frame: &mut crate::Frame<'_>,
+      ) -> Result<(), WebSocketError> {
+        Ok(())
+      }
+    }
+
+    let headers = [
+      ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+      ("Sec-WebSocket-Version", "13"),
+      (
+        "Sec-WebSocket-Extensions",
+        "permessage-deflate, x-custom; level=3, X-Custom",
+      ),
+    ];
+    let (response, fut) =
+      upgrade_with_extension(request(&headers), Arc::new(Custom)).unwrap();
+    assert_eq!(
+      response.headers()[hyper::header::SEC_WEBSOCKET_EXTENSIONS],
+      "x-custom; level=1"
+    );
+    assert!(fut.extension.is_some());
+    assert_eq!(fut.extensions(), Some("x-custom; level=1"));
+
+    // Not offered, so `accept` is not called.
+    let (response, fut) =
+      upgrade_with_extension(request(&headers[..2]), Arc::new(Custom)).unwrap();
+    assert!(!response
+      .headers()
+      .contains_key(hyper::header::SEC_WEBSOCKET_EXTENSIONS));
+    assert!(fut.extension.is_none());
+  }
+
+  #[test]
+  fn forwarded() {
+    let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+    let req = request(&[(
+      "Forwarded",
+      r#"for=192.0.2.60;proto=https;host=example.com, for="[2001:db8::1]:4711""#,
+    )]);
+    let info = forwarded_info(&req, ip("10.0.0.1"), &trusted);
+    assert_eq!(info.client(), ip("2001:db8::1"));
+    assert_eq!(info.proto(), None);
+
+    let req = request(&[(
+      "Forwarded",
+      "for=192.0.2.60;proto=https;host=example.com, for=10.0.0.2",
+    )]);
+    let info = forwarded_info(&req, ip("10.0.0.1"), &trusted);
+    assert_eq!(info.client(), ip("192.0.2.60"));
+    assert_eq!(info.proto(), Some("https"));
+    assert_eq!(info.host(), Some("example.com"));
+
+    // Headers from an untrusted peer are ignored.
+    let info = forwarded_info(&req, ip("192.0.2.1"), &trusted);
+    assert_eq!(info.client(), ip("192.0.2.1"));
+
+    // An obfuscated hop stops the walk at the last trusted proxy.
+    let req = request(&[("Forwarded", "for=192.0.2.60, for=_hidden")]);
+    let info = forwarded_info(&req, ip("10.0.0.1"), &trusted);
+    assert_eq!(info.client(), ip("10.0.0.1"));
+  }
+
+  #[test]
+  fn x_forwarded() {
+    let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+    let req = request(&[
+      ("X-Forwarded-For", "203.0.113.7, 192.0.2.60:1234, 10.0.0.2"),
+      ("X-Forwarded-Proto", "https"),
+    ]);
+    let info = forwarded_info(&req, ip("10.0.0.1"), &trusted);
+    assert_eq!(info.client(), ip("192.0.2.60"));
+    assert_eq!(info.proto(), None);
+
+    let req = request(&[
+      ("X-Forwarded-For", "203.0.113.7"),
+      ("X-Forwarded-Proto", "https"),
+    ]);
+    let info = forwarded_info(&req, ip("10.0.0.1"), &trusted);
+    assert_eq!(info.client(), ip("203.0.113.7"));
+    assert_eq!(info.proto(), Some("https"));
+  }
+
+  #[test]
+  fn forwarded_after_upgrade() {
+    let trusted = [ip("10.0.0.1")];
+    let mut req = request(&[
+      ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+      ("Sec-WebSocket-Version", "13"),
+      ("X-Forwarded-For", "203.0.113.7"),
+      ("X-Forwarded-Host", "example.com"),
+    ]);
+    let (_, fut) = upgrade(&mut req).unwrap();
+    assert_eq!(fut.protocol(), None);
+    assert_eq!(fut.extensions(), None);
+    let info = fut.forwarded_info(ip("10.0.0.1"), &trusted);
+    assert_eq!(info, forwarded_info(&req, ip("10.0.0.1"), &trusted));
+    assert_eq!(info.client(), ip("203.0.113.7"));
+    assert_eq!(info.host(), Some("example.com"));
+  }
+
+  #[tokio::test]
+  async fn upgrade_with_info() {
+    use crate::Frame;
+
+    let (client, server) = tokio::io::duplex(4096);
+    tokio::spawn(async move {
+      let service = hyper::service::service_fn(|mut req| async move {
+        let (response, fut) = upgrade_with_protocol(&mut req, &["chat"])?;
+        tokio::spawn(async move {
+          let mut upgraded = fut.with_info().await.unwrap();
+          assert_eq!(upgraded.protocol(), Some("chat"));
+          assert_eq!(upgraded.extensions(), None);
+          let info = upgraded.forwarded_info(ip("10.0.0.1"), &[ip("10.0.0.1")]);
+          assert_eq!(info.client(), ip("203.0.113.7"));
+          let ws = upgraded.websocket();
+          let frame = ws.read_frame().await.unwrap();
+          ws.write_frame(frame).await.unwrap();
+        });
+        Ok::<_, WebSocketError>(response)
+      });
+      hyper::server::conn::http1::Builder::new()
+        .serve_connection(TokioIo::new(server), service)
+        .with_upgrades()
+        .await
+        .unwrap();
+    });
+
+    let (mut sender, conn) =
+      hyper::client::conn::http1::handshake(TokioIo::new(client))
+        .await
+        .unwrap();
+    tokio::spawn(conn.with_upgrades());
+    let request = Request::builder()
+      .uri("/chat")
+      .header(hyper::header::HOST, "localhost")
+      .header(hyper::header::UPGRADE, "websocket")
+      .header(hyper::header::CONNECTION, "upgrade")
+      .header("Sec-WebSocket-Key", crate::handshake::generate_key())
+      .header("Sec-WebSocket-Version", "13")
+      .header("Sec-WebSocket-Protocol", "chat")
+      .header("X-Forwarded-For", "203.0.113.7")
+      .body(Empty::<Bytes>::new())
+      .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::SWITCHING_PROTOCOLS);
+    let upgraded = hyper::upgrade::on(response).await.unwrap();
+    let mut ws =
+      WebSocket::after_handshake(TokioIo::new(upgraded), Role::Client);
+
+    ws.write_frame(Frame::text(b"hello".as_ref().into()))
+      .await
+      .unwrap();
+    assert_eq!(ws.read_frame().await.unwrap().as_text(), Some("hello"));
+  }
+
+  #[cfg(feature = "deflate")]
+  #[test]
+  fn deflate_negotiation_callback() {
+    let headers = [
+      ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+      ("Sec-WebSocket-Version", "13"),
+      (
+        "Sec-WebSocket-Extensions",
+        "permessage-deflate; server_max_window_bits=10, permessage-deflate",
+      ),
+    ];
+    let extensions = |response: &Response<Empty<Bytes>>| {
+      response
+        .headers()
+        .get(hyper::header::SEC_WEBSOCKET_EXTENSIONS)
+        .map(|value| value.to_str().unwrap().to_owned())
+    };
+
+    let (response, _) = upgrade_with_deflate_fn(request(&headers), |offers| {
+      assert_eq!(offers.len(), 2);
+      assert_eq!(offers[0].server_max_window_bits(), Some(10));
+      offers[1].accept(&DeflateConfig::default())
+    })
+    .unwrap();
+    assert_eq!(extensions(&response).as_deref(), Some("permessage-deflate"));
+
+    let (response, _) =
+      upgrade_with_deflate_fn(request(&headers), |_| None).unwrap();
+    assert_eq!(extensions(&response), None);
+
+    // The client did not allow the server to limit its window.
+    let result = upgrade_with_deflate_fn(request(&headers), |_| {
+      Some(DeflateConfig {
+        client_max_window_bits: 10,
+        ..Default::default()
+      })
+    });
+    assert!(matches!(
+      result,
+      Err(WebSocketError::InvalidDeflateParameters)
+    ));
+
+    let (_, _) = upgrade_with_deflate_fn(request(&headers[..2]), |_| {
+      unreachable!("called without offers")
+    })
+    .unwrap();
+  }
+
+  #[cfg(feature = "brotli")]
+  #[tokio::test]
+  async fn brotli_messages() {
+    use crate::Frame;
+
+    let (response, fut) = upgrade_with_brotli(
+      request(&[
+        ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+        ("Sec-WebSocket-Version", "13"),
+        (
+          "Sec-WebSocket-Extensions",
+          "permessage-brotli, permessage-deflate",
+        ),
+      ]),
+      BrotliConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+      response.headers()[hyper::header::SEC_WEBSOCKET_EXTENSIONS],
+      "permessage-brotli"
+    );
+    assert_eq!(fut.brotli, Some(BrotliConfig::default()));
+
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_brotli(Some(BrotliConfig::default()));
+    server.set_brotli(Some(BrotliConfig::default()));
+
+    let text = "hello ".repeat(100);
+    client
+      .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.as_text(), Some(text.as_str()));
+    server
+      .write_frame(Frame::binary(text.as_bytes().into()))
+      .await
+      .unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, text.as_bytes());
+  }
+
+  #[cfg(feature = "zstd")]
+  #[tokio::test]
+  async fn zstd_messages() {
+    use crate::Frame;
+
+    let policy = ZstdConfig {
+      server_max_window_log: 20,
+      ..Default::default()
+    };
+    let (response, fut) = upgrade_with_zstd(
+      request(&[
+        ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+        ("Sec-WebSocket-Version", "13"),
+        (
+          "Sec-WebSocket-Extensions",
+          "permessage-deflate, permessage-zstd; client_max_window_log=18",
+        ),
+      ]),
+      &policy,
+    )
+    .unwrap();
+    assert_eq!(
+      response.headers()[hyper::header::SEC_WEBSOCKET_EXTENSIONS],
+      "permessage-zstd; server_max_window_log=20; client_max_window_log=18"
+    );
+    let agreed = fut.zstd.unwrap();
+
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    client.set_zstd(Some(agreed));
+    server.set_zstd(Some(agreed));
+
+    let text = "hello ".repeat(100);
+    for _ in 0..2 {
+      client
+        .write_frame(Frame::text(text.as_bytes().to_vec().into()))
+        .await
+        .unwrap();
+      let frame = server.read_frame().await.unwrap();
+      assert_eq!(frame.as_text(), Some(text.as_str()));
+    }
+    server
+      .write_frame(Frame::binary(text.as_bytes().into()))
+      .await
+      .unwrap();
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(&*frame.payload, text.as_bytes());
+  }
+
+  #[cfg(feature = "http2")]
+  #[tokio::test]
+  async fn websocket_over_http2() {
+    use crate::Frame;
+    use crate::OpCode;
+    use hyper_util::rt::TokioExecutor;
+
+    let (client, server) = tokio::io::duplex(4096);
+    tokio::spawn(async move {
+      let service = hyper::service::service_fn(|mut req| async move {
+        assert!(is_upgrade_request(&req));
+        let (response, fut) = upgrade_with_protocol(&mut req, &["chat"])?;
+        tokio::spawn(async move {
+          let mut ws = fut.await.unwrap();
+          let frame = ws.read_frame().await.unwrap();
+          ws.write_frame(frame).await.unwrap();
+        });
+        Ok::<_, WebSocketError>(response)
+      });
+      hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+        .enable_connect_protocol()
+        .serve_connection(TokioIo::new(server), service)
+        .await
+        .unwrap();
+    });
+
+    let (mut sender, conn) = hyper::client::conn::http2::handshake(
+      TokioExecutor::new(),
+      TokioIo::new(client),
+    )
+    .await
+    .unwrap();
+    tokio::spawn(conn);
+    let request = Request::builder()
+      .uri("http://localhost/chat")
+      .header("Sec-WebSocket-Protocol", "chat")
+      .body(Empty::<Bytes>::new())
+      .unwrap();
+    let (mut ws, response) =
+      crate::handshake::client_http2(&mut sender, request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(selected_protocol(&response), Some("chat"));
+
+    ws.write_frame(Frame::text(b"hello".as_ref().into()))
+      .await
+      .unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Text);
+    assert_eq!(frame.as_text(), Some("hello"));
   }
 }
@@ -0,0 +1,89 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::WebSocketError;
+
+/// Checks the payload of a fragmented text message for valid UTF-8 as the fragments arrive, so an invalid message
+/// fails at the first bad fragment instead of after it has been collected.
+#[derive(Default)]
+pub(crate) struct Utf8Validator {
+  /// A character split at the end of the previous fragment.
+  incomplete: Option<utf8::Incomplete>,
+}
+
+impl Utf8Validator {
+  /// Checks the next fragment. A character may be split between fragments, but not left unfinished at the end of
+  /// the message.
+  pub fn feed(
+    &mut self,
+    mut data: &[u8],
+    fin: bool,
+  ) -> Result<(), WebSocketError> {
+    if let Some(mut incomplete) = self.incomplete.take() {
+      match incomplete.try_complete(data) {
+        Some((Ok(_), rest)) => data = rest,
+        Some((Err(_), _)) => return Err(WebSocketError::InvalidUTF8),
+        None if fin => return Err(WebSocketError::InvalidUTF8),
+        None => {
+          self.incomplete = Some(incomplete);
+          return Ok(());
+        }
+      }
+    }
+
+    #[cfg(feature = "simd")]
+    let result = simdutf8::compat::from_utf8(data);
+    #[cfg(not(feature = "simd"))]
+    let result = std::str::from_utf8(data);
+
+    match result {
+      Ok(_) => Ok(()),
+      // The fragment ends in the middle of a character, which the next one may finish.
+      Err(e) if e.error_len().is_none() && !fin => {
+        self.incomplete = Some(utf8::Incomplete::new(&data[e.valid_up_to()..]));
+        Ok(())
+      }
+      Err(_) => Err(WebSocketError::InvalidUTF8),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn validate(fragments: &[&[u8]]) -> bool {
+    let mut validator = Utf8Validator::default();
+    let last = fragments.len() - 1;
+    fragments
+      .iter()
+      .enumerate()
+      .all(|(i, data)| validator.feed(data, i == last).is_ok())
+  }
+
+  #[test]
+  fn characters_split_between_fragments() {
+    assert!(validate(&[b"caf\xc3", b"\xa9!"]));
+    assert!(validate(&[b"\xf0\x9f", b"", b"\x98", b"\x80"]));
+    assert!(validate(&[b"", b""]));
+  }
+
+  #[test]
+  fn invalid_sequences() {
+    assert!(!validate(&[b"caf\xc3", b"("]));
+    assert!(!validate(&[b"\xff", b"ok"]));
+    assert!(!validate(&[b"ok", b"\xf0\x9f"]));
+    assert!(!validate(&[b"\xf0\x9f", b"\x98"]));
+  }
+}
@@ -0,0 +1,369 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use zstd::stream::raw::CParameter;
+use zstd::stream::raw::DParameter;
+use zstd::stream::raw::Decoder;
+use zstd::stream::raw::Encoder;
+use zstd::stream::raw::InBuffer;
+use zstd::stream::raw::Operation;
+use zstd::stream::raw::OutBuffer;
+
+use crate::Role;
+use crate::WebSocketError;
+
+const EXTENSION_NAME: &str = "permessage-zstd";
+const MIN_WINDOW_LOG: u32 = 10;
+/// The largest window zstd decoders accept without opting in to more memory.
+const MAX_WINDOW_LOG: u32 = 27;
+
+/// Parameters of the experimental, non-standard `permessage-zstd` extension.
+///
+/// Every message is compressed as a separate zstd frame. The extension is not registered with IANA, so only enable it
+/// when both endpoints use this crate. Like permessage-deflate, each direction has a window size that the receiving
+/// end bounds during the handshake, which in turn bounds the memory its decoder needs. The compression level is not
+/// negotiated and only affects the messages written by the local end.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::ZstdConfig;
+///
+/// let policy = ZstdConfig {
+///   client_max_window_log: 20,
+///   ..Default::default()
+/// };
+/// let agreed = policy
+///   .accept("permessage-zstd; server_max_window_log=18; client_max_window_log=23")
+///   .unwrap();
+/// assert_eq!(
+///   agreed.response(),
+///   "permessage-zstd; server_max_window_log=18; client_max_window_log=20"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdConfig {
+  /// Compression level, from 1 (fastest) to 22 (smallest output). Negative levels trade ratio for even more speed.
+  pub level: i32,
+  /// Base-2 logarithm of the largest window the server compresses with, between 10 and 27.
+  pub server_max_window_log: u32,
+  /// Base-2 logarithm of the largest window the client compresses with, between 10 and 27.
+  pub client_max_window_log: u32,
+}
+
+impl Default for ZstdConfig {
+  fn default() -> Self {
+    Self {
+      level: 3,
+      server_max_window_log: 23,
+      client_max_window_log: 23,
+    }
+  }
+}
+
+impl ZstdConfig {
+  /// The value of the `Sec-WebSocket-Extensions` header a client sends to offer these parameters.
+  pub fn offer(&self) -> String {
+    self.response()
+  }
+
+  /// Negotiates the offers in a client's `Sec-WebSocket-Extensions` header against this server policy. Returns the
+  /// agreed parameters of the first valid offer, with each window the smaller of the two, or `None` if the client
+  /// did not offer the extension.
+  pub fn accept(&self, offers: &str) -> Option<ZstdConfig> {
+    first_offer(offers).map(|offer| ZstdConfig {
+      level: self.level,
+      server_max_window_log: clamp(self.server_max_window_log)
+        .min(offer.server_max_window_log),
+      client_max_window_log: clamp(self.client_max_window_log)
+        .min(offer.client_max_window_log),
+    })
+  }
+
+  /// The value of the `Sec-WebSocket-Extensions` header a server sends to accept these parameters.
+  pub fn response(&self) -> String {
+    format!(
+      "{}; server_max_window_log={}; client_max_window_log={}",
+      EXTENSION_NAME,
+      clamp(self.server_max_window_log),
+      clamp(self.client_max_window_log)
+    )
+  }
+
+  /// Parses the `Sec-WebSocket-Extensions` header of a server response to an offer of these parameters. Returns
+  /// `None` if the server did not accept the extension, and fails if it chose larger windows than offered.
+  pub fn from_response(
+    &self,
+    response: &str,
+  ) -> Result<Option<ZstdConfig>, WebSocketError> {
+    let mut elements = response.split(',').filter_map(parse_element);
+    let Some(agreed) = elements.next() else {
+      return Ok(None);
+    };
+    let agreed = agreed.map_err(|_| WebSocketError::InvalidZstdParameters)?;
+    if elements.next().is_some()
+      || agreed.server_max_window_log > clamp(self.server_max_window_log)
+      || agreed.client_max_window_log > clamp(self.client_max_window_log)
+    {
+      return Err(WebSocketError::InvalidZstdParameters);
+    }
+    Ok(Some(ZstdConfig {
+      level: self.level,
+      ..agreed
+    }))
+  }
+
+  /// The windows the end with `role` compresses and decompresses with.
+  fn windows(&self, role: Role) -> (u32, u32) {
+    let server = clamp(self.server_max_window_log);
+    let client = clamp(self.client_max_window_log);
+    match role {
+      Role::Server => (server, client),
+      Role::Client => (client, server),
+    }
+  }
+}
+
+fn clamp(window_log: u32) -> u32 {
+  window_log.clamp(MIN_WINDOW_LOG, MAX_WINDOW_LOG)
+}
+
+/// Parses one extension element. Returns `None` for other extensions and `Some(Err(()))` for a malformed
+/// `permessage-zstd` element. Missing windows default to the largest one.
+fn parse_element(element: &str) -> Option<Result<ZstdConfig, ()>> {
+  let mut parts = element.split(';').map(str::trim);
+  if !parts.next()?.eq_ignore_ascii_case(EXTENSION_NAME) {
+    return None;
+  }
+
+  let mut server_max_window_log = None;
+  let mut client_max_window_log = None;
+  for part in parts {
+    let Some((name, value)) = part.split_once('=') else {
+      return Some(Err(()));
+    };
+    let window = match name.trim() {
+      "server_max_window_log" => &mut server_max_window_log,
+      "client_max_window_log" => &mut client_max_window_log,
+      _ => return Some(Err(())),
+    };
+    match value.trim().trim_matches('"').parse() {
+      Ok(log @ MIN_WINDOW_LOG..=MAX_WINDOW_LOG) if window.is_none() => {
+        *window = Some(log)
+      }
+      _ => return Some(Err(())),
+    }
+  }
+  Some(Ok(ZstdConfig {
+    level: ZstdConfig::default().level,
+    server_max_window_log: server_max_window_log.unwrap_or(MAX_WINDOW_LOG),
+    client_max_window_log: client_max_window_log.unwrap_or(MAX_WINDOW_LOG),
+  }))
+}
+
+/// Returns the first valid `permessage-zstd` offer of a client's `Sec-WebSocket-Extensions` header.
+pub(crate) fn first_offer(header: &str) -> Option<ZstdConfig> {
+  header
+    .split(',')
+    .filter_map(parse_element)
+    .find_map(Result::ok)
+}
+
+/// Reserves room for more output once less than 1 KiB is left.
+fn reserve(output: &mut Vec<u8>) {
+  if output.capacity() - output.len() < 1024 {
+    output.reserve(output.capacity().max(1024));
+  }
+}
+
+/// Compresses outgoing messages.
+pub(crate) struct ZstdEncoder {
+  encoder: Encoder<'static>,
+}
+
+impl ZstdEncoder {
+  pub fn new(config: &ZstdConfig, role: Role) -> Self {
+    let (window_log, _) = config.windows(role);
+    let mut encoder =
+      Encoder::new(config.level).expect("failed to create zstd context");
+    encoder
+      .set_parameter(CParameter::WindowLog(window_log))
+      .expect("bug: invalid zstd window");
+    Self { encoder }
+  }
+
+  /// Compresses the payload of one frame. `fin` marks the last frame of the message, which ends the zstd frame.
+  pub fn compress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 64);
+    let mut input = InBuffer::around(input);
+    while input.pos() < input.src.len() {
+      reserve(&mut output);
+      let len = output.len();
+      self
+        .encoder
+        .run(&mut input, &mut OutBuffer::around_pos(&mut output, len))?;
+    }
+    loop {
+      reserve(&mut output);
+      let len = output.len();
+      let mut buffer = OutBuffer::around_pos(&mut output, len);
+      let remaining = match fin {
+        true => self.encoder.finish(&mut buffer, true)?,
+        false => self.encoder.flush(&mut buffer)?,
+      };
+      if remaining == 0 {
+        break;
+      }
+    }
+    if fin {
+      self.encoder.reinit()?;
+    }
+    Ok(output)
+  }
+}
+
+/// Decompresses incoming messages.
+pub(crate) struct ZstdDecoder {
+  decoder: Decoder<'static>,
+  /// Whether the last input ended a zstd frame.
+  frame_done: bool,
+}
+
+impl ZstdDecoder {
+  pub fn new(config: &ZstdConfig, role: Role) -> Self {
+    let (_, window_log) = config.windows(role);
+    let mut decoder = Decoder::new().expect("failed to create zstd context");
+    decoder
+      .set_parameter(DParameter::WindowLogMax(window_log))
+      .expect("bug: invalid zstd window");
+    Self {
+      decoder,
+      frame_done: true,
+    }
+  }
+
+  /// Decompresses the payload of one frame. `fin` marks the last frame of the message, which must end the zstd
+  /// frame. Fails with `WebSocketError::FrameTooLarge` if the output would grow beyond `max_size` bytes.
+  pub fn decompress(
+    &mut self,
+    input: &[u8],
+    fin: bool,
+    max_size: usize,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let result = self.feed(input, max_size);
+    if result.is_err() || fin {
+      let frame_done = std::mem::replace(&mut self.frame_done, true);
+      self.decoder.reinit()?;
+      if result.is_ok() && !frame_done {
+        return Err(WebSocketError::InvalidCompressedData);
+      }
+    }
+    result
+  }
+
+  fn feed(
+    &mut self,
+    input: &[u8],
+    max_size: usize,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let mut output = Vec::with_capacity((input.len() * 2 + 64).min(max_size));
+    let mut input = InBuffer::around(input);
+    loop {
+      if output.len() == output.capacity() {
+        if output.len() >= max_size {
+          return Err(WebSocketError::FrameTooLarge);
+        }
+        let additional =
+          output.capacity().max(1024).min(max_size - output.len());
+        output.reserve_exact(additional);
+      }
+
+      let len = output.len();
+      let hint = self
+        .decoder
+        .run(&mut input, &mut OutBuffer::around_pos(&mut output, len))
+        .map_err(|_| WebSocketError::InvalidCompressedData)?;
+      if input.pos() > 0 || output.len() > len {
+        self.frame_done = hint == 0;
+      }
+      // Everything is decoded once the input is consumed and the decoder left room in the output.
+      if input.pos() == input.src.len() && output.len() < output.capacity() {
+        return Ok(output);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiation() {
+    let policy = ZstdConfig::default();
+    let agreed = policy
+      .accept(
+        "permessage-zstd; foo=1, permessage-zstd; client_max_window_log=17",
+      )
+      .unwrap();
+    assert_eq!(agreed.server_max_window_log, 23);
+    assert_eq!(agreed.client_max_window_log, 17);
+    assert!(policy.accept("permessage-deflate").is_none());
+
+    let client = ZstdConfig::default();
+    assert_eq!(
+      client.from_response(&agreed.response()).unwrap(),
+      Some(agreed)
+    );
+    assert_eq!(client.from_response("").unwrap(), None);
+    assert!(client
+      .from_response("permessage-zstd; server_max_window_log=27")
+      .is_err());
+  }
+
+  #[test]
+  fn round_trip() {
+    let config = ZstdConfig::default();
+    let mut encoder = ZstdEncoder::new(&config, Role::Client);
+    let mut decoder = ZstdDecoder::new(&config, Role::Server);
+    let text = r#"{"type":"telemetry","value":42}"#.repeat(20);
+
+    // A message split across frames is a single zstd frame.
+    let first = encoder.compress(&text.as_bytes()[..100], false).unwrap();
+    let last = encoder.compress(&text.as_bytes()[100..], true).unwrap();
+    assert!(first.len() + last.len() < text.len() / 4);
+    let mut output = decoder.decompress(&first, false, 1024).unwrap();
+    output.extend(decoder.decompress(&last, true, 1024).unwrap());
+    assert_eq!(output, text.as_bytes());
+
+    let message = encoder.compress(text.as_bytes(), true).unwrap();
+    let output = decoder.decompress(&message, true, 1024).unwrap();
+    assert_eq!(output, text.as_bytes());
+
+    assert!(matches!(
+      decoder.decompress(&message, true, 100),
+      Err(WebSocketError::FrameTooLarge)
+    ));
+    assert!(matches!(
+      decoder.decompress(&message[..message.len() / 2], true, 1024),
+      Err(WebSocketError::InvalidCompressedData)
+    ));
+    let output = decoder.decompress(&message, true, 1024).unwrap();
+    assert_eq!(output, text.as_bytes());
+  }
+}
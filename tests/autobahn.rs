@@ -0,0 +1,101 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use fastwebsockets::autobahn;
+use fastwebsockets::autobahn::AutobahnClient;
+use fastwebsockets::upgrade;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketError;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+// Stands in for `wstest -m fuzzingserver` with two cases that expect their message echoed.
+async fn fuzzing_server(
+  mut req: Request<Incoming>,
+  log: Log,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let path = req.uri().path_and_query().unwrap().to_string();
+  let (response, fut) = upgrade::upgrade(&mut req)?;
+  tokio::spawn(async move {
+    let mut ws = fut.await.unwrap();
+    if path == "/getCaseCount" {
+      ws.write_frame(Frame::text(b"2".to_vec().into()))
+        .await
+        .unwrap();
+    } else if path.starts_with("/runCase") {
+      let message = path.as_bytes().to_vec();
+      ws.write_frame(Frame::binary(message.as_slice().into()))
+        .await
+        .unwrap();
+      let echo = ws.read_frame().await.unwrap();
+      assert_eq!(echo.opcode, OpCode::Binary);
+      assert_eq!(&*echo.payload, message.as_slice());
+    }
+    log.lock().unwrap().push(path);
+    ws.write_frame(Frame::close(1000, &[])).await.unwrap();
+    let _ = ws.read_frame().await;
+  });
+  Ok(response)
+}
+
+#[tokio::test]
+async fn runs_every_case() -> Result<()> {
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?;
+  let log = Log::default();
+
+  let server_log = log.clone();
+  tokio::spawn(async move {
+    loop {
+      let (stream, _) = listener.accept().await.unwrap();
+      let log = server_log.clone();
+      tokio::spawn(async move {
+        let service = service_fn(move |req| fuzzing_server(req, log.clone()));
+        let _ = http1::Builder::new()
+          .serve_connection(TokioIo::new(stream), service)
+          .with_upgrades()
+          .await;
+      });
+    }
+  });
+
+  let client = AutobahnClient::new(addr.to_string(), "fastwebsockets");
+  assert_eq!(client.case_count().await?, 2);
+  client.run(autobahn::echo).await?;
+  assert_eq!(
+    *log.lock().unwrap(),
+    [
+      "/getCaseCount",
+      "/getCaseCount",
+      "/runCase?case=1&agent=fastwebsockets",
+      "/runCase?case=2&agent=fastwebsockets",
+      "/updateReports?agent=fastwebsockets",
+    ]
+  );
+  Ok(())
+}
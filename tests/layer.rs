@@ -0,0 +1,98 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use fastwebsockets::handshake;
+use fastwebsockets::upgrade::UpgradeFut;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketLayer;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::header::CONNECTION;
+use hyper::header::UPGRADE;
+use hyper::http::request::Parts;
+use hyper::Request;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: std::future::Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::task::spawn(fut);
+  }
+}
+
+async fn echo(fut: UpgradeFut, _parts: Parts) {
+  let mut ws = fut.await.unwrap();
+  loop {
+    let frame = ws.read_frame().await.unwrap();
+    match frame.opcode {
+      OpCode::Close => break,
+      OpCode::Text | OpCode::Binary => {
+        ws.write_frame(Frame::new(true, frame.opcode, None, frame.payload))
+          .await
+          .unwrap();
+      }
+      _ => {}
+    }
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn layer_alongside_router() -> Result<()> {
+  let app = axum::Router::new()
+    .route("/", axum::routing::get(|| async { "hello" }))
+    .layer(WebSocketLayer::path("/ws", echo));
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?;
+  tokio::spawn(async move { axum::serve(listener, app).await });
+
+  let stream = TcpStream::connect(addr).await?;
+  let request = Request::builder()
+    .method("GET")
+    .uri(format!("http://{}/ws", addr))
+    .header("Host", addr.to_string())
+    .header(UPGRADE, "websocket")
+    .header(CONNECTION, "upgrade")
+    .header("Sec-WebSocket-Key", handshake::generate_key())
+    .header("Sec-WebSocket-Version", "13")
+    .body(Empty::<Bytes>::new())?;
+  let (mut ws, _) = handshake::client(&SpawnExecutor, request, stream).await?;
+
+  ws.write_frame(Frame::text(b"hello"[..].into())).await?;
+  let frame = ws.read_frame().await?;
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"hello");
+  ws.write_frame(Frame::close(1000, b"")).await?;
+
+  // Plain requests still reach the router.
+  let stream = TcpStream::connect(addr).await?;
+  let (mut sender, conn) =
+    hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(stream))
+      .await?;
+  tokio::spawn(conn);
+  let request = Request::builder()
+    .uri("/")
+    .header("Host", addr.to_string())
+    .body(Empty::<Bytes>::new())?;
+  let response = sender.send_request(request).await?;
+  assert_eq!(response.status(), 200);
+  Ok(())
+}
@@ -0,0 +1,185 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+
+use anyhow::Result;
+use fastwebsockets::handshake;
+use fastwebsockets::mqtt;
+use fastwebsockets::upgrade;
+use fastwebsockets::WebSocketError;
+use fastwebsockets::WsByteStream;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::header::CONNECTION;
+use hyper::header::UPGRADE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper::Response;
+use hyper::StatusCode;
+use hyper_util::rt::TokioIo;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::task::spawn(fut);
+  }
+}
+
+// The fixed header and the start of an MQTT 3.1.1 CONNECT packet.
+const CONNECT: &[u8] = &[0x10, 0x0c, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04];
+const CONNACK: &[u8] = &[0x20, 0x02, 0x00, 0x00];
+
+async fn broker(
+  mut req: Request<Incoming>,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let (response, fut) = match mqtt::upgrade(&mut req) {
+    Ok(upgrade) => upgrade,
+    Err(_) => {
+      let mut response = Response::new(Empty::new());
+      *response.status_mut() = StatusCode::BAD_REQUEST;
+      return Ok(response);
+    }
+  };
+  tokio::spawn(async move {
+    let mut stream = WsByteStream::new(fut.await.unwrap());
+    let mut packet = [0; CONNECT.len()];
+    stream.read_exact(&mut packet).await.unwrap();
+    assert_eq!(packet, CONNECT);
+    stream.write_all(CONNACK).await.unwrap();
+    stream.flush().await.unwrap();
+  });
+  Ok(response)
+}
+
+/// Accepts every upgrade and selects the `chat` subprotocol, whether it was offered or not.
+async fn chat_server(
+  mut req: Request<Incoming>,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let (mut response, _) = upgrade::upgrade(&mut req)?;
+  response
+    .headers_mut()
+    .insert("Sec-WebSocket-Protocol", "chat".parse().unwrap());
+  Ok(response)
+}
+
+async fn serve<F, Fut>(service: F) -> Result<std::net::SocketAddr>
+where
+  F: Fn(Request<Incoming>) -> Fut + Copy + Send + 'static,
+  Fut: Future<Output = Result<Response<Empty<Bytes>>, WebSocketError>>
+    + Send
+    + 'static,
+{
+  let listener = TcpListener::bind("127.0.0.1:0").await?;
+  let addr = listener.local_addr()?;
+  tokio::spawn(async move {
+    loop {
+      let (stream, _) = listener.accept().await.unwrap();
+      tokio::spawn(async move {
+        http1::Builder::new()
+          .serve_connection(TokioIo::new(stream), service_fn(service))
+          .with_upgrades()
+          .await
+      });
+    }
+  });
+  Ok(addr)
+}
+
+fn request(
+  addr: std::net::SocketAddr,
+  protocol: Option<&str>,
+) -> Result<Request<Empty<Bytes>>> {
+  let mut request = Request::builder()
+    .method("GET")
+    .uri(format!("http://{}/mqtt", addr))
+    .header("Host", addr.to_string())
+    .header(UPGRADE, "websocket")
+    .header(CONNECTION, "upgrade")
+    .header("Sec-WebSocket-Key", handshake::generate_key())
+    .header("Sec-WebSocket-Version", "13");
+  if let Some(protocol) = protocol {
+    request = request.header("Sec-WebSocket-Protocol", protocol);
+  }
+  Ok(request.body(Empty::new())?)
+}
+
+#[tokio::test]
+async fn mqtt_connect() -> Result<()> {
+  let addr = serve(broker).await?;
+
+  let socket = TcpStream::connect(addr).await?;
+  let (mut stream, response) =
+    mqtt::connect(&SpawnExecutor, request(addr, None)?, socket).await?;
+  assert_eq!(
+    upgrade::selected_protocol(&response),
+    Some(mqtt::SUBPROTOCOL)
+  );
+
+  // Split the packet across frames; the broker reads it as one byte stream.
+  stream.write_all(&CONNECT[..3]).await?;
+  stream.write_all(&CONNECT[3..]).await?;
+  let mut packet = [0; CONNACK.len()];
+  stream.read_exact(&mut packet).await?;
+  assert_eq!(packet, CONNACK);
+
+  // Clients that do not offer the subprotocol are turned away.
+  let socket = TcpStream::connect(addr).await?;
+  let result = handshake::client(&SpawnExecutor, request(addr, None)?, socket);
+  assert!(matches!(
+    result.await,
+    Err(WebSocketError::InvalidStatusCode(400))
+  ));
+  Ok(())
+}
+
+#[tokio::test]
+async fn unoffered_subprotocol_fails_the_handshake() -> Result<()> {
+  let addr = serve(chat_server).await?;
+
+  let socket = TcpStream::connect(addr).await?;
+  let result = handshake::client(&SpawnExecutor, request(addr, None)?, socket);
+  assert!(matches!(
+    result.await,
+    Err(WebSocketError::UnexpectedSubprotocol)
+  ));
+
+  let socket = TcpStream::connect(addr).await?;
+  let req = request(addr, Some("superchat, chat"))?;
+  let (_, response) = handshake::client(&SpawnExecutor, req, socket).await?;
+  assert_eq!(upgrade::selected_protocol(&response), Some("chat"));
+  let response = handshake::HandshakeResponse::from(response);
+  assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+  assert_eq!(response.protocol(), Some("chat"));
+  assert_eq!(response.extensions(), None);
+
+  let socket = TcpStream::connect(addr).await?;
+  let result = mqtt::connect(&SpawnExecutor, request(addr, None)?, socket);
+  assert!(matches!(
+    result.await,
+    Err(WebSocketError::UnexpectedSubprotocol)
+  ));
+  Ok(())
+}